@@ -92,7 +92,7 @@ def plot_backtest(data: dict):
         "trades": trades,
         "daily_portvals": data['performance']['daily_portvals'],
         "bench_portvals": data['benchmark']['daily_portvals'],
-        "price": data['prices']['map']
+        "price": {k: v['adjusted_close'] for k, v in data['prices']['map'].items()}
         # "daily_returns" : data['performance']['daily_returns']
     }
     df = pd.DataFrame.from_dict(structure,)
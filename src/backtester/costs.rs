@@ -0,0 +1,138 @@
+//! Models the costs of executing trades (commissions, fees, slippage, and
+//! crossing the bid-ask spread).
+
+use crate::config::BacktesterParams;
+use serde::{Deserialize, Serialize};
+
+/// The result of executing an order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    /// The price per share the order was actually filled at.
+    pub price: f64,
+
+    /// Commissions and fees paid on top of the cost of the shares.
+    pub fees: f64,
+}
+
+impl Fill {
+    /// How much worse the fill was than trading `shares` at `quoted_price`
+    /// for free.
+    pub fn cost(&self, shares: f64, quoted_price: f64) -> f64 {
+        (self.price - quoted_price) * shares + self.fees
+    }
+}
+
+/// Decides how an order is filled. Implement this to plug a different cost
+/// model into the BackTester.
+pub trait CostModel {
+    /// Fills an order for `shares` (positive to buy, negative to sell) at a
+    /// `quoted_price`.
+    fn fill(&self, shares: f64, quoted_price: f64) -> Fill;
+}
+
+/// A cost model covering the usual brokerage costs. Every cost defaults to
+/// zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransactionCosts {
+    /// Commission paid per share traded.
+    pub commission_per_share: f64,
+
+    /// Commission paid as a fraction of the value traded (0.001 = 0.1%).
+    pub commission_rate: f64,
+
+    /// Flat fee paid on every order.
+    pub fee_per_order: f64,
+
+    /// Adverse price movement between deciding to trade and the fill, in
+    /// basis points.
+    pub slippage_bps: f64,
+
+    /// Width of the bid-ask spread in basis points. Half of it is paid on
+    /// every fill.
+    pub spread_bps: f64,
+}
+
+impl From<&BacktesterParams> for TransactionCosts {
+    fn from(p: &BacktesterParams) -> Self {
+        Self {
+            commission_per_share: p.commission_per_share,
+            commission_rate: p.commission_rate,
+            fee_per_order: p.fee_per_order,
+            slippage_bps: p.slippage_bps,
+            spread_bps: p.spread_bps,
+        }
+    }
+}
+
+impl CostModel for TransactionCosts {
+    fn fill(&self, shares: f64, quoted_price: f64) -> Fill {
+        if shares == 0.0 {
+            return Fill {
+                price: quoted_price,
+                fees: 0.0,
+            };
+        }
+
+        // Buying fills above the quote, selling fills below it.
+        let direction = shares.signum();
+        let adverse_bps = self.slippage_bps + self.spread_bps / 2.0;
+        let price = quoted_price * (1.0 + direction * adverse_bps / 10_000.0);
+
+        let abs_shares = shares.abs();
+        let fees = self.fee_per_order
+            + self.commission_per_share * abs_shares
+            + self.commission_rate * abs_shares * price;
+
+        Fill { price, fees }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CostModel, TransactionCosts};
+    use crate::util::nearly_equal;
+
+    #[test]
+    fn no_costs() {
+        let fill = TransactionCosts::default().fill(10.0, 100.0);
+        assert_eq!(fill.price, 100.0);
+        assert_eq!(fill.fees, 0.0);
+        assert_eq!(fill.cost(10.0, 100.0), 0.0);
+    }
+
+    #[test]
+    fn slippage_and_spread_are_adverse() {
+        let costs = TransactionCosts {
+            slippage_bps: 10.0,
+            spread_bps: 20.0,
+            ..Default::default()
+        };
+
+        // 10 bps slippage + 10 bps half-spread
+        let buy = costs.fill(10.0, 100.0);
+        assert!(nearly_equal(buy.price, 100.2));
+        assert!(nearly_equal(buy.cost(10.0, 100.0), 2.0));
+
+        let sell = costs.fill(-10.0, 100.0);
+        assert!(nearly_equal(sell.price, 99.8));
+        assert!(nearly_equal(sell.cost(-10.0, 100.0), 2.0));
+    }
+
+    #[test]
+    fn commissions_and_fees() {
+        let costs = TransactionCosts {
+            commission_per_share: 0.01,
+            commission_rate: 0.001,
+            fee_per_order: 1.0,
+            ..Default::default()
+        };
+
+        let fill = costs.fill(-100.0, 50.0);
+        assert_eq!(fill.price, 50.0);
+        // 1.0 flat + 100 * 0.01 + 0.1% of 5000
+        assert!(nearly_equal(fill.fees, 7.0));
+
+        // Not trading is free
+        assert_eq!(costs.fill(0.0, 50.0).fees, 0.0);
+    }
+}
@@ -1,15 +1,22 @@
 //! The BackTester runs backtests for a given set of Trades on a Prices time
-//! series. It returns a [PortfolioPerformance](performance/struct.PortfolioPerformance.html)
+//! series, crediting the dividends in `Prices::actions` to long positions and
+//! debiting them from short ones. It returns a [PortfolioPerformance](performance/struct.PortfolioPerformance.html)
 //! containing portfolio value over time as well as other statistics.
 
+pub mod costs;
 pub mod performance;
+pub mod portfolio;
+pub mod statistics;
+pub mod walkforward;
 
 use crate::trading::tradingmodel::Trades;
 
 use crate::date::Date;
 use crate::marketdata::prices::Prices;
 use crate::trading::Position;
-use performance::{PerformanceError, PortfolioPerformance};
+use costs::{CostModel, TransactionCosts};
+use performance::{PerformanceError, PortfolioPerformance, TradingActivity};
+use statistics::TRADING_DAYS_PER_YEAR;
 use std::collections::BTreeMap;
 use thiserror::Error;
 
@@ -19,6 +26,10 @@ pub enum BackTesterError {
     /// No position found for this trading day
     #[error("No Position/trade could be found on date {0}")]
     NoPositionFound(Date),
+
+    /// The same symbol was added to a portfolio twice
+    #[error("Symbol {0} is in the portfolio more than once")]
+    DuplicateSymbol(String),
 }
 
 /// Backtests a strategy given as a map of Date => Trade
@@ -32,41 +43,87 @@ pub struct BackTester<'a> {
     /// How much cash the portfolio starts with/has currently
     cash: f64,
 
+    /// How orders are filled. Defaults to no costs at all.
+    costs: Box<dyn CostModel>,
+
+    /// Trading days in a year, for annualizing statistics.
+    days_per_year: f64,
+
     current_position: Position,
-    current_shares: i32,
+    current_shares: f64,
+
+    /// An order that was rejected, to send again while the model holds.
+    pending_order: Option<Position>,
 }
 
 impl<'a> BackTester<'a> {
     /// Constructs a BackTester. There must be a Position in `trades` for every
     /// day in Prices.
     pub fn new(trades: Trades, prices: &'a Prices, cash: f64) -> Result<Self, BackTesterError> {
-        // For every day in the time series, there must be some Position.
-        for day in prices.map.keys() {
-            if trades.get(day).is_none() {
-                return Err(BackTesterError::NoPositionFound(*day));
-            }
-        }
+        check_positions(&trades, prices)?;
 
         Ok(Self {
             cash,
             prices,
             trades,
+            costs: Box::new(TransactionCosts::default()),
+            days_per_year: TRADING_DAYS_PER_YEAR,
             current_position: Position::Out,
-            current_shares: 0,
+            current_shares: 0.0,
+            pending_order: None,
         })
     }
 
+    /// Uses `costs` to fill orders instead of trading for free.
+    pub fn with_costs(mut self, costs: impl CostModel + 'static) -> Self {
+        self.costs = Box::new(costs);
+        self
+    }
+
+    /// Annualizes statistics with `days_per_year` trading days in a year
+    /// (e.g. 365 for crypto) instead of TRADING_DAYS_PER_YEAR.
+    pub fn with_days_per_year(mut self, days_per_year: f64) -> Self {
+        self.days_per_year = days_per_year;
+        self
+    }
+
     // TODO: can we do backtesting immutably?
     /// Runs the backtest and returns portfolio value at each day of the period.
     pub fn backtest(&mut self) -> Result<PortfolioPerformance, PerformanceError> {
         let mut portvals = BTreeMap::new();
+        let mut activity = TradingActivity::default();
+
+        let mut last_day = None;
 
         // For every day in the series
-        for (day, price) in self.prices.map.iter() {
-            // Execute this position
-            let mut trade = self.trades.get(day).cloned().unwrap();
-            let cash_difference = BackTester::do_trade(self.current_shares, price, &trade);
+        for (day, price) in self.prices.iter() {
+            // Dividends that went ex since the last day are paid on the
+            // shares held since then (and paid out of pocket when short).
+            let dividends = self.current_shares * self.prices.dividends(last_day, *day);
+            self.cash += dividends;
+            activity.daily_dividends.insert(*day, dividends);
+            last_day = Some(*day);
+
+            // Execute this position, unless we can't afford it
+            let buying_power = self.cash + self.current_shares * price;
+            let (mut trade, rejected) = place_order(
+                &mut self.pending_order,
+                self.current_shares,
+                self.trades.get(day).cloned().unwrap(),
+                *price,
+                buying_power,
+            );
+            if let Some(order) = rejected {
+                activity.rejected_orders.insert(*day, order);
+            }
+            let (cash_difference, cost) = self.do_trade(price, &trade);
             self.cash += cash_difference;
+            activity.daily_costs.insert(*day, cost);
+            // Costs aside, the cash difference is the value of the shares at
+            // the quoted price.
+            activity
+                .daily_traded_value
+                .insert(*day, (cash_difference + cost).abs());
 
             if trade == Position::Hold {
                 trade = self.current_position;
@@ -77,15 +134,15 @@ impl<'a> BackTester<'a> {
             // Calculate portfolio value (equity plus cash)
             let equity_value = match trade {
                 Position::Long(shares) => {
-                    self.current_shares = shares as i32;
-                    shares as f64 * *price
+                    self.current_shares = shares;
+                    shares * *price
                 }
                 Position::Short(shares) => {
-                    self.current_shares = -(shares as i32);
-                    -(shares as f64) * *price
+                    self.current_shares = -shares;
+                    -shares * *price
                 }
                 Position::Out => {
-                    self.current_shares = 0;
+                    self.current_shares = 0.0;
                     0.0
                 }
                 Position::Hold => unreachable!(), // FIXME: dead code
@@ -93,40 +150,112 @@ impl<'a> BackTester<'a> {
 
             // Store
             portvals.insert(*day, equity_value + self.cash);
+            activity.daily_shares.insert(*day, self.current_shares);
         }
 
-        PortfolioPerformance::new(portvals)
+        PortfolioPerformance::with_activity(portvals, activity, self.days_per_year)
     }
 
-    /// Returns cash difference from making a trade. Buying stocks costs money,
-    /// shorting stocks or selling them results in a positive credit.
-    fn do_trade(current_shares: i32, price: &f64, trade: &Position) -> f64 {
-        // We must implicitly exit whatever trade we are currently in (e.g.
-        // going long to short involves selling the shares first). We can do
-        // this by subtracting our current shares from the shares in the trade.
-        let shares: i32 = match trade {
-            // Long trades are positive shares
-            Position::Long(s) => *s as i32 - current_shares,
-            // Short trades are negative shares
-            Position::Short(s) => -(*s as i32) - current_shares,
-            // Return to zero shares
-            Position::Out => -current_shares,
-            // do nothing
-            Position::Hold => 0,
-        };
+    /// Returns cash difference from making a trade, and how much of that was
+    /// lost to transaction costs. Buying stocks costs money, shorting stocks
+    /// or selling them results in a positive credit.
+    fn do_trade(&self, price: &f64, trade: &Position) -> (f64, f64) {
+        execute(self.costs.as_ref(), self.current_shares, *price, trade)
+    }
+}
 
-        // TODO: add optional slippage or commission costs
+/// For every day in the time series, there must be some Position.
+fn check_positions(trades: &Trades, prices: &Prices) -> Result<(), BackTesterError> {
+    match prices.map.keys().find(|day| trades.get(day).is_none()) {
+        Some(day) => Err(BackTesterError::NoPositionFound(*day)),
+        None => Ok(()),
+    }
+}
+
+/// Shares held after executing `trade` while holding `current_shares`.
+fn target_shares(current_shares: f64, trade: &Position) -> f64 {
+    match trade {
+        // Long trades are positive shares
+        Position::Long(s) => *s,
+        // Short trades are negative shares
+        Position::Short(s) => -*s,
+        // Return to zero shares
+        Position::Out => 0.0,
+        // do nothing
+        Position::Hold => current_shares,
+    }
+}
+
+/// True if `trade` grows the position held, and the position it leaves would
+/// be worth more than `buying_power` at `price`. Long and short positions
+/// alike must be fully covered, there is no leverage.
+fn exceeds_buying_power(
+    current_shares: f64,
+    trade: &Position,
+    price: f64,
+    buying_power: f64,
+) -> bool {
+    let target = target_shares(current_shares, trade).abs();
+    target > current_shares.abs() && target * price > buying_power
+}
+
+/// What to execute for `trade` while holding `current_shares`, and the order
+/// that was rejected, if any. Models only size a position when they open it
+/// and hold it after that, so an order rejected for going beyond
+/// `buying_power` is kept in `pending` and sent again on each Hold, until it
+/// fills or the model trades again. Rejecting a switch to the other side
+/// still closes the position being switched away from.
+fn place_order(
+    pending: &mut Option<Position>,
+    current_shares: f64,
+    trade: Position,
+    price: f64,
+    buying_power: f64,
+) -> (Position, Option<Position>) {
+    let order = match (trade, pending.take()) {
+        (Position::Hold, Some(pending)) => pending,
+        (trade, _) => trade,
+    };
+    if !exceeds_buying_power(current_shares, &order, price, buying_power) {
+        return (order, None);
+    }
 
-        // For long (positive) shares, we lose cash. For short (negative)
-        // shares, we gain cash.
-        -(shares as f64 * price)
+    *pending = Some(order);
+    if target_shares(current_shares, &order) * current_shares < 0.0 {
+        (Position::Out, Some(order))
+    } else {
+        (Position::Hold, Some(order))
     }
 }
 
+/// Executes `trade` while holding `current_shares`. Returns the cash
+/// difference and how much of it was lost to transaction costs.
+fn execute(costs: &dyn CostModel, current_shares: f64, price: f64, trade: &Position) -> (f64, f64) {
+    // We must implicitly exit whatever trade we are currently in (e.g.
+    // going long to short involves selling the shares first). We can do
+    // this by subtracting our current shares from the shares in the trade.
+    let shares = target_shares(current_shares, trade) - current_shares;
+
+    if shares == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let fill = costs.fill(shares, price);
+
+    // For long (positive) shares, we lose cash. For short (negative)
+    // shares, we gain cash.
+    (-(shares * fill.price) - fill.fees, fill.cost(shares, price))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::{nearly_equal, TimeSeries};
+    use crate::{
+        marketdata::CorporateAction,
+        signals::Signal,
+        trading::{manual::ManualTradingModel, sizing::PositionSizer, tradingmodel::TradingModel},
+        util::{nearly_equal, TimeSeries},
+    };
 
     // TODO: less copypasted code for fixtures
 
@@ -138,7 +267,7 @@ mod tests {
 
         // Buy and hold 1000 shares for the duration of a few days
         let strat: TimeSeries<Position> = vec![
-            (day1, Position::Long(1)),
+            (day1, Position::Long(1.0)),
             (day2, Position::Hold),
             (day3, Position::Hold),
         ]
@@ -151,11 +280,8 @@ mod tests {
             .cloned()
             .collect();
 
-        let p = Prices {
-            map: prices,
-            symbol: "TLZR".to_string(),
-        };
-        let mut bt = BackTester::new(Trades { trades: strat }, &p, 100.0).unwrap();
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 100.0).unwrap();
 
         let result = bt.backtest().unwrap();
         assert!(nearly_equal(result.daily_portvals[&day1], 100.0));
@@ -171,8 +297,8 @@ mod tests {
 
         // Buy 2 shares, sell 1, hold the other.
         let strat: TimeSeries<Position> = vec![
-            (day1, Position::Long(2)),
-            (day2, Position::Long(1)),
+            (day1, Position::Long(2.0)),
+            (day2, Position::Long(1.0)),
             (day3, Position::Hold),
         ]
         .iter()
@@ -184,11 +310,8 @@ mod tests {
             .cloned()
             .collect();
 
-        let p = Prices {
-            map: prices,
-            symbol: "TLZR".to_string(),
-        };
-        let mut bt = BackTester::new(Trades { trades: strat }, &p, 200.0).unwrap();
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 200.0).unwrap();
 
         let result = bt.backtest().unwrap();
         // let debug: Vec<f64> = result.daily_portvals.values().into_iter().cloned().collect();
@@ -205,7 +328,7 @@ mod tests {
 
         // Buy and hold 1000 shares for the duration of a few days
         let strat: TimeSeries<Position> = vec![
-            (day1, Position::Short(1)),
+            (day1, Position::Short(1.0)),
             (day2, Position::Hold),
             (day3, Position::Hold),
         ]
@@ -218,11 +341,8 @@ mod tests {
             .cloned()
             .collect();
 
-        let p = Prices {
-            map: prices,
-            symbol: "TLZR".to_string(),
-        };
-        let mut bt = BackTester::new(Trades { trades: strat }, &p, 100.0).unwrap();
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 100.0).unwrap();
 
         let result = bt.backtest().unwrap();
         assert!(nearly_equal(result.daily_portvals[&day1], 100.0));
@@ -239,9 +359,9 @@ mod tests {
 
         // Buy and hold 1000 shares for the duration of a few days
         let strat: TimeSeries<Position> = vec![
-            (day1, Position::Long(1)),
+            (day1, Position::Long(1.0)),
             (day2, Position::Hold),
-            (day3, Position::Short(1)),
+            (day3, Position::Short(1.0)),
             (day4, Position::Hold),
         ]
         .iter()
@@ -254,11 +374,8 @@ mod tests {
                 .cloned()
                 .collect();
 
-        let p = Prices {
-            map: prices,
-            symbol: "TLZR".to_string(),
-        };
-        let mut bt = BackTester::new(Trades { trades: strat }, &p, 100.0).unwrap();
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 100.0).unwrap();
 
         let result = bt.backtest().unwrap();
         assert!(nearly_equal(result.daily_portvals[&day1], 100.0));
@@ -276,7 +393,7 @@ mod tests {
 
         // Buy and hold 1000 shares for the duration of a few days
         let strat: TimeSeries<Position> = vec![
-            (day1, Position::Long(1)),
+            (day1, Position::Long(1.0)),
             (day2, Position::Hold),
             (day3, Position::Out),
             (day4, Position::Hold),
@@ -291,11 +408,8 @@ mod tests {
                 .cloned()
                 .collect();
 
-        let p = Prices {
-            map: prices,
-            symbol: "TLZR".to_string(),
-        };
-        let mut bt = BackTester::new(Trades { trades: strat }, &p, 100.0).unwrap();
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 100.0).unwrap();
 
         let result = bt.backtest().unwrap();
         assert!(nearly_equal(result.daily_portvals[&day1], 100.0));
@@ -303,4 +417,224 @@ mod tests {
         assert!(nearly_equal(result.daily_portvals[&day3], 110.0));
         assert!(nearly_equal(result.daily_portvals[&day4], 110.0));
     }
+
+    #[test]
+    fn backtest_with_costs() {
+        let day1 = Date::from_ymd(2012, 1, 1);
+        let day2 = Date::from_ymd(2012, 1, 2);
+        let day3 = Date::from_ymd(2012, 1, 3);
+
+        // Buy 1 share, then sell it.
+        let strat: TimeSeries<Position> = vec![
+            (day1, Position::Long(1.0)),
+            (day2, Position::Hold),
+            (day3, Position::Out),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let prices: TimeSeries<f64> = vec![(day1, 100.0), (day2, 105.0), (day3, 110.0)]
+            .iter()
+            .cloned()
+            .collect();
+
+        let p = Prices::from_closes("TLZR", prices);
+        let costs = TransactionCosts {
+            fee_per_order: 1.0,
+            slippage_bps: 100.0,
+            ..Default::default()
+        };
+        let mut bt = BackTester::new(Trades::from(strat), &p, 200.0)
+            .unwrap()
+            .with_costs(costs);
+
+        let result = bt.backtest().unwrap();
+        // Bought at 101 plus a 1.0 fee
+        assert!(nearly_equal(result.daily_portvals[&day1], 198.0));
+        assert!(nearly_equal(result.daily_portvals[&day2], 203.0));
+        // Sold at 108.9 plus a 1.0 fee
+        assert!(nearly_equal(result.daily_portvals[&day3], 205.9));
+        assert!(nearly_equal(result.total_costs, 4.1));
+        assert!(nearly_equal(result.daily_costs[&day3], 2.1));
+    }
+
+    #[test]
+    fn positions_are_sized_once() {
+        // A perma-bull sizing by cash would want a different number of
+        // shares every day the price moves, but only buys once.
+        let start = Date::from_ymd(2012, 1, 2);
+        let prices: TimeSeries<f64> = [100.0, 90.0, 110.0, 95.0, 120.0, 105.0]
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (start + chrono::Duration::days(i as i64), *p))
+            .collect();
+        let p = Prices::from_closes("TLZR", prices);
+
+        let trades = ManualTradingModel::new(0.0, Signal::new(0.0), Signal::new(1.0))
+            .with_sizer(PositionSizer::new("cash:0.5".parse().unwrap(), 1000.0))
+            .get_trades(&p)
+            .unwrap();
+        let costs = TransactionCosts {
+            fee_per_order: 1.0,
+            ..Default::default()
+        };
+        let result = BackTester::new(trades, &p, 1000.0)
+            .unwrap()
+            .with_costs(costs)
+            .backtest()
+            .unwrap();
+
+        let fills = result
+            .daily_traded_value
+            .values()
+            .filter(|v| **v > 0.0)
+            .count();
+        assert_eq!(fills, 1);
+        assert!(nearly_equal(result.total_costs, 1.0));
+        assert!(result.daily_shares.values().all(|s| *s == 5.0));
+    }
+
+    #[test]
+    fn rejects_orders_beyond_buying_power() {
+        let day1 = Date::from_ymd(2012, 1, 1);
+        let day2 = Date::from_ymd(2012, 1, 2);
+        let day3 = Date::from_ymd(2012, 1, 3);
+
+        // Can afford 1 share but not 3, selling down is always allowed
+        let strat: TimeSeries<Position> = [
+            (day1, Position::Long(1.0)),
+            (day2, Position::Long(3.0)),
+            (day3, Position::Short(1.0)),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let prices: TimeSeries<f64> = [(day1, 100.0), (day2, 110.0), (day3, 100.0)]
+            .iter()
+            .cloned()
+            .collect();
+
+        let p = Prices::from_closes("TLZR", prices);
+        let mut bt = BackTester::new(Trades::from(strat), &p, 150.0).unwrap();
+
+        let result = bt.backtest().unwrap();
+        assert_eq!(result.rejected_orders.len(), 1);
+        assert_eq!(result.rejected_orders[&day2], Position::Long(3.0));
+        assert_eq!(result.daily_shares[&day2], 1.0);
+        assert!(nearly_equal(result.daily_portvals[&day2], 160.0));
+        assert_eq!(result.daily_shares[&day3], -1.0);
+    }
+
+    #[test]
+    fn rejected_orders_are_sent_again() {
+        let start = Date::from_ymd(2012, 1, 2);
+        let day = |i: i64| start + chrono::Duration::days(i);
+        let strat: TimeSeries<Position> = [
+            Position::Long(3.0),
+            Position::Hold,
+            Position::Hold,
+            Position::Short(5.0),
+            Position::Hold,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (day(i as i64), *p))
+        .collect();
+        let prices: TimeSeries<f64> = [100.0, 90.0, 80.0, 80.0, 40.0]
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (day(i as i64), *p))
+            .collect();
+
+        let p = Prices::from_closes("TLZR", prices);
+        let result = BackTester::new(Trades::from(strat), &p, 250.0)
+            .unwrap()
+            .backtest()
+            .unwrap();
+        let shares: Vec<f64> = result.daily_shares.values().cloned().collect();
+        // Bought once the price came down, and closed out when the switch to
+        // short was too big, until it wasn't.
+        assert_eq!(shares, vec![0.0, 0.0, 3.0, 0.0, -5.0]);
+        assert_eq!(result.rejected_orders.len(), 3);
+        assert_eq!(result.rejected_orders[&day(3)], Position::Short(5.0));
+    }
+
+    #[test]
+    fn dividends_credited_and_debited() {
+        let day1 = Date::from_ymd(2012, 1, 1);
+        let day2 = Date::from_ymd(2012, 1, 2);
+        let day3 = Date::from_ymd(2012, 1, 3);
+        let day4 = Date::from_ymd(2012, 1, 4);
+
+        // Long 2 shares through the first dividend, short 1 through the
+        // second, which went ex on a day with no price data.
+        let strat: TimeSeries<Position> = [
+            (day1, Position::Long(2.0)),
+            (day2, Position::Short(1.0)),
+            (day4, Position::Hold),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let prices: TimeSeries<f64> = [(day1, 100.0), (day2, 100.0), (day4, 100.0)]
+            .iter()
+            .cloned()
+            .collect();
+
+        let mut p = Prices::from_closes("TLZR", prices);
+        p.actions.insert(day2, CorporateAction::dividend(1.0));
+        p.actions.insert(day3, CorporateAction::dividend(0.5));
+        let mut bt = BackTester::new(Trades::from(strat), &p, 200.0).unwrap();
+
+        let result = bt.backtest().unwrap();
+        assert!(nearly_equal(result.daily_dividends[&day2], 2.0));
+        assert!(nearly_equal(result.daily_dividends[&day4], -0.5));
+        assert!(nearly_equal(result.total_dividends, 1.5));
+        assert!(nearly_equal(result.daily_portvals[&day4], 201.5));
+    }
+
+    #[test]
+    fn fractional_shares_around_the_clock() {
+        let day1 = Date::from_ymd(2021, 1, 2); // a Saturday
+        let day2 = Date::from_ymd(2021, 1, 3);
+        let day3 = Date::from_ymd(2021, 1, 4);
+
+        let strat: TimeSeries<Position> = [
+            (day1, Position::Long(0.25)),
+            (day2, Position::Short(0.125)),
+            (day3, Position::Out),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        let prices: TimeSeries<f64> = [(day1, 32_000.0), (day2, 34_000.0), (day3, 33_000.0)]
+            .iter()
+            .cloned()
+            .collect();
+
+        let p = Prices::from_closes("BTC", prices);
+        let trades = Trades::from(strat);
+        let result = BackTester::new(trades.clone(), &p, 10_000.0)
+            .unwrap()
+            .backtest()
+            .unwrap();
+        assert_eq!(result.daily_shares[&day2], -0.125);
+        // Up 500 on the long, then up 125 on the short
+        assert!(nearly_equal(result.daily_portvals[&day3], 10_625.0));
+
+        // Annualized over a year of every day rather than of weekdays
+        let crypto = BackTester::new(trades, &p, 10_000.0)
+            .unwrap()
+            .with_days_per_year(365.0)
+            .backtest()
+            .unwrap();
+        assert_eq!(crypto.days_per_year, 365.0);
+        let ratio =
+            crypto.statistics.annualized_volatility / result.statistics.annualized_volatility;
+        assert!(nearly_equal(ratio, (365.0f64 / 252.0).sqrt()));
+    }
 }
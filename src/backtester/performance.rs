@@ -1,5 +1,6 @@
 //! Measures portfolio performance as total/daily returns over periods of time.
 
+use super::statistics::{BenchmarkStatistics, Statistics, TRADING_DAYS_PER_YEAR};
 use super::Position;
 use crate::util::{first_key, TimeSeries};
 use crate::{trading::tradingmodel::Trades, Date};
@@ -14,12 +15,59 @@ pub struct PortfolioPerformance {
     /// The running total portfolio value in a time series.
     pub daily_portvals: TimeSeries<f64>,
 
-    // pub sharpe_ratio: f64, // TODO: add this
     /// The daily portfolio returns in a time series.
     pub daily_returns: TimeSeries<f64>,
 
     /// Standard deviation of daily returns.
     pub volatility: f64,
+
+    /// Transaction costs (commissions, fees, slippage, etc) paid each day.
+    pub daily_costs: TimeSeries<f64>,
+
+    /// Sum of all transaction costs paid.
+    pub total_costs: f64,
+
+    /// Dividends received on long positions (or paid on short ones) each day.
+    pub daily_dividends: TimeSeries<f64>,
+
+    /// Sum of all dividends received, less those paid.
+    pub total_dividends: f64,
+
+    /// Value of the shares bought or sold each day.
+    pub daily_traded_value: TimeSeries<f64>,
+
+    /// Shares held at the end of each day (negative when short).
+    pub daily_shares: TimeSeries<f64>,
+
+    /// Orders that were not filled because they needed more buying power
+    /// than the portfolio had, by day.
+    pub rejected_orders: TimeSeries<Position>,
+
+    /// Risk-adjusted return statistics.
+    pub statistics: Statistics,
+
+    /// Trading days in a year, used to annualize the statistics.
+    pub days_per_year: f64,
+}
+
+/// What a portfolio did each day of a backtest, besides changing in value.
+#[derive(Debug, Default)]
+pub struct TradingActivity {
+    /// Transaction costs (commissions, fees, slippage, etc) paid each day.
+    pub daily_costs: TimeSeries<f64>,
+
+    /// Dividends received on long positions (or paid on short ones) each day.
+    pub daily_dividends: TimeSeries<f64>,
+
+    /// Value of the shares bought or sold each day.
+    pub daily_traded_value: TimeSeries<f64>,
+
+    /// Shares held at the end of each day (negative when short).
+    pub daily_shares: TimeSeries<f64>,
+
+    /// Orders that were not filled because they needed more buying power
+    /// than the portfolio had, by day.
+    pub rejected_orders: TimeSeries<Position>,
 }
 
 /// Errors that may occur during portfolio performance calculation.
@@ -39,6 +87,22 @@ impl PortfolioPerformance {
     /// Constructs a PortfolioPerformance. There must be at least one datapoint
     /// in `daily_portvals`.
     pub fn new(daily_portvals: TimeSeries<f64>) -> Result<Self, PerformanceError> {
+        Self::with_activity(
+            daily_portvals,
+            TradingActivity::default(),
+            TRADING_DAYS_PER_YEAR,
+        )
+    }
+
+    /// Constructs a PortfolioPerformance with a record of the trading that
+    /// happened each day, annualizing statistics with `days_per_year` trading
+    /// days in a year. There must be at least one datapoint in
+    /// `daily_portvals`.
+    pub fn with_activity(
+        daily_portvals: TimeSeries<f64>,
+        activity: TradingActivity,
+        days_per_year: f64,
+    ) -> Result<Self, PerformanceError> {
         // Calculate daily returns
         // TODO: this can be probably done more elegantly either with fold_first once
         // stabilized, or through a better pattern I'm not yet aware of. for
@@ -62,15 +126,42 @@ impl PortfolioPerformance {
         }
 
         let volatility = stddev(daily_returns.values().cloned());
+        let total_costs = activity.daily_costs.values().sum();
+        let total_dividends = activity.daily_dividends.values().sum();
+        let statistics = Statistics::new(
+            &daily_portvals,
+            &daily_returns,
+            &activity.daily_shares,
+            &activity.daily_traded_value,
+            days_per_year,
+        );
 
         Ok(Self {
             daily_portvals,
             daily_returns,
-            // sharpe_ratio: -1.0, // TODO:
             volatility,
+            daily_costs: activity.daily_costs,
+            total_costs,
+            daily_dividends: activity.daily_dividends,
+            total_dividends,
+            daily_traded_value: activity.daily_traded_value,
+            daily_shares: activity.daily_shares,
+            rejected_orders: activity.rejected_orders,
+            statistics,
+            days_per_year,
         })
     }
 
+    /// Measures performance against a benchmark portfolio (beta, alpha,
+    /// etc).
+    pub fn relative_to(&self, benchmark: &PortfolioPerformance) -> BenchmarkStatistics {
+        BenchmarkStatistics::new(
+            &self.daily_returns,
+            &benchmark.daily_returns,
+            self.days_per_year,
+        )
+    }
+
     /// Returns a percent accuracy for trades (what percentage of them are
     /// profitable). For the purposes of this measurement, a "trade" is counted
     /// as opening a long or short position, and then exiting the trade either
@@ -140,7 +231,7 @@ mod test {
     #[test]
     fn total_return_divide_by_zero() {
         let fixture = vec![
-            (Date::from_ymd(2020, 3, 1), 0.0, Pos::Long(1)),
+            (Date::from_ymd(2020, 3, 1), 0.0, Pos::Long(1.0)),
             (Date::from_ymd(2020, 3, 2), 10.0, Pos::Hold),
         ];
         let (daily_portvals, _) = construct_fixture(&fixture);
@@ -156,14 +247,14 @@ mod test {
             trades.insert(day, position);
         }
 
-        (portvals, Trades { trades })
+        (portvals, Trades::from(trades))
     }
 
     #[test]
     fn daily_returns() {
         let day1 = Date::from_ymd(2020, 3, 1);
         let day2 = Date::from_ymd(2020, 3, 2);
-        let data = vec![(day1, 10.0, Pos::Long(1)), (day2, 11.0, Pos::Out)];
+        let data = vec![(day1, 10.0, Pos::Long(1.0)), (day2, 11.0, Pos::Out)];
         let (pv, _) = construct_fixture(&data);
 
         let pp = PortfolioPerformance::new(pv).unwrap();
@@ -176,10 +267,10 @@ mod test {
     fn trades_accuracy_100_percent() {
         // successful long and short
         let data = vec![
-            (Date::from_ymd(2020, 3, 1), 1.0, Pos::Long(1)),
+            (Date::from_ymd(2020, 3, 1), 1.0, Pos::Long(1.0)),
             (Date::from_ymd(2020, 3, 2), 1.2, Pos::Hold),
             (Date::from_ymd(2020, 3, 3), 1.4, Pos::Out),
-            (Date::from_ymd(2020, 3, 4), 1.4, Pos::Short(1)),
+            (Date::from_ymd(2020, 3, 4), 1.4, Pos::Short(1.0)),
             (Date::from_ymd(2020, 3, 5), 1.5, Pos::Hold),
             (Date::from_ymd(2020, 3, 6), 1.6, Pos::Out),
         ];
@@ -194,18 +285,18 @@ mod test {
     fn trades_accuracy_66_percent() {
         let data = vec![
             // successful long and short
-            (Date::from_ymd(2020, 3, 1), 1.0, Pos::Long(1)),
+            (Date::from_ymd(2020, 3, 1), 1.0, Pos::Long(1.0)),
             (Date::from_ymd(2020, 3, 2), 1.2, Pos::Hold),
             (Date::from_ymd(2020, 3, 3), 1.4, Pos::Out),
-            (Date::from_ymd(2020, 3, 4), 1.4, Pos::Short(1)),
+            (Date::from_ymd(2020, 3, 4), 1.4, Pos::Short(1.0)),
             (Date::from_ymd(2020, 3, 5), 1.5, Pos::Hold),
             (Date::from_ymd(2020, 3, 6), 1.6, Pos::Out),
             // Unsuccessful long
-            (Date::from_ymd(2020, 3, 7), 1.6, Pos::Long(1)),
+            (Date::from_ymd(2020, 3, 7), 1.6, Pos::Long(1.0)),
             (Date::from_ymd(2020, 3, 8), 1.5, Pos::Hold),
             // This last short shouldn't be counted as a trade because it is
             // incomplete (effectively just an exit of the long trade)
-            (Date::from_ymd(2020, 3, 9), 1.4, Pos::Short(1)),
+            (Date::from_ymd(2020, 3, 9), 1.4, Pos::Short(1.0)),
         ];
         let (pv, trades) = construct_fixture(&data);
         let pp = PortfolioPerformance::new(pv).unwrap();
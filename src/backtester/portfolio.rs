@@ -0,0 +1,351 @@
+//! Backtests trades in several securities at once, paying for all of them out
+//! of one cash balance.
+
+use super::{
+    check_positions,
+    costs::{CostModel, TransactionCosts},
+    execute,
+    performance::{PerformanceError, PortfolioPerformance, TradingActivity},
+    place_order,
+    statistics::TRADING_DAYS_PER_YEAR,
+    BackTesterError,
+};
+use crate::{
+    marketdata::prices::Prices,
+    trading::{tradingmodel::Trades, Position},
+    util::TimeSeries,
+    Date,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Results of a portfolio backtest.
+#[derive(Debug, Serialize)]
+pub struct PortfolioBacktest {
+    /// Performance of the portfolio as a whole. Rejected orders are only
+    /// recorded per symbol.
+    pub performance: PortfolioPerformance,
+
+    /// Performance attributed to each symbol: the starting cash plus the
+    /// profit or loss (after costs) from trading that symbol.
+    pub per_symbol: BTreeMap<String, PortfolioPerformance>,
+}
+
+/// Backtests a set of Trades for each of several symbols, sharing one cash
+/// balance.
+///
+/// Symbols do not need to trade on the same days. The portfolio is valued on
+/// every day that any symbol trades, using the most recent price of each
+/// holding.
+pub struct PortfolioBackTester<'a> {
+    /// Price data and what trade to execute each day, by symbol.
+    holdings: BTreeMap<String, (&'a Prices, Trades)>,
+
+    /// How much cash the portfolio starts with.
+    cash: f64,
+
+    /// How orders are filled. Defaults to no costs at all.
+    costs: Box<dyn CostModel>,
+
+    /// Trading days in a year, for annualizing statistics.
+    days_per_year: f64,
+}
+
+/// Running state of one symbol during a backtest.
+#[derive(Default)]
+struct SymbolState {
+    shares: f64,
+    last_price: Option<f64>,
+
+    /// Last day the symbol had a price.
+    last_day: Option<Date>,
+
+    /// Cash spent (negative) or received (positive) trading the symbol, and
+    /// on its dividends.
+    cash_flow: f64,
+
+    /// An order that was rejected, to send again while the model holds.
+    pending_order: Option<Position>,
+
+    portvals: TimeSeries<f64>,
+    activity: TradingActivity,
+}
+
+impl SymbolState {
+    fn equity(&self) -> f64 {
+        self.shares * self.last_price.unwrap_or(0.0)
+    }
+}
+
+impl<'a> PortfolioBackTester<'a> {
+    /// Constructs a PortfolioBackTester from the prices and trades of each
+    /// symbol (named by `Prices::symbol`). There must be a Position in each
+    /// symbol's trades for every day in its Prices.
+    pub fn new(holdings: Vec<(&'a Prices, Trades)>, cash: f64) -> Result<Self, BackTesterError> {
+        let mut map = BTreeMap::new();
+        for (prices, trades) in holdings {
+            check_positions(&trades, prices)?;
+            if map
+                .insert(prices.symbol.clone(), (prices, trades))
+                .is_some()
+            {
+                return Err(BackTesterError::DuplicateSymbol(prices.symbol.clone()));
+            }
+        }
+
+        Ok(Self {
+            holdings: map,
+            cash,
+            costs: Box::new(TransactionCosts::default()),
+            days_per_year: TRADING_DAYS_PER_YEAR,
+        })
+    }
+
+    /// Uses `costs` to fill orders instead of trading for free.
+    pub fn with_costs(mut self, costs: impl CostModel + 'static) -> Self {
+        self.costs = Box::new(costs);
+        self
+    }
+
+    /// Annualizes statistics with `days_per_year` trading days in a year
+    /// instead of TRADING_DAYS_PER_YEAR.
+    pub fn with_days_per_year(mut self, days_per_year: f64) -> Self {
+        self.days_per_year = days_per_year;
+        self
+    }
+
+    /// Runs the backtest and returns the performance of the portfolio and of
+    /// each symbol in it.
+    pub fn backtest(&mut self) -> Result<PortfolioBacktest, PerformanceError> {
+        let days: BTreeSet<Date> = self
+            .holdings
+            .values()
+            .flat_map(|(prices, _)| prices.map.keys().cloned())
+            .collect();
+
+        let mut states: BTreeMap<&String, SymbolState> = self
+            .holdings
+            .keys()
+            .map(|s| (s, SymbolState::default()))
+            .collect();
+        let mut portvals = TimeSeries::new();
+        let mut activity = TradingActivity::default();
+        let starting_cash = self.cash;
+
+        for day in days {
+            let (mut day_costs, mut day_traded, mut day_dividends, mut gross_shares) =
+                (0.0, 0.0, 0.0, 0.0);
+            for (symbol, (prices, trades)) in &self.holdings {
+                // What the rest of the portfolio is worth, and how much of it
+                // is tied up in positions
+                let (others_value, others_exposure) = states
+                    .iter()
+                    .filter(|(s, _)| **s != symbol)
+                    .fold((0.0, 0.0), |(value, exposure), (_, state)| {
+                        (value + state.equity(), exposure + state.equity().abs())
+                    });
+                let state = states.get_mut(symbol).unwrap();
+                let (mut cost, mut traded, mut dividends) = (0.0, 0.0, 0.0);
+
+                // Only trade on days the symbol has a price
+                if let Some(price) = prices.get(&day) {
+                    // Dividends that went ex since the symbol last traded
+                    dividends = state.shares * prices.dividends(state.last_day, day);
+                    self.cash += dividends;
+                    state.cash_flow += dividends;
+                    state.last_day = Some(day);
+
+                    let buying_power =
+                        self.cash + others_value - others_exposure + state.shares * price;
+                    let (trade, rejected) = place_order(
+                        &mut state.pending_order,
+                        state.shares,
+                        trades.get(&day).cloned().unwrap(),
+                        *price,
+                        buying_power,
+                    );
+                    if let Some(order) = rejected {
+                        state.activity.rejected_orders.insert(day, order);
+                    }
+                    let (cash_difference, c) =
+                        execute(self.costs.as_ref(), state.shares, *price, &trade);
+                    self.cash += cash_difference;
+                    state.cash_flow += cash_difference;
+                    cost = c;
+                    traded = (cash_difference + c).abs();
+
+                    state.shares = match trade {
+                        Position::Long(shares) => shares,
+                        Position::Short(shares) => -shares,
+                        Position::Out => 0.0,
+                        Position::Hold => state.shares,
+                    };
+                    state.last_price = Some(*price);
+                }
+
+                day_costs += cost;
+                day_traded += traded;
+                day_dividends += dividends;
+                gross_shares += state.shares.abs();
+
+                state.activity.daily_costs.insert(day, cost);
+                state.activity.daily_dividends.insert(day, dividends);
+                state.activity.daily_traded_value.insert(day, traded);
+                state.activity.daily_shares.insert(day, state.shares);
+            }
+
+            let equity: f64 = states.values().map(SymbolState::equity).sum();
+            portvals.insert(day, self.cash + equity);
+            activity.daily_costs.insert(day, day_costs);
+            activity.daily_dividends.insert(day, day_dividends);
+            activity.daily_traded_value.insert(day, day_traded);
+            // Long and short holdings in different symbols should not cancel
+            // out, so count every share held.
+            activity.daily_shares.insert(day, gross_shares);
+
+            // Each symbol is valued as if it had all of the starting cash to
+            // itself.
+            for state in states.values_mut() {
+                let value = starting_cash + state.cash_flow + state.equity();
+                state.portvals.insert(day, value);
+            }
+        }
+
+        let days_per_year = self.days_per_year;
+        let mut per_symbol = BTreeMap::new();
+        for (symbol, state) in states {
+            let performance =
+                PortfolioPerformance::with_activity(state.portvals, state.activity, days_per_year)?;
+            per_symbol.insert(symbol.clone(), performance);
+        }
+
+        Ok(PortfolioBacktest {
+            performance: PortfolioPerformance::with_activity(portvals, activity, days_per_year)?,
+            per_symbol,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{marketdata::CorporateAction, trading::Position::*, util::nearly_equal};
+
+    fn series<T: Clone>(items: &[(Date, T)]) -> TimeSeries<T> {
+        items.iter().cloned().collect()
+    }
+
+    #[test]
+    fn shared_cash_and_mismatched_days() {
+        let day1 = Date::from_ymd(2020, 1, 1);
+        let day2 = Date::from_ymd(2020, 1, 2);
+        let day3 = Date::from_ymd(2020, 1, 3);
+
+        // AAA trades every day, BBB skips day 2.
+        let aaa = Prices::from_closes("AAA", series(&[(day1, 10.0), (day2, 11.0), (day3, 12.0)]));
+        let bbb = Prices::from_closes("BBB", series(&[(day1, 100.0), (day3, 90.0)]));
+        let aaa_trades = Trades::from(series(&[(day1, Long(10.0)), (day2, Hold), (day3, Out)]));
+        let bbb_trades = Trades::from(series(&[(day1, Long(1.0)), (day3, Hold)]));
+
+        let result = PortfolioBackTester::new(vec![(&aaa, aaa_trades), (&bbb, bbb_trades)], 1000.0)
+            .unwrap()
+            .backtest()
+            .unwrap();
+
+        let portvals = &result.performance.daily_portvals;
+        assert!(nearly_equal(portvals[&day1], 1000.0));
+        // AAA is up 10, BBB keeps its day 1 price
+        assert!(nearly_equal(portvals[&day2], 1010.0));
+        // AAA sold up 20, BBB down 10
+        assert!(nearly_equal(portvals[&day3], 1010.0));
+
+        let aaa_perf = &result.per_symbol["AAA"];
+        assert!(nearly_equal(aaa_perf.daily_portvals[&day3], 1020.0));
+        assert_eq!(aaa_perf.daily_shares[&day3], 0.0);
+        let bbb_perf = &result.per_symbol["BBB"];
+        assert!(nearly_equal(bbb_perf.daily_portvals[&day2], 1000.0));
+        assert!(nearly_equal(bbb_perf.daily_portvals[&day3], 990.0));
+    }
+
+    #[test]
+    fn symbols_share_buying_power() {
+        let day1 = Date::from_ymd(2020, 1, 1);
+        let aaa = Prices::from_closes("AAA", series(&[(day1, 10.0)]));
+        let bbb = Prices::from_closes("BBB", series(&[(day1, 10.0)]));
+        let trades = |shares| Trades::from(series(&[(day1, Long(shares))]));
+
+        // AAA uses up 600 of the 1000, leaving too little for 50 BBB
+        let result =
+            PortfolioBackTester::new(vec![(&aaa, trades(60.0)), (&bbb, trades(50.0))], 1000.0)
+                .unwrap()
+                .backtest()
+                .unwrap();
+        assert_eq!(result.per_symbol["AAA"].daily_shares[&day1], 60.0);
+        assert_eq!(result.per_symbol["BBB"].daily_shares[&day1], 0.0);
+        assert_eq!(result.per_symbol["BBB"].rejected_orders[&day1], Long(50.0));
+
+        // Once AAA is sold, the order for BBB goes through while it holds
+        let day2 = Date::from_ymd(2020, 1, 2);
+        let aaa = Prices::from_closes("AAA", series(&[(day1, 10.0), (day2, 10.0)]));
+        let bbb = Prices::from_closes("BBB", series(&[(day1, 10.0), (day2, 10.0)]));
+        let aaa_trades = Trades::from(series(&[(day1, Long(60.0)), (day2, Out)]));
+        let bbb_trades = Trades::from(series(&[(day1, Long(50.0)), (day2, Hold)]));
+        let result = PortfolioBackTester::new(vec![(&aaa, aaa_trades), (&bbb, bbb_trades)], 1000.0)
+            .unwrap()
+            .backtest()
+            .unwrap();
+        assert_eq!(result.per_symbol["BBB"].daily_shares[&day1], 0.0);
+        assert_eq!(result.per_symbol["BBB"].daily_shares[&day2], 50.0);
+    }
+
+    #[test]
+    fn missing_trades_and_duplicates() {
+        let day1 = Date::from_ymd(2020, 1, 1);
+        let day2 = Date::from_ymd(2020, 1, 2);
+        let aaa = Prices::from_closes("AAA", series(&[(day1, 10.0), (day2, 11.0)]));
+        let trades = Trades::from(series(&[(day1, Long(10.0))]));
+
+        assert!(matches!(
+            PortfolioBackTester::new(vec![(&aaa, trades.clone())], 100.0),
+            Err(BackTesterError::NoPositionFound(_))
+        ));
+
+        let trades = Trades::from(series(&[(day1, Long(10.0)), (day2, Hold)]));
+        assert!(matches!(
+            PortfolioBackTester::new(vec![(&aaa, trades.clone()), (&aaa, trades)], 100.0),
+            Err(BackTesterError::DuplicateSymbol(_))
+        ));
+    }
+
+    #[test]
+    fn dividends_paid_per_symbol() {
+        let day1 = Date::from_ymd(2020, 1, 1);
+        let day2 = Date::from_ymd(2020, 1, 2);
+        let day3 = Date::from_ymd(2020, 1, 3);
+
+        // BBB pays a dividend on day 2, when it has no price
+        let aaa = Prices::from_closes("AAA", series(&[(day1, 10.0), (day2, 10.0), (day3, 10.0)]));
+        let mut bbb = Prices::from_closes("BBB", series(&[(day1, 100.0), (day3, 100.0)]));
+        bbb.actions.insert(day2, CorporateAction::dividend(2.0));
+        let aaa_trades = Trades::from(series(&[(day1, Long(10.0)), (day2, Hold), (day3, Hold)]));
+        let bbb_trades = Trades::from(series(&[(day1, Long(3.0)), (day3, Hold)]));
+
+        let result = PortfolioBackTester::new(vec![(&aaa, aaa_trades), (&bbb, bbb_trades)], 1000.0)
+            .unwrap()
+            .backtest()
+            .unwrap();
+
+        assert!(nearly_equal(
+            result.performance.daily_portvals[&day2],
+            1000.0
+        ));
+        assert!(nearly_equal(
+            result.performance.daily_portvals[&day3],
+            1006.0
+        ));
+        assert!(nearly_equal(result.performance.total_dividends, 6.0));
+        let bbb_perf = &result.per_symbol["BBB"];
+        assert!(nearly_equal(bbb_perf.daily_dividends[&day3], 6.0));
+        assert!(nearly_equal(result.per_symbol["AAA"].total_dividends, 0.0));
+    }
+}
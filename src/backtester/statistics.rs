@@ -0,0 +1,354 @@
+//! Risk-adjusted statistics (Sharpe ratio, drawdowns, etc) computed from a
+//! portfolio's daily values and returns.
+//!
+//! Returns are per bar of price data (usually daily). They are annualized as if
+//! there were as many bars each trading day as the data has on average, and as
+//! many trading days in a year as the market has (252 for most stock
+//! exchanges, 365 for markets that never close). The risk-free rate is taken to
+//! be zero.
+
+use crate::{util::TimeSeries, Date};
+use serde::Serialize;
+use stats::{mean, stddev};
+use std::collections::BTreeSet;
+
+/// Number of trading days used to annualize daily statistics, unless the
+/// market says otherwise.
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// Number of trading days in a year for markets that trade every day.
+pub const CALENDAR_DAYS_PER_YEAR: f64 = 365.0;
+
+/// How many bars there are in a year of data keyed by `times`, given that
+/// there are `days_per_year` trading days in a year.
+pub fn periods_per_year<'a>(times: impl Iterator<Item = &'a Date>, days_per_year: f64) -> f64 {
+    let mut bars = 0;
+    let mut days = BTreeSet::new();
+    for time in times {
+        bars += 1;
+        days.insert(time.day());
+    }
+
+    if days.is_empty() {
+        days_per_year
+    } else {
+        days_per_year * bars as f64 / days.len() as f64
+    }
+}
+
+/// The largest peak-to-trough decline in portfolio value.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Drawdown {
+    /// Decline from peak to trough as a (negative) fraction of the peak.
+    pub max_drawdown: f64,
+
+    /// Day the portfolio value peaked before the decline.
+    pub peak: Date,
+
+    /// Day the portfolio value bottomed out.
+    pub trough: Date,
+
+    /// First day the portfolio value returned to the peak, if it ever did.
+    pub recovery: Option<Date>,
+}
+
+/// Statistics describing the risk and return of a single portfolio.
+#[derive(Debug, Serialize)]
+pub struct Statistics {
+    /// Compound annual growth rate.
+    pub cagr: f64,
+
+    /// Standard deviation of daily returns, annualized.
+    pub annualized_volatility: f64,
+
+    /// Annualized mean daily return divided by annualized volatility.
+    pub sharpe_ratio: f64,
+
+    /// Like the Sharpe ratio, but only penalizes downside volatility.
+    pub sortino_ratio: f64,
+
+    /// CAGR divided by the magnitude of the maximum drawdown.
+    pub calmar_ratio: f64,
+
+    /// Maximum drawdown. None if there is no data.
+    pub drawdown: Option<Drawdown>,
+
+    /// Fraction of days a position (long or short) was held.
+    pub exposure_time: f64,
+
+    /// Total value traded divided by the average portfolio value.
+    pub turnover: f64,
+}
+
+/// Statistics of a portfolio measured against a benchmark portfolio.
+#[derive(Debug, Serialize)]
+pub struct BenchmarkStatistics {
+    /// Sensitivity of the portfolio's returns to the benchmark's returns.
+    pub beta: f64,
+
+    /// Annualized return not explained by exposure to the benchmark.
+    pub alpha: f64,
+
+    /// Annualized standard deviation of the difference in returns.
+    pub tracking_error: f64,
+
+    /// Annualized mean excess return over the benchmark divided by tracking
+    /// error.
+    pub information_ratio: f64,
+}
+
+impl Statistics {
+    /// Computes statistics given daily portfolio values and returns (where the
+    /// first day's return is the 0.0 placeholder), the number of shares held
+    /// at the end of each day, and the value of shares traded each day.
+    /// Returns are annualized with `days_per_year` trading days in a year.
+    pub fn new(
+        daily_portvals: &TimeSeries<f64>,
+        daily_returns: &TimeSeries<f64>,
+        daily_shares: &TimeSeries<f64>,
+        daily_traded_value: &TimeSeries<f64>,
+        days_per_year: f64,
+    ) -> Self {
+        // The first day has no return, skip it.
+        let returns: Vec<f64> = daily_returns.values().skip(1).cloned().collect();
+        let periods = periods_per_year(daily_returns.keys(), days_per_year);
+        let annualized_mean = mean(returns.iter().cloned()) * periods;
+        let annualized_volatility = stddev(returns.iter().cloned()) * periods.sqrt();
+
+        let downside = returns.iter().map(|r| r.min(0.0).powi(2));
+        let downside_deviation = mean(downside).sqrt() * periods.sqrt();
+
+        let cagr = cagr(daily_portvals);
+        let drawdown = max_drawdown(daily_portvals);
+        let calmar_ratio = match &drawdown {
+            Some(d) => cagr / d.max_drawdown.abs(),
+            None => f64::NAN,
+        };
+
+        let days_exposed = daily_shares.values().filter(|s| **s != 0.0).count();
+        let exposure_time = if daily_shares.is_empty() {
+            0.0
+        } else {
+            days_exposed as f64 / daily_shares.len() as f64
+        };
+
+        let traded: f64 = daily_traded_value.values().sum();
+        let turnover = traded / mean(daily_portvals.values().cloned());
+
+        Self {
+            cagr,
+            annualized_volatility,
+            sharpe_ratio: annualized_mean / annualized_volatility,
+            sortino_ratio: annualized_mean / downside_deviation,
+            calmar_ratio,
+            drawdown,
+            exposure_time,
+            turnover,
+        }
+    }
+}
+
+impl BenchmarkStatistics {
+    /// Compares daily returns against a benchmark's daily returns. Only days
+    /// present in both (less the first, which has no return) are compared, and
+    /// annualized with `days_per_year` trading days in a year.
+    pub fn new(
+        daily_returns: &TimeSeries<f64>,
+        benchmark_returns: &TimeSeries<f64>,
+        days_per_year: f64,
+    ) -> Self {
+        let (returns, bench): (Vec<f64>, Vec<f64>) = daily_returns
+            .iter()
+            .skip(1)
+            .filter_map(|(day, r)| benchmark_returns.get(day).map(|b| (*r, *b)))
+            .unzip();
+        let periods = periods_per_year(daily_returns.keys(), days_per_year);
+
+        let mean_return = mean(returns.iter().cloned());
+        let mean_bench = mean(bench.iter().cloned());
+        let covariance = mean(
+            returns
+                .iter()
+                .zip(bench.iter())
+                .map(|(r, b)| (r - mean_return) * (b - mean_bench)),
+        );
+        let bench_variance = mean(bench.iter().map(|b| (b - mean_bench).powi(2)));
+        let beta = covariance / bench_variance;
+        let alpha = (mean_return - beta * mean_bench) * periods;
+
+        let excess: Vec<f64> = returns
+            .iter()
+            .zip(bench.iter())
+            .map(|(r, b)| r - b)
+            .collect();
+        let tracking_error = stddev(excess.iter().cloned()) * periods.sqrt();
+        let information_ratio = mean(excess.iter().cloned()) * periods / tracking_error;
+
+        Self {
+            beta,
+            alpha,
+            tracking_error,
+            information_ratio,
+        }
+    }
+}
+
+/// Compound annual growth rate between the first and last portfolio values,
+/// using calendar days.
+pub fn cagr(daily_portvals: &TimeSeries<f64>) -> f64 {
+    let (first_day, first) = match daily_portvals.iter().next() {
+        Some(e) => e,
+        None => return f64::NAN,
+    };
+    let (last_day, last) = daily_portvals.iter().next_back().unwrap();
+
+    let years = (*last_day - *first_day).num_seconds() as f64 / (365.25 * 86400.0);
+    (last / first).powf(1.0 / years) - 1.0
+}
+
+/// Finds the largest peak-to-trough decline in portfolio value, and when (if
+/// ever) the portfolio recovered from it.
+pub fn max_drawdown(daily_portvals: &TimeSeries<f64>) -> Option<Drawdown> {
+    let (first_day, first) = daily_portvals.iter().next()?;
+    let (mut peak_day, mut peak) = (*first_day, *first);
+    let mut worst = Drawdown {
+        max_drawdown: 0.0,
+        peak: *first_day,
+        trough: *first_day,
+        recovery: None,
+    };
+
+    for (day, value) in daily_portvals {
+        if *value > peak {
+            peak = *value;
+            peak_day = *day;
+        }
+
+        let drawdown = (value / peak) - 1.0;
+        if drawdown < worst.max_drawdown {
+            worst = Drawdown {
+                max_drawdown: drawdown,
+                peak: peak_day,
+                trough: *day,
+                recovery: None,
+            };
+        }
+    }
+
+    if worst.max_drawdown < 0.0 {
+        let peak_value = daily_portvals[&worst.peak];
+        worst.recovery = daily_portvals
+            .range(worst.trough..)
+            .find(|(_, v)| **v >= peak_value)
+            .map(|(d, _)| *d);
+    }
+
+    Some(worst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::nearly_equal;
+
+    fn series(start: Date, values: &[f64]) -> TimeSeries<f64> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (start + chrono::Duration::days(i as i64), *v))
+            .collect()
+    }
+
+    #[test]
+    fn drawdown_and_recovery() {
+        let start = Date::from_ymd(2020, 1, 1);
+        let portvals = series(start, &[100.0, 120.0, 90.0, 60.0, 100.0, 130.0, 125.0]);
+        let dd = max_drawdown(&portvals).unwrap();
+
+        assert!(nearly_equal(dd.max_drawdown, -0.5));
+        assert_eq!(dd.peak, Date::from_ymd(2020, 1, 2));
+        assert_eq!(dd.trough, Date::from_ymd(2020, 1, 4));
+        assert_eq!(dd.recovery, Some(Date::from_ymd(2020, 1, 6)));
+
+        // Never recovers
+        let portvals = series(start, &[100.0, 80.0, 90.0]);
+        let dd = max_drawdown(&portvals).unwrap();
+        assert!(nearly_equal(dd.max_drawdown, -0.2));
+        assert_eq!(dd.recovery, None);
+    }
+
+    #[test]
+    fn cagr_over_two_years() {
+        let mut portvals = TimeSeries::new();
+        portvals.insert(Date::from_ymd(2018, 1, 1), 100.0);
+        portvals.insert(Date::from_ymd(2020, 1, 1), 121.0);
+
+        // A hair over 10%, since 730 days is slightly less than two years of
+        // 365.25 days.
+        assert!((cagr(&portvals) - 0.1).abs() < 0.001);
+    }
+
+    #[test]
+    fn benchmark_against_itself() {
+        let start = Date::from_ymd(2020, 1, 1);
+        let returns = series(start, &[0.0, 0.01, -0.02, 0.03, 0.01]);
+        let stats = BenchmarkStatistics::new(&returns, &returns, TRADING_DAYS_PER_YEAR);
+
+        assert!(nearly_equal(stats.beta, 1.0));
+        assert!(nearly_equal(stats.alpha, 0.0));
+        assert!(nearly_equal(stats.tracking_error, 0.0));
+    }
+
+    #[test]
+    fn leveraged_benchmark_has_double_beta() {
+        let start = Date::from_ymd(2020, 1, 1);
+        let bench = series(start, &[0.0, 0.01, -0.02, 0.03, 0.01]);
+        let doubled = series(start, &[0.0, 0.02, -0.04, 0.06, 0.02]);
+        let stats = BenchmarkStatistics::new(&doubled, &bench, TRADING_DAYS_PER_YEAR);
+
+        assert!(nearly_equal(stats.beta, 2.0));
+        assert!(nearly_equal(stats.alpha, 0.0));
+    }
+
+    #[test]
+    fn exposure_and_turnover() {
+        let start = Date::from_ymd(2020, 1, 1);
+        let portvals = series(start, &[100.0, 100.0, 100.0, 100.0]);
+        let returns = series(start, &[0.0, 0.0, 0.0, 0.0]);
+        let shares: TimeSeries<f64> = vec![1.0, 1.0, 0.0, 0.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| (start + chrono::Duration::days(i as i64), s))
+            .collect();
+        let traded = series(start, &[50.0, 0.0, 50.0, 0.0]);
+
+        let stats = Statistics::new(&portvals, &returns, &shares, &traded, TRADING_DAYS_PER_YEAR);
+        assert!(nearly_equal(stats.exposure_time, 0.5));
+        assert!(nearly_equal(stats.turnover, 1.0));
+    }
+
+    #[test]
+    fn annualizes_intraday_bars() {
+        let start = Date::from_ymd(2020, 1, 1);
+        let daily = series(start, &[0.0; 5]);
+        assert!(nearly_equal(
+            periods_per_year(daily.keys(), TRADING_DAYS_PER_YEAR),
+            252.0
+        ));
+
+        // Four hourly bars a day
+        let hourly: Vec<Date> = (0..3)
+            .flat_map(|d| (0..4).map(move |h| start + chrono::Duration::hours(d * 24 + h)))
+            .collect();
+        assert!(nearly_equal(
+            periods_per_year(hourly.iter(), TRADING_DAYS_PER_YEAR),
+            1008.0
+        ));
+
+        // Markets that never close
+        assert!(nearly_equal(
+            periods_per_year(hourly.iter(), CALENDAR_DAYS_PER_YEAR),
+            1460.0
+        ));
+    }
+}
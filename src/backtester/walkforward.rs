@@ -0,0 +1,240 @@
+//! Walk-forward evaluation: splits a price history into a series of training
+//! windows, each followed by an out-of-sample test window, and stitches the
+//! test windows' results back together.
+
+use super::performance::{PerformanceError, PortfolioPerformance, TradingActivity};
+use super::statistics::TRADING_DAYS_PER_YEAR;
+use crate::{util::TimeSeries, Date};
+use thiserror::Error;
+
+/// Errors from setting up a walk-forward evaluation.
+#[derive(Debug, Error, PartialEq)]
+pub enum WalkForwardError {
+    /// Every day of the training window would be needed to label the others.
+    #[error(
+        "Training window of {train_days} days must be longer than the horizon of {horizon} days"
+    )]
+    TrainWindowTooShort {
+        /// Length of the training window
+        train_days: usize,
+        /// Days of future returns used for labelling
+        horizon: u32,
+    },
+
+    /// A test window must have at least one return in it.
+    #[error("Test window must be at least 2 days long, got {0}")]
+    TestWindowTooShort(usize),
+
+    /// The dataset is too short to fit a training window and a test window.
+    #[error("Need more than {needed} days of price data for a single fold, only have {available}")]
+    NotEnoughData {
+        /// Minimum days of data (exclusive)
+        needed: usize,
+        /// Days of data given
+        available: usize,
+    },
+}
+
+/// One training window and the out-of-sample window that follows it.
+#[derive(Debug, PartialEq)]
+pub struct Fold {
+    /// Every day in the training window, including the last `horizon` days,
+    /// which only supply future returns for labelling.
+    pub train_dates: Vec<Date>,
+
+    /// Days the trained model is tested on.
+    pub test_dates: Vec<Date>,
+}
+
+impl Fold {
+    /// Days that can be labelled without peeking into the test window.
+    pub fn label_dates(&self, horizon: u32) -> &[Date] {
+        let end = self.train_dates.len().saturating_sub(horizon as usize);
+        &self.train_dates[..end]
+    }
+
+    /// First day of the training window.
+    pub fn train_start(&self) -> Date {
+        self.train_dates[0]
+    }
+
+    /// Last day of the training window.
+    pub fn train_end(&self) -> Date {
+        self.train_dates[self.train_dates.len() - 1]
+    }
+
+    /// First day of the test window.
+    pub fn test_start(&self) -> Date {
+        self.test_dates[0]
+    }
+
+    /// Last day of the test window.
+    pub fn test_end(&self) -> Date {
+        self.test_dates[self.test_dates.len() - 1]
+    }
+}
+
+/// Splits trading days into folds. Test windows are `test_days` long, follow
+/// one another without overlapping, and the last one is cut short if the data
+/// runs out. Each training window is the `train_days` before its test window,
+/// or if `anchored`, everything before it.
+pub fn folds(
+    dates: &[Date],
+    train_days: usize,
+    test_days: usize,
+    horizon: u32,
+    anchored: bool,
+) -> Result<Vec<Fold>, WalkForwardError> {
+    if train_days <= horizon as usize {
+        return Err(WalkForwardError::TrainWindowTooShort {
+            train_days,
+            horizon,
+        });
+    }
+    if test_days < 2 {
+        return Err(WalkForwardError::TestWindowTooShort(test_days));
+    }
+
+    let mut folds = Vec::new();
+    let mut test_start = train_days;
+    // A return needs at least two days
+    while test_start + 1 < dates.len() {
+        let train_start = if anchored { 0 } else { test_start - train_days };
+        let test_end = (test_start + test_days).min(dates.len());
+        folds.push(Fold {
+            train_dates: dates[train_start..test_start].to_vec(),
+            test_dates: dates[test_start..test_end].to_vec(),
+        });
+        test_start = test_end;
+    }
+
+    if folds.is_empty() {
+        return Err(WalkForwardError::NotEnoughData {
+            needed: train_days + 1,
+            available: dates.len(),
+        });
+    }
+
+    Ok(folds)
+}
+
+/// Chains together the performance of consecutive test windows, each of which
+/// started over with `cash`, as if one portfolio had traded through all of
+/// them. Each window's values (and costs, dividends, and traded value) are
+/// scaled so that it starts where the previous one left off. Statistics are
+/// annualized like those of the windows.
+pub fn stitch(
+    performances: &[PortfolioPerformance],
+    cash: f64,
+) -> Result<PortfolioPerformance, PerformanceError> {
+    let mut portvals = TimeSeries::new();
+    let mut activity = TradingActivity::default();
+    let mut value = cash;
+
+    for p in performances {
+        let scale = value / cash;
+        for (day, v) in &p.daily_portvals {
+            portvals.insert(*day, v * scale);
+            value = v * scale;
+        }
+        for (day, c) in &p.daily_costs {
+            activity.daily_costs.insert(*day, c * scale);
+        }
+        for (day, d) in &p.daily_dividends {
+            activity.daily_dividends.insert(*day, d * scale);
+        }
+        for (day, t) in &p.daily_traded_value {
+            activity.daily_traded_value.insert(*day, t * scale);
+        }
+        activity.daily_shares.extend(p.daily_shares.iter());
+        activity.rejected_orders.extend(p.rejected_orders.iter());
+    }
+
+    let days_per_year = performances
+        .first()
+        .map_or(TRADING_DAYS_PER_YEAR, |p| p.days_per_year);
+    PortfolioPerformance::with_activity(portvals, activity, days_per_year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::nearly_equal;
+
+    fn days(n: usize) -> Vec<Date> {
+        (0..n)
+            .map(|i| Date::from_ymd(2020, 1, 1) + chrono::Duration::days(i as i64))
+            .collect()
+    }
+
+    #[test]
+    fn rolling_folds() {
+        let dates = days(25);
+        let folds = folds(&dates, 10, 5, 2, false).unwrap();
+
+        // 10 training days, then 5 + 5 + 5 test days
+        assert_eq!(folds.len(), 3);
+        assert_eq!(folds[0].train_dates, dates[0..10].to_vec());
+        assert_eq!(folds[0].test_dates, dates[10..15].to_vec());
+        assert_eq!(folds[0].label_dates(2), &dates[0..8]);
+        assert_eq!(folds[2].train_dates, dates[10..20].to_vec());
+        assert_eq!(folds[2].test_dates, dates[20..25].to_vec());
+
+        // Last test window is cut short
+        let folds = super::folds(&dates, 10, 10, 2, false).unwrap();
+        assert_eq!(folds.len(), 2);
+        assert_eq!(folds[1].test_dates, dates[20..25].to_vec());
+    }
+
+    #[test]
+    fn anchored_folds() {
+        let dates = days(25);
+        let folds = folds(&dates, 10, 5, 2, true).unwrap();
+        assert_eq!(folds.len(), 3);
+        assert!(folds.iter().all(|f| f.train_start() == dates[0]));
+        assert_eq!(folds[2].train_end(), dates[19]);
+    }
+
+    #[test]
+    fn bad_windows() {
+        let dates = days(25);
+        assert_eq!(
+            folds(&dates, 10, 5, 10, false),
+            Err(WalkForwardError::TrainWindowTooShort {
+                train_days: 10,
+                horizon: 10
+            })
+        );
+        assert_eq!(
+            folds(&dates, 10, 1, 2, false),
+            Err(WalkForwardError::TestWindowTooShort(1))
+        );
+        assert_eq!(
+            folds(&dates, 24, 5, 2, false),
+            Err(WalkForwardError::NotEnoughData {
+                needed: 25,
+                available: 25
+            })
+        );
+    }
+
+    #[test]
+    fn stitching_compounds_returns() {
+        let dates = days(4);
+        let first: TimeSeries<f64> = vec![(dates[0], 100.0), (dates[1], 110.0)]
+            .into_iter()
+            .collect();
+        let second: TimeSeries<f64> = vec![(dates[2], 100.0), (dates[3], 90.0)]
+            .into_iter()
+            .collect();
+        let perfs = vec![
+            PortfolioPerformance::new(first).unwrap(),
+            PortfolioPerformance::new(second).unwrap(),
+        ];
+
+        let stitched = stitch(&perfs, 100.0).unwrap();
+        assert!(nearly_equal(stitched.daily_portvals[&dates[2]], 110.0));
+        assert!(nearly_equal(stitched.daily_portvals[&dates[3]], 99.0));
+        assert!(nearly_equal(stitched.total_return().unwrap(), -0.01));
+    }
+}
@@ -6,15 +6,31 @@ use techalyzer::get_market_data;
 use techalyzer::secret::Secret;
 use techalyzer::subcommands::*;
 use techalyzer::{
-    config::{GeneralParams, TrainingParams},
+    calendar::TradingCalendar,
+    config::{
+        BacktesterParams, EnsembleMemberSpec, EnsembleParams, ExitParams, GeneralParams,
+        OptimizeParams, QLearningParams, TrainingParams, TuneParams, WalkForwardParams,
+    },
     date::{today, Date},
-    indicators::SupportedIndicators,
-    marketdata::prices::PricesError,
+    indicators::IndicatorSpec,
+    marketdata::{
+        actions::back_adjust,
+        prices::Prices,
+        resample::resample,
+        validation::{repair, validate},
+    },
     signals::Signal,
     trading::{
         buyandhold::BuyAndHold,
-        dtmodel::{DecisionTreeTrader, Trained},
+        dtmodel::{MachineLearningTrader, Trained},
+        ensemble::EnsembleModel,
+        exits::{ExitRules, RiskExits},
         manual::ManualTradingModel,
+        ml::Learner,
+        modelfile::{self, Metadata, ModelFileError, ModelKind},
+        qlearner::QLearningTrader,
+        sizing::{PositionSizer, SizingPolicy},
+        tradingmodel::TradingModel,
         SupportedTradingModel,
     },
     util::last_key,
@@ -39,12 +55,18 @@ enum SubCommands {
     /// Using time series price data, prints a technical indicator and the
     /// normalized signals generated by Techalyzer to STDOUT as JSON data.
     Print {
+        /// Indicator to print, optionally with parameters (e.g. `rsi:14` or
+        /// `bb:20,2.0`).
         #[structopt(short, long)]
-        indicator: SupportedIndicators,
+        indicator: IndicatorSpec,
 
         /// Print buy/sell signals along with the indicator
         #[structopt(short, long)]
         print_signals: bool,
+
+        /// Also render a chart to this file (.svg or .png)
+        #[structopt(long)]
+        plot: Option<PathBuf>,
     },
 
     /// Trains a machine learning model on stock data to make trades based on
@@ -69,9 +91,73 @@ enum SubCommands {
         out_path: Option<PathBuf>,
     },
 
+    /// Trains a Q-learning model to go long, short or out depending on the
+    /// signals of technical indicators and its current position, then
+    /// serializes it for later use.
+    TrainQLearner {
+        #[structopt(flatten)]
+        params: QLearningParams,
+
+        /// A file with Q-learning parameters as JSON.
+        #[structopt(short, long)]
+        paramfile: Option<PathBuf>,
+
+        /// File path to output a model file to [default: <symbol>.bin]
+        #[structopt(long, short)]
+        out_path: Option<PathBuf>,
+    },
+
     /// Suggests a trading course of action given recent developments in a
     /// security's price action.
-    Suggest { model: SupportedTradingModel },
+    Suggest {
+        /// Which trading model to use.
+        trading_model: SupportedTradingModel,
+
+        // ManualTradingAlgo params
+        #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
+        shares: Option<f64>,
+
+        #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
+        dead_zone: Option<Signal>,
+
+        #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
+        disposition: Option<Signal>,
+
+        /// Saved model file to use (generate one with `techalyzer train`, or
+        /// `techalyzer train-q-learner` for a QLearningModel)
+        #[structopt(
+            long,
+            short,
+            required_ifs(&[
+                ("trading-model", "MachineLearningModel"),
+                ("trading-model", "QLearningModel"),
+            ])
+        )]
+        model_file: Option<PathBuf>,
+
+        /// JSON file listing the models in the ensemble and how they vote
+        #[structopt(long, required_if("trading-model", "Ensemble"))]
+        ensemble: Option<PathBuf>,
+
+        /// Print the suggestion as JSON instead of text
+        #[structopt(long)]
+        json: bool,
+
+        /// Overrides how the model sizes its trades: `fixed:<shares>`,
+        /// `cash:<fraction>`, `vol:<target>[,<window>]`,
+        /// `kelly:<fraction>[,<window>]` or `signal:<max shares>`.
+        #[structopt(long)]
+        sizing: Option<SizingPolicy>,
+
+        /// How much cash trades are sized against.
+        #[structopt(long, default_value = "100000")]
+        cash: f64,
+
+        /// Smallest number of shares that can be traded, e.g. 0.0001 for
+        /// crypto.
+        #[structopt(long, default_value = "1")]
+        lot_size: f64,
+    },
 
     /// Backtests a strategy through a given dataset
     Backtest {
@@ -84,7 +170,7 @@ enum SubCommands {
 
         // ManualTradingAlgo params
         #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
-        shares: Option<u64>,
+        shares: Option<f64>,
 
         #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
         dead_zone: Option<Signal>,
@@ -92,18 +178,125 @@ enum SubCommands {
         #[structopt(long, required_if("trading-model", "ManualTradingAlgo"))]
         disposition: Option<Signal>,
 
-        /// Saved model file to use (generate one with `techalyzer train`)
-        #[structopt(long, short, required_if("trading-model", "MachineLearningModel"))]
+        /// Saved model file to use (generate one with `techalyzer train`, or
+        /// `techalyzer train-q-learner` for a QLearningModel)
+        #[structopt(
+            long,
+            short,
+            required_ifs(&[
+                ("trading-model", "MachineLearningModel"),
+                ("trading-model", "QLearningModel"),
+            ])
+        )]
         model_file: Option<PathBuf>,
 
+        /// JSON file listing the models in the ensemble and how they vote
+        #[structopt(long, required_if("trading-model", "Ensemble"))]
+        ensemble: Option<PathBuf>,
+
         /// How much cash the model begins with.
         cash: f64, // TODO: is there a good money type/bignum to avoid possible problems?
+
+        /// Overrides how the model sizes its trades: `fixed:<shares>`,
+        /// `cash:<fraction>`, `vol:<target>[,<window>]`,
+        /// `kelly:<fraction>[,<window>]` or `signal:<max shares>`.
+        #[structopt(long)]
+        sizing: Option<SizingPolicy>,
+
+        #[structopt(flatten)]
+        backtester_params: BacktesterParams,
+
+        #[structopt(flatten)]
+        exit_params: ExitParams,
+
+        /// Also render a chart to this file (.svg or .png)
+        #[structopt(long)]
+        plot: Option<PathBuf>,
+    },
+
+    /// Backtests a ManualTradingAlgo with many combinations of parameters and
+    /// ranks the results.
+    Optimize {
+        #[structopt(flatten)]
+        params: OptimizeParams,
+
+        /// How much cash each backtest begins with.
+        cash: f64,
+
+        /// Print the leaderboard as CSV instead of JSON
+        #[structopt(long)]
+        csv: bool,
+
+        #[structopt(flatten)]
+        backtester_params: BacktesterParams,
+    },
+
+    /// Repeatedly trains a machine learning model on a window of data and
+    /// backtests it on the days that follow, sliding both windows forward
+    /// through the dataset to measure out-of-sample performance.
+    Walkforward {
+        /// Training parameters. The training start and end dates are ignored,
+        /// the windows are chosen for each fold.
+        #[structopt(flatten)]
+        params: TrainingParams,
+
+        /// A file with training parameters as JSON.
+        #[structopt(short, long)]
+        paramfile: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        walk_forward_params: WalkForwardParams,
+
+        /// How much cash the model begins each test window with.
+        cash: f64,
+
+        #[structopt(flatten)]
+        backtester_params: BacktesterParams,
+    },
+
+    /// Cross-validates a machine learning model over the training dates with
+    /// each combination of the given hyperparameters, reports how each did on
+    /// every fold, and saves the best one trained on all of the dates.
+    Tune {
+        #[structopt(flatten)]
+        params: TrainingParams,
+
+        /// A file with training parameters as JSON.
+        #[structopt(short, long)]
+        paramfile: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        tune_params: TuneParams,
+
+        /// File path to output the best model file to [default: <symbol>.bin]
+        #[structopt(long, short)]
+        out_path: Option<PathBuf>,
+    },
+
+    /// Checks the price data for missing trading days, duplicate dates,
+    /// non-positive prices, and implausible one-day moves. Problems can be
+    /// repaired for the other subcommands with `--repair`.
+    CheckData {
+        /// Print the report as JSON instead of text
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Describes how a model file saved by `train` was trained (symbol, dates,
+    /// indicators, algorithm, etc.) and checks that it is intact. Needs no
+    /// price data, so it can be run as just `techalyzer model-info <file>`.
+    ModelInfo {
+        /// Model file to inspect.
+        model_file: PathBuf,
+
+        /// Print the description as JSON instead of text
+        #[structopt(long)]
+        json: bool,
     },
 }
 
 fn main() -> Result<()> {
-    let opts = Opts::from_args();
-    match run_program(opts) {
+    match run_program(Opts::from_args()) {
         Ok(_) => Ok(()),
         Err(e) => {
             println!("{}", e);
@@ -118,7 +311,14 @@ fn very_early_date() -> Date {
 
 /// Wrappable main function to make it easier to test.
 fn run_program(opts: Opts) -> Result<()> {
+    // The only subcommand that doesn't need price data
+    if let SubCommands::ModelInfo { model_file, json } = &opts.cmd {
+        return Ok(model_info(model_file.clone(), *json)?);
+    }
+
     let params = opts.params;
+    let cache = params.cache_config();
+    let calendar = params.calendar.load()?;
 
     // Date range for the data
     let start = params.start_date;
@@ -129,16 +329,63 @@ fn run_program(opts: Opts) -> Result<()> {
         data: params.secret,
     };
 
+    // The end date includes any intraday data on that day.
     let start_date = start.unwrap_or_else(very_early_date);
-    let end_date = end.unwrap_or_else(today);
+    let end_date = end.unwrap_or_else(today).end_of_day();
 
     // Get market data
-    let prices = get_market_data(
-        params.data_source,
-        params.symbol,
+    let data_source = params
+        .data_source
+        .ok_or(TechalyzerError::NoDataSourceSpecified)?;
+    let symbol = params.symbol.ok_or(TechalyzerError::NoSymbolSpecified)?;
+    let mut portfolio = get_market_data(
+        data_source,
+        symbol,
         start_date..=end_date,
         secret,
+        &cache,
+        calendar.session.timezone,
+        params.csv_profile,
     )?;
+    let several_symbols_ok = matches!(
+        opts.cmd,
+        SubCommands::Backtest { .. } | SubCommands::CheckData { .. }
+    );
+    if portfolio.len() > 1 && !several_symbols_ok {
+        return Err(TechalyzerError::MultipleSymbolsUnsupported.into());
+    }
+
+    if let Some(interval) = params.interval {
+        portfolio = portfolio
+            .iter()
+            .map(|prices| resample(prices, interval, &calendar))
+            .collect();
+    }
+
+    // Adjusting for splits first keeps them from looking like jumps.
+    let adjustment = params.adjust;
+    portfolio = portfolio
+        .into_iter()
+        .map(|prices| back_adjust(prices, adjustment))
+        .collect();
+
+    // CheckData reports on the data as it is, without repairs.
+    let max_jump = params.max_jump;
+    if let (Some(policy), false) = (
+        params.repair,
+        matches!(opts.cmd, SubCommands::CheckData { .. }),
+    ) {
+        portfolio = portfolio
+            .into_iter()
+            .map(|prices| {
+                let issues = validate(&prices, &calendar, max_jump);
+                repair(prices, &issues, policy)
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    // Everything but backtests and data checks works on exactly one symbol.
+    let prices = portfolio.remove(0);
     // {
     //     Ok(d) => d,
     //     Err(e) => {
@@ -151,153 +398,657 @@ fn run_program(opts: Opts) -> Result<()> {
         SubCommands::Print {
             indicator,
             print_signals: _,
+            plot,
         } => {
             // TODO: evaluate/benchmark signal generation using ndarray vs Vec<f64>
-            print(prices, indicator)?;
+            print(prices, indicator, plot)?;
+        }
+        SubCommands::Suggest {
+            trading_model,
+            shares,
+            dead_zone,
+            disposition,
+            model_file,
+            ensemble,
+            json,
+            sizing,
+            cash,
+            lot_size,
+        } => {
+            match trading_model {
+                SupportedTradingModel::BuyAndHold => {
+                    let sizer = sizer(
+                        sizing,
+                        SizingPolicy::CashFraction(1.0),
+                        cash,
+                        lot_size,
+                        &calendar,
+                    );
+                    suggest(prices, BuyAndHold::new(sizer), json)?;
+                }
+                SupportedTradingModel::ManualTradingAlgo => {
+                    let (shares, dead_zone, disposition) =
+                        (shares.unwrap(), dead_zone.unwrap(), disposition.unwrap());
+                    let sizer = sizer(
+                        sizing,
+                        SizingPolicy::FixedShares(shares),
+                        cash,
+                        lot_size,
+                        &calendar,
+                    );
+                    suggest(
+                        prices,
+                        ManualTradingModel::new(shares, dead_zone, disposition).with_sizer(sizer),
+                        json,
+                    )?;
+                }
+                SupportedTradingModel::MachineLearningModel => {
+                    let model = load_model(model_file)?;
+                    let sizer = sizer(sizing, model.sizer().policy(), cash, lot_size, &calendar);
+                    suggest(prices, model.with_sizer(sizer), json)?;
+                }
+                SupportedTradingModel::QLearningModel => {
+                    let model = load_q_model(model_file)?;
+                    let sizer = sizer(sizing, model.sizer().policy(), cash, lot_size, &calendar);
+                    suggest(prices, model.with_sizer(sizer), json)?;
+                }
+                SupportedTradingModel::Ensemble => {
+                    let model = load_ensemble(ensemble)?;
+                    let sizer = sizer(sizing, model.sizer().policy(), cash, lot_size, &calendar);
+                    suggest(prices, model.with_sizer(sizer), json)?;
+                }
+            };
         }
-        SubCommands::Suggest { model: _ } => todo!("Suggest not yet implemented"),
         SubCommands::Train {
             params: p,
             out_path,
             paramfile,
         } => {
-            // Paramfile is our default if it is present, otherwise use passed params.
-            let params: TrainingParams = match paramfile {
-                Some(path) => {
-                    let p: TrainingParams = serde_json::from_reader(File::open(path)?)?;
-                    // TODO: override parameters if cli ones are present
-                    p
-                }
+            let params = training_params(p, paramfile)?;
+            let range = training_dates(&prices, &params, &calendar)?;
+
+            // FIXME: need a way to output to null for testing
+            let out_path =
+                out_path.unwrap_or_else(|| PathBuf::from(format!("{}.bin", &prices.symbol)));
+            train(prices, range, &params, &calendar, out_path)?
+        }
+        SubCommands::TrainQLearner {
+            params: p,
+            paramfile,
+            out_path,
+        } => {
+            let params = match paramfile {
+                Some(path) => serde_json::from_reader(File::open(path)?)?,
                 None => p,
             };
-
-            // TODO: implement a verbose flag
-            // dbg!(&params);
-
             if params.signal_generators.is_empty() {
                 return Err(TechalyzerError::NoIndicatorSpecified.into());
             }
 
-            let start_date = match params.train_start_date {
-                Some(d) => d,
-                None => {
-                    *prices
-                        .first_entry()
-                        .ok_or_else(|| {
-                            TechalyzerError::Generic(
-                                "Could not find first entry in dataset".to_string(),
-                            )
-                        })?
-                        .0
-                }
-            };
-
-            // Manual end date or -horizon days before the end of the dataset.
-            let end_date = match end {
-                // if it's some end date, index into the prices until we're
-                // horizon days before it
-                Some(d) => {
-                    prices
-                        .get_before(&d, params.horizon.0)
-                        .ok_or(PricesError::DateNotFound(d))?
-                        .0
-                }
-                // else use the last n days
-                None => {
-                    last_key(&prices.map)
-                        .and_then(|d| prices.get_before(&d, params.horizon.0))
-                        .ok_or(TechalyzerError::Generic(format!(
-                            "Could not find {} days before last day in price data",
-                            params.horizon
-                        )))?
-                        .0
-                }
-            };
-
-            // Copy our training dates out of the Price data set.
-            let range: Vec<Date> = prices
-                .date_range(start_date..=end_date)
-                .map
-                .keys()
-                .cloned()
-                .collect();
-
-            // TODO: include date info
-            // FIXME: need a way to output to null for testing
             let out_path =
                 out_path.unwrap_or_else(|| PathBuf::from(format!("{}.bin", &prices.symbol)));
-            train(
-                prices,
-                range,
-                params.signal_generators.into(),
-                params.horizon,
-                out_path,
-            )?
+            train_q_learner(prices, &params, out_path)?
         }
         SubCommands::Backtest {
             trading_model,
             cash,
             model_file,
+            ensemble,
             dead_zone,
             disposition,
             shares,
+            sizing,
+            backtester_params,
+            exit_params,
+            plot,
         } => {
+            let portfolio: Vec<Prices> = std::iter::once(prices).chain(portfolio).collect();
+            let n = portfolio.len();
+            // Each symbol's trades are sized against an equal share of the cash
+            let capital = cash / n as f64;
+            let lot_size = backtester_params.lot_size();
             match trading_model {
                 // TODO: don't unwrap
                 SupportedTradingModel::BuyAndHold => {
-                    backtest(prices, BuyAndHold::default(), cash)?;
+                    let models = (0..n)
+                        .map(|_| {
+                            let policy = SizingPolicy::CashFraction(1.0);
+                            BuyAndHold::new(sizer(sizing, policy, capital, lot_size, &calendar))
+                        })
+                        .collect();
+                    backtest_symbols(
+                        portfolio,
+                        models,
+                        cash,
+                        &backtester_params,
+                        &exit_params,
+                        &calendar,
+                        plot,
+                    )?;
                 }
                 SupportedTradingModel::ManualTradingAlgo => {
                     let (shares, dead_zone, disposition) =
                         (shares.unwrap(), dead_zone.unwrap(), disposition.unwrap());
-                    backtest(
-                        prices,
-                        ManualTradingModel::new(shares, dead_zone, disposition),
+                    let models = (0..n)
+                        .map(|_| {
+                            ManualTradingModel::new(shares, dead_zone, disposition).with_sizer(
+                                sizer(
+                                    sizing,
+                                    SizingPolicy::FixedShares(shares),
+                                    capital,
+                                    lot_size,
+                                    &calendar,
+                                ),
+                            )
+                        })
+                        .collect();
+                    backtest_symbols(
+                        portfolio,
+                        models,
                         cash,
+                        &backtester_params,
+                        &exit_params,
+                        &calendar,
+                        plot,
                     )?;
                 }
                 SupportedTradingModel::MachineLearningModel => {
-                    let model: DecisionTreeTrader<Trained> = match model_file {
-                        Some(path) => bincode::deserialize(std::fs::read(path)?.as_slice())?,
-                        None => return Err(TechalyzerError::NoModelFileSpecified.into()),
-                    };
-                    backtest(prices, model, cash)?;
+                    let models = (0..n)
+                        .map(|_| {
+                            let model = load_model(model_file.clone())?;
+                            let sizer =
+                                sizer(sizing, model.sizer().policy(), capital, lot_size, &calendar);
+                            Ok(model.with_sizer(sizer))
+                        })
+                        .collect::<Result<_>>()?;
+                    backtest_symbols(
+                        portfolio,
+                        models,
+                        cash,
+                        &backtester_params,
+                        &exit_params,
+                        &calendar,
+                        plot,
+                    )?;
+                }
+                SupportedTradingModel::QLearningModel => {
+                    let models = (0..n)
+                        .map(|_| {
+                            let model = load_q_model(model_file.clone())?;
+                            let sizer =
+                                sizer(sizing, model.sizer().policy(), capital, lot_size, &calendar);
+                            Ok(model.with_sizer(sizer))
+                        })
+                        .collect::<Result<_>>()?;
+                    backtest_symbols(
+                        portfolio,
+                        models,
+                        cash,
+                        &backtester_params,
+                        &exit_params,
+                        &calendar,
+                        plot,
+                    )?;
+                }
+                SupportedTradingModel::Ensemble => {
+                    let models = (0..n)
+                        .map(|_| {
+                            let model = load_ensemble(ensemble.clone())?;
+                            let sizer =
+                                sizer(sizing, model.sizer().policy(), capital, lot_size, &calendar);
+                            Ok(model.with_sizer(sizer))
+                        })
+                        .collect::<Result<_>>()?;
+                    backtest_symbols(
+                        portfolio,
+                        models,
+                        cash,
+                        &backtester_params,
+                        &exit_params,
+                        &calendar,
+                        plot,
+                    )?;
                 }
             };
         }
+        SubCommands::Optimize {
+            params,
+            cash,
+            csv,
+            backtester_params,
+        } => {
+            optimize(prices, &params, cash, &backtester_params, &calendar, csv)?;
+        }
+        SubCommands::Walkforward {
+            params: p,
+            paramfile,
+            walk_forward_params,
+            cash,
+            backtester_params,
+        } => {
+            let params = training_params(p, paramfile)?;
+            walkforward(
+                prices,
+                &params,
+                &calendar,
+                &walk_forward_params,
+                cash,
+                &backtester_params,
+            )?;
+        }
+        SubCommands::Tune {
+            params: p,
+            paramfile,
+            tune_params,
+            out_path,
+        } => {
+            let params = training_params(p, paramfile)?;
+            let range = training_dates(&prices, &params, &calendar)?;
+            let out_path =
+                out_path.unwrap_or_else(|| PathBuf::from(format!("{}.bin", &prices.symbol)));
+            tune(prices, range, &params, &calendar, &tune_params, out_path)?
+        }
+        SubCommands::CheckData { json } => {
+            let portfolio: Vec<Prices> = std::iter::once(prices).chain(portfolio).collect();
+            check_data(portfolio, &calendar, max_jump, params.repair, json)?;
+        }
+        // Handled before loading any price data
+        SubCommands::ModelInfo { .. } => {}
+    }
+
+    Ok(())
+}
+
+/// Picks between training parameters from the command line and a paramfile,
+/// and makes sure they are usable.
+fn training_params(params: TrainingParams, paramfile: Option<PathBuf>) -> Result<TrainingParams> {
+    // Paramfile is our default if it is present, otherwise use passed params.
+    let params: TrainingParams = match paramfile {
+        Some(path) => {
+            let p: TrainingParams = serde_json::from_reader(File::open(path)?)?;
+            // TODO: override parameters if cli ones are present
+            p
+        }
+        None => params,
+    };
+
+    // TODO: implement a verbose flag
+    // dbg!(&params);
+
+    if params.signal_generators.is_empty() {
+        return Err(TechalyzerError::NoIndicatorSpecified.into());
+    }
+
+    Ok(params)
+}
+
+/// Days of `prices` to train on: from the training start date (or the start
+/// of the data) to `horizon` trading days before the end of the data (which
+/// stops at the manual end date, if there is one), so that every training day
+/// has a future return to label it with.
+fn training_dates(
+    prices: &Prices,
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+) -> Result<Vec<Date>> {
+    let start_date = match params.train_start_date {
+        Some(d) => d,
+        None => {
+            *prices
+                .first_entry()
+                .ok_or_else(|| {
+                    TechalyzerError::Generic("Could not find first entry in dataset".to_string())
+                })?
+                .0
+        }
+    };
+
+    let end_date = last_key(&prices.map)
+        .map(|d| calendar.sub_trading_days(*d, params.horizon.0))
+        .ok_or_else(|| {
+            TechalyzerError::Generic("Could not find last entry in dataset".to_string())
+        })?;
+
+    // Copy our training dates out of the Price data set.
+    Ok(prices
+        .date_range(start_date..=end_date)
+        .map
+        .keys()
+        .cloned()
+        .collect())
+}
+
+/// Sizes trades against `capital`, in lots of `lot_size` shares, with `sizing`
+/// if it was given, or `default` otherwise. Volatility is annualized over the
+/// trading days in a year of `calendar`.
+fn sizer(
+    sizing: Option<SizingPolicy>,
+    default: SizingPolicy,
+    capital: f64,
+    lot_size: f64,
+    calendar: &TradingCalendar,
+) -> PositionSizer {
+    PositionSizer::new(sizing.unwrap_or(default), capital)
+        .with_lot_size(lot_size)
+        .with_days_per_year(calendar.trading_days_per_year())
+}
+
+/// Backtests a single symbol on its own, or several symbols as a portfolio
+/// sharing the same cash, closing the models' positions early by the `exits`
+/// rules.
+fn backtest_symbols<M: TradingModel>(
+    mut portfolio: Vec<Prices>,
+    trading_models: Vec<M>,
+    cash: f64,
+    params: &BacktesterParams,
+    exits: &ExitParams,
+    calendar: &TradingCalendar,
+    plot: Option<PathBuf>,
+) -> Result<()> {
+    let rules = ExitRules::from(exits);
+    let mut trading_models: Vec<_> = trading_models
+        .into_iter()
+        .map(|m| RiskExits::new(m, rules))
+        .collect();
+    if portfolio.len() == 1 {
+        backtest(
+            portfolio.remove(0),
+            trading_models.remove(0),
+            cash,
+            params,
+            calendar,
+            plot,
+        )?;
+    } else {
+        portfolio_backtest(portfolio, trading_models, cash, params, calendar, plot)?;
     }
 
     Ok(())
 }
 
+/// Loads an ensemble of trading models from a JSON config file (see
+/// `EnsembleParams`). Model files are found relative to the config file.
+fn load_ensemble(config: Option<PathBuf>) -> Result<EnsembleModel> {
+    let path = config.ok_or(TechalyzerError::NoEnsembleSpecified)?;
+    let params: EnsembleParams = serde_json::from_reader(File::open(&path)?)?;
+    let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+
+    let mut ensemble = EnsembleModel::new(params.method)
+        .with_sizer(PositionSizer::new(params.sizing, 0.0))
+        .with_stacking(params.horizon, params.min_train);
+    for member in params.members {
+        ensemble = match member.model {
+            EnsembleMemberSpec::MachineLearning { model_file } => {
+                let model = load_model(Some(dir.join(model_file)))?;
+                ensemble.with_member(model, member.weight)
+            }
+            EnsembleMemberSpec::Manual {
+                shares,
+                dead_zone,
+                disposition,
+                indicators,
+            } => {
+                let mut model = ManualTradingModel::new(shares, dead_zone, disposition);
+                if !indicators.is_empty() {
+                    model = model.with_indicators(indicators)?;
+                }
+                ensemble.with_member(model, member.weight)
+            }
+            EnsembleMemberSpec::BuyAndHold => {
+                // Only the side it takes matters, not how much it buys
+                let model = BuyAndHold::new(PositionSizer::fixed(1.0));
+                ensemble.with_member(model, member.weight)
+            }
+        };
+    }
+
+    Ok(ensemble)
+}
+
+/// Loads a model file saved by `techalyzer train`.
+fn load_model(model_file: Option<PathBuf>) -> Result<MachineLearningTrader<Learner, Trained>> {
+    match model_file {
+        Some(path) => {
+            let (header, model): (_, MachineLearningTrader<Learner, Trained>) =
+                modelfile::from_bytes(&std::fs::read(path)?, ModelKind::MachineLearning)?;
+            let found = model.feature_names();
+            if let Metadata::MachineLearning(metadata) = header.metadata {
+                if found != metadata.features {
+                    return Err(ModelFileError::FeatureMismatch {
+                        expected: metadata.features,
+                        found,
+                    }
+                    .into());
+                }
+            }
+            Ok(model)
+        }
+        None => Err(TechalyzerError::NoModelFileSpecified.into()),
+    }
+}
+
+/// Loads a model file saved by `techalyzer train-q-learner`.
+fn load_q_model(model_file: Option<PathBuf>) -> Result<QLearningTrader<Trained>> {
+    let path = model_file.ok_or(TechalyzerError::NoModelFileSpecified)?;
+    let (_, model) = modelfile::from_bytes(&std::fs::read(path)?, ModelKind::QLearning)?;
+    Ok(model)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::SupportedIndicators;
     use super::{run_program, Opts, SubCommands};
-    use crate::TrainingParams;
+    use crate::{BacktesterParams, ExitParams, TrainingParams, WalkForwardParams};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use structopt::StructOpt;
+    use techalyzer::indicators::{IndicatorSpec, SupportedIndicators};
     use techalyzer::{
-        config::GeneralParams, datasource::SupportedDataSource, date::Date, util::Symbol,
+        calendar::{CalendarSpec, TradingCalendar},
+        config::GeneralParams,
+        datasource::{csv::CsvProfile, SupportedDataSource},
+        date::Date,
+        marketdata::{actions::Adjustment, validation::RepairPolicy},
+        signals::Signal,
+        trading::{
+            ml::Algorithm,
+            modelfile::{self, Metadata},
+            SupportedTradingModel,
+        },
+        util::Symbol,
     };
     use tempfile::NamedTempFile;
 
+    fn jpm_params() -> GeneralParams {
+        GeneralParams {
+            data_source: Some(SupportedDataSource::TechalyzerJson(
+                "test/json/jpm_rsi.json".into(),
+            )),
+            secret: None,
+            symbol: Some(Symbol::new("JPM".to_string()).into()),
+            start_date: None,
+            end_date: None,
+            offline: false,
+            refresh: false,
+            cache_dir: None,
+            calendar: CalendarSpec::Nyse,
+            adjust: Adjustment::Splits,
+            interval: None,
+            repair: None,
+            max_jump: 0.5,
+            csv_profile: CsvProfile::Headers,
+        }
+    }
+
     #[test]
     fn end_to_end_print_rsi() {
         // Basic smoke test that the program can go end to end
         let res = run_program(Opts {
             params: GeneralParams {
-                data_source: SupportedDataSource::TechalyzerJson("test/json/jpm_rsi.json".into()),
+                data_source: Some(SupportedDataSource::TechalyzerJson(
+                    "test/json/jpm_rsi.json".into(),
+                )),
                 secret: None,
-                symbol: Symbol::new("JPM".to_string()),
+                symbol: Some(Symbol::new("JPM".to_string()).into()),
                 start_date: None,
                 end_date: None,
+                offline: false,
+                refresh: false,
+                cache_dir: None,
+                calendar: CalendarSpec::Nyse,
+                adjust: Adjustment::Splits,
+                interval: None,
+                repair: None,
+                max_jump: 0.5,
+                csv_profile: CsvProfile::Headers,
+            },
+            cmd: SubCommands::Print {
+                indicator: SupportedIndicators::RelativeStrengthIndex.into(),
+                print_signals: true,
+                plot: None,
             },
+        });
+
+        res.unwrap();
+    }
+
+    #[test]
+    fn end_to_end_print_with_parameters() {
+        let res = run_program(Opts {
+            params: jpm_params(),
             cmd: SubCommands::Print {
-                indicator: SupportedIndicators::RelativeStrengthIndex,
+                indicator: "bb:20,2.5".parse().unwrap(),
                 print_signals: true,
+                plot: None,
             },
         });
 
         res.unwrap();
+
+        // Bad parameters are rejected
+        let res = run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Print {
+                indicator: IndicatorSpec::MACD {
+                    fast: 26,
+                    slow: 12,
+                    signal: 9,
+                },
+                print_signals: true,
+                plot: None,
+            },
+        });
+        assert!(res.unwrap_err().to_string().contains("'fast'"));
+    }
+
+    #[test]
+    fn end_to_end_plot() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in &["signals.svg", "signals.png"] {
+            let path = dir.path().join(file);
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::Print {
+                    indicator: SupportedIndicators::BollingerBands.into(),
+                    print_signals: true,
+                    plot: Some(path.clone()),
+                },
+            })
+            .unwrap();
+            assert!(path.metadata().unwrap().len() > 0);
+        }
+
+        let path = dir.path().join("backtest.svg");
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Backtest {
+                trading_model: SupportedTradingModel::ManualTradingAlgo,
+                shares: Some(100.0),
+                dead_zone: Some(Signal::new(0.2)),
+                disposition: Some(Signal::new(0.0)),
+                model_file: None,
+                ensemble: None,
+                sizing: None,
+                cash: 100_000.0,
+                backtester_params: BacktesterParams::default(),
+                exit_params: ExitParams::default(),
+                plot: Some(path.clone()),
+            },
+        })
+        .unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().contains("<svg"));
+
+        // Only SVG and PNG are supported
+        assert!(run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Print {
+                indicator: SupportedIndicators::RelativeStrengthIndex.into(),
+                print_signals: false,
+                plot: Some(dir.path().join("signals.gif")),
+            },
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn end_to_end_check_data() {
+        // Missing April 14th, and April 2nd twice
+        let mut csv = NamedTempFile::new().unwrap();
+        writeln!(csv, "date,close").unwrap();
+        let rows = [(1, 10.0), (2, 11.0), (2, 11.5), (3, 12.0), (6, 12.5)];
+        let more_rows = [(7, 13.0), (8, 13.5), (9, 14.0), (13, 14.5), (15, 15.0)];
+        for (day, close) in rows.iter().chain(more_rows.iter()) {
+            writeln!(csv, "2020-04-{:02},{}", day, close).unwrap();
+        }
+
+        let with_repair = |repair, cmd| {
+            let mut params = jpm_params();
+            params.data_source = Some(SupportedDataSource::CsvFile(csv.path().to_path_buf()));
+            params.repair = repair;
+            run_program(Opts { params, cmd })
+        };
+        let check = || SubCommands::CheckData { json: true };
+        let print = || SubCommands::Print {
+            indicator: SupportedIndicators::RelativeStrengthIndex.into(),
+            print_signals: false,
+            plot: None,
+        };
+
+        assert!(with_repair(None, check()).is_ok());
+        assert!(with_repair(Some(RepairPolicy::Fail), check()).is_err());
+        assert!(with_repair(Some(RepairPolicy::Fail), print()).is_err());
+        assert!(with_repair(Some(RepairPolicy::ForwardFill), print()).is_ok());
+    }
+
+    #[test]
+    fn train_across_a_gap() {
+        // Three months of trading days, but the feed missed April 20th
+        let mut csv = NamedTempFile::new().unwrap();
+        writeln!(csv, "date,close").unwrap();
+        let days = TradingCalendar::nyse()
+            .trading_days(Date::from_ymd(2020, 3, 2), Date::from_ymd(2020, 5, 29));
+        for (i, day) in days.iter().enumerate() {
+            if *day != Date::from_ymd(2020, 4, 20) {
+                writeln!(csv, "{},{}", day, 100.0 + 10.0 * (i as f64 / 5.0).sin()).unwrap();
+            }
+        }
+
+        for algorithm in &[Algorithm::DecisionTree, Algorithm::LinearRegression] {
+            let mut params = jpm_params();
+            params.data_source = Some(SupportedDataSource::CsvFile(csv.path().to_path_buf()));
+            let file = NamedTempFile::new().unwrap();
+            run_program(Opts {
+                params,
+                cmd: SubCommands::Train {
+                    params: TrainingParams {
+                        algorithm: *algorithm,
+                        ..TrainingParams::default()
+                    },
+                    out_path: Some(file.path().to_path_buf()),
+                    paramfile: Default::default(),
+                },
+            })
+            .unwrap();
+        }
     }
 
     // TODO: test behavior of each path (mainly whether required arguments work
@@ -315,11 +1066,255 @@ mod tests {
         let _ = run_program(Opts {
             params: GeneralParams {
                 secret: None,
-                data_source: SupportedDataSource::TechalyzerJson("test/json/jpm_rsi.json".into()),
-                symbol: Symbol::new("JPM".to_string()),
+                data_source: Some(SupportedDataSource::TechalyzerJson(
+                    "test/json/jpm_rsi.json".into(),
+                )),
+                symbol: Some(Symbol::new("JPM".to_string()).into()),
                 start_date: None,
                 end_date: Some(Date::from_ymd(2020, 06, 02)),
+                offline: false,
+                refresh: false,
+                cache_dir: None,
+                calendar: CalendarSpec::Nyse,
+                adjust: Adjustment::Splits,
+                interval: None,
+                repair: None,
+                max_jump: 0.5,
+                csv_profile: CsvProfile::Headers,
+            },
+            cmd: SubCommands::Train {
+                params: TrainingParams::default(),
+                out_path: Some(file.path().to_path_buf()),
+                paramfile: Default::default(),
+            },
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn end_to_end_backtest_with_costs() {
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Backtest {
+                trading_model: SupportedTradingModel::ManualTradingAlgo,
+                shares: Some(100.0),
+                dead_zone: Some(Signal::new(0.2)),
+                disposition: Some(Signal::new(0.0)),
+                model_file: None,
+                ensemble: None,
+                sizing: None,
+                cash: 100_000.0,
+                backtester_params: BacktesterParams {
+                    commission_per_share: 0.005,
+                    fee_per_order: 1.0,
+                    slippage_bps: 5.0,
+                    ..Default::default()
+                },
+                exit_params: ExitParams {
+                    stop_loss: Some(0.05),
+                    trailing_stop: Some(0.1),
+                    ..Default::default()
+                },
+                plot: None,
+            },
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn end_to_end_portfolio_backtest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy("test/json/jpm_rsi.json", dir.path().join("jpm.json")).unwrap();
+        std::fs::copy(
+            "test/json/apha_smacrossover.json",
+            dir.path().join("apha.json"),
+        )
+        .unwrap();
+
+        let portfolio_params = |path: PathBuf| GeneralParams {
+            data_source: Some(SupportedDataSource::TechalyzerJson(path)),
+            symbol: Some("jpm,apha".parse().unwrap()),
+            ..jpm_params()
+        };
+        let backtest = || SubCommands::Backtest {
+            trading_model: SupportedTradingModel::BuyAndHold,
+            shares: None,
+            dead_zone: None,
+            disposition: None,
+            model_file: None,
+            ensemble: None,
+            sizing: None,
+            cash: 100_000.0,
+            backtester_params: BacktesterParams::default(),
+            exit_params: ExitParams::default(),
+            plot: None,
+        };
+
+        run_program(Opts {
+            params: portfolio_params(dir.path().join("{symbol}.json")),
+            cmd: backtest(),
+        })
+        .unwrap();
+
+        // Every symbol can't come from the same file
+        assert!(run_program(Opts {
+            params: portfolio_params(dir.path().join("jpm.json")),
+            cmd: backtest(),
+        })
+        .is_err());
+
+        // Only backtests take more than one symbol
+        assert!(run_program(Opts {
+            params: portfolio_params(dir.path().join("{symbol}.json")),
+            cmd: SubCommands::Print {
+                indicator: SupportedIndicators::RelativeStrengthIndex.into(),
+                print_signals: false,
+                plot: None,
+            },
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn end_to_end_suggest() {
+        // Suggest with each kind of model
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Suggest {
+                trading_model: SupportedTradingModel::BuyAndHold,
+                shares: None,
+                dead_zone: None,
+                disposition: None,
+                model_file: None,
+                ensemble: None,
+                json: false,
+                sizing: None,
+                cash: 100_000.0,
+                lot_size: 1.0,
+            },
+        })
+        .unwrap();
+
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Suggest {
+                trading_model: SupportedTradingModel::ManualTradingAlgo,
+                shares: Some(100.0),
+                dead_zone: Some(Signal::new(0.2)),
+                disposition: Some(Signal::new(0.0)),
+                model_file: None,
+                ensemble: None,
+                json: true,
+                sizing: None,
+                cash: 100_000.0,
+                lot_size: 1.0,
             },
+        })
+        .unwrap();
+
+        let file = NamedTempFile::new().unwrap();
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Train {
+                params: TrainingParams::default(),
+                out_path: Some(file.path().to_path_buf()),
+                paramfile: Default::default(),
+            },
+        })
+        .unwrap();
+
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Suggest {
+                trading_model: SupportedTradingModel::MachineLearningModel,
+                shares: None,
+                dead_zone: None,
+                disposition: None,
+                model_file: Some(file.path().to_path_buf()),
+                ensemble: None,
+                json: false,
+                sizing: None,
+                cash: 100_000.0,
+                lot_size: 1.0,
+            },
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn end_to_end_train_algorithms() {
+        for algorithm in &[
+            Algorithm::DecisionTree,
+            Algorithm::RandomForest,
+            Algorithm::LogisticRegression,
+            Algorithm::GradientBoosting,
+            Algorithm::LinearRegression,
+            Algorithm::RidgeRegression,
+            Algorithm::RegressionTree,
+        ] {
+            let file = NamedTempFile::new().unwrap();
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::Train {
+                    params: TrainingParams {
+                        algorithm: *algorithm,
+                        max_depth: Some(4),
+                        num_trees: 10,
+                        raw_outputs: true,
+                        lags: 2,
+                        return_windows: vec![5, 20],
+                        volatility_windows: vec![20],
+                        day_of_week: true,
+                        holdout: 0.25,
+                        ..TrainingParams::default()
+                    },
+                    out_path: Some(file.path().to_path_buf()),
+                    paramfile: Default::default(),
+                },
+            })
+            .unwrap();
+
+            let model = super::load_model(Some(file.path().to_path_buf())).unwrap();
+            assert_eq!(
+                model.to_string(),
+                format!("MachineLearningTrader({})", algorithm)
+            );
+        }
+    }
+
+    #[test]
+    fn price_data_needs_source_and_symbol() {
+        let argv = &["techalyzer", "jpm", "print", "-i", "rsi"];
+        let err = run_program(Opts::from_iter_safe(argv).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            super::TechalyzerError::NoDataSourceSpecified.to_string()
+        );
+
+        let argv = &[
+            "techalyzer",
+            "-d",
+            "test/json/jpm_rsi.json",
+            "print",
+            "-i",
+            "rsi",
+        ];
+        let err = run_program(Opts::from_iter_safe(argv).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            super::TechalyzerError::NoSymbolSpecified.to_string()
+        );
+    }
+
+    #[test]
+    fn end_to_end_model_info() {
+        let file = NamedTempFile::new().unwrap();
+        run_program(Opts {
+            params: jpm_params(),
             cmd: SubCommands::Train {
                 params: TrainingParams::default(),
                 out_path: Some(file.path().to_path_buf()),
@@ -327,6 +1322,287 @@ mod tests {
             },
         })
         .unwrap();
+
+        let path = file.path().to_str().unwrap();
+        for argv in &[
+            vec!["techalyzer", "model-info", path],
+            vec!["techalyzer", "model-info", "--json", path],
+        ] {
+            // No data source or symbol needed
+            run_program(Opts::from_iter_safe(argv).unwrap()).unwrap();
+        }
+
+        // Models saved before there were model files can't be read
+        let model = super::load_model(Some(file.path().to_path_buf())).unwrap();
+        let bare = NamedTempFile::new().unwrap();
+        bincode::serialize_into(bare.as_file(), &model).unwrap();
+        let err = super::load_model(Some(bare.path().to_path_buf()))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Not a Techalyzer model file"));
+        let info = SubCommands::ModelInfo {
+            model_file: bare.path().to_path_buf(),
+            json: false,
+        };
+        assert!(run_program(Opts {
+            params: jpm_params(),
+            cmd: info,
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn end_to_end_tune() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::from_iter_safe(&[
+                "techalyzer",
+                "tune",
+                "--algorithm",
+                "gbt",
+                "--num-trees",
+                "5",
+                "--folds",
+                "3",
+                "--embargo",
+                "2",
+                "--depths",
+                "1,3",
+                "--min-samples-leaves",
+                "1,10",
+                "-o",
+                path,
+            ])
+            .unwrap(),
+        })
+        .unwrap();
+
+        // The best model is saved, with one of the depths searched
+        let model = super::load_model(Some(file.path().to_path_buf())).unwrap();
+        assert_eq!(
+            model.to_string(),
+            "MachineLearningTrader(gradient_boosting)"
+        );
+        let bytes = std::fs::read(file.path()).unwrap();
+        let (header, _) = modelfile::read_header(&bytes).unwrap();
+        let metadata = match header.metadata {
+            Metadata::MachineLearning(m) => m,
+            Metadata::QLearning(_) => panic!("expected a machine learning model"),
+        };
+        let depth = metadata.hyperparameters.max_depth;
+        assert!(depth == Some(1) || depth == Some(3));
+        assert_eq!(metadata.hyperparameters.num_trees, 5);
+
+        // The first expanding window is all purged
+        let argv = [
+            "techalyzer",
+            "tune",
+            "--cross-validation",
+            "expanding",
+            "--folds",
+            "50",
+            "-o",
+            path,
+        ];
+        assert!(run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::from_iter_safe(&argv).unwrap(),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn end_to_end_q_learner() {
+        let file = NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::from_iter_safe(&[
+                "techalyzer",
+                "train-q-learner",
+                "--signal-generators",
+                "macd rsi",
+                "--bins",
+                "3",
+                "--epochs",
+                "10",
+                "--dyna",
+                "5",
+                "-o",
+                path,
+            ])
+            .unwrap(),
+        })
+        .unwrap();
+
+        let bytes = std::fs::read(file.path()).unwrap();
+        let (header, _) = modelfile::read_header(&bytes).unwrap();
+        match header.metadata {
+            Metadata::QLearning(m) => {
+                assert_eq!(m.hyperparameters.bins, 3);
+                assert_eq!(m.hyperparameters.dyna, 5);
+                assert_eq!(m.signal_generators.len(), 2);
+            }
+            Metadata::MachineLearning(_) => panic!("expected a Q-learning model"),
+        }
+
+        for subcommand in &["suggest", "backtest"] {
+            let mut argv = vec!["techalyzer", subcommand, "QLearningModel", "-m", path];
+            if *subcommand == "backtest" {
+                argv.push("100000");
+            }
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::from_iter_safe(&argv).unwrap(),
+            })
+            .unwrap();
+        }
+
+        // Each kind of model only loads as that kind
+        let err = super::load_model(Some(file.path().to_path_buf()))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("holds a Q-learning model"));
+
+        // A model file is needed
+        let argv = ["techalyzer", "suggest", "QLearningModel"];
+        assert!(SubCommands::from_iter_safe(&argv).is_err());
+    }
+
+    #[test]
+    fn end_to_end_ensemble() {
+        let dir = tempfile::tempdir().unwrap();
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Train {
+                params: TrainingParams::default(),
+                out_path: Some(dir.path().join("jpm.bin")),
+                paramfile: Default::default(),
+            },
+        })
+        .unwrap();
+
+        let config = dir.path().join("ensemble.json");
+        let members = r#"[
+            { "model": "machine_learning", "model_file": "jpm.bin", "weight": 2.0 },
+            { "model": "manual", "shares": 100, "dead_zone": 0.2, "disposition": 0.0 },
+            { "model": "buy_and_hold" }
+        ]"#;
+        for method in &["majority", "weighted", "stacking"] {
+            let json = format!(r#"{{ "method": "{}", "members": {} }}"#, method, members);
+            std::fs::write(&config, json).unwrap();
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::Backtest {
+                    trading_model: SupportedTradingModel::Ensemble,
+                    shares: None,
+                    dead_zone: None,
+                    disposition: None,
+                    model_file: None,
+                    ensemble: Some(config.clone()),
+                    sizing: None,
+                    cash: 100_000.0,
+                    backtester_params: BacktesterParams::default(),
+                    exit_params: ExitParams::default(),
+                    plot: None,
+                },
+            })
+            .unwrap();
+        }
+
+        run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Suggest {
+                trading_model: SupportedTradingModel::Ensemble,
+                shares: None,
+                dead_zone: None,
+                disposition: None,
+                model_file: None,
+                ensemble: Some(config),
+                json: true,
+                sizing: None,
+                cash: 100_000.0,
+                lot_size: 1.0,
+            },
+        })
+        .unwrap();
+
+        // The model files have to exist
+        std::fs::remove_file(dir.path().join("jpm.bin")).unwrap();
+        assert!(run_program(Opts {
+            params: jpm_params(),
+            cmd: SubCommands::Suggest {
+                trading_model: SupportedTradingModel::Ensemble,
+                shares: None,
+                dead_zone: None,
+                disposition: None,
+                model_file: None,
+                ensemble: Some(dir.path().join("ensemble.json")),
+                json: true,
+                sizing: None,
+                cash: 100_000.0,
+                lot_size: 1.0,
+            },
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn end_to_end_walkforward() {
+        let walk = |train_days| {
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::Walkforward {
+                    params: TrainingParams::default(),
+                    paramfile: None,
+                    walk_forward_params: WalkForwardParams {
+                        train_days,
+                        test_days: 20,
+                        anchored: false,
+                    },
+                    cash: 100_000.0,
+                    backtester_params: BacktesterParams::default(),
+                },
+            })
+        };
+
+        walk(40).unwrap();
+
+        // Not enough data for even one fold
+        assert!(walk(100).is_err());
+    }
+
+    #[test]
+    fn end_to_end_optimize() {
+        let optimize = |args: &[&str]| {
+            let mut argv = vec!["techalyzer", "optimize", "--csv"];
+            argv.extend_from_slice(args);
+            argv.push("100000");
+            run_program(Opts {
+                params: jpm_params(),
+                cmd: SubCommands::from_iter_safe(argv).unwrap(),
+            })
+        };
+
+        optimize(&[
+            "--shares",
+            "100,1000",
+            "--dead-zone",
+            "0.0..0.2/0.1",
+            "--indicators",
+            "rsi:10..14/2",
+            "bb:20,1.5..2.5/0.5",
+            "--objective",
+            "sharpe",
+            "--samples",
+            "5",
+        ])
+        .unwrap();
+
+        // Dead zone outside of a signal's range
+        assert!(optimize(&["--dead-zone", "2.0"]).is_err());
     }
 
     #[test]
@@ -0,0 +1,543 @@
+//! Exchange calendars, for counting in trading days rather than calendar days
+//! or rows of price data (which can have gaps), and for finding the trading
+//! session an intraday bar belongs to.
+
+use crate::{
+    backtester::statistics::{CALENDAR_DAYS_PER_YEAR, TRADING_DAYS_PER_YEAR},
+    date::Date,
+    marketdata::prices::Prices,
+};
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeSet, convert::Infallible, fmt::Display, fs::File, io::BufReader,
+    path::PathBuf, str::FromStr,
+};
+use thiserror::Error;
+
+/// Days the NYSE closed outside of its regular holidays.
+const NYSE_SPECIAL_CLOSURES: [(i32, u32, u32); 10] = [
+    (2001, 9, 11), // September 11th attacks
+    (2001, 9, 12),
+    (2001, 9, 13),
+    (2001, 9, 14),
+    (2004, 6, 11),  // Funeral of Ronald Reagan
+    (2007, 1, 2),   // Funeral of Gerald Ford
+    (2012, 10, 29), // Hurricane Sandy
+    (2012, 10, 30),
+    (2018, 12, 5), // Funeral of George H.W. Bush
+    (2025, 1, 9),  // Funeral of Jimmy Carter
+];
+
+/// Errors loading a TradingCalendar.
+#[derive(Debug, Error)]
+pub enum CalendarError {
+    #[error("Could not open calendar file '{path}': {reason}")]
+    FileError { path: String, reason: String },
+
+    #[error("Invalid calendar file '{path}': {reason}")]
+    InvalidFile { path: String, reason: String },
+}
+
+/// The hours an exchange trades each trading day, in its local time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Time zone of the exchange, by its IANA name (e.g. `America/New_York`).
+    #[serde(serialize_with = "tz_name", deserialize_with = "tz_from_name")]
+    pub timezone: Tz,
+
+    pub open: NaiveTime,
+
+    /// If this is not after `open`, the session ends the next day (or, if it
+    /// is the same, runs around the clock).
+    pub close: NaiveTime,
+}
+
+fn tz_name<S: Serializer>(tz: &Tz, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(tz.name())
+}
+
+fn tz_from_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Tz, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse().map_err(serde::de::Error::custom)
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::nyse()
+    }
+}
+
+impl Session {
+    /// The NYSE's regular trading hours, 9:30 AM to 4 PM Eastern.
+    pub fn nyse() -> Self {
+        Self {
+            timezone: Tz::America__New_York,
+            open: NaiveTime::from_hms(9, 30, 0),
+            close: NaiveTime::from_hms(16, 0, 0),
+        }
+    }
+
+    /// Trading around the clock, with days starting at midnight UTC.
+    pub fn all_day() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            open: NaiveTime::from_hms(0, 0, 0),
+            close: NaiveTime::from_hms(0, 0, 0),
+        }
+    }
+
+    pub fn length(&self) -> Duration {
+        let length = self.close - self.open;
+        if length > Duration::zero() {
+            length
+        } else {
+            length + Duration::days(1)
+        }
+    }
+
+    /// When the session of `day` opens.
+    pub fn start(&self, day: Date) -> Date {
+        let open = NaiveDate::from(day).and_time(self.open);
+        // If the open was skipped by a DST change, it's an hour later.
+        Date::from_local(&open, &self.timezone)
+            .or_else(|| Date::from_local(&(open + Duration::hours(1)), &self.timezone))
+            .expect("Session open does not exist")
+    }
+}
+
+/// The days an exchange is open for trading, and its hours on those days.
+///
+/// Custom calendars are JSON files, e.g. `{ "nyse_holidays": true, "holidays":
+/// ["2020-12-24"] }` for the NYSE with an extra day off. The session defaults
+/// to the NYSE's, and can be set with e.g. `"session": { "timezone":
+/// "Europe/London", "open": "08:00:00", "close": "16:30:00" }`, and the
+/// number of trading days statistics are annualized with by `"days_per_year":
+/// 260`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TradingCalendar {
+    /// Closed on Saturdays and Sundays.
+    #[serde(default = "closed_on_weekends")]
+    pub weekends_closed: bool,
+
+    /// Closed on NYSE holidays (and its special closures).
+    #[serde(default)]
+    pub nyse_holidays: bool,
+
+    /// Any other days the exchange is closed.
+    #[serde(default)]
+    pub holidays: BTreeSet<Date>,
+
+    /// Trading hours of each trading day.
+    #[serde(default)]
+    pub session: Session,
+
+    /// Trading days in a year, if not the usual for the days the exchange is
+    /// open (see `trading_days_per_year`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days_per_year: Option<f64>,
+}
+
+fn closed_on_weekends() -> bool {
+    true
+}
+
+impl Default for TradingCalendar {
+    fn default() -> Self {
+        Self::nyse()
+    }
+}
+
+impl TradingCalendar {
+    /// The New York Stock Exchange, with holidays as observed since 1998 (when
+    /// it started closing for Martin Luther King Jr. Day).
+    pub fn nyse() -> Self {
+        Self {
+            weekends_closed: true,
+            nyse_holidays: true,
+            holidays: BTreeSet::new(),
+            session: Session::nyse(),
+            days_per_year: None,
+        }
+    }
+
+    /// Open every weekday, around the clock.
+    pub fn weekdays() -> Self {
+        Self {
+            weekends_closed: true,
+            nyse_holidays: false,
+            holidays: BTreeSet::new(),
+            session: Session::all_day(),
+            days_per_year: None,
+        }
+    }
+
+    /// Open every day of the year, around the clock.
+    pub fn every_day() -> Self {
+        Self {
+            weekends_closed: false,
+            nyse_holidays: false,
+            holidays: BTreeSet::new(),
+            session: Session::all_day(),
+            days_per_year: None,
+        }
+    }
+
+    /// Trading days in a year, for annualizing statistics: `days_per_year` if
+    /// it was given, otherwise 365 for exchanges open on weekends and 252 for
+    /// the rest.
+    pub fn trading_days_per_year(&self) -> f64 {
+        match self.days_per_year {
+            Some(days) => days,
+            None if self.weekends_closed => TRADING_DAYS_PER_YEAR,
+            None => CALENDAR_DAYS_PER_YEAR,
+        }
+    }
+
+    /// True if the day `date` falls on is a trading day.
+    pub fn is_trading_day(&self, date: Date) -> bool {
+        let day: NaiveDate = date.into();
+        let weekend = matches!(day.weekday(), Weekday::Sat | Weekday::Sun);
+        !(self.weekends_closed && weekend
+            || self.nyse_holidays && is_nyse_holiday(day)
+            || self.holidays.contains(&date.day()))
+    }
+
+    /// The first trading day after `date`.
+    pub fn next_trading_day(&self, date: Date) -> Date {
+        let mut day = date + Duration::days(1);
+        while !self.is_trading_day(day) {
+            day = day + Duration::days(1);
+        }
+        day
+    }
+
+    /// The last trading day before `date`.
+    pub fn previous_trading_day(&self, date: Date) -> Date {
+        let mut day = date - Duration::days(1);
+        while !self.is_trading_day(day) {
+            day = day - Duration::days(1);
+        }
+        day
+    }
+
+    /// The trading day `n` trading days after `date`.
+    pub fn add_trading_days(&self, date: Date, n: u32) -> Date {
+        (0..n).fold(date, |day, _| self.next_trading_day(day))
+    }
+
+    /// The trading day `n` trading days before `date`.
+    pub fn sub_trading_days(&self, date: Date, n: u32) -> Date {
+        (0..n).fold(date, |day, _| self.previous_trading_day(day))
+    }
+
+    /// Every trading day from the day of `start` to the day of `end`,
+    /// inclusive.
+    pub fn trading_days(&self, start: Date, end: Date) -> Vec<Date> {
+        let mut days = Vec::new();
+        let mut day = start.day();
+        while day <= end.day() {
+            if self.is_trading_day(day) {
+                days.push(day);
+            }
+            day = day + Duration::days(1);
+        }
+        days
+    }
+
+    /// Trading days between the first and last day of `prices` that have no
+    /// price data.
+    pub fn missing_days(&self, prices: &Prices) -> Vec<Date> {
+        let days: BTreeSet<Date> = prices
+            .map
+            .keys()
+            .filter_map(|t| self.session_day(*t))
+            .collect();
+        match (days.iter().next(), days.iter().next_back()) {
+            (Some(first), Some(last)) => self
+                .trading_days(*first, *last)
+                .into_iter()
+                .filter(|d| !days.contains(d))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The trading day whose session `time` is in, if any. Whole days (see
+    /// `Date::is_day`) are in their own session if they are trading days.
+    pub fn session_day(&self, time: Date) -> Option<Date> {
+        if time.is_day() {
+            return Some(time).filter(|d| self.is_trading_day(*d));
+        }
+
+        // The session that opened most recently before `time`
+        let local = time.with_timezone(&self.session.timezone);
+        let mut day = local.day();
+        if self.session.start(day) > time {
+            day = day - Duration::days(1);
+        }
+
+        let start = self.session.start(day);
+        Some(day).filter(|d| self.is_trading_day(*d) && time - start < self.session.length())
+    }
+}
+
+/// A holiday falling on a weekend is observed on the Friday before or the
+/// Monday after.
+fn observed(day: NaiveDate) -> NaiveDate {
+    match day.weekday() {
+        Weekday::Sat => day - Duration::days(1),
+        Weekday::Sun => day + Duration::days(1),
+        _ => day,
+    }
+}
+
+/// The `n`th `weekday` of a month (counting from 1).
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u32) -> NaiveDate {
+    NaiveDate::from_weekday_of_month(year, month, weekday, n as u8)
+}
+
+/// The last `weekday` of a month.
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    let next_month = if month == 12 {
+        NaiveDate::from_ymd(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(year, month + 1, 1)
+    };
+    let mut day = next_month - Duration::days(1);
+    while day.weekday() != weekday {
+        day -= Duration::days(1);
+    }
+    day
+}
+
+/// Easter Sunday in the Gregorian calendar (the anonymous Gregorian
+/// algorithm).
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd(year, month as u32, day as u32)
+}
+
+fn is_nyse_holiday(day: NaiveDate) -> bool {
+    let year = day.year();
+    let ymd = |m, d| NaiveDate::from_ymd(year, m, d);
+
+    // New Year's Day falling on a Saturday isn't observed on the Friday before.
+    let new_years = ymd(1, 1);
+    let mut holidays = vec![
+        nth_weekday(year, 2, Weekday::Mon, 3),  // Washington's Birthday
+        easter(year) - Duration::days(2),       // Good Friday
+        last_weekday(year, 5, Weekday::Mon),    // Memorial Day
+        observed(ymd(7, 4)),                    // Independence Day
+        nth_weekday(year, 9, Weekday::Mon, 1),  // Labor Day
+        nth_weekday(year, 11, Weekday::Thu, 4), // Thanksgiving
+        observed(ymd(12, 25)),                  // Christmas
+    ];
+    if new_years.weekday() != Weekday::Sat {
+        holidays.push(observed(new_years));
+    }
+    if year >= 1998 {
+        holidays.push(nth_weekday(year, 1, Weekday::Mon, 3)); // Martin Luther King Jr. Day
+    }
+    if year >= 2022 {
+        holidays.push(observed(ymd(6, 19))); // Juneteenth
+    }
+
+    holidays.contains(&day)
+        || NYSE_SPECIAL_CLOSURES
+            .iter()
+            .any(|&(y, m, d)| NaiveDate::from_ymd(y, m, d) == day)
+}
+
+/// A built-in calendar or the path to a calendar file, as given on the
+/// command line: `nyse`, `weekdays`, `every-day` (or `crypto`) or a path to a
+/// JSON file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(from = "String", into = "String")]
+pub enum CalendarSpec {
+    #[default]
+    Nyse,
+    Weekdays,
+    EveryDay,
+    File(PathBuf),
+}
+
+impl CalendarSpec {
+    pub fn load(&self) -> Result<TradingCalendar, CalendarError> {
+        let path = match self {
+            Self::Nyse => return Ok(TradingCalendar::nyse()),
+            Self::Weekdays => return Ok(TradingCalendar::weekdays()),
+            Self::EveryDay => return Ok(TradingCalendar::every_day()),
+            Self::File(path) => path,
+        };
+
+        let file = File::open(path).map_err(|e| CalendarError::FileError {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| CalendarError::InvalidFile {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })
+    }
+}
+
+impl FromStr for CalendarSpec {
+    type Err = Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "nyse" => Self::Nyse,
+            "weekdays" => Self::Weekdays,
+            "every-day" | "24/7" | "crypto" => Self::EveryDay,
+            _ => Self::File(PathBuf::from(s)),
+        })
+    }
+}
+
+impl Display for CalendarSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nyse => write!(f, "nyse"),
+            Self::Weekdays => write!(f, "weekdays"),
+            Self::EveryDay => write!(f, "every-day"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl From<String> for CalendarSpec {
+    fn from(s: String) -> Self {
+        match s.parse() {
+            Ok(spec) => spec,
+            Err(infallible) => match infallible {},
+        }
+    }
+}
+
+impl From<CalendarSpec> for String {
+    fn from(spec: CalendarSpec) -> Self {
+        spec.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn ymd(y: i32, m: u32, d: u32) -> Date {
+        Date::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn nyse_holidays() {
+        let nyse = TradingCalendar::nyse();
+
+        // Every NYSE holiday in 2020, plus a Saturday
+        let closed = [
+            ymd(2020, 1, 1),
+            ymd(2020, 1, 20),
+            ymd(2020, 2, 17),
+            ymd(2020, 4, 10),
+            ymd(2020, 5, 25),
+            ymd(2020, 7, 3),
+            ymd(2020, 9, 7),
+            ymd(2020, 11, 26),
+            ymd(2020, 12, 25),
+            ymd(2020, 12, 26),
+        ];
+        assert!(closed.iter().all(|d| !nyse.is_trading_day(*d)));
+        assert_eq!(
+            nyse.trading_days(ymd(2020, 1, 1), ymd(2020, 12, 31)).len(),
+            253
+        );
+
+        // Juneteenth (observed on a Monday), a special closure, and New Year's
+        // Day on a Saturday, which isn't observed on the Friday before.
+        assert!(!nyse.is_trading_day(ymd(2022, 6, 20)));
+        assert!(!nyse.is_trading_day(ymd(2012, 10, 29)));
+        assert!(nyse.is_trading_day(ymd(2021, 12, 31)));
+
+        assert!(TradingCalendar::weekdays().is_trading_day(ymd(2020, 12, 25)));
+        assert!(TradingCalendar::every_day().is_trading_day(ymd(2020, 12, 26)));
+    }
+
+    #[test]
+    fn trading_day_arithmetic() {
+        let nyse = TradingCalendar::nyse();
+
+        // Thursday before Good Friday, over the long weekend
+        assert_eq!(nyse.add_trading_days(ymd(2020, 4, 9), 1), ymd(2020, 4, 13));
+        assert_eq!(nyse.add_trading_days(ymd(2020, 4, 9), 5), ymd(2020, 4, 17));
+        assert_eq!(nyse.sub_trading_days(ymd(2020, 4, 13), 1), ymd(2020, 4, 9));
+        assert_eq!(nyse.add_trading_days(ymd(2020, 4, 9), 0), ymd(2020, 4, 9));
+
+        // Gaps are trading days with no data
+        let mut prices = Prices::from_closes("jpm", Default::default());
+        for day in nyse.trading_days(ymd(2020, 4, 6), ymd(2020, 4, 17)) {
+            if day != ymd(2020, 4, 14) {
+                prices.map.insert(day, 1.0.into());
+            }
+        }
+        assert_eq!(nyse.missing_days(&prices), vec![ymd(2020, 4, 14)]);
+    }
+
+    #[test]
+    fn custom_calendar_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"{{ "nyse_holidays": true, "holidays": ["2020-12-24"] }}"#
+        )
+        .unwrap();
+
+        let spec: CalendarSpec = file.path().to_str().unwrap().parse().unwrap();
+        let calendar = spec.load().unwrap();
+        assert!(calendar.weekends_closed);
+        assert!(!calendar.is_trading_day(ymd(2020, 12, 24)));
+        assert!(!calendar.is_trading_day(ymd(2020, 12, 25)));
+
+        assert_eq!(calendar.session, Session::nyse());
+        assert_eq!(calendar.trading_days_per_year(), 252.0);
+
+        // A session of its own
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            r#"{{ "session": {{ "timezone": "Europe/London", "open": "08:00:00", "close": "16:30:00" }} }}"#
+        )
+        .unwrap();
+        let spec = CalendarSpec::File(file.path().into());
+        let london = spec.load().unwrap().session;
+        assert_eq!(london.timezone, Tz::Europe__London);
+        assert_eq!(london.length(), Duration::minutes(510));
+
+        // Annualizing over more (or fewer) days than usual
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, r#"{{ "days_per_year": 260 }}"#).unwrap();
+        let spec = CalendarSpec::File(file.path().into());
+        assert_eq!(spec.load().unwrap().trading_days_per_year(), 260.0);
+        assert_eq!(TradingCalendar::every_day().trading_days_per_year(), 365.0);
+
+        let missing = CalendarSpec::File("no/such/calendar.json".into());
+        assert!(matches!(
+            missing.load(),
+            Err(CalendarError::FileError { .. })
+        ));
+        assert_eq!("NYSE".parse(), Ok(CalendarSpec::Nyse));
+    }
+}
@@ -1,14 +1,36 @@
 //! Configuration formats and parameters for various aspects of Techalyzer.
 
 use crate::{
-    datasource::SupportedDataSource,
+    calendar::CalendarSpec,
+    datasource::{
+        cache::{default_cache_dir, CacheConfig, CacheMode},
+        csv::CsvProfile,
+        SupportedDataSource,
+    },
     date::{today, Date},
-    indicators::{ListOfIndicators, SupportedIndicators},
-    trading::dtmodel::{DecisionThreshold, Horizon},
-    util::{Symbol, ToJson},
+    indicators::{IndicatorSpec, ListOfIndicators, SupportedIndicators},
+    marketdata::{
+        actions::Adjustment,
+        resample::Interval,
+        validation::{RepairPolicy, DEFAULT_MAX_JUMP},
+    },
+    optimizer::{IndicatorRange, Objective, ParamRange},
+    signals::Signal,
+    trading::{
+        dtmodel::{DecisionThreshold, Horizon},
+        ensemble::{EnsembleMethod, DEFAULT_STACKING_HORIZON, DEFAULT_STACKING_MIN_TRAIN},
+        exits::ExitRules,
+        features::FeatureSet,
+        labeling::Labeler,
+        ml::{Algorithm, Hyperparameters},
+        qlearner::QHyperparameters,
+        sizing::SizingPolicy,
+    },
+    tuning::{CrossValidation, HyperparameterGrid},
+    util::{Symbols, ToJson},
 };
 use serde::{Deserialize, Serialize};
-use std::{convert::Infallible, str::FromStr};
+use std::{convert::Infallible, path::PathBuf, str::FromStr};
 use structopt::StructOpt;
 
 // TODO: link to these as the central source of truth for the frontend args
@@ -26,13 +48,17 @@ pub struct GeneralParams {
 
     // TODO: it'd be better for error display if a data source were
     // selected as mutually exclusive flags (e.g. --file-data and --api-data)
-    /// Where to get stock data from
+    /// Where to get stock data from. Every subcommand except `model-info`
+    /// needs one.
     #[structopt(long, short)]
-    pub data_source: SupportedDataSource,
+    #[serde(default)]
+    pub data_source: Option<SupportedDataSource>,
 
-    /// The symbol of the security to analyze
+    /// The symbol of the security to analyze. Backtests also accept several
+    /// symbols separated by commas (e.g. `jpm,aapl`) to test a portfolio.
     #[structopt()]
-    pub symbol: Symbol,
+    #[serde(default)]
+    pub symbol: Option<Symbols>,
 
     /// Start date of the analysis. Defaults to the earliest possible date.
     #[structopt(long, short, parse(try_from_str = parse_date))]
@@ -44,12 +70,88 @@ pub struct GeneralParams {
     #[structopt(long, short, parse(try_from_str = parse_date))]
     #[serde(default)]
     pub end_date: Option<Date>,
+
+    /// Only use price data that has already been downloaded and cached.
+    #[structopt(long, conflicts_with = "refresh")]
+    #[serde(default)]
+    pub offline: bool,
+
+    /// Download price data again, even if it is already cached.
+    #[structopt(long)]
+    #[serde(default)]
+    pub refresh: bool,
+
+    /// Where to cache downloaded price data. Defaults to
+    /// `$XDG_CACHE_HOME/techalyzer`.
+    #[structopt(long)]
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Trading calendar that horizons and windows are counted in: `nyse`,
+    /// `weekdays`, `every-day` (or `crypto`), or a JSON calendar file. It also
+    /// decides how many trading days there are in a year (252, or 365 for
+    /// `every-day`) for annualizing statistics.
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub calendar: CalendarSpec,
+
+    /// Resample the price data to bars of this length, e.g. `15m`, `1h`, or
+    /// `1d`. Bars are kept within the trading sessions of the calendar.
+    #[structopt(long)]
+    #[serde(default)]
+    pub interval: Option<Interval>,
+
+    /// Back-adjust prices for the splits and dividends in the price data:
+    /// `splits` (dividends are then paid out in backtests), `all`, or `none`.
+    /// Only CSV files (with split and dividend columns) and Techalyzer JSON
+    /// files carry them. Alpha Vantage data has none, so this does nothing to
+    /// it, and `check-data` reports its splits as jumps.
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub adjust: Adjustment,
+
+    /// Repair problems found in the price data before using it: `ffill`,
+    /// `interpolate`, `drop`, or `fail` if there are any. See `check-data`.
+    #[structopt(long)]
+    #[serde(default)]
+    pub repair: Option<RepairPolicy>,
+
+    /// Largest believable one-day price change (0.5 = 50%). Bigger moves are
+    /// reported as bad ticks (if undone the next day) or jumps.
+    #[structopt(long, default_value = "0.5")]
+    #[serde(default = "default_max_jump")]
+    pub max_jump: f64,
+
+    /// How the columns of CSV files are laid out: `headers` (named by a header
+    /// row) or `exchange` (headerless candlestick exports from crypto
+    /// exchanges like Binance and Kraken).
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub csv_profile: CsvProfile,
+}
+
+fn default_max_jump() -> f64 {
+    DEFAULT_MAX_JUMP
+}
+
+impl GeneralParams {
+    /// How to cache downloaded price data.
+    pub fn cache_config(&self) -> CacheConfig {
+        CacheConfig {
+            dir: self.cache_dir.clone().unwrap_or_else(default_cache_dir),
+            mode: CacheMode::from_flags(self.offline, self.refresh),
+        }
+    }
 }
 
 impl ToJson for GeneralParams {}
 
-/// Parameters when running the Train command.
-#[derive(Debug, StructOpt, Deserialize, Serialize, PartialEq)]
+// Parameters when running the Train command.
+//
+// The parameter structs that get flattened into subcommands use plain comments
+// rather than doc comments: structopt would otherwise apply their doc comment
+// as the `about` of the subcommand, clobbering the subcommand's own help.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, PartialEq)]
 pub struct TrainingParams {
     /// Start date of the training dataset. Defaults to the beginning of the
     /// dataset.
@@ -74,10 +176,177 @@ pub struct TrainingParams {
     #[serde(default)]
     pub decision_threshold: DecisionThreshold,
 
+    /// How to label each training day from the prices up to the horizon:
+    /// `fixed` (the return at the horizon against the decision threshold),
+    /// `triple-barrier[:<profit take>,<stop loss>]` (whichever of the
+    /// threshold times these multiples is crossed first), `vol[:<multiplier>,
+    /// <window>]` (the return at the horizon against a threshold scaled to the
+    /// recent volatility of daily returns) or `mfe` (the larger of the best
+    /// and worst moves before the horizon).
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub labeler: Labeler,
+
     /// Which technical indicators to use to generate features for the learner.
     #[structopt(long, short, default_value)]
     #[serde(default)]
     pub signal_generators: ListOfIndicators,
+
+    /// How the model sizes its trades: `fixed:<shares>`, `cash:<fraction>`,
+    /// `vol:<target>[,<window>]`, `kelly:<fraction>[,<window>]` or
+    /// `signal:<max shares>`. Cash is sized against when the model is used.
+    /// Positions are sized when opened, and held until the side changes.
+    /// 1000 fixed shares by default, except for the regressors, which default
+    /// to `signal:1000` so that larger predicted returns buy more shares.
+    #[structopt(long)]
+    #[serde(default)]
+    pub sizing: Option<SizingPolicy>,
+
+    /// Which learner to train: the classifiers `decision_tree`,
+    /// `random_forest`, `logistic_regression` and `gradient_boosting`, or the
+    /// regressors `linear_regression`, `ridge_regression` and
+    /// `regression_tree`, which predict the return at the horizon (the
+    /// labeler is then unused).
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub algorithm: Algorithm,
+
+    /// Deepest the trees may grow. Unlimited by default, except for gradient
+    /// boosting which defaults to 3.
+    #[structopt(long)]
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+
+    /// Fewest training days a tree node needs before it is split.
+    #[structopt(long, default_value = "2")]
+    #[serde(default = "default_min_samples_split")]
+    pub min_samples_split: usize,
+
+    /// Fewest training days each side of a split must get. Only gradient
+    /// boosting honours it.
+    #[structopt(long, default_value = "1")]
+    #[serde(default = "default_min_samples_leaf")]
+    pub min_samples_leaf: usize,
+
+    /// Trees in a random forest, or rounds of gradient boosting.
+    #[structopt(long, default_value = "100")]
+    #[serde(default = "default_num_trees")]
+    pub num_trees: usize,
+
+    /// Step size of gradient boosting and logistic regression.
+    #[structopt(long, default_value = "0.1")]
+    #[serde(default = "default_learning_rate")]
+    pub learning_rate: f64,
+
+    /// How strongly ridge regression pulls its weights towards 0.
+    #[structopt(long, default_value = "1")]
+    #[serde(default = "default_ridge_penalty")]
+    pub ridge_penalty: f64,
+
+    /// Fraction of the training days, at the end, to first hold out and score
+    /// a model trained on the rest on. The saved model is still trained on
+    /// every day. 0 skips the report.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub holdout: f64,
+
+    /// Also learn from the raw outputs of the indicators (e.g. the MACD
+    /// histogram or the RSI level).
+    #[structopt(long)]
+    #[serde(default)]
+    pub raw_outputs: bool,
+
+    /// Also learn from each indicator's signals from 1 up to this many days
+    /// ago.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub lags: usize,
+
+    /// Also learn from the returns over each of these many days, separated by
+    /// commas (e.g. `5,20`).
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub return_windows: Vec<usize>,
+
+    /// Also learn from the volatility of daily returns over each of these many
+    /// days, separated by commas.
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub volatility_windows: Vec<usize>,
+
+    /// Also learn from the day of the week.
+    #[structopt(long)]
+    #[serde(default)]
+    pub day_of_week: bool,
+}
+
+fn default_min_samples_split() -> usize {
+    Hyperparameters::default().min_samples_split
+}
+
+fn default_min_samples_leaf() -> usize {
+    Hyperparameters::default().min_samples_leaf
+}
+
+fn default_num_trees() -> usize {
+    Hyperparameters::default().num_trees
+}
+
+fn default_learning_rate() -> f64 {
+    Hyperparameters::default().learning_rate
+}
+
+fn default_ridge_penalty() -> f64 {
+    Hyperparameters::default().ridge_penalty
+}
+
+impl From<&TrainingParams> for Hyperparameters {
+    fn from(params: &TrainingParams) -> Self {
+        Self {
+            max_depth: params.max_depth,
+            min_samples_split: params.min_samples_split,
+            min_samples_leaf: params.min_samples_leaf,
+            num_trees: params.num_trees,
+            learning_rate: params.learning_rate,
+            ridge_penalty: params.ridge_penalty,
+        }
+    }
+}
+
+impl TrainingParams {
+    /// The same parameters with `hyperparameters` instead.
+    pub fn with_hyperparameters(&self, hyperparameters: &Hyperparameters) -> Self {
+        Self {
+            max_depth: hyperparameters.max_depth,
+            min_samples_split: hyperparameters.min_samples_split,
+            min_samples_leaf: hyperparameters.min_samples_leaf,
+            num_trees: hyperparameters.num_trees,
+            learning_rate: hyperparameters.learning_rate,
+            ridge_penalty: hyperparameters.ridge_penalty,
+            ..self.clone()
+        }
+    }
+
+    /// How the model sizes its trades, defaulting by algorithm.
+    pub fn sizing(&self) -> SizingPolicy {
+        match self.sizing {
+            Some(sizing) => sizing,
+            None if self.algorithm.is_regression() => SizingPolicy::SignalProportional(1000.0),
+            None => SizingPolicy::default(),
+        }
+    }
+}
+
+impl From<&TrainingParams> for FeatureSet {
+    fn from(params: &TrainingParams) -> Self {
+        Self {
+            outputs: params.raw_outputs,
+            lags: params.lags,
+            returns: params.return_windows.clone(),
+            volatility: params.volatility_windows.clone(),
+            day_of_week: params.day_of_week,
+        }
+    }
 }
 
 impl ToJson for TrainingParams {}
@@ -94,15 +363,30 @@ impl Default for TrainingParams {
     fn default() -> Self {
         Self {
             signal_generators: ListOfIndicators(vec![
-                SupportedIndicators::RelativeStrengthIndex,
-                SupportedIndicators::BollingerBands,
-                SupportedIndicators::MACD,
+                SupportedIndicators::RelativeStrengthIndex.into(),
+                SupportedIndicators::BollingerBands.into(),
+                SupportedIndicators::MACD.into(),
             ]),
             train_start_date: None,
             train_end_date: None,
             // train_end_date: Some(Date::default()),
             horizon: 10.into(),
             decision_threshold: 0.03.into(),
+            labeler: Labeler::default(),
+            sizing: None,
+            algorithm: Algorithm::default(),
+            max_depth: None,
+            min_samples_split: default_min_samples_split(),
+            min_samples_leaf: default_min_samples_leaf(),
+            num_trees: default_num_trees(),
+            learning_rate: default_learning_rate(),
+            ridge_penalty: default_ridge_penalty(),
+            holdout: 0.0,
+            raw_outputs: false,
+            lags: 0,
+            return_windows: Vec::new(),
+            volatility_windows: Vec::new(),
+            day_of_week: false,
         }
     }
 }
@@ -110,9 +394,445 @@ impl Default for TrainingParams {
 // TODO: determine whether separate structs for different kinds of ml algorithms
 // are necessary
 
-/// Parameters for running the Backtester.
-#[derive(Serialize, Deserialize)]
-pub struct BacktesterParams {}
+// Parameters for running the Backtester.
+#[derive(Serialize, Deserialize, StructOpt, Debug, PartialEq, Default)]
+pub struct BacktesterParams {
+    /// Commission paid per share traded.
+    #[structopt(long, default_value = "0.0")]
+    #[serde(default)]
+    pub commission_per_share: f64,
+
+    /// Commission paid as a fraction of the value traded (0.001 = 0.1%).
+    #[structopt(long, default_value = "0.0")]
+    #[serde(default)]
+    pub commission_rate: f64,
+
+    /// Flat fee paid on every order.
+    #[structopt(long, default_value = "0.0")]
+    #[serde(default)]
+    pub fee_per_order: f64,
+
+    /// Adverse price movement on every fill, in basis points.
+    #[structopt(long, default_value = "0.0")]
+    #[serde(default)]
+    pub slippage_bps: f64,
+
+    /// Width of the bid-ask spread in basis points. Half of it is paid on
+    /// every fill.
+    #[structopt(long, default_value = "0.0")]
+    #[serde(default)]
+    pub spread_bps: f64,
+
+    /// Smallest number of shares that can be traded, e.g. 0.0001 for crypto
+    /// [default: 1]
+    #[structopt(long)]
+    #[serde(default)]
+    pub lot_size: Option<f64>,
+}
+
+impl BacktesterParams {
+    /// Smallest number of shares that can be traded, whole shares unless
+    /// `lot_size` says otherwise.
+    pub fn lot_size(&self) -> f64 {
+        self.lot_size.unwrap_or(1.0)
+    }
+}
+
+impl ToJson for BacktesterParams {}
+
+// Rules for closing a trading model's positions early. Returns are fractions
+// of the entry price, e.g. 0.05 for 5%.
+#[derive(Serialize, Deserialize, StructOpt, Debug, PartialEq, Default)]
+pub struct ExitParams {
+    /// Close a position once it has lost this much since it was entered.
+    #[structopt(long)]
+    #[serde(default)]
+    pub stop_loss: Option<f64>,
+
+    /// Close a position once it has gained this much since it was entered.
+    #[structopt(long)]
+    #[serde(default)]
+    pub take_profit: Option<f64>,
+
+    /// Close a position once it has given back this much from the best price
+    /// since it was entered.
+    #[structopt(long)]
+    #[serde(default)]
+    pub trailing_stop: Option<f64>,
+
+    /// Close a position after holding it for this many bars.
+    #[structopt(long)]
+    #[serde(default)]
+    pub max_holding: Option<u32>,
+}
+
+impl From<&ExitParams> for ExitRules {
+    fn from(p: &ExitParams) -> Self {
+        Self {
+            stop_loss: p.stop_loss,
+            take_profit: p.take_profit,
+            trailing_stop: p.trailing_stop,
+            max_holding: p.max_holding,
+        }
+    }
+}
+
+impl ToJson for ExitParams {}
+
+// Parameters for walk-forward evaluation. Window lengths are in trading days
+// of the trading calendar, whether or not there is price data for them.
+#[derive(Serialize, Deserialize, StructOpt, Debug, PartialEq)]
+pub struct WalkForwardParams {
+    /// Length of each training window. The last `horizon` days of it are only
+    /// used to label the rest.
+    #[structopt(long, default_value = "504")]
+    #[serde(default = "default_train_days")]
+    pub train_days: usize,
+
+    /// Length of each out-of-sample test window.
+    #[structopt(long, default_value = "126")]
+    #[serde(default = "default_test_days")]
+    pub test_days: usize,
+
+    /// Train on everything before each test window instead of a sliding
+    /// window of `train_days`.
+    #[structopt(long)]
+    #[serde(default)]
+    pub anchored: bool,
+}
+
+fn default_train_days() -> usize {
+    504
+}
+
+fn default_test_days() -> usize {
+    126
+}
+
+impl Default for WalkForwardParams {
+    fn default() -> Self {
+        Self {
+            train_days: default_train_days(),
+            test_days: default_test_days(),
+            anchored: false,
+        }
+    }
+}
+
+impl ToJson for WalkForwardParams {}
+
+// How the Tune command cross-validates and which hyperparameters it searches.
+#[derive(Debug, StructOpt, Deserialize, Serialize, PartialEq)]
+pub struct TuneParams {
+    /// How to split the training days into folds: `kfold` (each block of days
+    /// is tested on in turn, trained on the rest) or `expanding` (each block
+    /// is tested on, trained on every block before it).
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub cross_validation: CrossValidation,
+
+    /// Number of folds.
+    #[structopt(long, default_value = "5")]
+    #[serde(default = "default_folds")]
+    pub folds: usize,
+
+    /// Training days after each k-fold test window to leave out, on top of the
+    /// `horizon` days before it whose labels overlap it.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub embargo: usize,
+
+    /// Maximum tree depths to try, separated by commas.
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub depths: Vec<usize>,
+
+    /// Minimum samples to split a tree node to try, separated by commas.
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub min_samples_splits: Vec<usize>,
+
+    /// Minimum samples per leaf to try, separated by commas.
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub min_samples_leaves: Vec<usize>,
+
+    /// Tree counts to try for random forests and gradient boosting, separated
+    /// by commas.
+    #[structopt(long, use_delimiter = true, require_delimiter = true)]
+    #[serde(default)]
+    pub tree_counts: Vec<usize>,
+}
+
+fn default_folds() -> usize {
+    5
+}
+
+impl Default for TuneParams {
+    fn default() -> Self {
+        Self {
+            cross_validation: CrossValidation::default(),
+            folds: default_folds(),
+            embargo: 0,
+            depths: Vec::new(),
+            min_samples_splits: Vec::new(),
+            min_samples_leaves: Vec::new(),
+            tree_counts: Vec::new(),
+        }
+    }
+}
+
+impl From<&TuneParams> for HyperparameterGrid {
+    fn from(params: &TuneParams) -> Self {
+        Self {
+            max_depths: params.depths.clone(),
+            min_samples_splits: params.min_samples_splits.clone(),
+            min_samples_leaves: params.min_samples_leaves.clone(),
+            num_trees: params.tree_counts.clone(),
+        }
+    }
+}
+
+impl ToJson for TuneParams {}
+
+// Parameters when running the TrainQLearner command.
+#[derive(Debug, Clone, StructOpt, Deserialize, Serialize, PartialEq)]
+pub struct QLearningParams {
+    /// Start date of the training dataset. Defaults to the beginning of the
+    /// dataset.
+    #[structopt(long)]
+    #[serde(default)]
+    pub train_start_date: Option<Date>,
+
+    /// End date of the training dataset. Defaults to the end of the dataset.
+    #[structopt(long)]
+    #[serde(default)]
+    pub train_end_date: Option<Date>,
+
+    /// Which technical indicators' signals make up the learner's states.
+    #[structopt(long, short, default_value)]
+    #[serde(default)]
+    pub signal_generators: ListOfIndicators,
+
+    /// How the model sizes its trades: `fixed:<shares>`, `cash:<fraction>`,
+    /// `vol:<target>[,<window>]`, `kelly:<fraction>[,<window>]` or
+    /// `signal:<max shares>`. Cash is sized against when the model is used.
+    #[structopt(long, default_value)]
+    #[serde(default)]
+    pub sizing: SizingPolicy,
+
+    /// Bins each indicator's signal is split into.
+    #[structopt(long, default_value = "5")]
+    #[serde(default = "default_bins")]
+    pub bins: usize,
+
+    /// Learning rate.
+    #[structopt(long, default_value = "0.2")]
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+
+    /// Discount rate of future rewards.
+    #[structopt(long, default_value = "0.9")]
+    #[serde(default = "default_gamma")]
+    pub gamma: f64,
+
+    /// Chance of exploring a random action at the start of training.
+    #[structopt(long, default_value = "0.5")]
+    #[serde(default = "default_random_action_rate")]
+    pub random_action_rate: f64,
+
+    /// What the chance of a random action is multiplied by after each day.
+    #[structopt(long, default_value = "0.999")]
+    #[serde(default = "default_random_action_decay")]
+    pub random_action_decay: f64,
+
+    /// Passes over the training days.
+    #[structopt(long, default_value = "50")]
+    #[serde(default = "default_epochs")]
+    pub epochs: usize,
+
+    /// Remembered experiences to replay after each day (Dyna-Q planning).
+    /// 0 turns planning off.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub dyna: usize,
+
+    /// Penalty for changing position while training, as a fraction of the
+    /// price.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub impact: f64,
+
+    /// Seed for the random actions.
+    #[structopt(long, default_value = "0")]
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_bins() -> usize {
+    QHyperparameters::default().bins
+}
+
+fn default_alpha() -> f64 {
+    QHyperparameters::default().alpha
+}
+
+fn default_gamma() -> f64 {
+    QHyperparameters::default().gamma
+}
+
+fn default_random_action_rate() -> f64 {
+    QHyperparameters::default().random_action_rate
+}
+
+fn default_random_action_decay() -> f64 {
+    QHyperparameters::default().random_action_decay
+}
+
+fn default_epochs() -> usize {
+    QHyperparameters::default().epochs
+}
+
+impl From<&QLearningParams> for QHyperparameters {
+    fn from(params: &QLearningParams) -> Self {
+        Self {
+            bins: params.bins,
+            alpha: params.alpha,
+            gamma: params.gamma,
+            random_action_rate: params.random_action_rate,
+            random_action_decay: params.random_action_decay,
+            epochs: params.epochs,
+            dyna: params.dyna,
+            impact: params.impact,
+            seed: params.seed,
+        }
+    }
+}
+
+impl ToJson for QLearningParams {}
+
+/// A model in an ensemble, tagged by `model`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum EnsembleMemberSpec {
+    /// A model file saved by `techalyzer train`. A relative path is relative
+    /// to the ensemble's config file.
+    #[serde(alias = "decision_tree")]
+    MachineLearning {
+        model_file: PathBuf,
+    },
+
+    /// A ManualTradingAlgo. Uses the default indicators if none are given.
+    Manual {
+        shares: f64,
+        dead_zone: Signal,
+        disposition: Signal,
+        #[serde(default)]
+        indicators: Vec<IndicatorSpec>,
+    },
+
+    BuyAndHold,
+}
+
+/// A model in an ensemble and the weight of its vote.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EnsembleMemberParams {
+    #[serde(flatten)]
+    pub model: EnsembleMemberSpec,
+
+    /// How much the model's vote counts in a weighted vote.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// An ensemble of trading models, read from a JSON file, e.g.
+///
+/// ```json
+/// {
+///   "method": "weighted",
+///   "members": [
+///     { "model": "machine_learning", "model_file": "jpm.bin", "weight": 2.0 },
+///     { "model": "manual", "shares": 100, "dead_zone": 0.2, "disposition": 0.0 },
+///     { "model": "buy_and_hold" }
+///   ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EnsembleParams {
+    /// `majority`, `weighted` or `stacking`.
+    #[serde(default)]
+    pub method: EnsembleMethod,
+
+    pub members: Vec<EnsembleMemberParams>,
+
+    /// How the ensemble sizes its trades (see TrainingParams::sizing). The
+    /// members' own sizing is ignored.
+    #[serde(default)]
+    pub sizing: SizingPolicy,
+
+    /// Stacking fits votes to the return this many days after them.
+    #[serde(default = "default_stacking_horizon")]
+    pub horizon: usize,
+
+    /// Days stacking fits votes to before it stops counting them equally.
+    #[serde(default = "default_stacking_min_train")]
+    pub min_train: usize,
+}
+
+fn default_stacking_horizon() -> usize {
+    DEFAULT_STACKING_HORIZON
+}
+
+fn default_stacking_min_train() -> usize {
+    DEFAULT_STACKING_MIN_TRAIN
+}
+
+impl ToJson for EnsembleParams {}
+
+// Parameters for optimizing a ManualTradingModel. Values to try are written
+// as comma-separated values and inclusive ranges with an optional step, e.g.
+// `100,500,1000` or `0.0..0.5/0.1`.
+#[derive(StructOpt, Debug)]
+pub struct OptimizeParams {
+    /// Shares committed to each trade.
+    #[structopt(long, default_value = "1000")]
+    pub shares: ParamRange,
+
+    /// How far the average signal must be from zero to trade.
+    #[structopt(long, default_value = "0.0")]
+    pub dead_zone: ParamRange,
+
+    /// Added to the average signal to make the model more bullish or bearish.
+    #[structopt(long, default_value = "0.0")]
+    pub disposition: ParamRange,
+
+    /// Indicators to use, with a value or range for each parameter (e.g.
+    /// `rsi:10..20/2 bb:20,1.5..2.5/0.5 macd`). Defaults to the model's usual
+    /// indicators.
+    #[structopt(long, short)]
+    pub indicators: Vec<IndicatorRange>,
+
+    /// What to rank results by (TotalReturn, SharpeRatio or MaxDrawdown).
+    #[structopt(long, short, default_value = "TotalReturn")]
+    pub objective: Objective,
+
+    /// Backtest this many combinations picked at random instead of all of
+    /// them.
+    #[structopt(long)]
+    pub samples: Option<usize>,
+
+    /// Seed for picking random combinations.
+    #[structopt(long, default_value = "0")]
+    pub seed: u64,
+
+    /// Only report the best `top` results.
+    #[structopt(long)]
+    pub top: Option<usize>,
+}
 
 /// Gives us a little more flexibility when parsing dates from the command line
 /// for things like "today"
@@ -127,8 +847,25 @@ fn parse_date(datestr: &str) -> Result<Date, chrono::ParseError> {
 
 #[cfg(test)]
 mod tests {
-    use super::{GeneralParams, Symbol, TrainingParams};
+    use super::{
+        EnsembleMemberSpec, EnsembleParams, GeneralParams, QLearningParams, TrainingParams,
+        TuneParams,
+    };
+    use crate::calendar::CalendarSpec;
+    use crate::datasource::csv::CsvProfile;
+    use crate::indicators::{IndicatorSpec, SupportedIndicators};
+    use crate::marketdata::actions::Adjustment;
+    use crate::trading::{
+        ensemble::EnsembleMethod,
+        features::FeatureSet,
+        labeling::Labeler,
+        ml::{Algorithm, Hyperparameters},
+        qlearner::QHyperparameters,
+    };
+    use crate::tuning::{CrossValidation, HyperparameterGrid};
+    use crate::util::Symbol;
     use crate::{datasource::SupportedDataSource::TechalyzerJson, util::ToJson};
+    use structopt::StructOpt;
 
     #[test]
     fn test_trainingparams_json() {
@@ -160,6 +897,120 @@ mod tests {
         assert_eq!(params, TrainingParams::default());
     }
 
+    #[test]
+    fn test_trainingparams_indicator_specs() {
+        let json = r#"{ "signal_generators": ["rsi:10", "bb:20,2.5", "MACD"] }"#;
+        let params: TrainingParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            params.signal_generators.0,
+            vec![
+                IndicatorSpec::RelativeStrengthIndex { window: 10 },
+                IndicatorSpec::BollingerBands {
+                    length: 20,
+                    multiplier: 2.5
+                },
+                SupportedIndicators::MACD.into(),
+            ]
+        );
+
+        // Survives a round trip
+        let again: TrainingParams = serde_json::from_str(&params.to_json().unwrap()).unwrap();
+        assert_eq!(params, again);
+
+        // The error says which parameter was wrong
+        let json = r#"{ "signal_generators": ["bb:20,-1"] }"#;
+        let err = serde_json::from_str::<TrainingParams>(json).unwrap_err();
+        assert!(err.to_string().contains("'multiplier'"));
+    }
+
+    #[test]
+    fn test_trainingparams_algorithm() {
+        let json = r#"{ "algorithm": "random_forest", "max_depth": 4, "num_trees": 50 }"#;
+        let params: TrainingParams = serde_json::from_str(json).unwrap();
+        assert_eq!(params.algorithm, Algorithm::RandomForest);
+
+        let hyper = Hyperparameters::from(&params);
+        assert_eq!(hyper.max_depth, Some(4));
+        assert_eq!(hyper.num_trees, 50);
+        assert_eq!(hyper.min_samples_split, 2);
+        assert_eq!(hyper.learning_rate, 0.1);
+
+        let json = r#"{ "algorithm": "svm" }"#;
+        assert!(serde_json::from_str::<TrainingParams>(json).is_err());
+    }
+
+    #[test]
+    fn test_tuneparams() {
+        let json = r#"{ "cross_validation": "expanding", "depths": [2, 4] }"#;
+        let params: TuneParams = serde_json::from_str(json).unwrap();
+        assert_eq!(params.cross_validation, CrossValidation::Expanding);
+        assert_eq!(params.folds, 5);
+
+        let grid = HyperparameterGrid::from(&params);
+        assert_eq!(grid.max_depths, vec![2, 4]);
+        assert!(grid.num_trees.is_empty());
+
+        let training = TrainingParams::default();
+        let hyper = Hyperparameters {
+            max_depth: Some(3),
+            ..Hyperparameters::default()
+        };
+        let tuned = training.with_hyperparameters(&hyper);
+        assert_eq!(Hyperparameters::from(&tuned), hyper);
+        assert_eq!(tuned.signal_generators, training.signal_generators);
+    }
+
+    #[test]
+    fn test_qlearningparams() {
+        let json = r#"{ "signal_generators": ["macd"], "dyna": 20 }"#;
+        let params: QLearningParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            QHyperparameters::from(&params),
+            QHyperparameters {
+                dyna: 20,
+                ..QHyperparameters::default()
+            }
+        );
+        assert_eq!(params.signal_generators.0.len(), 1);
+
+        let from_cli = QLearningParams::from_iter(&["train-q-learner", "--bins", "3"]);
+        assert_eq!(QHyperparameters::from(&from_cli).bins, 3);
+        assert_eq!(from_cli.epochs, QHyperparameters::default().epochs);
+    }
+
+    #[test]
+    fn test_trainingparams_labeler() {
+        let params: TrainingParams = serde_json::from_str("{}").unwrap();
+        assert_eq!(params.labeler, Labeler::FixedHorizon);
+
+        let json = r#"{ "labeler": "triple-barrier:2,1" }"#;
+        let params: TrainingParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            params.labeler,
+            Labeler::TripleBarrier {
+                profit_take: 2.0,
+                stop_loss: 1.0
+            }
+        );
+        assert!(serde_json::from_str::<TrainingParams>(r#"{ "labeler": "coin" }"#).is_err());
+    }
+
+    #[test]
+    fn test_trainingparams_features() {
+        let json = r#"{ "lags": 2, "return_windows": [5, 20], "day_of_week": true }"#;
+        let params: TrainingParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            FeatureSet::from(&params),
+            FeatureSet {
+                outputs: false,
+                lags: 2,
+                returns: vec![5, 20],
+                volatility: vec![],
+                day_of_week: true,
+            }
+        );
+    }
+
     // FIXME: this test is largely obsolete because I decided not to go with
     // deserializable GeneralParams as a way of supplying arguments instead of
     // CLI due to limitations in structopt.
@@ -167,11 +1018,20 @@ mod tests {
     fn test_generalparams_json() {
         // results of dumping default parameters to json
         let gp = GeneralParams {
-            data_source: TechalyzerJson("test/json/jpm_rsi.json".into()),
-            symbol: Symbol::new("jpm".to_string()),
+            data_source: Some(TechalyzerJson("test/json/jpm_rsi.json".into())),
+            symbol: Some(Symbol::new("jpm".to_string()).into()),
             secret: None,
             start_date: None,
             end_date: None,
+            offline: false,
+            refresh: false,
+            cache_dir: None,
+            calendar: CalendarSpec::Nyse,
+            adjust: Adjustment::Splits,
+            interval: None,
+            repair: None,
+            max_jump: 0.5,
+            csv_profile: CsvProfile::Headers,
         };
         let _as_json_str = gp.to_json().unwrap();
 
@@ -202,4 +1062,52 @@ mod tests {
         let params: GeneralParams = serde_json::from_str(left_out_keys).unwrap();
         assert_eq!(params, gp);
     }
+
+    #[test]
+    fn test_ensembleparams_json() {
+        let json = r#"
+        {
+            "method": "stacking",
+            "members": [
+                { "model": "machine_learning", "model_file": "jpm.bin", "weight": 2.0 },
+                { "model": "manual", "shares": 100, "dead_zone": 0.2, "disposition": 0.0,
+                  "indicators": ["rsi:10"] },
+                { "model": "buy_and_hold" }
+            ],
+            "horizon": 10
+        }
+        "#;
+        let params: EnsembleParams = serde_json::from_str(json).unwrap();
+        assert_eq!(params.method, EnsembleMethod::Stacking);
+        assert_eq!(params.horizon, 10);
+        assert_eq!(params.members[0].weight, 2.0);
+        assert_eq!(params.members[2].weight, 1.0);
+        assert_eq!(params.members[2].model, EnsembleMemberSpec::BuyAndHold);
+        match &params.members[1].model {
+            EnsembleMemberSpec::Manual { indicators, .. } => {
+                assert_eq!(
+                    indicators,
+                    &vec![IndicatorSpec::RelativeStrengthIndex { window: 10 }]
+                )
+            }
+            other => panic!("Expected a manual model, got {:?}", other),
+        }
+
+        // Survives a round trip
+        let again: EnsembleParams = serde_json::from_str(&params.to_json().unwrap()).unwrap();
+        assert_eq!(params, again);
+
+        // Model files used to only hold decision trees
+        let json = r#"{ "members": [{ "model": "decision_tree", "model_file": "jpm.bin" }] }"#;
+        let params: EnsembleParams = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            params.members[0].model,
+            EnsembleMemberSpec::MachineLearning {
+                model_file: "jpm.bin".into()
+            }
+        );
+
+        let json = r#"{ "members": [{ "model": "magic" }] }"#;
+        assert!(serde_json::from_str::<EnsembleParams>(json).is_err());
+    }
 }
@@ -1,8 +1,15 @@
 //! Stock price information from [Alpha Vantage](https://www.alphavantage.co/).
 
 use crate::datasource::{DataSource, Error};
+use crate::date::{today, Date};
 use crate::marketdata::prices::Prices;
 use alphavantage::blocking::Client;
+use chrono::Duration;
+use std::ops::{Bound, RangeBounds};
+
+/// The compact time series has the latest 100 market days of data, which is
+/// always at least this many calendar days.
+const COMPACT_CALENDAR_DAYS: i64 = 130;
 
 /// Contains an Alpha Vantage client.
 pub struct AlphaVantage {
@@ -18,16 +25,33 @@ impl AlphaVantage {
 /// Gets the data from the ALpha Vantage API.
 impl DataSource for AlphaVantage {
     fn get(&self, symbol: &str) -> Result<Prices, Error> {
-        // TODO: if start is in the last 100 market days, don't request the full
-        // time series datas
-
-        // FIXME: this doesn't use adjusted close, consider submitting a PR for
-        // that.
+        // FIXME: the client only has the unadjusted daily series, so the
+        // prices come without splits or dividends (`Prices::actions`) and
+        // can't be adjusted. Consider submitting a PR for the adjusted one.
         match self.client.get_time_series_daily_full(symbol) {
             Ok(t) => Ok(t.into()),
             Err(e) => Err(Error::AlphaVantageError(e.to_string())),
         }
     }
+
+    /// Only requests the full time series if the compact one doesn't go back
+    /// far enough.
+    fn get_date_range(&self, symbol: &str, range: impl RangeBounds<Date>) -> Result<Prices, Error> {
+        let recent = match range.start_bound() {
+            Bound::Included(d) | Bound::Excluded(d) => {
+                *d >= today() - Duration::days(COMPACT_CALENDAR_DAYS)
+            }
+            Bound::Unbounded => false,
+        };
+        if !recent {
+            return Ok(self.get(symbol)?.date_range(range));
+        }
+
+        match self.client.get_time_series_daily(symbol) {
+            Ok(t) => Ok(Prices::from(t).date_range(range)),
+            Err(e) => Err(Error::AlphaVantageError(e.to_string())),
+        }
+    }
 }
 
 #[cfg(test)]
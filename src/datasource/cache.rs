@@ -0,0 +1,354 @@
+//! A local, on-disk cache of price data in front of another DataSource, so that
+//! repeated runs only download the dates they don't already have.
+
+use crate::datasource::{DataSource, Error};
+use crate::date::{today, Date};
+use crate::marketdata::prices::Prices;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+
+/// How a CachedDataSource uses its cache.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CacheMode {
+    /// Serve cached data, fetching only the dates that aren't cached yet.
+    #[default]
+    Normal,
+
+    /// Only serve cached data, never fetching anything.
+    Offline,
+
+    /// Ignore cached data and fetch everything again.
+    Refresh,
+}
+
+impl CacheMode {
+    /// The mode selected by the `--offline` and `--refresh` flags.
+    pub fn from_flags(offline: bool, refresh: bool) -> Self {
+        match (offline, refresh) {
+            (true, _) => Self::Offline,
+            (_, true) => Self::Refresh,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// Where and how to cache downloaded price data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    /// Directory the cache files live in.
+    pub dir: PathBuf,
+
+    pub mode: CacheMode,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_cache_dir(),
+            mode: CacheMode::default(),
+        }
+    }
+}
+
+/// `$XDG_CACHE_HOME/techalyzer`, falling back to `$HOME/.cache/techalyzer`
+/// (or the system temp directory if there is no home directory).
+pub fn default_cache_dir() -> PathBuf {
+    let xdg = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute());
+    let base = xdg
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+
+    base.join("techalyzer")
+}
+
+/// A fetch of price data from the underlying DataSource.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Fetch {
+    /// When the fetch was made.
+    pub at: DateTime<Utc>,
+
+    /// First date fetched, or None if it fetched from the start of history.
+    pub from: Option<Date>,
+
+    /// Last date fetched.
+    pub to: Date,
+}
+
+/// What the cache stores for each symbol.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// The cached prices are complete from this date on, or from the start of
+    /// history if None.
+    pub from: Option<Date>,
+
+    /// The cached prices are complete up to and including this date.
+    pub to: Date,
+
+    /// Every fetch that went into the cached prices, oldest first.
+    pub fetches: Vec<Fetch>,
+
+    pub prices: Prices,
+}
+
+/// Caches the prices from another DataSource on disk, one JSON file per
+/// symbol.
+///
+/// In CacheMode::Normal, dates outside of the range already cached are fetched
+/// and merged into the cache. The last cached date is always fetched again,
+/// since the source may have only had part of that day's data at the time.
+pub struct CachedDataSource<D: DataSource> {
+    source: D,
+    dir: PathBuf,
+    mode: CacheMode,
+
+    /// The latest date there can be data for.
+    today: Date,
+}
+
+impl<D: DataSource> CachedDataSource<D> {
+    pub fn new(source: D, dir: &Path, mode: CacheMode) -> Self {
+        Self {
+            source,
+            dir: dir.to_path_buf(),
+            mode,
+            today: today(),
+        }
+    }
+
+    /// Treats `today` as the current date.
+    pub fn as_of(mut self, today: Date) -> Self {
+        self.today = today;
+        self
+    }
+
+    /// Path to the cache file for `symbol`.
+    pub fn path(&self, symbol: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", symbol.to_lowercase()))
+    }
+
+    /// Reads the cache entry for `symbol`, if there is one.
+    pub fn load(&self, symbol: &str) -> Result<Option<CacheEntry>, Error> {
+        let path = self.path(symbol);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = File::open(&path).map_err(|e| cache_error(&path, e))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| cache_error(&path, e))
+    }
+
+    fn save(&self, symbol: &str, entry: &CacheEntry) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir).map_err(|e| cache_error(&self.dir, e))?;
+        let path = self.path(symbol);
+        let file = File::create(&path).map_err(|e| cache_error(&path, e))?;
+        serde_json::to_writer(BufWriter::new(file), entry).map_err(|e| cache_error(&path, e))
+    }
+
+    /// Fetches prices up to and including `to` from the source, and records
+    /// the fetch in `entry`.
+    fn fetch_into(
+        &self,
+        entry: &mut CacheEntry,
+        symbol: &str,
+        from: Option<Date>,
+        to: Date,
+    ) -> Result<(), Error> {
+        let start = from.map_or(Bound::Unbounded, Bound::Included);
+        let fetched = self
+            .source
+            .get_date_range(symbol, (start, Bound::Included(to)))?;
+        entry.prices.map.extend(fetched.map);
+        entry.prices.actions.extend(fetched.actions);
+        entry.fetches.push(Fetch {
+            at: Utc::now(),
+            from,
+            to,
+        });
+
+        Ok(())
+    }
+}
+
+fn cache_error(path: &Path, e: impl ToString) -> Error {
+    Error::CacheError(format!("{}: {}", path.display(), e.to_string()))
+}
+
+impl<D: DataSource> DataSource for CachedDataSource<D> {
+    fn get(&self, symbol: &str) -> Result<Prices, Error> {
+        self.get_date_range(symbol, ..)
+    }
+
+    fn get_date_range(&self, symbol: &str, range: impl RangeBounds<Date>) -> Result<Prices, Error> {
+        let start = match range.start_bound() {
+            Bound::Included(d) => Some(*d),
+            Bound::Excluded(d) => Some(*d + Duration::days(1)),
+            Bound::Unbounded => None,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(d) => *d,
+            Bound::Excluded(d) => *d - Duration::days(1),
+            Bound::Unbounded => self.today,
+        }
+        .min(self.today);
+
+        let cached = match self.mode {
+            CacheMode::Refresh => None,
+            _ => self.load(symbol)?,
+        };
+
+        let entry = match (self.mode, cached) {
+            (CacheMode::Offline, None) => return Err(Error::NotCached(symbol.to_string())),
+            (CacheMode::Offline, Some(entry)) => entry,
+            (_, None) => {
+                let mut entry = CacheEntry {
+                    from: start,
+                    to: end,
+                    fetches: Vec::new(),
+                    prices: Prices::from_closes(symbol, Default::default()),
+                };
+                self.fetch_into(&mut entry, symbol, start, end)?;
+                self.save(symbol, &entry)?;
+                entry
+            }
+            (_, Some(mut entry)) => {
+                let mut changed = false;
+
+                // Dates before the cached range
+                if let Some(from) = entry.from {
+                    if start.is_none_or(|s| s < from) {
+                        self.fetch_into(&mut entry, symbol, start, from - Duration::days(1))?;
+                        entry.from = start;
+                        changed = true;
+                    }
+                }
+
+                // Dates after the cached range
+                if end > entry.to {
+                    let last_cached = entry.to;
+                    self.fetch_into(&mut entry, symbol, Some(last_cached), end)?;
+                    entry.to = end;
+                    changed = true;
+                }
+
+                if changed {
+                    self.save(symbol, &entry)?;
+                }
+                entry
+            }
+        };
+
+        Ok(entry.prices.date_range(range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TimeSeries;
+    use std::cell::RefCell;
+
+    /// Stands in for an API, with a price every day of January 2020.
+    struct FakeSource {
+        fetches: RefCell<Vec<(Bound<Date>, Bound<Date>)>>,
+    }
+
+    impl FakeSource {
+        fn new() -> Self {
+            Self {
+                fetches: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl DataSource for FakeSource {
+        fn get(&self, symbol: &str) -> Result<Prices, Error> {
+            let closes: TimeSeries<f64> = (1..=31)
+                .map(|d| (Date::from_ymd(2020, 1, d), d as f64))
+                .collect();
+            Ok(Prices::from_closes(symbol, closes))
+        }
+
+        fn get_date_range(
+            &self,
+            symbol: &str,
+            range: impl RangeBounds<Date>,
+        ) -> Result<Prices, Error> {
+            self.fetches
+                .borrow_mut()
+                .push((range.start_bound().cloned(), range.end_bound().cloned()));
+            Ok(self.get(symbol)?.date_range(range))
+        }
+    }
+
+    fn jan(d: u32) -> Date {
+        Date::from_ymd(2020, 1, d)
+    }
+
+    #[test]
+    fn fetches_only_missing_dates() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache =
+            CachedDataSource::new(FakeSource::new(), dir.path(), CacheMode::Normal).as_of(jan(10));
+
+        let p = cache.get_date_range("jpm", jan(5)..=jan(20)).unwrap();
+        assert_eq!(p.map.len(), 6);
+        assert_eq!(cache.source.fetches.borrow().len(), 1);
+
+        // Everything requested is cached already
+        let p = cache.get_date_range("JPM", jan(6)..).unwrap();
+        assert_eq!(p.map.len(), 5);
+        assert_eq!(cache.source.fetches.borrow().len(), 1);
+
+        // Days go by, and only the new ones (plus the last cached one) are
+        // fetched.
+        let cache =
+            CachedDataSource::new(FakeSource::new(), dir.path(), CacheMode::Normal).as_of(jan(15));
+        let p = cache.get_date_range("jpm", jan(1)..=jan(31)).unwrap();
+        assert_eq!(p.map.len(), 15);
+        assert_eq!(
+            *cache.source.fetches.borrow(),
+            vec![
+                (Bound::Included(jan(1)), Bound::Included(jan(4))),
+                (Bound::Included(jan(10)), Bound::Included(jan(15))),
+            ]
+        );
+
+        let entry = cache.load("jpm").unwrap().unwrap();
+        assert_eq!((entry.from, entry.to), (Some(jan(1)), jan(15)));
+        assert_eq!(entry.fetches.len(), 3);
+    }
+
+    #[test]
+    fn offline_and_refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let with_mode =
+            |mode| CachedDataSource::new(FakeSource::new(), dir.path(), mode).as_of(jan(31));
+
+        let offline = with_mode(CacheMode::Offline);
+        assert!(matches!(offline.get("jpm"), Err(Error::NotCached(_))));
+
+        with_mode(CacheMode::Normal).get("jpm").unwrap();
+
+        // Served from the cache without fetching
+        let offline = with_mode(CacheMode::Offline).as_of(Date::from_ymd(2021, 1, 1));
+        assert_eq!(offline.get("jpm").unwrap().map.len(), 31);
+        assert!(offline.source.fetches.borrow().is_empty());
+
+        // Fetches everything again
+        let refresh = with_mode(CacheMode::Refresh);
+        assert_eq!(refresh.get("jpm").unwrap().map.len(), 31);
+        assert_eq!(
+            *refresh.source.fetches.borrow(),
+            vec![(Bound::Unbounded, Bound::Included(jan(31)))]
+        );
+        assert_eq!(refresh.load("jpm").unwrap().unwrap().fetches.len(), 1);
+    }
+}
@@ -2,54 +2,156 @@
 
 use super::{DataSource, Error};
 use crate::{date::Date, marketdata, util::TimeSeries};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use csv::StringRecord;
-use marketdata::Prices;
-use std::{fs::File, path::Path, str::FromStr};
+use marketdata::{Bar, CorporateAction, Prices};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, fs::File, path::Path, str::FromStr};
 
-/// The CSV file must have "date" and "adjusted close" or "adj. close" columns.
+/// How the columns of a CSV file are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum CsvProfile {
+    /// A header row names the columns (see CsvFile).
+    #[default]
+    Headers,
+
+    /// Candlestick (OHLCV) exports from crypto exchanges, like Binance's
+    /// klines and Kraken's OHLCVT files: no header row, and the open time (a
+    /// Unix timestamp), open, high, low, close, and volume in that order.
+    /// Any columns after those are ignored.
+    Exchange,
+}
+
+impl FromStr for CsvProfile {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "headers" => Ok(Self::Headers),
+            "exchange" | "binance" | "kraken" => Ok(Self::Exchange),
+            _ => Err(Error::UnknownCsvProfile(s.to_string())),
+        }
+    }
+}
+
+impl Display for CsvProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Headers => "headers",
+            Self::Exchange => "exchange",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The CSV file must have "date" (or "timestamp", "time", "open time", or
+/// "unix") and either "adjusted close", "adj. close", or "close" columns.
+/// "open", "high", "low", and "volume" (or e.g. "volume btc") columns are
+/// optional, as are "split coefficient" and "dividend amount" columns for
+/// corporate actions. Underscores in headers are read as spaces (e.g.
+/// "adjusted_close"). Files without headers can be read with a CsvProfile.
+///
+/// Dates are either days (`2020-01-02` or `2020/01/02`) for daily data, or
+/// times of day for intraday data: RFC 3339 timestamps, Unix timestamps (in
+/// seconds, milliseconds, or microseconds), or `2020-01-02 09:30[:00]` in the
+/// file's time zone (UTC unless set with `with_timezone`).
 pub struct CsvFile {
     file: File,
+    timezone: Tz,
+    profile: CsvProfile,
 }
 
 impl CsvFile {
     pub fn new(path: &Path) -> Result<Self, std::io::Error> {
         let file = File::open(path)?;
-        Ok(Self { file })
+        Ok(Self {
+            file,
+            timezone: Tz::UTC,
+            profile: CsvProfile::default(),
+        })
+    }
+
+    /// Reads times of day without an offset as being in `timezone`.
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Reads the columns as laid out by `profile`.
+    pub fn with_profile(mut self, profile: CsvProfile) -> Self {
+        self.profile = profile;
+        self
     }
 }
 
 impl DataSource for CsvFile {
     fn get(&self, symbol: &str) -> Result<marketdata::Prices, Error> {
-        // Must be at least a Date and Adjusted Close column.
-        let mut reader = csv::Reader::from_reader(&self.file);
-        let headers = reader
-            .headers()
-            .map_err(|e| Error::CsvError(e.to_string()))?;
-
-        let (date_idx, adj_close_idx) = required_field_indices(headers)?;
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(self.profile == CsvProfile::Headers)
+            .from_reader(&self.file);
+        let columns = match self.profile {
+            // Must be at least a Date and Adjusted Close column.
+            CsvProfile::Headers => {
+                let headers = reader
+                    .headers()
+                    .map_err(|e| Error::CsvError(e.to_string()))?;
+                ColumnIndices::from_headers(headers)?
+            }
+            CsvProfile::Exchange => ColumnIndices::exchange(),
+        };
 
-        let mut time_series = TimeSeries::new();
+        let mut rows = Vec::new();
+        let mut actions = TimeSeries::new();
         for row in reader.records() {
             let row = row.map_err(|e| Error::CsvError(e.to_string()))?;
-            let date = parse_date_in_csv(get_at_row_idx(&row, date_idx)?)?;
-            let price = f64::from_str(get_at_row_idx(&row, adj_close_idx)?)
-                .map_err(|e| Error::CsvError(e.to_string()))?;
-
-            time_series.insert(date, price);
+            let date = parse_date_in_csv(get_at_row_idx(&row, columns.date)?, &self.timezone)?;
+            rows.push((date, columns.bar(&row)?));
+            if let Some(action) = columns.action(&row)? {
+                actions.insert(date, action);
+            }
         }
 
-        Ok(Prices {
-            map: time_series,
-            symbol: symbol.into(),
-        })
+        let mut prices = Prices::from_rows(symbol, rows);
+        prices.actions = actions;
+        Ok(prices)
     }
 }
 
-fn parse_date_in_csv(datestr: &str) -> Result<Date, Error> {
+fn parse_date_in_csv(datestr: &str, timezone: &Tz) -> Result<Date, Error> {
+    if let Some(date) = parse_unix_time(datestr) {
+        return Ok(date);
+    }
+
     // try multiple date formats
     let result = Date::parse_from_str(datestr, "%Y-%m-%d")
-        .or_else(|_| Date::parse_from_str(datestr, "%Y/%m/%d"));
-    result.map_err(|e| Error::CsvError(e.to_string()))
+        .or_else(|_| Date::parse_from_str(datestr, "%Y/%m/%d"))
+        .or_else(|_| Date::from_str(datestr));
+    if let Ok(date) = result {
+        return Ok(date);
+    }
+
+    // Times of day in the file's time zone
+    let local = NaiveDateTime::parse_from_str(datestr, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(datestr, "%Y-%m-%d %H:%M"))
+        .map_err(|e| Error::CsvError(format!("{}: {}", datestr, e)))?;
+    Date::from_local(&local, timezone).ok_or_else(|| {
+        Error::CsvError(format!("{} did not happen in {}", datestr, timezone.name()))
+    })
+}
+
+/// Reads a Unix timestamp in seconds, milliseconds, or microseconds, going by
+/// how many digits it has. Timestamps at midnight UTC are whole days.
+fn parse_unix_time(s: &str) -> Option<Date> {
+    let t: i64 = s.trim().parse().ok()?;
+    let (secs, nanos) = match t.abs() {
+        // Seconds until the year 5138
+        n if n < 100_000_000_000 => (t, 0),
+        n if n < 100_000_000_000_000 => (t.div_euclid(1000), t.rem_euclid(1000) * 1_000_000),
+        _ => (t.div_euclid(1_000_000), t.rem_euclid(1_000_000) * 1000),
+    };
+    Utc.timestamp_opt(secs, nanos as u32)
+        .single()
+        .map(Date::from)
 }
 
 fn get_at_row_idx(row: &StringRecord, idx: usize) -> Result<&str, Error> {
@@ -57,31 +159,132 @@ fn get_at_row_idx(row: &StringRecord, idx: usize) -> Result<&str, Error> {
         .ok_or(Error::CsvError(format!("No field at index {}", idx)))
 }
 
-fn required_field_indices(row: &StringRecord) -> Result<(usize, usize), Error> {
-    let mut date_index = -1;
-    let mut adj_close_index = -1;
-    for (i, field) in row.iter().enumerate() {
-        let lowered = field.to_lowercase();
-        match lowered.as_str() {
-            "date" => date_index = i as i32,
-            "adjusted close" | "adj. close" => adj_close_index = i as i32,
-            _ => {}
+fn parse_at_row_idx(row: &StringRecord, idx: usize) -> Result<f64, Error> {
+    f64::from_str(get_at_row_idx(row, idx)?.trim()).map_err(|e| Error::CsvError(e.to_string()))
+}
+
+/// Where each column of interest is in the CSV file.
+struct ColumnIndices {
+    date: usize,
+    open: Option<usize>,
+    high: Option<usize>,
+    low: Option<usize>,
+    close: Option<usize>,
+    adjusted_close: Option<usize>,
+    volume: Option<usize>,
+    split: Option<usize>,
+    dividend: Option<usize>,
+}
+
+impl ColumnIndices {
+    fn from_headers(row: &StringRecord) -> Result<Self, Error> {
+        let mut date = None;
+        let mut columns = ColumnIndices {
+            date: 0,
+            open: None,
+            high: None,
+            low: None,
+            close: None,
+            adjusted_close: None,
+            volume: None,
+            split: None,
+            dividend: None,
+        };
+        for (i, field) in row.iter().enumerate() {
+            let lowered = field.trim().to_lowercase().replace('_', " ");
+            match lowered.as_str() {
+                "date" | "timestamp" | "time" | "open time" | "unix" | "datetime" => {
+                    date.get_or_insert(i);
+                }
+                "open" => columns.open = Some(i),
+                "high" => columns.high = Some(i),
+                "low" => columns.low = Some(i),
+                "close" => columns.close = Some(i),
+                "adjusted close" | "adj. close" => columns.adjusted_close = Some(i),
+                // The first of e.g. "volume btc" and "volume usdt"
+                v if v == "volume" || v.starts_with("volume ") => {
+                    columns.volume.get_or_insert(i);
+                }
+                "split" | "split coefficient" | "split ratio" | "stock splits" => {
+                    columns.split = Some(i)
+                }
+                "dividend" | "dividends" | "dividend amount" => columns.dividend = Some(i),
+                _ => {}
+            }
+        }
+
+        match date {
+            Some(i) if columns.close.is_some() || columns.adjusted_close.is_some() => {
+                columns.date = i;
+                Ok(columns)
+            }
+            _ => Err(Error::CsvError(
+                "Header does not have required columns (\"date\" and either \"adjusted close\", \"adj. close\", or \"close\")".to_string()
+            )),
         }
     }
 
-    if date_index == -1 || adj_close_index == -1 {
-        return Err(Error::CsvError(
-            "Header does not have required columns (\"date\" and either \"adjusted close\" or \"adj. close\")".to_string()
-        ));
+    /// The columns of CsvProfile::Exchange files.
+    fn exchange() -> Self {
+        ColumnIndices {
+            date: 0,
+            open: Some(1),
+            high: Some(2),
+            low: Some(3),
+            close: Some(4),
+            adjusted_close: None,
+            volume: Some(5),
+            split: None,
+            dividend: None,
+        }
     }
 
-    Ok((date_index as usize, adj_close_index as usize))
+    /// Reads a Bar out of a row. Missing prices are filled in from the close.
+    fn bar(&self, row: &StringRecord) -> Result<Bar, Error> {
+        let optional = |idx: Option<usize>| idx.map(|i| parse_at_row_idx(row, i)).transpose();
+
+        let close = optional(self.close)?;
+        let adjusted_close = optional(self.adjusted_close)?;
+        // One of the two is guaranteed to exist by from_headers()
+        let close = close.or(adjusted_close).expect("No close column");
+        let adjusted_close = adjusted_close.unwrap_or(close);
+
+        Ok(Bar {
+            open: optional(self.open)?.unwrap_or(close),
+            high: optional(self.high)?.unwrap_or(close),
+            low: optional(self.low)?.unwrap_or(close),
+            close,
+            adjusted_close,
+            volume: optional(self.volume)?.unwrap_or(0.0),
+        })
+    }
+
+    /// Reads the split and dividend out of a row, if there were any. Blank
+    /// fields mean there weren't, as does a split of 0 (which some sources use
+    /// instead of 1).
+    fn action(&self, row: &StringRecord) -> Result<Option<CorporateAction>, Error> {
+        let optional = |idx: Option<usize>| -> Result<Option<f64>, Error> {
+            match idx {
+                Some(i) if !get_at_row_idx(row, i)?.trim().is_empty() => {
+                    parse_at_row_idx(row, i).map(Some)
+                }
+                _ => Ok(None),
+            }
+        };
+
+        let action = CorporateAction {
+            split: optional(self.split)?.filter(|s| *s != 0.0).unwrap_or(1.0),
+            dividend: optional(self.dividend)?.unwrap_or(0.0),
+        };
+        Ok(Some(action).filter(|a| !a.is_none()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::CsvFile;
-    use crate::{datasource::DataSource, date::Date};
+    use super::{CsvFile, CsvProfile};
+    use crate::{datasource::DataSource, date::Date, marketdata::CorporateAction};
+    use chrono_tz::America::New_York;
     use std::path::Path;
 
     #[test]
@@ -93,5 +296,80 @@ mod tests {
             prices.first_entry().unwrap(),
             (&Date::from_ymd(2018, 10, 02), &301.0200)
         );
+
+        let bar = prices.get_bar(&Date::from_ymd(2018, 10, 15)).unwrap();
+        assert_eq!(bar.open, 259.06);
+        assert_eq!(bar.high, 263.28);
+        assert_eq!(bar.low, 254.5367);
+        assert_eq!(bar.close, 259.59);
+        assert_eq!(bar.adjusted_close, 259.59);
+        assert_eq!(bar.volume, 6189026.0);
+    }
+
+    #[test]
+    fn csv_with_corporate_actions() {
+        let a = CsvFile::new(Path::new("test/csv/actions.csv")).unwrap();
+        let prices = a.get("JPM").unwrap();
+
+        assert_eq!(prices.map.len(), 5);
+        assert_eq!(
+            prices.actions.into_iter().collect::<Vec<_>>(),
+            vec![
+                (Date::from_ymd(2020, 6, 3), CorporateAction::split(2.0)),
+                (Date::from_ymd(2020, 6, 5), CorporateAction::dividend(0.9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_with_times_of_day() {
+        let a = CsvFile::new(Path::new("test/csv/intraday.csv"))
+            .unwrap()
+            .with_timezone(New_York);
+        let prices = a.get("JPM").unwrap();
+
+        let times: Vec<String> = prices.map.keys().map(|t| t.to_string()).collect();
+        assert_eq!(
+            times,
+            vec![
+                "2020-04-03T09:30:00-04:00",
+                "2020-04-03T09:31:00-04:00",
+                // Given in UTC
+                "2020-04-03T13:32:00+00:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn crypto_exchange_exports() {
+        // Binance klines, with times in milliseconds
+        let a = CsvFile::new(Path::new("test/csv/binance.csv"))
+            .unwrap()
+            .with_profile(CsvProfile::Exchange);
+        let prices = a.get("BTCUSDT").unwrap();
+        assert_eq!(prices.map.len(), 3);
+        let bar = prices.get_bar(&Date::from_ymd(2021, 1, 2)).unwrap();
+        assert_eq!((bar.open, bar.close), (29331.70, 32178.33));
+        assert_eq!(bar.volume, 129993.873362);
+
+        // Coinbase candles, with times in seconds and columns in their own
+        // order
+        let a = CsvFile::new(Path::new("test/csv/coinbase.csv")).unwrap();
+        let prices = a.get("BTC-USD").unwrap();
+        let times: Vec<String> = prices.map.keys().map(|t| t.to_string()).collect();
+        assert_eq!(times, vec!["2021-01-01", "2021-01-01T01:00:00+00:00"]);
+        assert_eq!(prices.get(&Date::from_ymd(2021, 1, 1)), Some(&29331.69));
+
+        assert_eq!(
+            "binance".parse::<CsvProfile>().unwrap(),
+            CsvProfile::Exchange
+        );
+        assert!("excel".parse::<CsvProfile>().is_err());
+    }
+
+    #[test]
+    fn csv_missing_close_column() {
+        let a = CsvFile::new(Path::new("test/csv/invalid.csv")).unwrap();
+        assert!(a.get("TSLA").is_err());
     }
 }
@@ -8,6 +8,7 @@ use strum_macros::EnumIter;
 use thiserror::Error;
 
 pub mod alphavantage;
+pub mod cache;
 pub mod csv;
 pub mod techalyzerjson;
 
@@ -81,6 +82,18 @@ pub enum Error {
     #[error("Error with CSV file: {0}")]
     CsvError(String),
 
+    #[error("Unknown CSV profile '{0}' (supported: headers, exchange)")]
+    UnknownCsvProfile(String),
+
+    #[error("No cached data for '{0}' (run without --offline to download it)")]
+    NotCached(String),
+
+    #[error("Error with the price data cache: {0}")]
+    CacheError(String),
+
+    #[error("Path '{0}' must contain {{symbol}} to load more than one symbol from it")]
+    NeedsSymbolPlaceholder(String),
+
     #[error("Other error: {msg:?} (context: {context:?})")]
     Other { msg: String, context: String },
 }
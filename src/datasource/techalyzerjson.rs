@@ -51,10 +51,7 @@ impl DataSource for TechalyzerJson {
             });
         }
 
-        Ok(Prices {
-            map: data.map.iter().map(|e| (*e.0, e.1.price)).collect(),
-            symbol: data.symbol,
-        })
+        Ok(data.into())
     }
 }
 
@@ -97,12 +94,6 @@ mod tests {
             Date::parse_from_str("2020-03-12", "%Y-%m-%d").unwrap(),
             88.05,
         );
-        assert_eq!(
-            p,
-            Prices {
-                symbol: "jpm".to_string(),
-                map: m
-            }
-        );
+        assert_eq!(p, Prices::from_closes("jpm", m));
     }
 }
@@ -1,18 +1,28 @@
-//! A wrapper for Date with some conveniences and trait impls useful for
+//! A wrapper for a timestamp with some conveniences and trait impls useful for
 //! Techalyzer.
 
 use alphavantage::time_series::Entry;
-use chrono::{Duration, NaiveDate, NaiveDateTime, ParseError};
-use derive_more::Display;
-use serde::{Deserialize, Serialize};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    ParseError, TimeZone, Weekday,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
+    fmt::Display,
     ops::{Add, Sub},
     str::FromStr,
 };
 
-/// Wraps Date and provides a default value of today's date.
-#[derive(Debug, Display, PartialEq, Serialize, Deserialize, Ord, PartialOrd, Eq, Clone, Copy)]
-pub struct Date(NaiveDate);
+/// The time a bar of price data (or a trade, portfolio value, etc) is for.
+///
+/// Daily data is keyed by the day alone, which is midnight UTC of that day and
+/// is written `2020-01-02`. Intraday data is keyed by a timezone-aware
+/// timestamp (the start of the bar), written in RFC 3339 (e.g.
+/// `2020-01-02T09:30:00-05:00`). Dates compare by the instant they refer to.
+///
+/// Defaults to today's date.
+#[derive(Debug, PartialEq, Ord, PartialOrd, Eq, Clone, Copy, Hash)]
+pub struct Date(DateTime<FixedOffset>);
 
 impl Date {
     pub fn from_ymd(year: i32, month: u32, day: u32) -> Self {
@@ -23,7 +33,48 @@ impl Date {
         NaiveDate::parse_from_str(s, fmt).map(|ok| ok.into())
     }
 
-    /// Creates an inclusive range of Dates from start to end.
+    /// The timestamp of the wall clock time `local` in `timezone`, or None if
+    /// that time was skipped (by a daylight saving time change). Ambiguous
+    /// times resolve to the earlier of the two.
+    pub fn from_local<Tz: TimeZone>(local: &NaiveDateTime, timezone: &Tz) -> Option<Self> {
+        timezone
+            .from_local_datetime(local)
+            .earliest()
+            .map(Self::from)
+    }
+
+    /// The same instant, in `timezone`.
+    pub fn with_timezone<Tz: TimeZone>(&self, timezone: &Tz) -> Self {
+        self.0.with_timezone(timezone).into()
+    }
+
+    /// True if this is a whole day rather than a time of day (see `day`).
+    pub fn is_day(&self) -> bool {
+        self.0.offset().local_minus_utc() == 0 && self.0.time() == NaiveTime::from_hms(0, 0, 0)
+    }
+
+    /// The day this falls on (in its own time zone), as a whole day.
+    pub fn day(&self) -> Self {
+        self.0.naive_local().date().into()
+    }
+
+    /// The day of the week this falls on (in its own time zone).
+    pub fn weekday(&self) -> Weekday {
+        self.0.weekday()
+    }
+
+    /// The last instant of the day this falls on (in its own time zone).
+    pub fn end_of_day(&self) -> Self {
+        let last = self
+            .0
+            .naive_local()
+            .date()
+            .and_hms_nano(23, 59, 59, 999_999_999);
+        Self::from_local(&last, self.0.offset()).unwrap_or(*self)
+    }
+
+    /// Creates a range of every calendar day from start up to (but not
+    /// including) end. See `TradingCalendar::trading_days` for trading days.
     // TODO: this is mainly to get around limitations of Range/RangeBounds.
     // try replacing it with some type that guarantees sorting
     pub fn range(start: Date, end: Date) -> Vec<Date> {
@@ -39,7 +90,7 @@ impl Date {
 }
 
 pub fn today() -> Date {
-    Date(chrono::Utc::now().naive_local().date())
+    chrono::Utc::now().naive_local().date().into()
 }
 
 impl Default for Date {
@@ -48,22 +99,63 @@ impl Default for Date {
     }
 }
 
+impl Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_day() {
+            write!(f, "{}", self.0.format("%Y-%m-%d"))
+        } else {
+            write!(f, "{}", self.0.to_rfc3339())
+        }
+    }
+}
+
+/// Parses either a day (`2020-01-02`) or an RFC 3339 timestamp.
 impl FromStr for Date {
     type Err = chrono::ParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(NaiveDate::from_str(s)?))
+        NaiveDate::from_str(s)
+            .map(Self::from)
+            .or_else(|_| DateTime::parse_from_rfc3339(s).map(Self))
+    }
+}
+
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
     }
 }
 
 impl From<NaiveDate> for Date {
     fn from(d: NaiveDate) -> Self {
-        Self(d)
+        Self(DateTime::from_utc(d.and_hms(0, 0, 0), FixedOffset::east(0)))
+    }
+}
+
+/// The day a Date falls on, in its own time zone.
+impl From<Date> for NaiveDate {
+    fn from(d: Date) -> Self {
+        d.0.naive_local().date()
     }
 }
 
+impl<Tz: TimeZone> From<DateTime<Tz>> for Date {
+    fn from(d: DateTime<Tz>) -> Self {
+        let offset = d.offset().fix();
+        Self(d.with_timezone(&offset))
+    }
+}
+
+/// Only keeps the day.
 impl From<NaiveDateTime> for Date {
     fn from(d: NaiveDateTime) -> Self {
-        Self(d.date())
+        d.date().into()
     }
 }
 
@@ -80,9 +172,51 @@ impl Sub<Duration> for Date {
     }
 }
 
+impl Sub<Date> for Date {
+    type Output = Duration;
+    fn sub(self, rhs: Date) -> Self::Output {
+        self.0.signed_duration_since(rhs.0)
+    }
+}
+
 impl Add<Duration> for Date {
     type Output = Date;
     fn add(self, rhs: Duration) -> Self::Output {
         Date(self.0 + rhs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::New_York;
+
+    #[test]
+    fn days_and_timestamps() {
+        let day = Date::from_ymd(2020, 3, 9);
+        assert!(day.is_day());
+        assert_eq!(day.to_string(), "2020-03-09");
+        assert_eq!("2020-03-09".parse(), Ok(day));
+
+        // The morning after DST started
+        let local = NaiveDate::from_ymd(2020, 3, 9).and_hms(9, 30, 0);
+        let open = Date::from_local(&local, &New_York).unwrap();
+        assert!(!open.is_day());
+        assert_eq!(open.to_string(), "2020-03-09T09:30:00-04:00");
+        assert_eq!(open.to_string().parse(), Ok(open));
+        assert_eq!(open.day(), day);
+        assert!(day < open && open < day.end_of_day());
+        assert_eq!(NaiveDate::from(open), NaiveDate::from_ymd(2020, 3, 9));
+
+        // 2:30 AM didn't happen that Sunday
+        let skipped = NaiveDate::from_ymd(2020, 3, 8).and_hms(2, 30, 0);
+        assert_eq!(Date::from_local(&skipped, &New_York), None);
+
+        let json = serde_json::to_string(&vec![day, open]).unwrap();
+        assert_eq!(json, r#"["2020-03-09","2020-03-09T09:30:00-04:00"]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<Date>>(&json).unwrap(),
+            vec![day, open]
+        );
+    }
+}
@@ -7,10 +7,17 @@
 //! are stabilized (can just write `impl FromStr<T: ToString> for TechalyzerError`).
 
 use crate::{
-    backtester::{performance::PerformanceError, BackTesterError},
-    indicators::SupportedIndicators,
-    marketdata::prices::PricesError,
-    trading::{buyandhold::BuyAndHoldError, dtmodel::DecisionTreeError, manual::CanNeverHappen},
+    backtester::{performance::PerformanceError, walkforward::WalkForwardError, BackTesterError},
+    indicators::{IndicatorSpecError, SupportedIndicators},
+    marketdata::{prices::PricesError, validation::ValidationError},
+    optimizer::OptimizerError,
+    plot::PlotError,
+    trading::{
+        buyandhold::BuyAndHoldError, dtmodel::DecisionTreeError, ensemble::EnsembleError,
+        features::FeatureError, labeling::LabelerError, manual::CanNeverHappen,
+        modelfile::ModelFileError, qlearner::QLearningError, sizing::SizingError,
+    },
+    tuning::TuningError,
 };
 use derive_more::From;
 use strum::VariantNames;
@@ -30,6 +37,18 @@ pub enum TechalyzerError {
 
     #[error("Please supply a model file.")]
     NoModelFileSpecified,
+
+    #[error("Please supply an ensemble config file.")]
+    NoEnsembleSpecified,
+
+    #[error("Please supply a data source.")]
+    NoDataSourceSpecified,
+
+    #[error("Please supply a symbol.")]
+    NoSymbolSpecified,
+
+    #[error("Only backtests support more than one symbol")]
+    MultipleSymbolsUnsupported,
 }
 
 fn list_of_indicators() -> String {
@@ -56,9 +75,22 @@ impl_techalyzer_error_from_stringable_type!(PerformanceError);
 impl_techalyzer_error_from_stringable_type!(BuyAndHoldError);
 impl_techalyzer_error_from_stringable_type!(CanNeverHappen);
 impl_techalyzer_error_from_stringable_type!(DecisionTreeError);
+impl_techalyzer_error_from_stringable_type!(EnsembleError);
+impl_techalyzer_error_from_stringable_type!(FeatureError);
+impl_techalyzer_error_from_stringable_type!(LabelerError);
+impl_techalyzer_error_from_stringable_type!(ModelFileError);
+impl_techalyzer_error_from_stringable_type!(QLearningError);
 impl_techalyzer_error_from_stringable_type!(std::io::Error);
 impl_techalyzer_error_from_stringable_type!(bincode::Error);
 impl_techalyzer_error_from_stringable_type!(bincode::ErrorKind);
 impl_techalyzer_error_from_stringable_type!(BackTesterError);
+impl_techalyzer_error_from_stringable_type!(WalkForwardError);
+impl_techalyzer_error_from_stringable_type!(OptimizerError);
+impl_techalyzer_error_from_stringable_type!(TuningError);
+impl_techalyzer_error_from_stringable_type!(csv::Error);
 impl_techalyzer_error_from_stringable_type!(PricesError);
+impl_techalyzer_error_from_stringable_type!(IndicatorSpecError);
+impl_techalyzer_error_from_stringable_type!(SizingError);
+impl_techalyzer_error_from_stringable_type!(PlotError);
+impl_techalyzer_error_from_stringable_type!(ValidationError);
 impl_techalyzer_error_from_stringable_type!(crate::trading::ml::mlmodel::Error);
@@ -1,7 +1,16 @@
-use crate::{config::TrainingParams, signals::SignalsIter};
+use crate::{
+    config::TrainingParams,
+    signals::{
+        bollingerbandssignals::BBSignalsIter, macdsignals::MACDSignalsIter,
+        relativestrengthindexsignals::RSISignalsIter, smacrossovers::SmaCrossoversSignalsIter,
+        SignalsIter,
+    },
+};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Display, str::FromStr};
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+use strum::VariantNames;
 use strum_macros::{Display, EnumIter, EnumString, EnumVariantNames};
+use thiserror::Error;
 
 /// The list of technical indicators supported by Techalyzer.
 #[derive(
@@ -29,7 +38,272 @@ pub enum SupportedIndicators {
     SmaCrossover,
 }
 
-fn default_indicators() -> Vec<SupportedIndicators> {
+/// A technical indicator along with the parameters to construct it with.
+///
+/// Written as the indicator's name, optionally followed by a colon and a
+/// comma-separated list of parameters, e.g. `rsi:14`, `bb:20,2.0`,
+/// `macd:12,26,9` or `SmaCrossover:50,200`. Parameters that are left off use
+/// the indicator's defaults, so `bb` and `bb:20` are also valid.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum IndicatorSpec {
+    BollingerBands { length: u32, multiplier: f64 },
+    RelativeStrengthIndex { window: u32 },
+    MACD { fast: u32, slow: u32, signal: u32 },
+    SmaCrossover { fast: u32, slow: u32 },
+}
+
+/// Errors from parsing or validating an IndicatorSpec.
+#[derive(Debug, Error, PartialEq)]
+pub enum IndicatorSpecError {
+    #[error("Unknown indicator '{0}' (supported: {})", SupportedIndicators::VARIANTS.join(", "))]
+    UnknownIndicator(String),
+
+    #[error("{indicator} takes at most {expected} parameter(s) ({names}), got {found}")]
+    TooManyParameters {
+        indicator: SupportedIndicators,
+        expected: usize,
+        names: String,
+        found: usize,
+    },
+
+    #[error("Invalid value '{value}' for parameter '{param}' of {indicator}: {reason}")]
+    InvalidParameter {
+        indicator: SupportedIndicators,
+        param: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    #[error("At least one indicator is needed")]
+    NoIndicators,
+}
+
+impl IndicatorSpec {
+    /// Which indicator this is a spec for.
+    pub fn indicator(&self) -> SupportedIndicators {
+        match self {
+            Self::BollingerBands { .. } => SupportedIndicators::BollingerBands,
+            Self::RelativeStrengthIndex { .. } => SupportedIndicators::RelativeStrengthIndex,
+            Self::MACD { .. } => SupportedIndicators::MACD,
+            Self::SmaCrossover { .. } => SupportedIndicators::SmaCrossover,
+        }
+    }
+
+    /// Names of the indicator's parameters, in the order they are written.
+    pub fn param_names(&self) -> &'static [&'static str] {
+        match self {
+            Self::BollingerBands { .. } => &["length", "multiplier"],
+            Self::RelativeStrengthIndex { .. } => &["window"],
+            Self::MACD { .. } => &["fast", "slow", "signal"],
+            Self::SmaCrossover { .. } => &["fast", "slow"],
+        }
+    }
+
+    /// Checks that the parameters can be used to construct the indicator.
+    pub fn validate(&self) -> Result<(), IndicatorSpecError> {
+        let indicator = self.indicator();
+        let invalid = |param: &'static str, value: &dyn Display, reason: &str| {
+            Err(IndicatorSpecError::InvalidParameter {
+                indicator,
+                param,
+                value: value.to_string(),
+                reason: reason.to_string(),
+            })
+        };
+        let positive = |param: &'static str, value: u32| match value {
+            0 => invalid(param, &value, "must be greater than zero"),
+            _ => Ok(()),
+        };
+
+        match *self {
+            Self::BollingerBands { length, multiplier } => {
+                positive("length", length)?;
+                if !(multiplier.is_finite() && multiplier > 0.0) {
+                    return invalid("multiplier", &multiplier, "must be greater than zero");
+                }
+            }
+            Self::RelativeStrengthIndex { window } => positive("window", window)?,
+            Self::MACD { fast, slow, signal } => {
+                positive("fast", fast)?;
+                positive("slow", slow)?;
+                positive("signal", signal)?;
+                if fast >= slow {
+                    return invalid("fast", &fast, "must be less than the slow length");
+                }
+            }
+            Self::SmaCrossover { fast, slow } => {
+                positive("fast", fast)?;
+                positive("slow", slow)?;
+                if fast == slow {
+                    return invalid("fast", &fast, "must differ from the slow window");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Constructs a signal generator for the indicator.
+    pub fn signals_iter(&self) -> Result<Box<dyn SignalsIter>, IndicatorSpecError> {
+        self.validate()?;
+
+        // Validation should have caught anything the constructors reject.
+        let failed = |e: ta::errors::ErrorKind| IndicatorSpecError::InvalidParameter {
+            indicator: self.indicator(),
+            param: "(all)",
+            value: self.to_string(),
+            reason: format!("{:?}", e),
+        };
+
+        Ok(match *self {
+            Self::BollingerBands { length, multiplier } => {
+                Box::new(BBSignalsIter::new(length, multiplier).map_err(failed)?)
+            }
+            Self::RelativeStrengthIndex { window } => {
+                Box::new(RSISignalsIter::new(window).map_err(failed)?)
+            }
+            Self::MACD { fast, slow, signal } => {
+                Box::new(MACDSignalsIter::new(fast, slow, signal).map_err(failed)?)
+            }
+            Self::SmaCrossover { fast, slow } => {
+                Box::new(SmaCrossoversSignalsIter::new(fast, slow).map_err(failed)?)
+            }
+        })
+    }
+}
+
+/// The default parameters for each indicator.
+impl From<SupportedIndicators> for IndicatorSpec {
+    fn from(s: SupportedIndicators) -> Self {
+        match s {
+            SupportedIndicators::BollingerBands => Self::BollingerBands {
+                length: 9,
+                multiplier: 2.0,
+            },
+            SupportedIndicators::RelativeStrengthIndex => {
+                Self::RelativeStrengthIndex { window: 14 }
+            }
+            SupportedIndicators::MACD => Self::MACD {
+                fast: 12,
+                slow: 26,
+                signal: 9,
+            },
+            SupportedIndicators::SmaCrossover => Self::SmaCrossover {
+                fast: 50,
+                slow: 200,
+            },
+        }
+    }
+}
+
+/// Hands out an indicator's parameters one at a time, falling back to
+/// defaults for any that were left off.
+struct ParamParser<'a> {
+    indicator: SupportedIndicators,
+    values: std::vec::IntoIter<&'a str>,
+}
+
+impl<'a> ParamParser<'a> {
+    fn next<T: FromStr>(
+        &mut self,
+        param: &'static str,
+        default: T,
+    ) -> Result<T, IndicatorSpecError> {
+        match self.values.next() {
+            None => Ok(default),
+            Some(v) => v.parse().map_err(|_| IndicatorSpecError::InvalidParameter {
+                indicator: self.indicator,
+                param,
+                value: v.to_string(),
+                reason: format!("expected a {}", std::any::type_name::<T>()),
+            }),
+        }
+    }
+}
+
+impl FromStr for IndicatorSpec {
+    type Err = IndicatorSpecError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.trim().splitn(2, ':');
+        let name = split.next().unwrap_or_default();
+        let indicator = SupportedIndicators::from_str(name)
+            .map_err(|_| IndicatorSpecError::UnknownIndicator(name.to_string()))?;
+        let defaults = IndicatorSpec::from(indicator);
+
+        let values: Vec<&str> = match split.next() {
+            Some(params) => params.split(',').map(str::trim).collect(),
+            None => Vec::new(),
+        };
+        let names = defaults.param_names();
+        if values.len() > names.len() {
+            return Err(IndicatorSpecError::TooManyParameters {
+                indicator,
+                expected: names.len(),
+                names: names.join(", "),
+                found: values.len(),
+            });
+        }
+
+        let mut p = ParamParser {
+            indicator,
+            values: values.into_iter(),
+        };
+        let spec = match defaults {
+            Self::BollingerBands { length, multiplier } => Self::BollingerBands {
+                length: p.next("length", length)?,
+                multiplier: p.next("multiplier", multiplier)?,
+            },
+            Self::RelativeStrengthIndex { window } => Self::RelativeStrengthIndex {
+                window: p.next("window", window)?,
+            },
+            Self::MACD { fast, slow, signal } => Self::MACD {
+                fast: p.next("fast", fast)?,
+                slow: p.next("slow", slow)?,
+                signal: p.next("signal", signal)?,
+            },
+            Self::SmaCrossover { fast, slow } => Self::SmaCrossover {
+                fast: p.next("fast", fast)?,
+                slow: p.next("slow", slow)?,
+            },
+        };
+
+        spec.validate()?;
+        Ok(spec)
+    }
+}
+
+impl Display for IndicatorSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BollingerBands { length, multiplier } => {
+                write!(f, "{}:{},{}", self.indicator(), length, multiplier)
+            }
+            Self::RelativeStrengthIndex { window } => write!(f, "{}:{}", self.indicator(), window),
+            Self::MACD { fast, slow, signal } => {
+                write!(f, "{}:{},{},{}", self.indicator(), fast, slow, signal)
+            }
+            Self::SmaCrossover { fast, slow } => {
+                write!(f, "{}:{},{}", self.indicator(), fast, slow)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for IndicatorSpec {
+    type Error = IndicatorSpecError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<IndicatorSpec> for String {
+    fn from(s: IndicatorSpec) -> Self {
+        s.to_string()
+    }
+}
+
+fn default_indicators() -> Vec<IndicatorSpec> {
     TrainingParams::default().signal_generators.0
 }
 
@@ -39,12 +313,12 @@ impl From<&SupportedIndicators> for Box<dyn SignalsIter> {
     }
 }
 
-/// Newtype wrapper for a vector of SupportedIndicator. This type exists solely
+/// Newtype wrapper for a vector of IndicatorSpec. This type exists solely
 /// because Strum errors out when the user passes an empty Vec of enums, but we
 /// want that to be an acceptable input for Techalyzer (should use a default).
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(transparent)]
-pub struct ListOfIndicators(pub Vec<SupportedIndicators>);
+pub struct ListOfIndicators(pub Vec<IndicatorSpec>);
 
 impl ListOfIndicators {
     pub fn is_empty(&self) -> bool {
@@ -52,7 +326,7 @@ impl ListOfIndicators {
     }
 }
 
-impl From<ListOfIndicators> for Vec<SupportedIndicators> {
+impl From<ListOfIndicators> for Vec<IndicatorSpec> {
     fn from(l: ListOfIndicators) -> Self {
         l.0
     }
@@ -66,20 +340,88 @@ impl Default for ListOfIndicators {
 
 impl Display for ListOfIndicators {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let strings: Vec<String> = self.0.iter().map(SupportedIndicators::to_string).collect();
+        let strings: Vec<String> = self.0.iter().map(IndicatorSpec::to_string).collect();
         write!(f, "{}", strings.join(" "))
     }
 }
 
 impl FromStr for ListOfIndicators {
-    type Err = strum::ParseError;
+    type Err = IndicatorSpecError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // Split on whitespace and then parse as strings
+        // Split on whitespace and then parse each spec
         let mut res = ListOfIndicators(Vec::new());
         for indic in s.split_whitespace() {
-            res.0.push(SupportedIndicators::from_str(indic)?);
+            res.0.push(IndicatorSpec::from_str(indic)?);
         }
 
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{IndicatorSpec, IndicatorSpecError, ListOfIndicators, SupportedIndicators};
+
+    #[test]
+    fn parse_specs() {
+        let l: ListOfIndicators = "rsi:10 bb:20,2.5 macd:5,35,5 SmaCrossover:20,50 bb:20 MACD"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            l.0,
+            vec![
+                IndicatorSpec::RelativeStrengthIndex { window: 10 },
+                IndicatorSpec::BollingerBands {
+                    length: 20,
+                    multiplier: 2.5
+                },
+                IndicatorSpec::MACD {
+                    fast: 5,
+                    slow: 35,
+                    signal: 5
+                },
+                IndicatorSpec::SmaCrossover { fast: 20, slow: 50 },
+                IndicatorSpec::BollingerBands {
+                    length: 20,
+                    multiplier: 2.0
+                },
+                SupportedIndicators::MACD.into(),
+            ]
+        );
+
+        // Round trips through Display
+        let again: ListOfIndicators = l.to_string().parse().unwrap();
+        assert_eq!(l, again);
+    }
+
+    #[test]
+    fn errors_name_the_bad_parameter() {
+        match "bb:20,wide".parse::<IndicatorSpec>() {
+            Err(IndicatorSpecError::InvalidParameter { param, value, .. }) => {
+                assert_eq!(param, "multiplier");
+                assert_eq!(value, "wide");
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+
+        match "rsi:0".parse::<IndicatorSpec>() {
+            Err(e @ IndicatorSpecError::InvalidParameter { .. }) => {
+                assert!(e.to_string().contains("'window'"))
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+
+        assert!(matches!(
+            "macd:26,12,9".parse::<IndicatorSpec>(),
+            Err(IndicatorSpecError::InvalidParameter { param: "fast", .. })
+        ));
+        assert!(matches!(
+            "rsi:14,3".parse::<IndicatorSpec>(),
+            Err(IndicatorSpecError::TooManyParameters { found: 2, .. })
+        ));
+        assert!(matches!(
+            "stochastic:14".parse::<IndicatorSpec>(),
+            Err(IndicatorSpecError::UnknownIndicator(_))
+        ));
+    }
+}
@@ -7,81 +7,128 @@
 
 #[deny(missing_docs)]
 pub mod backtester;
+pub mod calendar;
 pub mod config;
 pub mod datasource;
 pub mod date;
 pub mod error;
 pub mod indicators;
 pub mod marketdata;
+pub mod optimizer;
 pub mod output;
+pub mod plot;
 pub mod secret;
 pub mod signals;
 pub mod subcommands;
 pub mod trading;
+pub mod tuning;
 pub mod util;
 
 use crate::datasource::alphavantage;
+use crate::datasource::cache::{CacheConfig, CachedDataSource};
 use crate::datasource::techalyzerjson::TechalyzerJson;
 use crate::datasource::SupportedDataSource;
 use crate::datasource::{DataSource, Error};
 use crate::marketdata::prices::Prices;
 use ::alphavantage::blocking::Client;
-use datasource::csv::CsvFile;
+use chrono_tz::Tz;
+use datasource::csv::{CsvFile, CsvProfile};
 use date::Date;
 use secret::Secret;
-use std::ops::RangeInclusive;
-use util::Symbol;
+use std::{ops::RangeInclusive, path::PathBuf};
+use util::Symbols;
 
-/// Gets stock price time series data from a given `SupportedDataSource`, within the given
-/// date range. A Secret is used to access the data source, if necessary.
+/// Gets stock price time series data for each of `symbols` from a given
+/// `SupportedDataSource`, within the given date range. A Secret is used to
+/// access the data source, if necessary.
+///
+/// Data downloaded from an API is cached as set by `cache`, so that later runs
+/// only download what they don't already have.
+///
+/// Data files hold one symbol each, so to load several symbols from files,
+/// put `{symbol}` in the file path where the symbol goes (e.g.
+/// `data/{symbol}.csv`).
+///
+/// Times of day without an offset in CSV files are read as being in
+/// `timezone` (usually that of the exchange's trading session), and their
+/// columns are laid out as in `csv_profile`.
 pub fn get_market_data(
     source: SupportedDataSource,
-    symbol: Symbol,
+    symbols: Symbols,
     date_range: RangeInclusive<Date>,
     secret: Secret,
-) -> Result<Prices, Error> {
-    let market_data: Prices = match source {
-        SupportedDataSource::AlphaVantage => {
-            let key = secret.data.unwrap_or_else(|| "".to_string());
-            let cl = Client::new(key.as_str());
-            let av = alphavantage::AlphaVantage::new(cl);
-            av.get_date_range(symbol.as_str(), date_range)?
+    cache: &CacheConfig,
+    timezone: Tz,
+    csv_profile: CsvProfile,
+) -> Result<Vec<Prices>, Error> {
+    let several = symbols.len() > 1;
+    let for_symbol = |path: &PathBuf, symbol: &str| {
+        let path_str = path.to_string_lossy();
+        if path_str.contains(SYMBOL_PLACEHOLDER) {
+            Ok(PathBuf::from(path_str.replace(SYMBOL_PLACEHOLDER, symbol)))
+        } else if several {
+            Err(Error::NeedsSymbolPlaceholder(path_str.to_string()))
+        } else {
+            Ok(path.clone())
         }
-        SupportedDataSource::TechalyzerJson(path) => {
-            if !path.exists() {
-                return Err(Error::FileNotFound(
-                    path.into_os_string().into_string().expect("invalid string"),
-                ));
+    };
+
+    let mut market_data = Vec::new();
+    for symbol in symbols.iter() {
+        let prices = match &source {
+            SupportedDataSource::AlphaVantage => {
+                let key = secret.data.clone().unwrap_or_else(|| "".to_string());
+                let cl = Client::new(key.as_str());
+                let av = alphavantage::AlphaVantage::new(cl);
+                let cached = CachedDataSource::new(av, &cache.dir.join("alphavantage"), cache.mode);
+                cached.get_date_range(symbol.as_str(), date_range.clone())?
             }
+            SupportedDataSource::TechalyzerJson(path) => {
+                let path = for_symbol(path, symbol)?;
+                if !path.exists() {
+                    return Err(Error::FileNotFound(
+                        path.into_os_string().into_string().expect("invalid string"),
+                    ));
+                }
 
-            match TechalyzerJson::new(path.as_path()) {
-                Ok(t) => t.get_date_range(symbol.as_str(), date_range)?,
-                Err(io_err) => {
-                    return Err(Error::Other {
-                        msg: io_err.to_string(),
-                        context: format!("Tried to open {:?}", path),
-                    })
+                match TechalyzerJson::new(path.as_path()) {
+                    Ok(t) => t.get_date_range(symbol.as_str(), date_range.clone())?,
+                    Err(io_err) => {
+                        return Err(Error::Other {
+                            msg: io_err.to_string(),
+                            context: format!("Tried to open {:?}", path),
+                        })
+                    }
                 }
             }
-        }
-        SupportedDataSource::CsvFile(path) => {
-            if !path.exists() {
-                return Err(Error::FileNotFound(
-                    path.into_os_string().into_string().expect("invalid string"),
-                ));
-            }
+            SupportedDataSource::CsvFile(path) => {
+                let path = for_symbol(path, symbol)?;
+                if !path.exists() {
+                    return Err(Error::FileNotFound(
+                        path.into_os_string().into_string().expect("invalid string"),
+                    ));
+                }
 
-            match CsvFile::new(path.as_path()) {
-                Ok(csv) => csv.get_date_range(symbol.as_str(), date_range)?,
-                Err(e) => {
-                    return Err(Error::Other {
-                        msg: e.to_string(),
-                        context: format!("Tried to open {:?}", path),
-                    })
+                match CsvFile::new(path.as_path()) {
+                    Ok(csv) => csv
+                        .with_timezone(timezone)
+                        .with_profile(csv_profile)
+                        .get_date_range(symbol.as_str(), date_range.clone())?,
+                    Err(e) => {
+                        return Err(Error::Other {
+                            msg: e.to_string(),
+                            context: format!("Tried to open {:?}", path),
+                        })
+                    }
                 }
             }
-        }
-    };
+        };
+
+        market_data.push(prices);
+    }
 
     Ok(market_data)
 }
+
+/// Stands in for the symbol in data file paths.
+const SYMBOL_PLACEHOLDER: &str = "{symbol}";
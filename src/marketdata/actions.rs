@@ -0,0 +1,241 @@
+//! Corporate actions (stock splits and cash dividends), and back-adjusting
+//! prices for them.
+
+use super::Prices;
+use crate::{date::Date, util::TimeSeries};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Splits and dividends that go ex on one day.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+pub struct CorporateAction {
+    /// Shares held after the split for each share held before it (2.0 for a
+    /// 2-for-1 split). 1.0 if there was no split.
+    pub split: f64,
+
+    /// Cash dividend per share (after any split that day). 0.0 if there was no
+    /// dividend.
+    pub dividend: f64,
+}
+
+impl Default for CorporateAction {
+    fn default() -> Self {
+        Self {
+            split: 1.0,
+            dividend: 0.0,
+        }
+    }
+}
+
+impl CorporateAction {
+    /// A `ratio`-for-1 stock split.
+    pub fn split(ratio: f64) -> Self {
+        Self {
+            split: ratio,
+            ..Self::default()
+        }
+    }
+
+    /// A cash dividend of `amount` per share.
+    pub fn dividend(amount: f64) -> Self {
+        Self {
+            dividend: amount,
+            ..Self::default()
+        }
+    }
+
+    /// True if there is neither a split nor a dividend.
+    pub fn is_none(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// How to back-adjust prices for corporate actions.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Adjustment {
+    /// Use prices as they come from the data source.
+    None,
+
+    /// Adjust for splits only. Dividends are left for the backtester to credit
+    /// (or debit, on short positions) as cash.
+    #[default]
+    Splits,
+
+    /// Adjust for splits and dividends, so that returns include dividends and
+    /// there is nothing left for the backtester to credit.
+    All,
+}
+
+impl FromStr for Adjustment {
+    type Err = ActionsError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "splits" => Ok(Self::Splits),
+            "all" => Ok(Self::All),
+            _ => Err(ActionsError::UnknownAdjustment(s.to_string())),
+        }
+    }
+}
+
+impl Display for Adjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::None => "none",
+            Self::Splits => "splits",
+            Self::All => "all",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Errors working with corporate actions.
+#[derive(Debug, Error, PartialEq)]
+pub enum ActionsError {
+    #[error("Unknown price adjustment '{0}' (supported: none, splits, all)")]
+    UnknownAdjustment(String),
+}
+
+/// The adjustment for the actions after some day, built up from the latest
+/// action back.
+struct Running {
+    /// Multiplies the prices of the day.
+    factor: f64,
+
+    /// How many shares one of the day's shares has become.
+    shares: f64,
+
+    with_dividends: bool,
+}
+
+impl Running {
+    /// Takes in an action that went ex the day after the close of
+    /// `close_before`.
+    fn apply(&mut self, action: &CorporateAction, close_before: f64) {
+        if action.split > 0.0 {
+            self.factor /= action.split;
+            self.shares *= action.split;
+        }
+        if self.with_dividends && action.dividend > 0.0 && close_before > 0.0 {
+            // The dividend relative to the close the day before, in the same
+            // (post-split) shares.
+            let kept = 1.0 - action.dividend * action.split / close_before;
+            if kept > 0.0 {
+                self.factor *= kept;
+            }
+        }
+    }
+
+    /// Unless it is adjusted for, keeps the dividend of an action in terms of
+    /// the latest shares. Must be called before `apply` for the action.
+    fn keep_dividend(
+        &self,
+        kept: &mut TimeSeries<CorporateAction>,
+        ex_date: Date,
+        action: &CorporateAction,
+    ) {
+        if !self.with_dividends && action.dividend != 0.0 {
+            kept.insert(
+                ex_date,
+                CorporateAction::dividend(action.dividend / self.shares),
+            );
+        }
+    }
+}
+
+/// Sets the adjusted close of every day in `prices` to its close back-adjusted
+/// for the corporate actions that went ex after it, so that the latest prices
+/// are unchanged.
+///
+/// The actions adjusted for are removed from `prices.actions`. With
+/// Adjustment::Splits, the dividends left over are restated per split-adjusted
+/// share. Prices without any actions (including those whose source already
+/// adjusted them) are returned as they are.
+pub fn back_adjust(mut prices: Prices, adjustment: Adjustment) -> Prices {
+    if adjustment == Adjustment::None || prices.actions.is_empty() {
+        return prices;
+    }
+
+    let mut running = Running {
+        factor: 1.0,
+        shares: 1.0,
+        with_dividends: adjustment == Adjustment::All,
+    };
+    let mut remaining = TimeSeries::new();
+    let mut pending = prices.actions.iter().rev().peekable();
+    for (date, bar) in prices.map.iter_mut().rev() {
+        while let Some((ex_date, action)) = pending.next_if(|(ex_date, _)| *ex_date > date) {
+            running.keep_dividend(&mut remaining, *ex_date, action);
+            running.apply(action, bar.close);
+        }
+        bar.adjusted_close = bar.close * running.factor;
+    }
+
+    // Actions on or before the first day don't affect any prices.
+    for (ex_date, action) in pending {
+        running.keep_dividend(&mut remaining, *ex_date, action);
+        running.apply(action, 0.0);
+    }
+
+    prices.actions = remaining;
+    prices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::nearly_equal;
+
+    fn ymd(d: u32) -> Date {
+        Date::from_ymd(2020, 6, d)
+    }
+
+    /// A 2-for-1 split on the 3rd, and a $1 dividend on the 5th.
+    fn prices() -> Prices {
+        let closes = [(1, 100.0), (2, 102.0), (3, 50.0), (4, 50.0), (5, 49.0)]
+            .iter()
+            .map(|&(d, p)| (ymd(d), p))
+            .collect();
+        let mut p = Prices::from_closes("jpm", closes);
+        p.actions.insert(ymd(3), CorporateAction::split(2.0));
+        p.actions.insert(ymd(5), CorporateAction::dividend(1.0));
+        p
+    }
+
+    #[test]
+    fn adjusts_for_splits() {
+        let p = back_adjust(prices(), Adjustment::Splits);
+        assert_eq!(p.get(&ymd(1)), Some(&50.0));
+        assert_eq!(p.get(&ymd(2)), Some(&51.0));
+        assert_eq!(p.get(&ymd(5)), Some(&49.0));
+        assert_eq!(p.get_bar(&ymd(1)).unwrap().close, 100.0);
+
+        // Only the dividend is left
+        let remaining: Vec<_> = p.actions.into_iter().collect();
+        assert_eq!(remaining, vec![(ymd(5), CorporateAction::dividend(1.0))]);
+
+        // Dividends before a split are restated in post-split shares
+        let mut p = prices();
+        p.actions.insert(ymd(2), CorporateAction::dividend(0.5));
+        let p = back_adjust(p, Adjustment::Splits);
+        assert_eq!(p.actions[&ymd(2)].dividend, 0.25);
+    }
+
+    #[test]
+    fn adjusts_for_dividends() {
+        let p = back_adjust(prices(), Adjustment::All);
+        assert!(p.actions.is_empty());
+        assert_eq!(p.get(&ymd(5)), Some(&49.0));
+
+        // The $1 dividend was 2% of the close before it
+        assert!(nearly_equal(*p.get(&ymd(4)).unwrap(), 49.0));
+        assert!(nearly_equal(*p.get(&ymd(1)).unwrap(), 49.0));
+        assert!(nearly_equal(*p.get(&ymd(2)).unwrap(), 49.98));
+
+        let unchanged = back_adjust(prices(), Adjustment::None);
+        assert_eq!(unchanged, prices());
+        assert_eq!("all".parse(), Ok(Adjustment::All));
+        assert!("some".parse::<Adjustment>().is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! A single bar (open, high, low, close, adjusted close, volume) of price data.
+
+use serde::{Deserialize, Serialize};
+
+/// One period (usually one trading day) of price and volume data.
+///
+/// Data sources that do not provide every field fill in what they can from
+/// the closing price, so a Bar is always complete.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+pub struct Bar {
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+
+    /// Unadjusted closing price.
+    pub close: f64,
+
+    /// Closing price adjusted for splits and dividends. Same as `close` if the
+    /// data source does not provide it.
+    pub adjusted_close: f64,
+
+    pub volume: f64,
+}
+
+impl Bar {
+    /// The price used by close-only consumers (signal generators, the
+    /// backtester, etc).
+    pub fn price(&self) -> f64 {
+        self.adjusted_close
+    }
+}
+
+/// Makes a Bar out of a lone closing price. Open, high, low, close, and
+/// adjusted close are all set to the price, and volume is zero.
+impl From<f64> for Bar {
+    fn from(price: f64) -> Self {
+        Self {
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            adjusted_close: price,
+            volume: 0.0,
+        }
+    }
+}
+
+impl From<&alphavantage::time_series::Entry> for Bar {
+    fn from(e: &alphavantage::time_series::Entry) -> Self {
+        Self {
+            open: e.open,
+            high: e.high,
+            low: e.low,
+            close: e.close,
+            // FIXME: Alpha Vantage daily time series are not adjusted.
+            adjusted_close: e.close,
+            volume: e.volume as f64,
+        }
+    }
+}
+
+impl ta::Open for Bar {
+    fn open(&self) -> f64 {
+        self.open
+    }
+}
+
+impl ta::High for Bar {
+    fn high(&self) -> f64 {
+        self.high
+    }
+}
+
+impl ta::Low for Bar {
+    fn low(&self) -> f64 {
+        self.low
+    }
+}
+
+impl ta::Close for Bar {
+    fn close(&self) -> f64 {
+        self.close
+    }
+}
+
+impl ta::Volume for Bar {
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bar;
+
+    #[test]
+    fn bar_from_close() {
+        let bar = Bar::from(30.0);
+        assert_eq!(bar.open, 30.0);
+        assert_eq!(bar.high, 30.0);
+        assert_eq!(bar.low, 30.0);
+        assert_eq!(bar.close, 30.0);
+        assert_eq!(bar.price(), 30.0);
+        assert_eq!(bar.volume, 0.0);
+    }
+}
@@ -1,4 +1,10 @@
-//! Stock market data in a format recognizable to Techalyzer.
-
-pub mod prices;
-pub use prices::*;
+//! Stock market data in a format recognizable to Techalyzer.
+
+pub mod actions;
+pub mod bar;
+pub mod prices;
+pub mod resample;
+pub mod validation;
+pub use actions::CorporateAction;
+pub use bar::*;
+pub use prices::*;
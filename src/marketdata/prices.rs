@@ -1,11 +1,12 @@
 //! The standard stock price time series data format for Techalyzer.
 
+use super::{Bar, CorporateAction};
 use crate::Date;
 use crate::{output::TechalyzerPrintOutput, util::TimeSeries};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::btree_map::{Iter, IterMut},
-    ops::RangeBounds,
+    ops::{Bound, RangeBounds},
 };
 use thiserror::Error;
 
@@ -23,26 +24,73 @@ pub enum PricesError {
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Prices {
     /// Price data.
-    pub map: TimeSeries<f64>,
+    pub map: TimeSeries<Bar>,
 
     /// The ticker symbol of the stock.
     pub symbol: String,
+
+    /// Dates that appeared more than once in the source data. Only the last
+    /// row for each of them was kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub duplicate_dates: Vec<Date>,
+
+    /// Splits and dividends, by the date they went ex. See
+    /// `actions::back_adjust`.
+    #[serde(default, skip_serializing_if = "TimeSeries::is_empty")]
+    pub actions: TimeSeries<CorporateAction>,
 }
 
 impl IntoIterator for Prices {
-    type Item = (Date, f64);
-    type IntoIter = std::collections::btree_map::IntoIter<Date, f64>;
+    type Item = (Date, Bar);
+    type IntoIter = std::collections::btree_map::IntoIter<Date, Bar>;
     fn into_iter(self) -> Self::IntoIter {
         self.map.into_iter()
     }
 }
 
 impl Prices {
-    pub fn iter(&self) -> Iter<Date, f64> {
+    /// Constructs Prices out of a time series of closing prices only (see
+    /// `Bar::from(f64)`).
+    pub fn from_closes(symbol: &str, closes: TimeSeries<f64>) -> Self {
+        Prices {
+            map: closes.into_iter().map(|(d, p)| (d, p.into())).collect(),
+            symbol: symbol.into(),
+            duplicate_dates: Vec::new(),
+            actions: TimeSeries::new(),
+        }
+    }
+
+    /// Constructs Prices out of rows of source data, keeping the last row for
+    /// any date that appears more than once (and noting that it did).
+    pub fn from_rows(symbol: &str, rows: impl IntoIterator<Item = (Date, Bar)>) -> Self {
+        let mut map = TimeSeries::new();
+        let mut duplicate_dates = Vec::new();
+        for (date, bar) in rows {
+            if map.insert(date, bar).is_some() && !duplicate_dates.contains(&date) {
+                duplicate_dates.push(date);
+            }
+        }
+        duplicate_dates.sort();
+
+        Prices {
+            map,
+            symbol: symbol.into(),
+            duplicate_dates,
+            actions: TimeSeries::new(),
+        }
+    }
+
+    /// Iterates over the closing price of each day.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Date, &f64)> {
+        self.map.iter().map(|(d, bar)| (d, &bar.adjusted_close))
+    }
+
+    /// Iterates over the full Bar of each day.
+    pub fn bars(&self) -> Iter<Date, Bar> {
         self.map.iter()
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<Date, f64> {
+    pub fn iter_mut(&mut self) -> IterMut<Date, Bar> {
         self.map.iter_mut()
     }
 
@@ -51,20 +99,47 @@ impl Prices {
     }
 
     pub fn date_range(&self, range: impl RangeBounds<Date>) -> Prices {
-        let slice = self.map.range(range).map(|e| (*e.0, *e.1)).collect();
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let slice: TimeSeries<Bar> = self.map.range(bounds).map(|e| (*e.0, *e.1)).collect();
+        let duplicate_dates = self
+            .duplicate_dates
+            .iter()
+            .filter(|d| slice.contains_key(*d))
+            .cloned()
+            .collect();
 
         Prices {
             map: slice,
             symbol: self.symbol.clone(),
+            duplicate_dates,
+            actions: self.actions.range(bounds).map(|e| (*e.0, *e.1)).collect(),
         }
     }
 
+    /// Total dividend per share that went ex after `after` (or any time before,
+    /// if None), up to and including `through`.
+    pub fn dividends(&self, after: Option<Date>, through: Date) -> f64 {
+        let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+        self.actions
+            .range((start, Bound::Included(through)))
+            .map(|(_, action)| action.dividend)
+            .sum()
+    }
+
+    /// Gets the closing price on `date`.
     pub fn get(&self, date: &Date) -> Option<&f64> {
+        self.map.get(date).map(|bar| &bar.adjusted_close)
+    }
+
+    /// Gets the full Bar on `date`.
+    pub fn get_bar(&self, date: &Date) -> Option<&Bar> {
         self.map.get(date)
     }
 
+    /// Gets the closing price `days_after` rows of data after `date`. Gaps in
+    /// the data are skipped over; see `TradingCalendar` for trading days.
     pub fn get_after(&self, date: &Date, days_after: u32) -> Option<(Date, f64)> {
-        self.get_offset(self.map.iter(), date, days_after)
+        self.get_offset(self.iter(), date, days_after)
     }
 
     fn get_offset<'a, I>(&self, iter: I, date: &Date, days_after: u32) -> Option<(Date, f64)>
@@ -90,22 +165,16 @@ impl Prices {
         None
     }
 
+    /// Gets the closing price `days_before` rows of data before `date`.
     pub fn get_before(&self, date: &Date, days_before: u32) -> Option<(Date, f64)> {
-        self.get_offset(self.map.iter().rev(), date, days_before)
+        self.get_offset(self.iter().rev(), date, days_before)
     }
 }
 
 impl From<alphavantage::time_series::TimeSeries> for Prices {
     fn from(t: alphavantage::time_series::TimeSeries) -> Self {
-        let mut m = std::collections::BTreeMap::new();
-        for e in t.entries {
-            m.insert(Date::from(&e), e.close);
-        }
-
-        Prices {
-            symbol: t.symbol,
-            map: m,
-        }
+        let rows = t.entries.iter().map(|e| (Date::from(e), Bar::from(e)));
+        Prices::from_rows(&t.symbol, rows)
     }
 }
 
@@ -113,7 +182,9 @@ impl From<TechalyzerPrintOutput> for Prices {
     fn from(t: TechalyzerPrintOutput) -> Self {
         Prices {
             symbol: t.symbol,
-            map: t.map.iter().map(|e| (*e.0, e.1.price)).collect(),
+            map: t.map.iter().map(|e| (*e.0, e.1.bar())).collect(),
+            duplicate_dates: Vec::new(),
+            actions: TimeSeries::new(),
         }
     }
 }
@@ -147,7 +218,18 @@ mod tests {
         let p: Prices = ts.into();
 
         let date = Date::from(dt.naive_local().date());
-        assert!(p.map[&date] == 30.0);
+        assert!(p.get(&date) == Some(&30.0));
+        assert_eq!(
+            p.get_bar(&date).unwrap(),
+            &Bar {
+                open: 30.0,
+                high: 32.0,
+                low: 28.0,
+                close: 30.0,
+                adjusted_close: 30.0,
+                volume: 300.0,
+            }
+        );
         assert!(p.map.iter().next().unwrap().0 == &date);
     }
 
@@ -163,10 +245,7 @@ mod tests {
             dt = dt + Duration::days(1);
         }
 
-        Prices {
-            map: entries,
-            symbol: "jpm".to_string(),
-        }
+        Prices::from_closes("jpm", entries)
     }
 
     #[test]
@@ -197,4 +276,16 @@ mod tests {
 
         assert_eq!(result.0, target);
     }
+
+    #[test]
+    fn from_rows_keeps_last_duplicate() {
+        let day = Date::from_ymd(2012, 1, 3);
+        let rows = vec![(day, 1.0.into()), (day, 2.0.into()), (day, 3.0.into())];
+        let p = Prices::from_rows("jpm", rows);
+        assert_eq!(p.get(&day), Some(&3.0));
+        assert_eq!(p.duplicate_dates, vec![day]);
+
+        // Only duplicates in the range are kept
+        assert!(p.date_range(..day).duplicate_dates.is_empty());
+    }
 }
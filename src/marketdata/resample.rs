@@ -0,0 +1,211 @@
+//! Resamples price data to longer bars (e.g. minute bars to hourly or daily
+//! ones) without letting any bar span two trading sessions.
+
+use super::{Bar, Prices};
+use crate::{calendar::TradingCalendar, date::Date, util::TimeSeries};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Errors resampling price data.
+#[derive(Debug, Error, PartialEq)]
+pub enum ResampleError {
+    #[error("Invalid bar interval '{0}' (expected e.g. 1m, 15m, 1h, or 1d)")]
+    InvalidInterval(String),
+}
+
+/// How long each bar of price data is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Interval {
+    /// Bars of some number of minutes (or hours), counted from the open of
+    /// each session.
+    Minutes(u32),
+
+    /// One bar per trading day.
+    Daily,
+}
+
+impl FromStr for Interval {
+    type Err = ResampleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ResampleError::InvalidInterval(s.to_string());
+        let lowered = s.to_lowercase();
+        if lowered == "daily" {
+            return Ok(Self::Daily);
+        }
+
+        let split = lowered
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let n: u32 = lowered[..split].parse().map_err(|_| invalid())?;
+        match (n, &lowered[split..]) {
+            (0, _) => Err(invalid()),
+            (n, "m") | (n, "min") => Ok(Self::Minutes(n)),
+            (n, "h") => Ok(Self::Minutes(n * 60)),
+            (1, "d") => Ok(Self::Daily),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Minutes(n) if n % 60 == 0 => write!(f, "{}h", n / 60),
+            Self::Minutes(n) => write!(f, "{}m", n),
+            Self::Daily => write!(f, "1d"),
+        }
+    }
+}
+
+impl TryFrom<String> for Interval {
+    type Error = ResampleError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Interval> for String {
+    fn from(interval: Interval) -> Self {
+        interval.to_string()
+    }
+}
+
+/// Resamples `prices` to bars of `interval`. Each new bar is keyed by when it
+/// starts (daily bars by their trading day), opens at the first bar in it,
+/// closes at the last, and has the highest high, lowest low, and total volume
+/// of them all.
+///
+/// Bars are grouped within the sessions of `calendar`, so the last bar of a
+/// session may be short; bars outside of every session are dropped. Splits and
+/// dividends move to the bar they fall in.
+pub fn resample(prices: &Prices, interval: Interval, calendar: &TradingCalendar) -> Prices {
+    let bucket = |time: Date| {
+        let day = calendar.session_day(time)?;
+        match interval {
+            Interval::Daily => Some(day),
+            // A whole day is in the first bar of its session.
+            Interval::Minutes(n) => {
+                let start = calendar.session.start(day);
+                let length = Duration::minutes(n as i64);
+                let elapsed = (time - start).max(Duration::zero());
+                let bars = elapsed.num_seconds() / length.num_seconds();
+                Some(start + length * bars as i32)
+            }
+        }
+    };
+
+    let mut map: TimeSeries<Bar> = TimeSeries::new();
+    for (time, bar) in prices.bars() {
+        if let Some(key) = bucket(*time) {
+            map.entry(key)
+                .and_modify(|b| *b = combine(b, bar))
+                .or_insert(*bar);
+        }
+    }
+
+    let mut resampled = Prices::from_rows(&prices.symbol, map);
+    for (time, action) in &prices.actions {
+        resampled
+            .actions
+            .insert(bucket(*time).unwrap_or(*time), *action);
+    }
+    resampled
+}
+
+/// A bar covering `first` and then `next`.
+fn combine(first: &Bar, next: &Bar) -> Bar {
+    Bar {
+        open: first.open,
+        high: first.high.max(next.high),
+        low: first.low.min(next.low),
+        close: next.close,
+        adjusted_close: next.adjusted_close,
+        volume: first.volume + next.volume,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::marketdata::CorporateAction;
+    use chrono::NaiveDate;
+    use chrono_tz::America::New_York;
+
+    /// A bar every 30 minutes from 9:00 AM to 4:30 PM Eastern on Friday April
+    /// 3rd and Monday April 6th 2020, with a close that counts up.
+    fn half_hours() -> Prices {
+        let mut rows = Vec::new();
+        for &day in &[3, 6] {
+            for i in 0..16 {
+                let local = NaiveDate::from_ymd(2020, 4, day).and_hms(9 + i / 2, 30 * (i % 2), 0);
+                let time = Date::from_local(&local, &New_York).unwrap();
+                let close = rows.len() as f64;
+                let bar = Bar {
+                    high: close + 0.5,
+                    volume: 100.0,
+                    ..close.into()
+                };
+                rows.push((time, bar));
+            }
+        }
+        Prices::from_rows("jpm", rows)
+    }
+
+    fn at(day: u32, h: u32, m: u32) -> Date {
+        let local = NaiveDate::from_ymd(2020, 4, day).and_hms(h, m, 0);
+        Date::from_local(&local, &New_York).unwrap()
+    }
+
+    #[test]
+    fn resamples_within_sessions() {
+        let nyse = TradingCalendar::nyse();
+
+        // 9:30-10:30, ..., 3:30-4:00 (the 9:00 and 4:00 and 4:30 bars are
+        // outside the session)
+        let hourly = resample(&half_hours(), Interval::Minutes(60), &nyse);
+        assert_eq!(hourly.map.len(), 14);
+        let first = hourly.get_bar(&at(3, 9, 30)).unwrap();
+        assert_eq!((first.open, first.close, first.high), (1.0, 2.0, 2.5));
+        assert_eq!(first.volume, 200.0);
+        let last = hourly.get_bar(&at(3, 15, 30)).unwrap();
+        assert_eq!((last.open, last.close, last.volume), (13.0, 13.0, 100.0));
+        assert!(hourly.get_bar(&at(6, 9, 30)).is_some());
+
+        let mut prices = half_hours();
+        prices
+            .actions
+            .insert(at(6, 12, 0), CorporateAction::dividend(1.0));
+        let daily = resample(&prices, Interval::Daily, &nyse);
+        let friday = Date::from_ymd(2020, 4, 3);
+        let monday = Date::from_ymd(2020, 4, 6);
+        assert_eq!(
+            daily.map.keys().cloned().collect::<Vec<_>>(),
+            vec![friday, monday]
+        );
+        let bar = daily.get_bar(&friday).unwrap();
+        assert_eq!((bar.open, bar.close, bar.volume), (1.0, 13.0, 1300.0));
+        assert_eq!(daily.dividends(Some(friday), monday), 1.0);
+
+        // Daily bars stay put
+        assert_eq!(resample(&daily, Interval::Daily, &nyse), daily);
+
+        // Around the clock, nothing is dropped
+        let every_day = TradingCalendar::every_day();
+        let daily = resample(&half_hours(), Interval::Daily, &every_day);
+        assert_eq!(daily.get_bar(&friday).unwrap().volume, 1600.0);
+    }
+
+    #[test]
+    fn parse_intervals() {
+        assert_eq!("15m".parse(), Ok(Interval::Minutes(15)));
+        assert_eq!("1H".parse(), Ok(Interval::Minutes(60)));
+        assert_eq!("daily".parse(), Ok(Interval::Daily));
+        assert_eq!(Interval::Minutes(120).to_string(), "2h");
+        assert!("0m".parse::<Interval>().is_err());
+        assert!("2d".parse::<Interval>().is_err());
+        assert!("m".parse::<Interval>().is_err());
+    }
+}
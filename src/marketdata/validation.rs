@@ -0,0 +1,354 @@
+//! Checks price data for problems (gaps, duplicate dates, bad prices, and
+//! absurd one-day moves) and optionally repairs them.
+
+use super::{Bar, Prices};
+use crate::{calendar::TradingCalendar, date::Date};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Default for the largest believable one-day price change (50%).
+pub const DEFAULT_MAX_JUMP: f64 = 0.5;
+
+/// A problem found in price data.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    /// A trading day with no price data.
+    MissingDay { date: Date },
+
+    /// A date that appeared more than once in the source data. Only the last
+    /// row was kept.
+    DuplicateDate { date: Date },
+
+    /// A price that is zero or negative.
+    NonPositivePrice { date: Date, price: f64 },
+
+    /// A one-day move bigger than the maximum that is undone the next day.
+    /// Most likely a bad tick.
+    Spike { date: Date, change: f64 },
+
+    /// A one-day move bigger than the maximum that holds. It could be real (or
+    /// an unadjusted split), so it is never repaired.
+    Jump { date: Date, change: f64 },
+}
+
+impl Issue {
+    pub fn date(&self) -> Date {
+        match self {
+            Self::MissingDay { date }
+            | Self::DuplicateDate { date }
+            | Self::NonPositivePrice { date, .. }
+            | Self::Spike { date, .. }
+            | Self::Jump { date, .. } => *date,
+        }
+    }
+
+    /// Whether a RepairPolicy replaces or drops the day.
+    pub fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            Self::MissingDay { .. } | Self::NonPositivePrice { .. } | Self::Spike { .. }
+        )
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDay { date } => write!(f, "{}: missing trading day", date),
+            Self::DuplicateDate { date } => write!(f, "{}: duplicate date", date),
+            Self::NonPositivePrice { date, price } => {
+                write!(f, "{}: price of {} is not positive", date, price)
+            }
+            Self::Spike { date, change } => write!(
+                f,
+                "{}: price moved {:+.1}% and came back the next day",
+                date,
+                change * 100.0
+            ),
+            Self::Jump { date, change } => {
+                write!(f, "{}: price moved {:+.1}%", date, change * 100.0)
+            }
+        }
+    }
+}
+
+/// What to do about the problems found in price data.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RepairPolicy {
+    /// Fill bad and missing days in with the last good day's close.
+    ForwardFill,
+
+    /// Fill bad and missing days in by interpolating between the good days
+    /// around them.
+    Interpolate,
+
+    /// Remove bad days.
+    Drop,
+
+    /// Fail if there are any problems at all.
+    Fail,
+}
+
+impl FromStr for RepairPolicy {
+    type Err = ValidationError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ffill" | "forward-fill" => Ok(Self::ForwardFill),
+            "interpolate" => Ok(Self::Interpolate),
+            "drop" => Ok(Self::Drop),
+            "fail" => Ok(Self::Fail),
+            _ => Err(ValidationError::UnknownPolicy(s.to_string())),
+        }
+    }
+}
+
+impl Display for RepairPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ForwardFill => "ffill",
+            Self::Interpolate => "interpolate",
+            Self::Drop => "drop",
+            Self::Fail => "fail",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Errors validating or repairing price data.
+#[derive(Debug, Error, PartialEq)]
+pub enum ValidationError {
+    #[error("Unknown repair policy '{0}' (supported: ffill, interpolate, drop, fail)")]
+    UnknownPolicy(String),
+
+    #[error("Found {} problem(s) in the price data for {symbol}, starting with {}", .issues.len(), .issues[0])]
+    Failed { symbol: String, issues: Vec<Issue> },
+}
+
+/// Finds the problems in `prices`, in date order. Missing days are trading
+/// days in `calendar` with no data. A one-day change in price of more than
+/// `max_jump` (0.5 = 50%) is a Spike if the next day is back within
+/// `max_jump` of the day before it, or a Jump otherwise.
+pub fn validate(prices: &Prices, calendar: &TradingCalendar, max_jump: f64) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = calendar
+        .missing_days(prices)
+        .into_iter()
+        .map(|date| Issue::MissingDay { date })
+        .collect();
+    issues.extend(
+        prices
+            .duplicate_dates
+            .iter()
+            .map(|&date| Issue::DuplicateDate { date }),
+    );
+
+    let mut positive = Vec::new();
+    for (date, price) in prices.iter() {
+        if *price > 0.0 {
+            positive.push((*date, *price));
+        } else {
+            issues.push(Issue::NonPositivePrice {
+                date: *date,
+                price: *price,
+            });
+        }
+    }
+
+    // Changes are measured from the last price that wasn't a spike.
+    let change = |from: f64, to: f64| to / from - 1.0;
+    let mut before: Option<f64> = None;
+    for (i, &(date, price)) in positive.iter().enumerate() {
+        let prev = before.unwrap_or(price);
+        let moved = change(prev, price);
+        if moved.abs() > max_jump {
+            let undone = positive
+                .get(i + 1)
+                .is_some_and(|(_, after)| change(prev, *after).abs() <= max_jump);
+            if undone {
+                issues.push(Issue::Spike {
+                    date,
+                    change: moved,
+                });
+                continue;
+            }
+            issues.push(Issue::Jump {
+                date,
+                change: moved,
+            });
+        }
+        before = Some(price);
+    }
+
+    issues.sort_by_key(Issue::date);
+    issues
+}
+
+/// Repairs the problems in `prices` found by `validate`. Days that can't be
+/// filled in (for lack of a good day before or after them) are dropped.
+pub fn repair(
+    mut prices: Prices,
+    issues: &[Issue],
+    policy: RepairPolicy,
+) -> Result<Prices, ValidationError> {
+    let bad: BTreeSet<Date> = issues
+        .iter()
+        .filter(|i| i.is_repairable())
+        .map(Issue::date)
+        .collect();
+
+    match policy {
+        RepairPolicy::Fail if !issues.is_empty() => {
+            return Err(ValidationError::Failed {
+                symbol: prices.symbol,
+                issues: issues.to_vec(),
+            })
+        }
+        RepairPolicy::Fail => {}
+        RepairPolicy::Drop => prices.map.retain(|d, _| !bad.contains(d)),
+        RepairPolicy::ForwardFill | RepairPolicy::Interpolate => {
+            // Every row, good and bad, in order.
+            let rows: Vec<Date> = prices
+                .map
+                .keys()
+                .cloned()
+                .collect::<BTreeSet<_>>()
+                .union(&bad)
+                .cloned()
+                .collect();
+            let good: Vec<(usize, Bar)> = rows
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| !bad.contains(d))
+                .map(|(i, d)| (i, prices.map[d]))
+                .collect();
+
+            for (i, date) in rows.iter().enumerate().filter(|(_, d)| bad.contains(d)) {
+                // Good rows either side of this one
+                let after = good.partition_point(|(j, _)| *j < i);
+                let before = after.checked_sub(1).map(|b| good[b]);
+                let filled = match (policy, before, good.get(after)) {
+                    (RepairPolicy::ForwardFill, Some((_, b)), _) => Some(interpolate(&b, &b, 0.0)),
+                    (RepairPolicy::Interpolate, Some((bi, b)), Some((ai, a))) => {
+                        let t = (i - bi) as f64 / (ai - bi) as f64;
+                        Some(interpolate(&b, a, t))
+                    }
+                    _ => None,
+                };
+
+                match filled {
+                    Some(bar) => prices.map.insert(*date, bar),
+                    None => prices.map.remove(date),
+                };
+            }
+        }
+    }
+
+    Ok(prices)
+}
+
+/// A day with no trading, with prices `t` of the way from the close of `from`
+/// to the close of `to`.
+fn interpolate(from: &Bar, to: &Bar, t: f64) -> Bar {
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+    let close = lerp(from.close, to.close);
+    Bar {
+        open: close,
+        high: close,
+        low: close,
+        close,
+        adjusted_close: lerp(from.adjusted_close, to.adjusted_close),
+        volume: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TimeSeries;
+
+    fn ymd(d: u32) -> Date {
+        Date::from_ymd(2020, 4, d)
+    }
+
+    /// Two weeks of NYSE trading days (April 10th is Good Friday), with a gap
+    /// on the 14th, a bad tick on the 8th, and a zero price on the 16th.
+    fn prices() -> Prices {
+        let closes: TimeSeries<f64> = [
+            (1, 10.0),
+            (2, 11.0),
+            (3, 12.0),
+            (6, 13.0),
+            (7, 14.0),
+            (8, 140.0),
+            (9, 16.0),
+            (13, 17.0),
+            (15, 19.0),
+            (16, 0.0),
+            (17, 21.0),
+        ]
+        .iter()
+        .map(|&(d, p)| (ymd(d), p))
+        .collect();
+        Prices::from_closes("jpm", closes)
+    }
+
+    #[test]
+    fn finds_problems() {
+        let mut p = prices();
+        p.duplicate_dates.push(ymd(2));
+        let issues = validate(&p, &TradingCalendar::nyse(), DEFAULT_MAX_JUMP);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::DuplicateDate { date: ymd(2) },
+                Issue::Spike {
+                    date: ymd(8),
+                    change: 9.0
+                },
+                Issue::MissingDay { date: ymd(14) },
+                Issue::NonPositivePrice {
+                    date: ymd(16),
+                    price: 0.0
+                },
+            ]
+        );
+
+        // A move that holds is a jump
+        let mut p = prices();
+        p.map.insert(ymd(9), 150.0.into());
+        let issues = validate(&p, &TradingCalendar::nyse(), DEFAULT_MAX_JUMP);
+        assert!(issues.contains(&Issue::Jump {
+            date: ymd(8),
+            change: 9.0
+        }));
+    }
+
+    #[test]
+    fn repairs_problems() {
+        let nyse = TradingCalendar::nyse();
+        let issues = validate(&prices(), &nyse, DEFAULT_MAX_JUMP);
+
+        let filled = repair(prices(), &issues, RepairPolicy::ForwardFill).unwrap();
+        assert_eq!(filled.get(&ymd(8)), Some(&14.0));
+        assert_eq!(filled.get(&ymd(14)), Some(&17.0));
+        assert_eq!(filled.get(&ymd(16)), Some(&19.0));
+        assert!(validate(&filled, &nyse, DEFAULT_MAX_JUMP).is_empty());
+
+        let interpolated = repair(prices(), &issues, RepairPolicy::Interpolate).unwrap();
+        assert_eq!(interpolated.get(&ymd(8)), Some(&15.0));
+        assert_eq!(interpolated.get(&ymd(14)), Some(&18.0));
+        assert_eq!(interpolated.get(&ymd(16)), Some(&20.0));
+
+        let dropped = repair(prices(), &issues, RepairPolicy::Drop).unwrap();
+        assert_eq!(dropped.map.len(), 9);
+        assert_eq!(dropped.get(&ymd(8)), None);
+
+        let failed = repair(prices(), &issues, RepairPolicy::Fail).unwrap_err();
+        assert!(failed.to_string().contains("3 problem(s)"));
+        assert!(repair(prices(), &[], RepairPolicy::Fail).is_ok());
+
+        assert_eq!("ffill".parse(), Ok(RepairPolicy::ForwardFill));
+        assert!("guess".parse::<RepairPolicy>().is_err());
+    }
+}
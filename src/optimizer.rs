@@ -0,0 +1,471 @@
+//! Searches for the parameters of a ManualTradingModel that perform best in a
+//! backtest. Every combination of the given values can be tried (grid
+//! search), or a random sample of them (random search).
+
+use crate::{
+    backtester::performance::{PerformanceError, PortfolioPerformance},
+    indicators::{IndicatorSpec, IndicatorSpecError, SupportedIndicators},
+    signals::Signal,
+    trading::manual::ManualTradingModel,
+};
+use rand::{rngs::StdRng, seq::index::sample, SeedableRng};
+use serde::Serialize;
+use std::str::FromStr;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+use thiserror::Error;
+
+/// Errors from setting up a search.
+#[derive(Debug, Error, PartialEq)]
+pub enum OptimizerError {
+    #[error("Invalid range '{0}': {1}")]
+    InvalidRange(String, String),
+
+    #[error("Invalid value {value} for {param}: {reason}")]
+    InvalidValue {
+        param: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("No valid parameters for {indicator} in '{spec}': {error}")]
+    NoValidParameters {
+        indicator: SupportedIndicators,
+        spec: String,
+        error: IndicatorSpecError,
+    },
+
+    #[error("Too many combinations to search")]
+    TooManyCombinations,
+
+    #[error("At least one indicator is needed to search over")]
+    NoIndicators,
+}
+
+/// What to rank the results of a search by. Higher is always better.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, EnumVariantNames, Display, Serialize)]
+pub enum Objective {
+    #[strum(serialize = "TotalReturn", serialize = "total-return")]
+    TotalReturn,
+
+    #[strum(serialize = "SharpeRatio", serialize = "sharpe")]
+    SharpeRatio,
+
+    /// Ranks the shallowest maximum drawdown first.
+    #[strum(serialize = "MaxDrawdown", serialize = "max-drawdown")]
+    MaxDrawdown,
+}
+
+impl Objective {
+    /// Scores a backtest.
+    pub fn score(&self, performance: &PortfolioPerformance) -> Result<f64, PerformanceError> {
+        Ok(match self {
+            Objective::TotalReturn => performance.total_return()?,
+            Objective::SharpeRatio => performance.statistics.sharpe_ratio,
+            Objective::MaxDrawdown => max_drawdown(performance),
+        })
+    }
+}
+
+/// Maximum drawdown of a backtest (zero if there was none).
+pub fn max_drawdown(performance: &PortfolioPerformance) -> f64 {
+    performance
+        .statistics
+        .drawdown
+        .as_ref()
+        .map_or(0.0, |d| d.max_drawdown)
+}
+
+/// Values to try for a parameter. Written as a comma-separated list of values
+/// and inclusive ranges with an optional step (1 by default), e.g. `0.1`,
+/// `100,500,1000` or `0.0..0.5/0.1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamRange(pub Vec<f64>);
+
+impl FromStr for ParamRange {
+    type Err = OptimizerError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = Vec::new();
+        for item in s.split(',') {
+            values.extend(parse_range_item(item.trim())?);
+        }
+
+        Ok(Self(values))
+    }
+}
+
+/// Parses a single value or `start..end/step` range.
+fn parse_range_item(s: &str) -> Result<Vec<f64>, OptimizerError> {
+    let invalid = |reason: String| OptimizerError::InvalidRange(s.to_string(), reason);
+    let parse = |v: &str| {
+        v.trim()
+            .parse::<f64>()
+            .map_err(|_| invalid(format!("'{}' is not a number", v)))
+    };
+
+    let i = match s.find("..") {
+        Some(i) => i,
+        None => return Ok(vec![parse(s)?]),
+    };
+
+    let (start, rest) = (parse(&s[..i])?, &s[i + 2..]);
+    let (end, step) = match rest.find('/') {
+        Some(j) => (parse(&rest[..j])?, parse(&rest[j + 1..])?),
+        None => (parse(rest)?, 1.0),
+    };
+
+    if step.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+        return Err(invalid("step must be greater than zero".to_string()));
+    }
+    if end < start {
+        return Err(invalid("end is before start".to_string()));
+    }
+
+    // Tolerate floating point error on the last step, and round away the
+    // error that accumulates (0.1 * 3 = 0.30000000000000004).
+    let steps = ((end - start) / step + 1e-9).floor() as usize;
+    Ok((0..=steps)
+        .map(|i| ((start + i as f64 * step) * 1e9).round() / 1e9)
+        .collect())
+}
+
+/// An indicator with values to try for each of its parameters, written like
+/// an [IndicatorSpec](../indicators/enum.IndicatorSpec.html) with a single
+/// value or range per parameter, e.g. `rsi:10..20/2` or `bb:20,1.5..2.5/0.5`.
+/// Parameters left off use their defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndicatorRange {
+    pub indicator: SupportedIndicators,
+    pub params: Vec<ParamRange>,
+}
+
+impl FromStr for IndicatorRange {
+    type Err = OptimizerError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.trim().splitn(2, ':');
+        let name = split.next().unwrap_or_default();
+        let indicator = SupportedIndicators::from_str(name).map_err(|_| {
+            let e = IndicatorSpecError::UnknownIndicator(name.to_string());
+            OptimizerError::InvalidRange(s.to_string(), e.to_string())
+        })?;
+
+        let params = match split.next() {
+            Some(params) => params
+                .split(',')
+                .map(|p| parse_range_item(p.trim()).map(ParamRange))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Self { indicator, params })
+    }
+}
+
+/// An indicator with its default parameters.
+impl From<SupportedIndicators> for IndicatorRange {
+    fn from(indicator: SupportedIndicators) -> Self {
+        Self {
+            indicator,
+            params: Vec::new(),
+        }
+    }
+}
+
+impl IndicatorRange {
+    /// Every valid IndicatorSpec in the range. Combinations that the indicator
+    /// rejects (e.g. a MACD fast length longer than the slow length) are
+    /// skipped, but at least one must be valid.
+    pub fn specs(&self) -> Result<Vec<IndicatorSpec>, OptimizerError> {
+        let mut combinations: Vec<Vec<f64>> = vec![Vec::new()];
+        for param in &self.params {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|c| {
+                    param.0.iter().map(move |v| {
+                        let mut c = c.clone();
+                        c.push(*v);
+                        c
+                    })
+                })
+                .collect();
+        }
+
+        let mut specs = Vec::new();
+        let mut last_error = None;
+        for c in combinations {
+            let values: Vec<String> = c.iter().map(f64::to_string).collect();
+            let spec = format!("{}:{}", self.indicator, values.join(","));
+            match IndicatorSpec::from_str(spec.trim_end_matches(':')) {
+                Ok(s) => specs.push(s),
+                Err(e @ IndicatorSpecError::TooManyParameters { .. }) => {
+                    return Err(OptimizerError::NoValidParameters {
+                        indicator: self.indicator,
+                        spec,
+                        error: e,
+                    })
+                }
+                Err(e) => last_error = Some((spec, e)),
+            }
+        }
+
+        match (specs.is_empty(), last_error) {
+            (true, Some((spec, error))) => Err(OptimizerError::NoValidParameters {
+                indicator: self.indicator,
+                spec,
+                error,
+            }),
+            _ => Ok(specs),
+        }
+    }
+}
+
+/// One set of parameters for a ManualTradingModel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub shares: f64,
+    pub dead_zone: f64,
+    pub disposition: f64,
+    pub indicators: Vec<IndicatorSpec>,
+}
+
+impl Candidate {
+    /// Makes the trading model these parameters describe.
+    pub fn model(&self) -> Result<ManualTradingModel, IndicatorSpecError> {
+        ManualTradingModel::new(
+            self.shares,
+            Signal::new(self.dead_zone),
+            Signal::new(self.disposition),
+        )
+        .with_indicators(self.indicators.clone())
+    }
+}
+
+/// Every combination of parameters to search over. Combinations are numbered
+/// so they can be sampled without building all of them.
+#[derive(Debug)]
+pub struct SearchSpace {
+    shares: Vec<f64>,
+    dead_zone: Vec<f64>,
+    disposition: Vec<f64>,
+
+    /// Options for each indicator the model uses.
+    indicators: Vec<Vec<IndicatorSpec>>,
+
+    len: usize,
+}
+
+impl SearchSpace {
+    /// Builds a search space. Shares must not be negative, the dead zone
+    /// and disposition must be valid Signals (between -1.0 and 1.0), and
+    /// there must be at least one indicator.
+    pub fn new(
+        shares: &ParamRange,
+        dead_zone: &ParamRange,
+        disposition: &ParamRange,
+        indicators: &[IndicatorRange],
+    ) -> Result<Self, OptimizerError> {
+        if let Some(s) = shares.0.iter().find(|s| **s < 0.0 || !s.is_finite()) {
+            return Err(OptimizerError::InvalidValue {
+                param: "shares",
+                value: *s,
+                reason: "must be a number of shares, 0 or more",
+            });
+        }
+        check_signals("dead_zone", dead_zone)?;
+        check_signals("disposition", disposition)?;
+        if indicators.is_empty() {
+            return Err(OptimizerError::NoIndicators);
+        }
+        let indicators = indicators
+            .iter()
+            .map(IndicatorRange::specs)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let len = indicators
+            .iter()
+            .map(Vec::len)
+            .chain(vec![shares.0.len(), dead_zone.0.len(), disposition.0.len()])
+            .try_fold(1usize, |acc, n| acc.checked_mul(n))
+            .ok_or(OptimizerError::TooManyCombinations)?;
+
+        Ok(Self {
+            shares: shares.0.clone(),
+            dead_zone: dead_zone.0.clone(),
+            disposition: disposition.0.clone(),
+            indicators,
+            len,
+        })
+    }
+
+    /// Number of combinations in the search space.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the `i`th combination.
+    pub fn get(&self, mut i: usize) -> Candidate {
+        // Treat i as a mixed-radix number, one digit per parameter.
+        let mut digit = |n: usize| {
+            let d = i % n;
+            i /= n;
+            d
+        };
+
+        let shares = self.shares[digit(self.shares.len())];
+        let dead_zone = self.dead_zone[digit(self.dead_zone.len())];
+        let disposition = self.disposition[digit(self.disposition.len())];
+        let indicators = self
+            .indicators
+            .iter()
+            .map(|options| options[digit(options.len())])
+            .collect();
+
+        Candidate {
+            shares,
+            dead_zone,
+            disposition,
+            indicators,
+        }
+    }
+
+    /// Picks `n` distinct combinations at random, or all of them if there
+    /// are not that many.
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        sample(&mut rng, self.len, n.min(self.len)).into_vec()
+    }
+}
+
+fn check_signals(param: &'static str, range: &ParamRange) -> Result<(), OptimizerError> {
+    match range.0.iter().find(|v| !(-1.0..=1.0).contains(*v)) {
+        Some(v) => Err(OptimizerError::InvalidValue {
+            param,
+            value: *v,
+            reason: "must be between -1.0 and 1.0",
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Sorts by score, best first. NaN scores go last.
+pub fn rank<T>(results: &mut [(f64, T)]) {
+    let key = |s: f64| if s.is_nan() { f64::NEG_INFINITY } else { s };
+    results.sort_by(|a, b| key(b.0).partial_cmp(&key(a.0)).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ranges() {
+        let r: ParamRange = "0.0..0.3/0.1".parse().unwrap();
+        assert_eq!(r.0, vec![0.0, 0.1, 0.2, 0.3]);
+
+        let r: ParamRange = "100, 500..700/100,1000".parse().unwrap();
+        assert_eq!(r.0, vec![100.0, 500.0, 600.0, 700.0, 1000.0]);
+
+        let r: ParamRange = "1..3".parse().unwrap();
+        assert_eq!(r.0, vec![1.0, 2.0, 3.0]);
+
+        assert!("1..3/0".parse::<ParamRange>().is_err());
+        assert!("3..1".parse::<ParamRange>().is_err());
+        assert!("one".parse::<ParamRange>().is_err());
+    }
+
+    #[test]
+    fn indicator_ranges() {
+        let r: IndicatorRange = "bb:10..20/10,1.5..2.0/0.5".parse().unwrap();
+        let specs = r.specs().unwrap();
+        assert_eq!(specs.len(), 4);
+        assert!(specs.contains(&IndicatorSpec::BollingerBands {
+            length: 20,
+            multiplier: 1.5
+        }));
+
+        // Defaults
+        let r: IndicatorRange = "rsi".parse().unwrap();
+        assert_eq!(
+            r.specs().unwrap(),
+            vec![SupportedIndicators::RelativeStrengthIndex.into()]
+        );
+
+        // Invalid combinations are skipped, fast must be under slow
+        let r: IndicatorRange = "macd:10..30/10,20".parse().unwrap();
+        assert_eq!(r.specs().unwrap().len(), 1);
+
+        let r: IndicatorRange = "rsi:0".parse().unwrap();
+        assert!(r.specs().is_err());
+    }
+
+    #[test]
+    fn search_space() {
+        let space = SearchSpace::new(
+            &"100,200".parse().unwrap(),
+            &"0.0..0.2/0.1".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            &["rsi:10..14/4".parse().unwrap()],
+        )
+        .unwrap();
+        assert_eq!(space.len(), 12);
+
+        // Every combination is distinct
+        let all: Vec<Candidate> = (0..space.len()).map(|i| space.get(i)).collect();
+        for (i, a) in all.iter().enumerate() {
+            assert!(all[i + 1..].iter().all(|b| a != b));
+        }
+
+        let mut sampled = space.sample(4, 1);
+        sampled.sort();
+        sampled.dedup();
+        assert_eq!(sampled.len(), 4);
+        assert_eq!(space.sample(100, 1).len(), 12);
+
+        // Shares can be fractional, but not negative
+        let rsi: IndicatorRange = "rsi".parse().unwrap();
+        assert!(SearchSpace::new(
+            &"10.5".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            std::slice::from_ref(&rsi),
+        )
+        .is_ok());
+        assert!(SearchSpace::new(
+            &"-10".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            std::slice::from_ref(&rsi),
+        )
+        .is_err());
+        assert!(SearchSpace::new(
+            &"10".parse().unwrap(),
+            &"2.0".parse().unwrap(),
+            &"0.0".parse().unwrap(),
+            &[rsi],
+        )
+        .is_err());
+
+        // An average of no signals would be NaN
+        assert_eq!(
+            SearchSpace::new(
+                &"10".parse().unwrap(),
+                &"0.0".parse().unwrap(),
+                &"0.0".parse().unwrap(),
+                &[],
+            )
+            .err(),
+            Some(OptimizerError::NoIndicators)
+        );
+    }
+
+    #[test]
+    fn ranking() {
+        let mut results = vec![(0.1, "a"), (f64::NAN, "b"), (0.3, "c"), (-0.2, "d")];
+        rank(&mut results);
+        let order: Vec<&str> = results.iter().map(|r| r.1).collect();
+        assert_eq!(order, vec!["c", "a", "d", "b"]);
+    }
+}
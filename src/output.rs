@@ -1,14 +1,28 @@
 //! Structs for serializing/deserializing the output of Techalyzer to JSON.
 
 use crate::{
-    backtester::performance::PortfolioPerformance,
+    backtester::{
+        performance::PortfolioPerformance,
+        statistics::{BenchmarkStatistics, Statistics},
+    },
+    date::Date,
     indicators::SupportedIndicators,
-    marketdata::prices::Prices,
-    signals::{Output, Signal},
-    trading::tradingmodel::Trades,
+    marketdata::{validation::Issue, Bar, Prices},
+    optimizer::Objective,
+    signals::{IndicatorSignal, Output, Signal},
+    trading::{
+        exits::ExitReason,
+        ml::Algorithm,
+        ml::Hyperparameters,
+        modelfile::{Metadata, ModelMetadata, QLearningMetadata},
+        tradingmodel::Trades,
+        Position,
+    },
+    tuning::{ClassReport, ConfusionMatrix, CrossValidation, RegressionMetrics},
     util::TimeSeries,
 };
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
 
 /// An entry at some date with price, signal, and technical indicator data.
 #[derive(Serialize, Deserialize)]
@@ -16,6 +30,18 @@ pub struct TechalyzerEntry {
     pub signal: Signal,
     pub price: f64,
     pub output: Output,
+
+    /// Full price data for the day. Absent in output from older versions of
+    /// Techalyzer.
+    #[serde(default)]
+    pub bar: Option<Bar>,
+}
+
+impl TechalyzerEntry {
+    /// Returns `bar`, or makes one out of `price` if there is none.
+    pub fn bar(&self) -> Bar {
+        self.bar.unwrap_or_else(|| self.price.into())
+    }
 }
 
 /// Organizes the output of Print the way we want before printing to JSON.
@@ -33,10 +59,344 @@ pub struct TechalyzerBacktestOutput {
     pub trades: Trades,
     pub performance: PortfolioPerformance,
     pub benchmark: PortfolioPerformance,
+    /// Beta, alpha, etc of the trading model versus the benchmark.
+    pub relative_to_benchmark: BenchmarkStatistics,
+    pub trades_accuracy: f64,
+    pub total_return: f64,
+    /// Transaction costs paid by the trading model over the whole backtest.
+    pub total_costs: f64,
+    /// Dividends received by the trading model, less those paid on shorts.
+    pub total_dividends: f64,
+    /// How many positions each exit rule closed (see `trading::exits`).
+    pub exit_counts: BTreeMap<ExitReason, usize>,
+    pub model_name: String,
+    pub symbol: String,
+}
+
+/// One row of the leaderboard produced by Optimize.
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    /// Value of the objective the leaderboard is ranked by.
+    pub score: f64,
+    pub shares: f64,
+    pub dead_zone: f64,
+    pub disposition: f64,
+    /// Space-separated indicator specs, e.g. `RelativeStrengthIndex:14 MACD:12,26,9`.
+    pub indicators: String,
+    pub total_return: f64,
+    pub sharpe_ratio: f64,
+    pub max_drawdown: f64,
+    pub total_costs: f64,
+}
+
+/// Organizes the output of Optimize before printing to JSON.
+#[derive(Serialize)]
+pub struct TechalyzerOptimizeOutput {
+    pub symbol: String,
+    pub objective: Objective,
+    /// How many parameter combinations were backtested.
+    pub combinations_tried: usize,
+    pub leaderboard: Vec<LeaderboardEntry>,
+}
+
+/// Results of one fold of a walk-forward evaluation.
+#[derive(Serialize)]
+pub struct WalkForwardFold {
+    pub train_start: Date,
+    pub train_end: Date,
+    pub test_start: Date,
+    pub test_end: Date,
+    pub total_return: f64,
+    pub benchmark_return: f64,
     pub trades_accuracy: f64,
+    pub total_costs: f64,
+    pub statistics: Statistics,
+    pub relative_to_benchmark: BenchmarkStatistics,
+}
+
+/// Organizes the output of WalkForward before printing to JSON.
+#[derive(Serialize)]
+pub struct TechalyzerWalkForwardOutput {
+    pub symbol: String,
+    pub model_name: String,
+    pub folds: Vec<WalkForwardFold>,
+    /// The test windows chained together, as if one portfolio had traded
+    /// through all of them.
+    pub performance: PortfolioPerformance,
+    /// Buy and hold over the same test windows, chained the same way.
+    pub benchmark: PortfolioPerformance,
+    pub relative_to_benchmark: BenchmarkStatistics,
+    pub total_return: f64,
+    pub benchmark_return: f64,
+    /// Average of the folds' total returns.
+    pub mean_fold_return: f64,
+    /// Fraction of folds with a positive return.
+    pub profitable_folds: f64,
+}
+
+/// How one candidate's model did on one cross-validation fold (or a model on
+/// the days held out by Train).
+#[derive(Serialize)]
+pub struct TuneFold {
+    pub train_days: usize,
+    pub test_start: Date,
+    pub test_end: Date,
+    /// None if no test day could be labelled.
+    pub accuracy: Option<f64>,
+    pub classes: ClassReport,
+    /// Test days by actual label (rows) and predicted label (columns), each
+    /// in the order short, out, long.
+    pub confusion_matrix: ConfusionMatrix,
+    /// How close a regressor's predicted returns came to the actual ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regression: Option<RegressionMetrics>,
+}
+
+/// One combination of hyperparameters tried by Tune.
+#[derive(Serialize)]
+pub struct TuneCandidate {
+    pub rank: usize,
+    pub hyperparameters: Hyperparameters,
+    /// Average of the folds' accuracies.
+    pub mean_accuracy: f64,
+    pub folds: Vec<TuneFold>,
+}
+
+/// Organizes the output of Tune before printing to JSON.
+#[derive(Serialize)]
+pub struct TechalyzerTuneOutput {
+    pub symbol: String,
+    pub algorithm: Algorithm,
+    pub cross_validation: CrossValidation,
+    pub embargo: usize,
+    /// Every combination tried, best first.
+    pub candidates: Vec<TuneCandidate>,
+    /// Where the best candidate, trained on every training day, was saved.
+    pub model_file: PathBuf,
+}
+
+/// What Train reports when it holds out some of the training days.
+#[derive(Serialize)]
+pub struct TechalyzerTrainOutput {
+    pub symbol: String,
+    pub algorithm: Algorithm,
+    /// How a model trained on the other days did on the held out days.
+    pub holdout: TuneFold,
+    /// Where the model trained on every training day was saved.
+    pub model_file: PathBuf,
+}
+
+/// Organizes the output of a portfolio BackTest before printing to JSON.
+#[derive(Serialize)]
+pub struct TechalyzerPortfolioBacktestOutput {
+    pub symbols: Vec<String>,
+    pub trades: BTreeMap<String, Trades>,
+    pub performance: PortfolioPerformance,
+    /// Each symbol's contribution to the portfolio's performance.
+    pub per_symbol: BTreeMap<String, PortfolioPerformance>,
+    /// Buying and holding every symbol in the portfolio.
+    pub benchmark: PortfolioPerformance,
+    pub relative_to_benchmark: BenchmarkStatistics,
     pub total_return: f64,
+    pub total_costs: f64,
+    pub total_dividends: f64,
+    /// How many positions each exit rule closed, across every symbol.
+    pub exit_counts: BTreeMap<ExitReason, usize>,
     pub model_name: String,
+}
+
+/// Organizes the output of Suggest before printing it as text or JSON.
+#[derive(Serialize)]
+pub struct TechalyzerSuggestOutput {
+    pub symbol: String,
+    /// The most recent day of price data the suggestion is based on.
+    pub date: Date,
+    pub model_name: String,
+    /// The position the model recommends holding.
+    pub position: Position,
+    /// The signals informing the model's decision, if it uses any.
+    pub signals: Vec<IndicatorSignal>,
+}
+
+impl Display for TechalyzerSuggestOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} suggests {} for {} as of {}",
+            self.model_name,
+            self.position,
+            self.symbol.to_uppercase(),
+            self.date
+        )?;
+
+        for s in &self.signals {
+            // HashMap ordering is random, so sort the outputs for readability.
+            let mut outputs: Vec<String> = s
+                .output
+                .output
+                .iter()
+                .map(|(k, v)| format!("{}: {:.4}", k, v))
+                .collect();
+            outputs.sort();
+            writeln!(
+                f,
+                "  {}: {:.4} ({})",
+                s.indicator,
+                s.signal.0,
+                outputs.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Problems found in the price data of one symbol by CheckData, printed as
+/// text or JSON.
+#[derive(Serialize)]
+pub struct TechalyzerCheckDataOutput {
     pub symbol: String,
+    pub first_date: Option<Date>,
+    pub last_date: Option<Date>,
+    /// Number of days with price data.
+    pub days: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Display for TechalyzerCheckDataOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} days of data",
+            self.symbol.to_uppercase(),
+            self.days
+        )?;
+        if let (Some(first), Some(last)) = (self.first_date, self.last_date) {
+            write!(f, " from {} to {}", first, last)?;
+        }
+
+        if self.issues.is_empty() {
+            return writeln!(f, ", no problems found");
+        }
+        writeln!(f, ", {} problem(s) found:", self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(f, "  {}", issue)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What ModelInfo found in a model file, printed as text or JSON.
+#[derive(Serialize)]
+pub struct TechalyzerModelInfoOutput {
+    pub format_version: u32,
+    /// Checksum of the model, which matched the model in the file.
+    pub checksum: String,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+impl Display for TechalyzerModelInfoOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.metadata {
+            Metadata::MachineLearning(m) => self.fmt_machine_learning(m, f),
+            Metadata::QLearning(m) => self.fmt_q_learning(m, f),
+        }
+    }
+}
+
+impl TechalyzerModelInfoOutput {
+    fn fmt_machine_learning(
+        &self,
+        m: &ModelMetadata,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} model for {}, trained with Techalyzer {} on {}",
+            m.algorithm,
+            m.symbol.to_uppercase(),
+            m.crate_version,
+            m.created
+        )?;
+        writeln!(
+            f,
+            "  model file format version {}, checksum {} (ok)",
+            self.format_version, self.checksum
+        )?;
+        write!(f, "  trained on {} days", m.training_days)?;
+        if let (Some(start), Some(end)) = (m.train_start, m.train_end) {
+            write!(f, " from {} to {}", start, end)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  horizon {}, decision threshold {}, labeler {}",
+            m.horizon, m.decision_threshold, m.labeler
+        )?;
+        let indicators: Vec<String> = m.signal_generators.iter().map(|s| s.to_string()).collect();
+        writeln!(f, "  indicators: {}", indicators.join(", "))?;
+        writeln!(f, "  features: {}", m.features.join(", "))?;
+        let h = &m.hyperparameters;
+        let depth = h
+            .max_depth
+            .map_or_else(|| "default".to_string(), |d| d.to_string());
+        writeln!(
+            f,
+            "  max depth {}, min samples split {}, min samples leaf {}, {} trees, learning rate {}, \
+             ridge penalty {}",
+            depth,
+            h.min_samples_split,
+            h.min_samples_leaf,
+            h.num_trees,
+            h.learning_rate,
+            h.ridge_penalty
+        )?;
+        writeln!(f, "  sizing: {}", m.sizing)
+    }
+
+    fn fmt_q_learning(
+        &self,
+        m: &QLearningMetadata,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Q-learning model for {}, trained with Techalyzer {} on {}",
+            m.symbol.to_uppercase(),
+            m.crate_version,
+            m.created
+        )?;
+        writeln!(
+            f,
+            "  model file format version {}, checksum {} (ok)",
+            self.format_version, self.checksum
+        )?;
+        write!(f, "  trained on {} days", m.training_days)?;
+        if let (Some(start), Some(end)) = (m.train_start, m.train_end) {
+            write!(f, " from {} to {}", start, end)?;
+        }
+        writeln!(f)?;
+        let indicators: Vec<String> = m.signal_generators.iter().map(|s| s.to_string()).collect();
+        writeln!(f, "  indicators: {}", indicators.join(", "))?;
+        let h = &m.hyperparameters;
+        writeln!(
+            f,
+            "  {} bins, alpha {}, gamma {}, random action rate {} (decay {}), {} epochs, dyna {}, impact {}, seed {}",
+            h.bins,
+            h.alpha,
+            h.gamma,
+            h.random_action_rate,
+            h.random_action_decay,
+            h.epochs,
+            h.dyna,
+            h.impact,
+            h.seed
+        )?;
+        writeln!(f, "  sizing: {}", m.sizing)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,428 @@
+//! Renders charts of Techalyzer's output (indicators and signals, backtests)
+//! straight to SVG or PNG files, without needing the Python scripts.
+
+use crate::{
+    backtester::performance::PortfolioPerformance,
+    output::{TechalyzerBacktestOutput, TechalyzerPortfolioBacktestOutput, TechalyzerPrintOutput},
+    trading::{tradingmodel::Trades, Position},
+    util::TimeSeries,
+    Date,
+};
+use plotters::{coord::Shift, drawing::DrawingAreaErrorKind, prelude::*};
+use std::{collections::BTreeSet, error::Error, path::Path};
+use thiserror::Error;
+
+/// Size of every chart, in pixels.
+const SIZE: (u32, u32) = (1280, 960);
+
+/// Things that can go wrong while plotting.
+#[derive(Debug, Error)]
+pub enum PlotError {
+    #[error("Can't plot to '{0}', the file name must end in .svg or .png")]
+    UnsupportedFormat(String),
+
+    #[error("Nothing to plot")]
+    NoData,
+
+    #[error("Error while drawing the chart: {0}")]
+    Drawing(String),
+}
+
+impl<E: Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self {
+        PlotError::Drawing(e.to_string())
+    }
+}
+
+/// Image formats charts can be saved as.
+#[derive(Debug, PartialEq)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    /// Picks the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Result<Self, PlotError> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("svg") => Ok(ImageFormat::Svg),
+            Some("png") => Ok(ImageFormat::Png),
+            _ => Err(PlotError::UnsupportedFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// Draws a chart to `path` with a function generic over the drawing backend.
+macro_rules! render {
+    ($path:expr, $draw:ident, $($arg:expr),*) => {
+        match ImageFormat::from_path($path)? {
+            ImageFormat::Svg => $draw(SVGBackend::new($path, SIZE).into_drawing_area(), $($arg),*),
+            ImageFormat::Png => $draw(BitMapBackend::new($path, SIZE).into_drawing_area(), $($arg),*),
+        }
+    };
+}
+
+/// Plots the price, the indicator's outputs, and its buy/sell signals, one
+/// above the other.
+pub fn plot_signals(output: &TechalyzerPrintOutput, path: &Path) -> Result<(), PlotError> {
+    render!(path, draw_signals, output)
+}
+
+/// Plots the price with markers where the model changed its position, and
+/// the portfolio's value against the benchmark's.
+pub fn plot_backtest(output: &TechalyzerBacktestOutput, path: &Path) -> Result<(), PlotError> {
+    render!(path, draw_backtest, output)
+}
+
+/// Plots the portfolio's value against the benchmark's, and what each symbol
+/// contributed.
+pub fn plot_portfolio_backtest(
+    output: &TechalyzerPortfolioBacktestOutput,
+    path: &Path,
+) -> Result<(), PlotError> {
+    render!(path, draw_portfolio_backtest, output)
+}
+
+fn draw_signals<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    output: &TechalyzerPrintOutput,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let dates: Vec<Date> = output.map.keys().cloned().collect();
+    let title = format!("{} {}", output.symbol.to_uppercase(), output.indicator);
+    let root = titled(root, &title)?;
+    let panes = root.split_evenly((3, 1));
+
+    let prices = output.map.values().map(|e| e.price).collect();
+    line_chart(&panes[0], "Price", &dates, vec![("price", prices)], &[])?;
+
+    // Outputs have the same keys every day, but not necessarily in order.
+    let names: BTreeSet<&String> = output
+        .map
+        .values()
+        .flat_map(|e| e.output.output.keys())
+        .collect();
+    let outputs = names
+        .into_iter()
+        .map(|name| {
+            let values = output
+                .map
+                .values()
+                .map(|e| e.output.output.get(name).cloned().unwrap_or(f64::NAN))
+                .collect();
+            (name.as_str(), values)
+        })
+        .collect();
+    line_chart(&panes[1], "Indicator", &dates, outputs, &[])?;
+
+    let signals: Vec<f64> = output.map.values().map(|e| e.signal.0).collect();
+    signal_chart(&panes[2], &dates, &signals)?;
+
+    root.present()?;
+    Ok(())
+}
+
+fn draw_backtest<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    output: &TechalyzerBacktestOutput,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let dates: Vec<Date> = output.prices.map.keys().cloned().collect();
+    let title = format!(
+        "{} {} backtest",
+        output.symbol.to_uppercase(),
+        output.model_name
+    );
+    let root = titled(root, &title)?;
+    let panes = root.split_evenly((2, 1));
+
+    let prices: Vec<f64> = output.prices.iter().map(|(_, p)| *p).collect();
+    let markers = trade_markers(&dates, &prices, &output.trades);
+    line_chart(
+        &panes[0],
+        "Price",
+        &dates,
+        vec![("price", prices)],
+        &markers,
+    )?;
+
+    let portvals = vec![
+        (
+            output.model_name.as_str(),
+            values_on(&dates, &output.performance),
+        ),
+        ("benchmark", values_on(&dates, &output.benchmark)),
+    ];
+    line_chart(&panes[1], "Portfolio value", &dates, portvals, &[])?;
+
+    root.present()?;
+    Ok(())
+}
+
+fn draw_portfolio_backtest<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    output: &TechalyzerPortfolioBacktestOutput,
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let dates: Vec<Date> = output.performance.daily_portvals.keys().cloned().collect();
+    let title = format!(
+        "{} {} backtest",
+        output.symbols.join(", ").to_uppercase(),
+        output.model_name
+    );
+    let root = titled(root, &title)?;
+    let panes = root.split_evenly((2, 1));
+
+    let portvals = vec![
+        (
+            output.model_name.as_str(),
+            values_on(&dates, &output.performance),
+        ),
+        ("benchmark", values_on(&dates, &output.benchmark)),
+    ];
+    line_chart(&panes[0], "Portfolio value", &dates, portvals, &[])?;
+
+    let per_symbol = output
+        .per_symbol
+        .iter()
+        .map(|(symbol, perf)| (symbol.as_str(), values_on(&dates, perf)))
+        .collect();
+    line_chart(&panes[1], "Value by symbol", &dates, per_symbol, &[])?;
+
+    root.present()?;
+    Ok(())
+}
+
+/// Draws a title across the top of `root` and returns the area under it.
+fn titled<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    title: &str,
+) -> Result<DrawingArea<DB, Shift>, PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    Ok(root.titled(title, ("sans-serif", 28))?)
+}
+
+/// A point on a chart where the model changed its position.
+struct Marker {
+    index: usize,
+    price: f64,
+    position: Position,
+}
+
+/// Finds every day the position held changes. Repeating the current
+/// position or holding is not a change.
+fn trade_markers(dates: &[Date], prices: &[f64], trades: &Trades) -> Vec<Marker> {
+    let mut current = Position::Out;
+    let mut markers = Vec::new();
+    for (index, (day, price)) in dates.iter().zip(prices).enumerate() {
+        match trades.get(day) {
+            Some(Position::Hold) | None => (),
+            Some(p) if *p == current => (),
+            Some(p) => {
+                current = *p;
+                markers.push(Marker {
+                    index,
+                    price: *price,
+                    position: *p,
+                });
+            }
+        }
+    }
+
+    markers
+}
+
+/// Portfolio values lined up with `dates`, NaN on days with no value.
+fn values_on(dates: &[Date], performance: &PortfolioPerformance) -> Vec<f64> {
+    series_on(dates, &performance.daily_portvals)
+}
+
+fn series_on(dates: &[Date], series: &TimeSeries<f64>) -> Vec<f64> {
+    dates
+        .iter()
+        .map(|d| series.get(d).cloned().unwrap_or(f64::NAN))
+        .collect()
+}
+
+/// Range covering the finite values of every series, with a little room
+/// above and below.
+fn value_range<'a>(values: impl Iterator<Item = &'a f64>) -> Option<(f64, f64)> {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold(None, |range, v| match range {
+            None => Some((*v, *v)),
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+        })?;
+    let pad = match (max - min) * 0.05 {
+        p if p > 0.0 => p,
+        _ => 1.0,
+    };
+    Some((min - pad, max + pad))
+}
+
+/// Labels an x coordinate (an index into `dates`) with its date.
+fn date_label(dates: &[Date], x: f64) -> String {
+    match dates.get(x.round() as usize) {
+        Some(d) if x >= 0.0 => d.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Draws each series as a line against the index of its day in `dates`,
+/// with a legend, and any trade markers on top. Gaps (NaN) break the lines.
+fn line_chart<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    caption: &str,
+    dates: &[Date],
+    series: Vec<(&str, Vec<f64>)>,
+    markers: &[Marker],
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let (min, max) = value_range(series.iter().flat_map(|(_, values)| values.iter()))
+        .ok_or(PlotError::NoData)?;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(70)
+        .build_cartesian_2d(0.0..dates.len().max(2) as f64 - 1.0, min..max)?;
+    chart
+        .configure_mesh()
+        .x_labels(8)
+        .x_label_formatter(&|x| date_label(dates, *x))
+        .draw()?;
+
+    for (i, (name, values)) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        // One line per run of finite values
+        let mut runs: Vec<Vec<(f64, f64)>> = vec![Vec::new()];
+        for (x, y) in values.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if y.is_finite() => run.push((x as f64, *y)),
+                Some(run) if !run.is_empty() => runs.push(Vec::new()),
+                _ => (),
+            }
+        }
+        for (j, run) in runs.into_iter().enumerate() {
+            let line = chart.draw_series(LineSeries::new(run, &color))?;
+            if j == 0 {
+                line.label(*name)
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+            }
+        }
+    }
+
+    chart.draw_series(markers.iter().map(|m| {
+        let point = (m.index as f64, m.price);
+        match m.position {
+            Position::Long(_) => TriangleMarker::new(point, 8, GREEN.filled()).into_dyn(),
+            Position::Short(_) => Cross::new(point, 6, RED.stroke_width(2)).into_dyn(),
+            _ => Circle::new(point, 5, BLACK.filled()).into_dyn(),
+        }
+    }))?;
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Draws signals as bars, green when bullish and red when bearish.
+fn signal_chart<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    dates: &[Date],
+    signals: &[f64],
+) -> Result<(), PlotError>
+where
+    DB::ErrorType: 'static,
+{
+    let mut chart = ChartBuilder::on(area)
+        .caption("Bullish/bearish signals", ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(70)
+        .build_cartesian_2d(-0.5..dates.len().max(2) as f64 - 0.5, -1.0..1.0)?;
+    chart
+        .configure_mesh()
+        .x_labels(8)
+        .x_label_formatter(&|x| date_label(dates, *x))
+        .draw()?;
+
+    chart.draw_series(signals.iter().enumerate().map(|(x, s)| {
+        let color = if *s < 0.0 { RED } else { GREEN };
+        let x = x as f64;
+        Rectangle::new([(x - 0.4, 0.0), (x + 0.4, *s)], color.filled())
+    }))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn format_from_extension() {
+        let format = |p: &str| ImageFormat::from_path(&PathBuf::from(p));
+        assert_eq!(format("chart.svg").unwrap(), ImageFormat::Svg);
+        assert_eq!(format("out/CHART.PNG").unwrap(), ImageFormat::Png);
+        assert!(matches!(
+            format("chart.jpg"),
+            Err(PlotError::UnsupportedFormat(_))
+        ));
+        assert!(format("chart").is_err());
+    }
+
+    #[test]
+    fn markers_only_on_position_changes() {
+        let dates: Vec<Date> = (1..=5).map(|d| Date::from_ymd(2020, 1, d)).collect();
+        let prices = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let trades = Trades::from(
+            dates
+                .iter()
+                .cloned()
+                .zip(vec![
+                    Position::Out,
+                    Position::Long(10.0),
+                    Position::Long(10.0),
+                    Position::Hold,
+                    Position::Short(10.0),
+                ])
+                .collect::<TimeSeries<_>>(),
+        );
+
+        let markers = trade_markers(&dates, &prices, &trades);
+        let indices: Vec<usize> = markers.iter().map(|m| m.index).collect();
+        assert_eq!(indices, vec![1, 4]);
+        assert_eq!(markers[1].position, Position::Short(10.0));
+    }
+
+    #[test]
+    fn range_ignores_gaps() {
+        let values = [f64::NAN, 10.0, 20.0, f64::INFINITY];
+        let (min, max) = value_range(values.iter()).unwrap();
+        assert!(min < 10.0 && min > 9.0);
+        assert!(max > 20.0 && max < 21.0);
+        assert!(value_range([f64::NAN].iter()).is_none());
+    }
+}
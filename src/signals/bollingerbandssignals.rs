@@ -1,6 +1,7 @@
 //! Signals generated with Bollinger Bands.
 
 use super::{Output, Signal, SignalsIter};
+use crate::indicators::SupportedIndicators;
 use crate::util::clamp;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -46,6 +47,10 @@ impl SignalsIter for BBSignalsIter {
             ),
         }
     }
+
+    fn indicator(&self) -> SupportedIndicators {
+        SupportedIndicators::BollingerBands
+    }
 }
 
 impl From<BollingerBandsOutput> for Output {
@@ -99,10 +104,7 @@ mod tests {
         .cloned()
         .collect();
         let l = map.len();
-        let prices = Prices {
-            map,
-            symbol: "jpm".to_string(),
-        };
+        let prices = Prices::from_closes("jpm", map);
 
         let mut sig_gen = BBSignalsIter::new(5, 2.0).unwrap();
         let signals: Vec<Signal> = prices.iter().map(|p| sig_gen.next(*p.1).0).collect();
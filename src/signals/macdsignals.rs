@@ -1,6 +1,7 @@
 //! Signals generated with Moving Average Convergence Divergence (MACD).
 
 use super::{Output, Signal, SignalsIter};
+use crate::indicators::SupportedIndicators;
 use crate::util::clamp;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -29,7 +30,8 @@ impl MACDSignalsIter {
 
 impl Reset for MACDSignalsIter {
     fn reset(&mut self) {
-        self.macd.reset()
+        self.macd.reset();
+        self.macd_line_prev = 0.0;
     }
 }
 
@@ -64,6 +66,10 @@ impl SignalsIter for MACDSignalsIter {
         let signal = Signal::new(clamp(norm_macd_slope, -0.5, 0.5).unwrap() + above_or_below);
         (signal, o.into())
     }
+
+    fn indicator(&self) -> SupportedIndicators {
+        SupportedIndicators::MACD
+    }
 }
 
 impl From<MovingAverageConvergenceDivergenceOutput> for Output {
@@ -118,10 +124,7 @@ mod tests {
         .iter()
         .cloned()
         .collect();
-        let prices = Prices {
-            map,
-            symbol: "jpm".to_string(),
-        };
+        let prices = Prices::from_closes("jpm", map);
 
         let mut sig_gen = MACDSignalsIter::default();
         let signals: Vec<Signal> = prices.iter().map(|p| sig_gen.next(*p.1).0).collect();
@@ -9,6 +9,7 @@ pub mod macdsignals;
 pub mod relativestrengthindexsignals;
 pub mod smacrossovers;
 
+use crate::{indicators::SupportedIndicators, marketdata::Prices};
 use derive_more::{Display, FromStr};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -89,6 +90,40 @@ impl Signal {
 pub trait SignalsIter: Reset + Debug {
     /// Return a tuple of the next Signal and technical indicator Output.
     fn next(&mut self, price: f64) -> (Signal, Output);
+
+    /// Which technical indicator the signals are generated from.
+    fn indicator(&self) -> SupportedIndicators;
+}
+
+/// The Signal and Output a signal generator produced on a given day.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndicatorSignal {
+    pub indicator: SupportedIndicators,
+    pub signal: Signal,
+    pub output: Output,
+}
+
+/// Runs each signal generator over the whole of `prices`, and returns what
+/// each of them produced on the last day. The generators are reset before
+/// and after.
+pub fn latest_signals(
+    signal_generators: &mut [Box<dyn SignalsIter>],
+    prices: &Prices,
+) -> Vec<IndicatorSignal> {
+    let mut latest = Vec::new();
+    for gen in signal_generators.iter_mut() {
+        gen.reset();
+        if let Some((signal, output)) = prices.iter().map(|(_, p)| gen.next(*p)).last() {
+            latest.push(IndicatorSignal {
+                indicator: gen.indicator(),
+                signal,
+                output,
+            });
+        }
+        gen.reset();
+    }
+
+    latest
 }
 
 /// Represents a single point output of a ta technical indicator. Usually a
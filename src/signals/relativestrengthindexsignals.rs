@@ -1,6 +1,7 @@
 //! Signals generated with Relative Strength Index (RSI).
 
 use super::{Output, Signal, SignalsIter};
+use crate::indicators::SupportedIndicators;
 use serde::{Deserialize, Serialize};
 use ta::indicators::RelativeStrengthIndex;
 use ta::{Next, Reset};
@@ -35,6 +36,10 @@ impl SignalsIter for RSISignalsIter {
         // Instead of 0 to 100, signal is -1.0 to 1.0
         (Signal::new(-((rsi_val / 50.0) - 1.0)), rsi_val.into())
     }
+
+    fn indicator(&self) -> SupportedIndicators {
+        SupportedIndicators::RelativeStrengthIndex
+    }
 }
 
 #[cfg(test)]
@@ -83,9 +88,6 @@ mod tests {
         .iter()
         .cloned()
         .collect();
-        Prices {
-            map,
-            symbol: "jpm".to_string(),
-        }
+        Prices::from_closes("jpm", map)
     }
 }
@@ -3,6 +3,7 @@
 //! applied.
 
 use super::{Output, Signal, SignalsIter};
+use crate::indicators::SupportedIndicators;
 use crate::util::{clamp, slope};
 use serde::{Deserialize, Serialize};
 use ta::{errors::ErrorKind, indicators::SimpleMovingAverage, Next, Reset};
@@ -96,6 +97,10 @@ impl SignalsIter for SmaCrossoversSignalsIter {
 
         signal_output_pair(signal, fast, slow)
     }
+
+    fn indicator(&self) -> SupportedIndicators {
+        SupportedIndicators::SmaCrossover
+    }
 }
 
 #[cfg(test)]
@@ -140,10 +145,7 @@ mod tests {
         .iter()
         .cloned()
         .collect();
-        Prices {
-            map,
-            symbol: "jpm".to_string(),
-        }
+        Prices::from_closes("jpm", map)
     }
 
     #[test]
@@ -1,13 +1,31 @@
 //! Subcommands for the Techalyzer program. They are split off to allow for
 //! easier integration and end-to-end testing.
 
-use crate::Date;
 use crate::{
-    backtester::BackTester,
+    backtester::{
+        costs::TransactionCosts, performance::PortfolioPerformance, portfolio::PortfolioBackTester,
+        walkforward, BackTester,
+    },
+    calendar::TradingCalendar,
+    config::{
+        BacktesterParams, OptimizeParams, QLearningParams, TrainingParams, TuneParams,
+        WalkForwardParams,
+    },
     error::TechalyzerError,
-    indicators::SupportedIndicators,
-    marketdata::prices::Prices,
-    output::{TechalyzerBacktestOutput, TechalyzerEntry, TechalyzerPrintOutput},
+    indicators::{IndicatorSpec, SupportedIndicators},
+    marketdata::{
+        prices::Prices,
+        validation::{self, RepairPolicy, ValidationError},
+    },
+    optimizer::{self, IndicatorRange, SearchSpace},
+    output::{
+        LeaderboardEntry, TechalyzerBacktestOutput, TechalyzerCheckDataOutput, TechalyzerEntry,
+        TechalyzerModelInfoOutput, TechalyzerOptimizeOutput, TechalyzerPortfolioBacktestOutput,
+        TechalyzerPrintOutput, TechalyzerSuggestOutput, TechalyzerTrainOutput,
+        TechalyzerTuneOutput, TechalyzerWalkForwardOutput, TuneCandidate, TuneFold,
+        WalkForwardFold,
+    },
+    plot,
     signals::{
         bollingerbandssignals::BBSignalsIter, macdsignals::MACDSignalsIter,
         relativestrengthindexsignals::RSISignalsIter, smacrossovers::SmaCrossoversSignalsIter,
@@ -15,73 +33,206 @@ use crate::{
     },
     trading::{
         buyandhold::BuyAndHold,
-        dtmodel::{DecisionTreeError, DecisionTreeTrader, Horizon, Trained},
-        tradingmodel::TradingModel,
+        dtmodel::{MachineLearningTrader, Trained},
+        features::{FeaturePipeline, FeatureSet},
+        labeling::{forward_return, Label},
+        ml::{Hyperparameters, Learner},
+        modelfile::{self, ModelMetadata, QLearningMetadata, FORMAT_VERSION},
+        qlearner::{QHyperparameters, QLearningTrader},
+        sizing::PositionSizer,
+        tradingmodel::{Trades, TradingModel},
+        Position,
     },
+    tuning::{self, ConfusionMatrix, CvFold, HyperparameterGrid, RegressionMetrics},
+    util::{first_key, last_key},
+};
+use crate::{date::today, Date};
+use rayon::prelude::*;
+use std::{
+    collections::BTreeMap,
+    ops::Bound::{Included, Unbounded},
+    path::PathBuf,
 };
-use std::{fs::File, path::PathBuf};
 
 /// Using price time series info and a technical indicator, prints the buy/sell
-/// signals, the indicator outputs, and prices to STDOUT as JSON.
-pub fn print(prices: Prices, indicator: SupportedIndicators) -> Result<(), TechalyzerError> {
+/// signals, the indicator outputs, and prices to STDOUT as JSON. If `plot` is
+/// given, they are also charted to that file (SVG or PNG).
+pub fn print(
+    prices: Prices,
+    indicator: IndicatorSpec,
+    plot: Option<PathBuf>,
+) -> Result<(), TechalyzerError> {
     // TODO: evaluate/benchmark signal generation using ndarray vs Vec<f64>
 
     // Calculate the technical indicator outputs and signals
-    // TODO: allow parameters for each indicator
     // FIXME: is there any way we can avoid heap allocating/dynamic dispatch?
-    let mut sig_iter: Box<dyn SignalsIter> = indicator.into();
+    let mut sig_iter = indicator.signals_iter()?;
 
     let results: Vec<(Signal, Output)> = prices.iter().map(|p| sig_iter.next(*p.1)).collect();
 
     let mut m = std::collections::BTreeMap::new();
-    for (i, (date, price)) in prices.iter().enumerate() {
+    for (i, (date, bar)) in prices.bars().enumerate() {
         m.insert(
             *date,
             TechalyzerEntry {
-                price: *price,
+                price: bar.price(),
                 signal: results[i].0,
                 output: results[i].1.clone(),
+                bar: Some(*bar),
             },
         );
     }
 
     let output = TechalyzerPrintOutput {
         symbol: prices.symbol,
-        indicator,
+        indicator: indicator.indicator(),
         map: m,
     };
 
     // TODO: genericize the output stream to allow for writing to a file
     print!("{}", serde_json::to_string(&output)?);
+
+    if let Some(path) = plot {
+        plot::plot_signals(&output, &path)?;
+    }
     Ok(())
 }
 
-/// Trains a machine learning classifier using a Prices time series, across the given
-/// set of train_dates, using a list of technical indicators. The model will be
-/// serialized to a binary file for later use.
+/// Trains a machine learning classifier (or return regressor) using a Prices
+/// time series, across the given set of train_dates, using a list of technical
+/// indicators. The model will be serialized to a model file for later use,
+/// along with how it was trained (see `modelfile`). If `params` holds out some
+/// of the training days, how a model trained on the rest did on them is
+/// printed to STDOUT as JSON.
 ///
 /// ### Arguments
 ///
 /// * `prices` - Prices dataset.
-/// * `train_dates` - Date range to train the model on. Should be `horizon` days
-/// less than the end of `prices`.
-/// * `signal_generators` - Technical Indicators to serve as features for the model.
-/// * `horizon` - During labelling, returns from this many days in the future are
-/// used. If the returns are positive or negative,
+/// * `train_dates` - Date range to train the model on. Should end `horizon`
+/// trading days before the end of `prices`.
+/// * `params` - Technical Indicators (and their parameters) to serve as
+/// features for the model, the labelling horizon, which learner to train and
+/// its hyperparameters, and how the model sizes its trades. The cash to size
+/// against is given when the model is used. The training dates are ignored.
+/// * `calendar` - Trading days to count the horizon in.
 /// * `outpath` - Where to save the serialized model file for later use.
 pub fn train(
     prices: Prices,
     train_dates: Vec<Date>,
-    signal_generators: Vec<SupportedIndicators>,
-    horizon: Horizon,
-    // TODO: add threshold as a param here
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+    out_path: PathBuf,
+) -> Result<(), TechalyzerError> {
+    let holdout = if params.holdout == 0.0 {
+        None
+    } else {
+        let fold = tuning::holdout_fold(&train_dates, params.holdout, params.horizon.0)?;
+        Some(cross_validate(&prices, &fold, params, calendar)?)
+    };
+
+    save_model(&prices, train_dates, params, calendar, out_path.clone())?;
+
+    if let Some(holdout) = holdout {
+        let output = TechalyzerTrainOutput {
+            symbol: prices.symbol.clone(),
+            algorithm: params.algorithm,
+            holdout,
+            model_file: out_path,
+        };
+        print!("{}", serde_json::to_string(&output)?);
+    }
+    Ok(())
+}
+
+/// Trains a model on every one of `train_dates` and saves it to `out_path`.
+fn save_model(
+    prices: &Prices,
+    train_dates: Vec<Date>,
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+    out_path: PathBuf,
+) -> Result<(), TechalyzerError> {
+    let mut metadata = ModelMetadata {
+        symbol: prices.symbol.clone(),
+        train_start: train_dates.first().cloned(),
+        train_end: train_dates.last().cloned(),
+        training_days: train_dates.len(),
+        horizon: params.horizon,
+        decision_threshold: params.decision_threshold,
+        labeler: params.labeler,
+        signal_generators: params.signal_generators.0.clone(),
+        algorithm: params.algorithm,
+        hyperparameters: Hyperparameters::from(params),
+        sizing: params.sizing(),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: today(),
+        features: Vec::new(),
+    };
+    let sizer = PositionSizer::new(params.sizing(), 0.0);
+    let model = train_model(prices, train_dates, params, calendar, sizer)?;
+    metadata.features = model.feature_names();
+
+    std::fs::write(out_path, modelfile::to_bytes(metadata, &model)?)?;
+    Ok(())
+}
+
+/// Trains a `QLearningTrader` on the days of `prices` between the training
+/// start and end dates in `params`, and saves it to a model file along with
+/// how it was trained.
+pub fn train_q_learner(
+    prices: Prices,
+    params: &QLearningParams,
     out_path: PathBuf,
 ) -> Result<(), TechalyzerError> {
-    let gens = signal_generators.iter().map(|f| f.into()).collect();
-    let model = train_model(&prices, train_dates, gens, horizon)?;
+    // The end date includes any intraday data on that day.
+    let start = params.train_start_date.map_or(Unbounded, Included);
+    let end = params
+        .train_end_date
+        .map_or(Unbounded, |d| Included(d.end_of_day()));
+    let train_prices = prices.date_range((start, end));
+
+    let signal_generators = params
+        .signal_generators
+        .0
+        .iter()
+        .map(IndicatorSpec::signals_iter)
+        .collect::<Result<Vec<_>, _>>()?;
+    let hyperparameters = QHyperparameters::from(params);
+    let sizer = PositionSizer::new(params.sizing, 0.0);
+    let model =
+        QLearningTrader::new(signal_generators, hyperparameters, sizer)?.train(&train_prices)?;
+
+    let metadata = QLearningMetadata {
+        symbol: prices.symbol.clone(),
+        train_start: first_key(&train_prices.map).cloned(),
+        train_end: last_key(&train_prices.map).cloned(),
+        training_days: train_prices.map.len(),
+        signal_generators: params.signal_generators.0.clone(),
+        hyperparameters,
+        sizing: params.sizing,
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: today(),
+    };
+    std::fs::write(out_path, modelfile::to_bytes(metadata, &model)?)?;
+    Ok(())
+}
 
-    let file = File::create(out_path)?;
-    bincode::serialize_into(file, &model)?;
+/// Describes how the model in a model file was trained, as text or JSON, after
+/// checking that the file is intact.
+pub fn model_info(model_file: PathBuf, json: bool) -> Result<(), TechalyzerError> {
+    let bytes = std::fs::read(model_file)?;
+    let (header, _) = modelfile::read_header(&bytes)?;
+    let output = TechalyzerModelInfoOutput {
+        format_version: FORMAT_VERSION,
+        checksum: format!("{:016x}", header.checksum),
+        metadata: header.metadata,
+    };
+
+    if json {
+        print!("{}", serde_json::to_string(&output)?);
+    } else {
+        print!("{}", output);
+    }
     Ok(())
 }
 
@@ -96,17 +247,37 @@ impl From<SupportedIndicators> for Box<dyn SignalsIter> {
     }
 }
 
+/// Trains the learner `params` asks for on the features it asks for.
 fn train_model(
     prices: &Prices,
     train_dates: Vec<Date>,
-    signal_generators: Vec<Box<dyn SignalsIter>>,
-    horizon: Horizon,
-) -> Result<DecisionTreeTrader<Trained>, DecisionTreeError> {
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+    sizer: PositionSizer,
+) -> Result<MachineLearningTrader<Learner, Trained>, TechalyzerError> {
+    let signal_generators = params
+        .signal_generators
+        .0
+        .iter()
+        .map(IndicatorSpec::signals_iter)
+        .collect::<Result<Vec<_>, _>>()?;
+    let features = FeaturePipeline::new(signal_generators, FeatureSet::from(params))?;
+    let learner = Learner::new(
+        params.algorithm,
+        &Hyperparameters::from(params),
+        features.names().len(),
+    );
+
     // TODO: either load a model or train a new one right here.
-    // TODO: don't hardcore shares
-    let model = DecisionTreeTrader::new(signal_generators, 1000)?;
-    // TODO: don't hardcode threshold.
-    let trained = model.train(prices, train_dates, horizon, 0.03)?;
+    let model = MachineLearningTrader::new(learner, features, sizer)?;
+    let trained = model.train(
+        prices,
+        train_dates,
+        &params.labeler,
+        params.horizon,
+        calendar,
+        params.decision_threshold.0 as f32,
+    )?;
 
     Ok(trained)
 }
@@ -117,12 +288,18 @@ fn train_model(
 /// ### Arguments
 /// * `prices` - Dataset to test the trading model over.
 /// * `trading_model` - One of the trading models supported by Techalyzer.
-/// * `model_file` - If given, the file from which to load the trading model.
 /// * `cash` - How much cash the trading model starts with.
+/// * `params` - Transaction costs and other backtester settings. The same
+/// costs are applied to the benchmark.
+/// * `calendar` - The market traded in, for annualizing statistics.
+/// * `plot` - Also chart the backtest to this file (SVG or PNG).
 pub fn backtest(
     prices: Prices,
     trading_model: impl TradingModel,
     cash: f64,
+    params: &BacktesterParams,
+    calendar: &TradingCalendar,
+    plot: Option<PathBuf>,
 ) -> Result<(), TechalyzerError> {
     // TODO: allow parameters for the models here.
 
@@ -135,24 +312,34 @@ pub fn backtest(
     // TODO: have a way for the model to tell us its signal data
 
     // Give the backtester the trades
-    let performance = BackTester::new(trades.clone(), &prices, cash)?.backtest()?;
+    let costs = TransactionCosts::from(params);
+    let days_per_year = calendar.trading_days_per_year();
+    let performance = run_backtester(trades.clone(), &prices, cash, costs, days_per_year)?;
 
-    let bench_trades = BuyAndHold::default().get_trades(&prices)?;
-    let bench_perf = BackTester::new(bench_trades, &prices, cash)?.backtest()?;
+    let bench_trades = BuyAndHold::all_in(cash, params.lot_size()).get_trades(&prices)?;
+    let bench_perf = run_backtester(bench_trades, &prices, cash, costs, days_per_year)?;
 
     let total_return = performance.total_return()?;
+    let total_costs = performance.total_costs;
+    let total_dividends = performance.total_dividends;
+    let relative_to_benchmark = performance.relative_to(&bench_perf);
 
     let symbol = prices.symbol.clone();
     let trades_accuracy = performance.trades_accuracy(&trades)?;
+    let exit_counts = trades.exit_counts();
     let output = TechalyzerBacktestOutput {
         performance,
         total_return,
+        total_costs,
+        total_dividends,
         trades,
         trades_accuracy,
+        exit_counts,
         model_name,
         symbol,
         prices,
         benchmark: bench_perf,
+        relative_to_benchmark,
     };
 
     // Serialize the backtest
@@ -161,11 +348,732 @@ pub fn backtest(
         Err(e) => return Err(TechalyzerError::Generic(e.to_string())),
     };
 
-    // TODO: other stats like daily return here
-    // print!(
-    //     "Total return: {}",
-    //     output.total_return
-    // );
+    if let Some(path) = plot {
+        plot::plot_backtest(&output, &path)?;
+    }
+    Ok(())
+}
+
+/// Backtests a trading model on several securities at once, paying for every
+/// trade out of one cash balance. The backtest results, for the portfolio and
+/// for each security, are written to STDOUT as JSON.
+///
+/// ### Arguments
+/// * `portfolio` - Dataset for each security to test the trading model over.
+/// * `trading_models` - A trading model for each security, in the same order.
+/// * `cash` - How much cash the portfolio begins with.
+/// * `params` - Transaction costs and other backtester settings. The same
+/// costs are applied to the benchmark.
+/// * `calendar` - The market traded in, for annualizing statistics.
+/// * `plot` - Also chart the backtest to this file (SVG or PNG).
+pub fn portfolio_backtest<M: TradingModel>(
+    portfolio: Vec<Prices>,
+    trading_models: Vec<M>,
+    cash: f64,
+    params: &BacktesterParams,
+    calendar: &TradingCalendar,
+    plot: Option<PathBuf>,
+) -> Result<(), TechalyzerError> {
+    if portfolio.len() != trading_models.len() {
+        return Err(TechalyzerError::Generic(format!(
+            "Got {} trading models for {} symbols",
+            trading_models.len(),
+            portfolio.len()
+        )));
+    }
+
+    let model_name = match trading_models.first() {
+        Some(m) => m.to_string(),
+        None => return Err(TechalyzerError::Generic("No symbols given".to_string())),
+    };
+
+    let mut holdings = Vec::new();
+    let mut bench_holdings = Vec::new();
+    for (prices, model) in portfolio.iter().zip(trading_models) {
+        let trades: Result<_, TechalyzerError> = model.get_trades(prices).map_err(Into::into);
+        holdings.push((prices, trades?));
+        // Splits the cash evenly between the symbols
+        let bench = BuyAndHold::all_in(cash / portfolio.len() as f64, params.lot_size());
+        bench_holdings.push((prices, bench.get_trades(prices)?));
+    }
+    let trades: BTreeMap<String, Trades> = holdings
+        .iter()
+        .map(|(p, t)| (p.symbol.clone(), t.clone()))
+        .collect();
+    let mut exit_counts = BTreeMap::new();
+    for (reason, count) in trades.values().flat_map(Trades::exit_counts) {
+        *exit_counts.entry(reason).or_insert(0) += count;
+    }
+
+    let costs = TransactionCosts::from(params);
+    let days_per_year = calendar.trading_days_per_year();
+    let result = PortfolioBackTester::new(holdings, cash)?
+        .with_costs(costs)
+        .with_days_per_year(days_per_year)
+        .backtest()?;
+    let benchmark = PortfolioBackTester::new(bench_holdings, cash)?
+        .with_costs(costs)
+        .with_days_per_year(days_per_year)
+        .backtest()?
+        .performance;
+
+    let output = TechalyzerPortfolioBacktestOutput {
+        symbols: portfolio.iter().map(|p| p.symbol.clone()).collect(),
+        trades,
+        total_return: result.performance.total_return()?,
+        total_costs: result.performance.total_costs,
+        total_dividends: result.performance.total_dividends,
+        relative_to_benchmark: result.performance.relative_to(&benchmark),
+        performance: result.performance,
+        per_symbol: result.per_symbol,
+        benchmark,
+        exit_counts,
+        model_name,
+    };
+
+    print!("{}", serde_json::to_string(&output)?);
+
+    if let Some(path) = plot {
+        plot::plot_portfolio_backtest(&output, &path)?;
+    }
+    Ok(())
+}
+
+fn run_backtester(
+    trades: Trades,
+    prices: &Prices,
+    cash: f64,
+    costs: TransactionCosts,
+    days_per_year: f64,
+) -> Result<PortfolioPerformance, TechalyzerError> {
+    Ok(BackTester::new(trades, prices, cash)?
+        .with_costs(costs)
+        .with_days_per_year(days_per_year)
+        .backtest()?)
+}
+
+/// Trains a model on a walk-forward fold's training window and backtests it
+/// on the test window. Returns the model's name, its performance, and the
+/// accuracy of its trades.
+fn walkforward_fold(
+    prices: &Prices,
+    fold: &walkforward::Fold,
+    training: &TrainingParams,
+    calendar: &TradingCalendar,
+    cash: f64,
+    backtester_params: &BacktesterParams,
+) -> Result<(String, PortfolioPerformance, f64), TechalyzerError> {
+    let days_per_year = calendar.trading_days_per_year();
+    let train_prices = prices.date_range(fold.train_start()..=fold.train_end());
+    let label_dates = fold
+        .label_dates(training.horizon.0)
+        .iter()
+        .filter(|d| train_prices.get(d).is_some())
+        .cloned()
+        .collect();
+    let model = train_model(
+        &train_prices,
+        label_dates,
+        training,
+        calendar,
+        PositionSizer::new(training.sizing(), cash)
+            .with_lot_size(backtester_params.lot_size())
+            .with_days_per_year(days_per_year),
+    )?;
+    let model_name = model.to_string();
+
+    // Trade from the start of the training window so the signal generators
+    // are warmed up by the first test day, opening whatever position the
+    // model already holds by then.
+    let mut trades = model.get_trades(&prices.date_range(fold.train_start()..=fold.test_end()))?;
+    let test_prices = prices.date_range(fold.test_start()..=fold.test_end());
+    if let Some(first) = first_key(&test_prices.map) {
+        trades.open_held_at(*first);
+    }
+    let costs = TransactionCosts::from(backtester_params);
+    let performance = run_backtester(trades.clone(), &test_prices, cash, costs, days_per_year)?;
+    let accuracy = performance.trades_accuracy(&trades)?;
+
+    Ok((model_name, performance, accuracy))
+}
+
+/// Evaluates a MachineLearningTrader out of sample. The model is retrained on a
+/// window of `params.train_days` and then backtested on the
+/// `params.test_days` that follow, after which both windows slide forward to
+/// the next test window. Results for each fold and for all of the test
+/// windows chained together are written to STDOUT as JSON.
+///
+/// ### Arguments
+/// * `prices` - Dataset to walk forward through.
+/// * `training` - Indicators to use as features, the labelling horizon, the
+/// learner and its hyperparameters, and how the model sizes its trades
+/// (against `cash`). The training dates are ignored.
+/// * `calendar` - Trading days to count the horizon and windows in. Days
+/// missing from `prices` still count towards the windows.
+/// * `params` - Lengths of the training and test windows.
+/// * `cash` - How much cash the model starts each test window with.
+/// * `backtester_params` - Transaction costs and other backtester settings.
+/// The same costs are applied to the benchmark.
+pub fn walkforward(
+    prices: Prices,
+    training: &TrainingParams,
+    calendar: &TradingCalendar,
+    params: &WalkForwardParams,
+    cash: f64,
+    backtester_params: &BacktesterParams,
+) -> Result<(), TechalyzerError> {
+    let dates = match (first_key(&prices.map), last_key(&prices.map)) {
+        (Some(first), Some(last)) => calendar.trading_days(*first, *last),
+        _ => Vec::new(),
+    };
+    let folds = walkforward::folds(
+        &dates,
+        params.train_days,
+        params.test_days,
+        training.horizon.0,
+        params.anchored,
+    )?;
+    let costs = TransactionCosts::from(backtester_params);
+    let days_per_year = calendar.trading_days_per_year();
+
+    let mut performances = Vec::new();
+    let mut benchmarks = Vec::new();
+    let mut accuracies = Vec::new();
+    let mut model_name = String::new();
+    for fold in &folds {
+        let (name, performance, accuracy) =
+            walkforward_fold(&prices, fold, training, calendar, cash, backtester_params)?;
+        model_name = name;
+        accuracies.push(accuracy);
+        performances.push(performance);
+
+        let test_prices = prices.date_range(fold.test_start()..=fold.test_end());
+
+        let bench_trades =
+            BuyAndHold::all_in(cash, backtester_params.lot_size()).get_trades(&test_prices)?;
+        benchmarks.push(run_backtester(
+            bench_trades,
+            &test_prices,
+            cash,
+            costs,
+            days_per_year,
+        )?);
+    }
+
+    let performance = walkforward::stitch(&performances, cash)?;
+    let benchmark = walkforward::stitch(&benchmarks, cash)?;
+
+    let mut fold_results = Vec::new();
+    for (((fold, perf), bench), trades_accuracy) in folds
+        .iter()
+        .zip(performances)
+        .zip(benchmarks)
+        .zip(accuracies)
+    {
+        fold_results.push(WalkForwardFold {
+            train_start: fold.train_start(),
+            train_end: fold.train_end(),
+            test_start: fold.test_start(),
+            test_end: fold.test_end(),
+            total_return: perf.total_return()?,
+            benchmark_return: bench.total_return()?,
+            trades_accuracy,
+            total_costs: perf.total_costs,
+            relative_to_benchmark: perf.relative_to(&bench),
+            statistics: perf.statistics,
+        });
+    }
+
+    let fold_returns = fold_results.iter().map(|f| f.total_return);
+    let mean_fold_return = stats::mean(fold_returns.clone());
+    let profitable_folds =
+        fold_returns.filter(|r| *r > 0.0).count() as f64 / fold_results.len() as f64;
+
+    let output = TechalyzerWalkForwardOutput {
+        symbol: prices.symbol,
+        model_name,
+        folds: fold_results,
+        total_return: performance.total_return()?,
+        benchmark_return: benchmark.total_return()?,
+        relative_to_benchmark: performance.relative_to(&benchmark),
+        performance,
+        benchmark,
+        mean_fold_return,
+        profitable_folds,
+    };
+
+    print!("{}", serde_json::to_string(&output)?);
+    Ok(())
+}
+
+/// Cross-validates the learner `params` asks for with every combination of
+/// the hyperparameters in `tune_params`, ranks them by their mean accuracy
+/// over the folds, and saves the best one, trained on all of `train_dates`,
+/// to `out_path`. Per-fold accuracy, precision and recall per class, and
+/// confusion matrices are written to STDOUT as JSON.
+///
+/// ### Arguments
+/// * `prices` - Prices dataset.
+/// * `train_dates` - Days to cross-validate over, and to train the best model
+/// on. Should end `horizon` trading days before the end of `prices`.
+/// * `params` - How to train the model. Its hyperparameters are used for any
+/// that aren't searched.
+/// * `calendar` - Trading days to count the horizon in.
+/// * `tune_params` - How to split the days into folds, and the values to try.
+/// * `out_path` - Where to save the best model file.
+pub fn tune(
+    prices: Prices,
+    train_dates: Vec<Date>,
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+    tune_params: &TuneParams,
+    out_path: PathBuf,
+) -> Result<(), TechalyzerError> {
+    let folds = tuning::cv_folds(
+        &train_dates,
+        tune_params.folds,
+        tune_params.cross_validation,
+        params.horizon.0,
+        tune_params.embargo,
+    )?;
+    let candidates = HyperparameterGrid::from(tune_params)
+        .candidates(&Hyperparameters::from(params), params.algorithm);
+
+    let results = candidates
+        .par_iter()
+        .map(|hyperparameters| {
+            let params = params.with_hyperparameters(hyperparameters);
+            let folds = folds
+                .iter()
+                .map(|fold| cross_validate(&prices, fold, &params, calendar))
+                .collect::<Result<Vec<_>, TechalyzerError>>()?;
+            let mean_accuracy = stats::mean(folds.iter().filter_map(|f| f.accuracy));
+            Ok(TuneCandidate {
+                rank: 0,
+                hyperparameters: *hyperparameters,
+                mean_accuracy,
+                folds,
+            })
+        })
+        .collect::<Result<Vec<_>, TechalyzerError>>()?;
+    let results = rank_candidates(results);
+
+    let output = TechalyzerTuneOutput {
+        symbol: prices.symbol.clone(),
+        algorithm: params.algorithm,
+        cross_validation: tune_params.cross_validation,
+        embargo: tune_params.embargo,
+        model_file: out_path.clone(),
+        candidates: results,
+    };
+    let best = params.with_hyperparameters(&output.candidates[0].hyperparameters);
+    save_model(&prices, train_dates, &best, calendar, out_path)?;
+
+    print!("{}", serde_json::to_string(&output)?);
+    Ok(())
+}
+
+/// Sorts `candidates` by mean accuracy, best first, and numbers their ranks.
+/// The first of any tied candidates wins, and candidates with no accuracy at
+/// all (NaN) go last.
+fn rank_candidates(candidates: Vec<TuneCandidate>) -> Vec<TuneCandidate> {
+    let mut ranked: Vec<(f64, TuneCandidate)> = candidates
+        .into_iter()
+        .map(|c| (c.mean_accuracy, c))
+        .collect();
+    optimizer::rank(&mut ranked);
+    ranked
+        .into_iter()
+        .enumerate()
+        .map(|(i, (_, candidate))| TuneCandidate {
+            rank: i + 1,
+            ..candidate
+        })
+        .collect()
+}
+
+/// Trains a model on a fold's training days, and scores its predictions on
+/// its test days against their labels (and, for a regressor, its predicted
+/// returns against the actual ones).
+fn cross_validate(
+    prices: &Prices,
+    fold: &CvFold,
+    params: &TrainingParams,
+    calendar: &TradingCalendar,
+) -> Result<TuneFold, TechalyzerError> {
+    let mut model = train_model(
+        prices,
+        fold.train_dates.clone(),
+        params,
+        calendar,
+        PositionSizer::fixed(1.0),
+    )?;
+
+    // Predict from the start of the data so the features are warmed up by
+    // the first test day.
+    let (test_start, test_end) = (
+        fold.test_dates[0],
+        fold.test_dates[fold.test_dates.len() - 1],
+    );
+    let prices_to_test_end = prices.date_range(..=test_end);
+    let regression = if model.is_regressor() {
+        let predictions = model.predict(&prices_to_test_end)?;
+        let (predicted, actual): (Vec<f64>, Vec<f64>) = fold
+            .test_dates
+            .iter()
+            .filter_map(|day| {
+                let actual = forward_return(prices, *day, params.horizon.0, calendar)?;
+                Some((*predictions.get(day)? as f64, actual))
+            })
+            .unzip();
+        Some(RegressionMetrics::new(&predicted, &actual))
+    } else {
+        None
+    };
+    // Score the side held on each day, not just the days it was opened
+    let held = model.get_trades(&prices_to_test_end)?.held();
+
+    let mut confusion_matrix = ConfusionMatrix::default();
+    for day in &fold.test_dates {
+        let actual = params.labeler.label_day(
+            prices,
+            *day,
+            params.horizon.0,
+            calendar,
+            params.decision_threshold.0,
+        );
+        let predicted = held.get(day).map(|position| match position {
+            Position::Long(_) => Label::Long,
+            Position::Short(_) => Label::Short,
+            Position::Out | Position::Hold => Label::Out,
+        });
+        if let (Some(actual), Some(predicted)) = (actual, predicted) {
+            confusion_matrix.add(actual, predicted);
+        }
+    }
+
+    Ok(TuneFold {
+        train_days: fold.train_dates.len(),
+        test_start,
+        test_end,
+        accuracy: confusion_matrix.accuracy(),
+        classes: confusion_matrix.report(),
+        confusion_matrix,
+        regression,
+    })
+}
+
+/// Searches for the ManualTradingModel parameters that perform best in a
+/// backtest over `prices`. Combinations are backtested in parallel and ranked
+/// by `params.objective`, and the leaderboard is written to STDOUT as JSON,
+/// or as CSV if `csv` is set.
+///
+/// ### Arguments
+/// * `prices` - Dataset to backtest each combination over.
+/// * `params` - Values to search over and how to rank the results.
+/// * `cash` - How much cash each backtest begins with.
+/// * `backtester_params` - Transaction costs and other backtester settings.
+/// * `calendar` - The market traded in, for annualizing statistics.
+/// * `csv` - Print the leaderboard as CSV instead of JSON.
+pub fn optimize(
+    prices: Prices,
+    params: &OptimizeParams,
+    cash: f64,
+    backtester_params: &BacktesterParams,
+    calendar: &TradingCalendar,
+    csv: bool,
+) -> Result<(), TechalyzerError> {
+    let indicators = match params.indicators.as_slice() {
+        [] => vec![
+            IndicatorRange::from(SupportedIndicators::RelativeStrengthIndex),
+            IndicatorRange::from(SupportedIndicators::BollingerBands),
+            IndicatorRange::from(SupportedIndicators::MACD),
+        ],
+        given => given.to_vec(),
+    };
+    let space = SearchSpace::new(
+        &params.shares,
+        &params.dead_zone,
+        &params.disposition,
+        &indicators,
+    )?;
+
+    let candidates: Vec<usize> = match params.samples {
+        Some(n) => space.sample(n, params.seed),
+        None => (0..space.len()).collect(),
+    };
+
+    let costs = TransactionCosts::from(backtester_params);
+    let days_per_year = calendar.trading_days_per_year();
+    let mut results = candidates
+        .par_iter()
+        .map(|i| {
+            let candidate = space.get(*i);
+            let trades = candidate.model()?.get_trades(&prices)?;
+            let performance = run_backtester(trades, &prices, cash, costs, days_per_year)?;
+            Ok((
+                params.objective.score(&performance)?,
+                (candidate, performance),
+            ))
+        })
+        .collect::<Result<Vec<_>, TechalyzerError>>()?;
+    optimizer::rank(&mut results);
+
+    let leaderboard = results
+        .iter()
+        .take(params.top.unwrap_or(results.len()))
+        .enumerate()
+        .map(|(i, (score, (candidate, performance)))| {
+            let indicators: Vec<String> = candidate
+                .indicators
+                .iter()
+                .map(IndicatorSpec::to_string)
+                .collect();
+            Ok(LeaderboardEntry {
+                rank: i + 1,
+                score: *score,
+                shares: candidate.shares,
+                dead_zone: candidate.dead_zone,
+                disposition: candidate.disposition,
+                indicators: indicators.join(" "),
+                total_return: performance.total_return()?,
+                sharpe_ratio: performance.statistics.sharpe_ratio,
+                max_drawdown: optimizer::max_drawdown(performance),
+                total_costs: performance.total_costs,
+            })
+        })
+        .collect::<Result<Vec<_>, TechalyzerError>>()?;
+
+    if csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        for entry in &leaderboard {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+    } else {
+        let output = TechalyzerOptimizeOutput {
+            symbol: prices.symbol,
+            objective: params.objective,
+            combinations_tried: results.len(),
+            leaderboard,
+        };
+        print!("{}", serde_json::to_string(&output)?);
+    }
+
+    Ok(())
+}
+
+/// Suggests what position to hold in a security given all of its price data
+/// up to the most recent day. The suggestion, along with the signals that
+/// informed it, is written to STDOUT as text or JSON.
+///
+/// ### Arguments
+/// * `prices` - Price history to warm the model's signal generators on. The
+/// suggestion is for the last day in the dataset.
+/// * `trading_model` - One of the trading models supported by Techalyzer.
+/// * `json` - Print JSON instead of human-readable text.
+pub fn suggest(
+    prices: Prices,
+    mut trading_model: impl TradingModel,
+    json: bool,
+) -> Result<(), TechalyzerError> {
+    let model_name = trading_model.to_string();
+    let signals = trading_model.latest_signals(&prices);
+    let trades = match trading_model.get_trades(&prices) {
+        Ok(t) => t,
+        Err(e) => return Err(e.into()),
+    };
+
+    let (date, position) = trades
+        .latest_position()
+        .ok_or_else(|| TechalyzerError::Generic("No price data to suggest from".to_string()))?;
+
+    let output = TechalyzerSuggestOutput {
+        symbol: prices.symbol,
+        date,
+        model_name,
+        position,
+        signals,
+    };
+
+    if json {
+        print!("{}", serde_json::to_string(&output)?);
+    } else {
+        print!("{}", output);
+    }
 
     Ok(())
 }
+
+/// Checks the price data of each symbol for missing trading days, duplicate
+/// dates, non-positive prices, and one-day moves bigger than `max_jump`, and
+/// writes what it finds to STDOUT as text or JSON.
+///
+/// ### Arguments
+/// * `portfolio` - Price data for each symbol, as loaded (before any repair).
+/// * `calendar` - Trading days that should have data.
+/// * `max_jump` - Largest believable one-day price change (0.5 = 50%).
+/// * `repair` - If `RepairPolicy::Fail`, fails after printing when any
+/// problems were found.
+/// * `json` - Print a JSON array (one entry per symbol) instead of text.
+pub fn check_data(
+    portfolio: Vec<Prices>,
+    calendar: &TradingCalendar,
+    max_jump: f64,
+    repair: Option<RepairPolicy>,
+    json: bool,
+) -> Result<(), TechalyzerError> {
+    let reports: Vec<TechalyzerCheckDataOutput> = portfolio
+        .into_iter()
+        .map(|prices| TechalyzerCheckDataOutput {
+            issues: validation::validate(&prices, calendar, max_jump),
+            first_date: first_key(&prices.map).cloned(),
+            last_date: last_key(&prices.map).cloned(),
+            days: prices.map.len(),
+            symbol: prices.symbol,
+        })
+        .collect();
+
+    if json {
+        print!("{}", serde_json::to_string(&reports)?);
+    } else {
+        for report in &reports {
+            print!("{}", report);
+        }
+    }
+
+    match reports.into_iter().find(|r| !r.issues.is_empty()) {
+        Some(report) if repair == Some(RepairPolicy::Fail) => Err(ValidationError::Failed {
+            symbol: report.symbol,
+            issues: report.issues,
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{indicators::ListOfIndicators, trading::ml::Algorithm};
+
+    /// Two months of a steady climb, which a model goes long on from the
+    /// first day.
+    fn rising() -> Prices {
+        let start = Date::from_ymd(2012, 1, 2);
+        let closes = (0..60)
+            .map(|i| (start + chrono::Duration::days(i), 20.0 + i as f64))
+            .collect();
+        Prices::from_closes("jpm", closes)
+    }
+
+    fn bullish_training() -> TrainingParams {
+        TrainingParams {
+            signal_generators: ListOfIndicators(vec![SupportedIndicators::MACD.into()]),
+            horizon: 3.into(),
+            ..TrainingParams::default()
+        }
+    }
+
+    #[test]
+    fn regressors_size_by_predicted_return() {
+        let start = Date::from_ymd(2012, 1, 2);
+        let opened = |growth: f64| {
+            let closes = (0..80)
+                .map(|i| {
+                    (
+                        start + chrono::Duration::days(i),
+                        20.0 * growth.powi(i as i32),
+                    )
+                })
+                .collect();
+            let prices = Prices::from_closes("jpm", closes);
+            let dates: Vec<Date> = prices.map.keys().cloned().collect();
+            let params = TrainingParams {
+                algorithm: Algorithm::LinearRegression,
+                ..TrainingParams::default()
+            };
+            let sizer = PositionSizer::new(params.sizing(), 0.0);
+            let calendar = TradingCalendar::every_day();
+            let model =
+                train_model(&prices, dates[..60].to_vec(), &params, &calendar, sizer).unwrap();
+            match model.get_trades(&prices).unwrap().held().values().last() {
+                Some(Position::Long(shares)) => *shares,
+                other => panic!("expected a long position, got {:?}", other),
+            }
+        };
+
+        // Returns of 4% and 5% over the horizon, between the threshold and
+        // twice it
+        let smaller = opened(1.004);
+        let larger = opened(1.005);
+        assert!(0.0 < smaller && smaller < larger, "{} {}", smaller, larger);
+    }
+
+    #[test]
+    fn cross_validation_scores_held_positions() {
+        let prices = rising();
+        let dates: Vec<Date> = prices.map.keys().cloned().collect();
+        let fold = CvFold {
+            train_dates: dates[..40].to_vec(),
+            test_dates: dates[45..55].to_vec(),
+        };
+
+        let scored = cross_validate(
+            &prices,
+            &fold,
+            &bullish_training(),
+            &TradingCalendar::every_day(),
+        )
+        .unwrap();
+        assert_eq!(scored.accuracy, Some(1.0));
+    }
+
+    #[test]
+    fn walkforward_opens_positions_held_from_training() {
+        let prices = rising();
+        let calendar = TradingCalendar::every_day();
+        let dates: Vec<Date> = prices.map.keys().cloned().collect();
+        let fold = &walkforward::folds(&dates, 30, 10, 3, false).unwrap()[0];
+
+        let (_, performance, _) = walkforward_fold(
+            &prices,
+            fold,
+            &bullish_training(),
+            &calendar,
+            1e6,
+            &BacktesterParams::default(),
+        )
+        .unwrap();
+        assert!(performance
+            .daily_shares
+            .values()
+            .all(|shares| *shares == 1000.0));
+        assert!(performance.total_return().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn candidates_without_accuracy_rank_last() {
+        let candidate = |max_depth, mean_accuracy| TuneCandidate {
+            rank: 0,
+            hyperparameters: Hyperparameters {
+                max_depth: Some(max_depth),
+                ..Hyperparameters::default()
+            },
+            mean_accuracy,
+            folds: Vec::new(),
+        };
+        let ranked = rank_candidates(vec![
+            candidate(1, 0.5),
+            candidate(2, f64::NAN),
+            candidate(3, 0.7),
+            candidate(4, 0.5),
+        ]);
+
+        let order: Vec<_> = ranked
+            .iter()
+            .map(|c| (c.rank, c.hyperparameters.max_depth.unwrap()))
+            .collect();
+        assert_eq!(order, vec![(1, 3), (2, 1), (3, 4), (4, 2)]);
+    }
+}
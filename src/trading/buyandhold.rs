@@ -1,19 +1,29 @@
 //! Buys and holds shares for the entirety of the trading period.
 
-use super::tradingmodel::{Trades, TradingModel};
+use super::{
+    sizing::{PositionSizer, SizingPolicy},
+    tradingmodel::{Trades, TradingModel},
+};
 use crate::marketdata::prices::Prices;
 use crate::trading::Position::*;
 use std::{collections::BTreeMap, fmt::Display};
 use thiserror::Error;
 
 pub struct BuyAndHold {
-    /// How many share to buy and hold.
-    shares: u64,
+    /// Decides how many shares to buy and hold.
+    sizer: PositionSizer,
 }
 
-impl Default for BuyAndHold {
-    fn default() -> Self {
-        Self { shares: 1000 }
+impl BuyAndHold {
+    /// Buys on the first day `sizer` gives a nonzero number of shares, then
+    /// holds.
+    pub fn new(sizer: PositionSizer) -> Self {
+        Self { sizer }
+    }
+
+    /// Spends all of `cash` on the first day, on lots of `lot_size` shares.
+    pub fn all_in(cash: f64, lot_size: f64) -> Self {
+        Self::new(PositionSizer::new(SizingPolicy::CashFraction(1.0), cash).with_lot_size(lot_size))
     }
 }
 
@@ -33,16 +43,27 @@ pub enum BuyAndHoldError {
 impl TradingModel for BuyAndHold {
     type Error = BuyAndHoldError;
 
-    fn get_trades(self, prices: &Prices) -> Result<Trades, Self::Error> {
+    fn get_trades(mut self, prices: &Prices) -> Result<Trades, Self::Error> {
+        if prices.map.is_empty() {
+            return Err(BuyAndHoldError::NoFirstDay);
+        }
+
         let mut trades = BTreeMap::new();
-        let mut iter = prices.map.iter();
-        let (first_day, _) = iter.next().ok_or(BuyAndHoldError::NoFirstDay)?;
-        trades.insert(*first_day, Long(self.shares));
-        for (d, _) in iter {
-            trades.insert(*d, Hold);
+        let mut bought = false;
+        for (d, price) in prices.iter() {
+            self.sizer.next(*price);
+            let trade = match self.sizer.shares(*price, 1.0) {
+                _ if bought => Hold,
+                shares if shares > 0.0 => {
+                    bought = true;
+                    Long(shares)
+                }
+                _ => Out,
+            };
+            trades.insert(*d, trade);
         }
 
-        Ok(Trades { trades })
+        Ok(Trades::from(trades))
     }
 }
 
@@ -56,19 +77,20 @@ mod tests {
         let day1 = Date::from_ymd(2020, 1, 1);
         let day2 = Date::from_ymd(2020, 1, 2);
         let day3 = Date::from_ymd(2020, 1, 3);
-        let model = BuyAndHold { shares: 1000 };
+        let model = BuyAndHold::new(PositionSizer::fixed(1000.0));
         let map: TimeSeries<f64> = vec![(day1, 30.0), (day2, 32.0), (day3, 34.0)]
             .iter()
             .cloned()
             .collect();
-        let prices = Prices {
-            symbol: "JPM".to_string(),
-            map: map,
-        };
+        let prices = Prices::from_closes("JPM", map);
 
         let trades = model.get_trades(&prices).unwrap();
-        assert_eq!(trades.get(&day1).unwrap(), &Long(1000));
+        assert_eq!(trades.get(&day1).unwrap(), &Long(1000.0));
         assert_eq!(trades.get(&day2).unwrap(), &Hold);
         assert_eq!(trades.get(&day3).unwrap(), &Hold);
+
+        // Only spends the cash it has
+        let trades = BuyAndHold::all_in(100.0, 1.0).get_trades(&prices).unwrap();
+        assert_eq!(trades.get(&day1).unwrap(), &Long(3.0));
     }
 }
@@ -1,29 +1,38 @@
-//! A one-vs-rest decision tree-based trader. Technical indicator signal
-//! generators provide features, future returns provide labels. Various
-//! parameters influence trading behavior.
+//! A machine learning-based trader. Technical indicator signal generators
+//! provide features, future returns provide labels (or, for regressors, the
+//! targets themselves), and any MachineLearningAlgorithm learns from them.
+//! Various parameters influence trading behavior.
 
 use super::{
-    ml::{decisiontree::DecisionTreeClassifier, mlmodel::MachineLearningAlgorithm},
+    features::FeaturePipeline,
+    labeling::{forward_return, Label, Labeler},
+    ml::{mlmodel::MachineLearningAlgorithm, Algorithm, Hyperparameters, Learner},
     tradingmodel::{Trades, TradingModel},
 };
 use crate::Date;
 use crate::{
+    calendar::TradingCalendar,
     marketdata::prices::Prices,
     signals::{
-        bollingerbandssignals::BBSignalsIter, macdsignals::MACDSignalsIter,
-        relativestrengthindexsignals::RSISignalsIter, SignalsIter,
+        bollingerbandssignals::BBSignalsIter, latest_signals, macdsignals::MACDSignalsIter,
+        relativestrengthindexsignals::RSISignalsIter, IndicatorSignal, SignalsIter,
     },
-    trading::Position,
+    trading::sizing::PositionSizer,
+    util::TimeSeries,
 };
 use derive_more::Display;
 use derive_more::{From, FromStr};
-use rustlearn::trees::decision_tree::Hyperparameters;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt::Display, marker::PhantomData, ops::Deref};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    marker::PhantomData,
+    ops::Deref,
+};
 use thiserror::Error;
 
-/// Newtype wrapper for the 'horizon' parameter of the model (how many days in
-/// the future it will look for returns when labelling features).
+/// Newtype wrapper for the 'horizon' parameter of the model (how many trading
+/// days in the future it will look for returns when labelling features).
 #[derive(Debug, Display, Serialize, Deserialize, FromStr, From, Copy, Clone, PartialEq)]
 pub struct Horizon(pub u32);
 impl Default for Horizon {
@@ -52,29 +61,32 @@ impl Default for DecisionThreshold {
 /// Session type that denotes a trained model.
 pub struct Trained;
 
-/// Predicts trading opportunities using a one-vs-rest decision tree classifier
-/// with technical indicators.
+/// Predicts trading opportunities using a multi-class classifier (or a
+/// regressor of forward returns) with technical indicators.
 #[derive(Serialize, Deserialize)]
-pub struct DecisionTreeTrader<TrainedState = ()> {
-    /// Our multi-class decision tree classifier
-    // learner:
-    learner: DecisionTreeClassifier,
-    // learner: OneVsRestWrapper<DecisionTree>,
-    /// Signals that will inform the model.
-    signal_generators: Vec<Box<dyn SignalsIter>>,
+pub struct MachineLearningTrader<L = Learner, TrainedState = ()> {
+    /// Our multi-class classifier or return regressor
+    learner: L,
+    /// Turns prices and the signals of the technical indicators into the
+    /// features that inform the model.
+    features: FeaturePipeline,
+
+    /// Decides how many shares the bot will commit to a given trade
+    sizer: PositionSizer,
 
-    /// The maximum shares that the bot will commit to a given trade
-    max_shares: u32,
+    /// How large a predicted return must be to trade on. Only regressors use
+    /// it, classifiers learn it from their labels.
+    decision_threshold: f32,
 
     /// Silences the compiler as we implement session types for
     /// trained/untrained models.
     phantom: PhantomData<TrainedState>,
 }
 
-/// Things that can go wrong while training or using a DecisionTreeTrader.
+/// Things that can go wrong while training or using a MachineLearningTrader.
 #[derive(Debug, Error)]
 pub enum DecisionTreeError {
-    #[error("No price information found looking ahead {0} days after {1}")]
+    #[error("No price information found looking ahead {0} trading days after {1}")]
     NoLookAheadPriceData(Horizon, Date),
 
     // TODO: this is kind of just copy and paste from mlmodel errors, can we do better?
@@ -106,183 +118,282 @@ const LONG: f32 = 1.0;
 const OUT: f32 = 0.0;
 const SHORT: f32 = -1.0;
 
-// Private constructor to control construction of untrained/trained DecisionTreeTrader.
-fn state_constructor<State>(
-    learner: DecisionTreeClassifier,
-    signal_generators: Vec<Box<dyn SignalsIter>>,
-    max_shares: u32,
-) -> DecisionTreeTrader<State> {
-    DecisionTreeTrader {
+// Private constructor to control construction of untrained/trained MachineLearningTrader.
+fn state_constructor<L, State>(
+    learner: L,
+    features: FeaturePipeline,
+    sizer: PositionSizer,
+    decision_threshold: f32,
+) -> MachineLearningTrader<L, State> {
+    MachineLearningTrader {
         learner,
-        signal_generators,
+        features,
         phantom: PhantomData,
-        max_shares,
+        sizer,
+        decision_threshold,
+    }
+}
+
+/// The side a regressor takes on a predicted return, and how convinced it is
+/// (0 to 1): out if the return is smaller than `threshold`, growing to full
+/// conviction at twice the threshold.
+fn return_position(predicted: f32, threshold: f32) -> (Label, f64) {
+    let size = predicted.abs();
+    if size < threshold || size == 0.0 {
+        return (Label::Out, 0.0);
     }
+
+    let side = if predicted > 0.0 {
+        Label::Long
+    } else {
+        Label::Short
+    };
+    let strength = if threshold > 0.0 {
+        (size / (2.0 * threshold)).min(1.0)
+    } else {
+        1.0
+    };
+    (side, strength as f64)
 }
 
-impl Default for DecisionTreeTrader {
+impl Default for MachineLearningTrader {
     fn default() -> Self {
         let signal_generators: Vec<Box<dyn SignalsIter>> = vec![
             Box::new(MACDSignalsIter::default()),
             Box::new(RSISignalsIter::default()),
             Box::new(BBSignalsIter::default()),
         ];
-        let learner = DecisionTreeClassifier::new(
-            Hyperparameters::new(signal_generators.len()).one_vs_rest(),
+        let features = FeaturePipeline::signals_only(signal_generators);
+        let learner = Learner::new(
+            Algorithm::default(),
+            &Hyperparameters::default(),
+            features.names().len(),
         );
 
-        state_constructor(learner, signal_generators, 1000)
+        let threshold = DecisionThreshold::default().0 as f32;
+        state_constructor(learner, features, PositionSizer::default(), threshold)
     }
 }
 
-impl DecisionTreeTrader {
-    /// At least one signal generator must be given.
+impl<L: MachineLearningAlgorithm<Vec<f32>>> MachineLearningTrader<L> {
+    /// `learner` should expect the features of `features`. At least one
+    /// signal generator must be given.
     pub fn new(
-        signal_generators: Vec<Box<dyn SignalsIter>>,
-        max_shares: u32,
+        learner: L,
+        features: FeaturePipeline,
+        sizer: PositionSizer,
     ) -> Result<Self, DecisionTreeError> {
-        if signal_generators.is_empty() {
+        if features.signal_generators().is_empty() {
             return Err(DecisionTreeError::NoSignalGeneratorsProvided);
         }
 
-        // TODO: be able to adjust the parameters (and figure out how to reduce
-        // overfitting)
-        let learner = DecisionTreeClassifier::new(
-            Hyperparameters::new(signal_generators.len()).one_vs_rest(),
-        );
-
-        Ok(state_constructor(learner, signal_generators, max_shares))
+        Ok(state_constructor(learner, features, sizer, 0.0))
     }
 
     /// Trains the model using technical indicator signal generators for the
     /// given Prices time series. Consumes the caller and returns a trained
-    /// DecisionTreeTrader.
+    /// MachineLearningTrader.
     ///
     /// ## Arguments
     ///
     /// * `train_prices` - Prices time series to train the model on.
-    /// * `train_dates` - Range of dates to train on. Should be `horizon` less
-    /// than the dates in `train_prices`.
-    /// * `horizon` - Uses the returns this many days in the future for Y.
-    /// * `threshold` - How good (or bad) the returns have to be for the model to go Long or Short.
+    /// * `train_dates` - Range of dates to train on. Should end `horizon`
+    /// trading days before the end of `train_prices`.
+    /// * `labeler` - How to label each day from the prices that follow it.
+    /// * `horizon` - Labels each day from the prices up to this many trading
+    /// days in the future.
+    /// * `calendar` - Trading days to count the horizon in.
+    /// * `threshold` - How good (or bad) the returns have to be for the model
+    /// to go Long or Short. Regressors learn the return at the horizon
+    /// instead of a label, and only trade predictions at least this large.
     pub fn train(
         mut self,
         train_prices: &Prices,
         train_dates: Vec<Date>,
+        labeler: &Labeler,
         horizon: Horizon,
+        calendar: &TradingCalendar,
         threshold: f32,
-    ) -> Result<DecisionTreeTrader<Trained>, DecisionTreeError> {
+    ) -> Result<MachineLearningTrader<L, Trained>, DecisionTreeError> {
         let mut x = Vec::new();
         let mut y = Vec::new();
 
-        for day in train_dates {
-            let price = train_prices
-                .get(&day)
-                .ok_or(DecisionTreeError::NoPriceFound(day))?;
-
-            let signals: Vec<f32> = next_signals(&mut self.signal_generators, price);
-
-            // look ahead for n-day future return
-            let future_price = train_prices
-                .get_after(&day, horizon.0)
-                .ok_or(DecisionTreeError::NoLookAheadPriceData(horizon, day))?
-                .1;
-            let future_return = ((future_price / price) - 1.0) as f32;
-
-            // Returns above threshold is Long, below is Short, otherwise Out.
-            let label = match future_return {
-                r if r >= threshold => LONG,
-                r if r <= -threshold => SHORT,
-                _ => OUT,
+        let train_dates: BTreeSet<Date> = train_dates.into_iter().collect();
+        if let Some(day) = train_dates.iter().find(|d| train_prices.get(d).is_none()) {
+            return Err(DecisionTreeError::NoPriceFound(*day));
+        }
+
+        // Every day from the first training day to the last goes through the
+        // features, so that they carry on across any gaps in the training
+        // dates (e.g. a cross-validation fold held out of the middle).
+        let (first, last) = match (train_dates.iter().next(), train_dates.iter().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => {
+                return Err(DecisionTreeError::TrainingError(
+                    "No training dates".to_string(),
+                ))
+            }
+        };
+        for (&day, bar) in train_prices.map.range(first..=last) {
+            let features = self.features.next(&day, bar.price());
+            if !train_dates.contains(&day) {
+                continue;
+            }
+
+            // look ahead at the prices through the horizon
+            let no_price = DecisionTreeError::NoLookAheadPriceData(horizon, day);
+            let target = if self.learner.is_regressor() {
+                forward_return(train_prices, day, horizon.0, calendar).ok_or(no_price)? as f32
+            } else {
+                let label = labeler
+                    .label_day(train_prices, day, horizon.0, calendar, threshold as f64)
+                    .ok_or(no_price)?;
+                label_value(label)
             };
 
-            // X is the signals, Y our long/short/out decision based on future return
-            x.push(signals);
-            y.push(label);
+            // X is the features, Y our long/short/out decision (or the
+            // return) based on the future
+            x.push(features);
+            y.push(target);
         }
 
         // Construct X train, Y train data out of the prices
         self.learner.fit(&x, &y)?;
 
-        Ok(state_constructor::<Trained>(
+        Ok(state_constructor::<L, Trained>(
             self.learner,
-            self.signal_generators,
-            1000, // TODO: don't hardcore shares
+            self.features,
+            self.sizer,
+            threshold,
         ))
     }
 }
 
-/// Gets the next set of signals from the signal generators
-fn next_signals(signal_generators: &mut Vec<Box<dyn SignalsIter>>, price: &f64) -> Vec<f32> {
-    signal_generators
-        .iter_mut()
-        .map(|g| f32::from(g.next(*price).0))
-        .collect()
+fn label_value(label: Label) -> f32 {
+    match label {
+        Label::Long => LONG,
+        Label::Out => OUT,
+        Label::Short => SHORT,
+    }
+}
+
+impl<L: MachineLearningAlgorithm<Vec<f32>>> MachineLearningTrader<L, Trained> {
+    /// What the learner predicts on each day of `prices`: 1 (long), 0 (out)
+    /// or -1 (short) for a classifier, the return at the horizon for a
+    /// regressor.
+    pub fn predict(&mut self, prices: &Prices) -> Result<TimeSeries<f32>, DecisionTreeError> {
+        // Reset our technical indicators
+        self.features.reset();
+
+        // TODO: Should we pre-emptively error out if all the signals are a
+        // contant value (0/1/-1)? That will cause an error while predicting
+        let rows: Vec<Vec<f32>> = prices
+            .iter()
+            .map(|(day, price)| self.features.next(day, *price))
+            .collect();
+        let predictions = if rows.is_empty() {
+            Vec::new()
+        } else {
+            self.learner.predict(&rows)?
+        };
+
+        Ok(prices
+            .iter()
+            .zip(predictions)
+            .map(|((day, _), prediction)| (*day, prediction[0]))
+            .collect())
+    }
+
+    pub fn is_regressor(&self) -> bool {
+        self.learner.is_regressor()
+    }
+}
+
+impl<L, T> MachineLearningTrader<L, T> {
+    /// Sizes trades with `sizer` instead of the one the model was built with.
+    pub fn with_sizer(mut self, sizer: PositionSizer) -> Self {
+        self.sizer = sizer;
+        self
+    }
+
+    pub fn sizer(&self) -> &PositionSizer {
+        &self.sizer
+    }
+
+    /// Names of the features the model learns from, in order.
+    pub fn feature_names(&self) -> Vec<String> {
+        self.features.names()
+    }
 }
 
-impl<T> Display for DecisionTreeTrader<T> {
+impl<L: Display, T> Display for MachineLearningTrader<L, T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "DecisionTreeTrader")
+        write!(f, "MachineLearningTrader({})", self.learner)
     }
 }
 
-impl TradingModel for DecisionTreeTrader<Trained> {
+impl<L> TradingModel for MachineLearningTrader<L, Trained>
+where
+    L: MachineLearningAlgorithm<Vec<f32>> + Display,
+{
     type Error = DecisionTreeError;
 
     fn get_trades(mut self, prices: &Prices) -> Result<Trades, Self::Error> {
-        // Reset our technical indicators
-        self.signal_generators.iter_mut().for_each(|g| g.reset());
+        // TODO: start submitting PRs to improve rustlearn, it has no
+        // error enums for one thing
+        let predictions = self.predict(prices)?;
+        self.sizer.reset();
 
         let mut trades = BTreeMap::new();
-        // Given each day and it's technical indicators, predict the return and
-        // act accordingly
-        for (day, price) in prices.iter() {
-            // TODO: Should we pre-emptively error out if all the signals are a
-            // contant value (0/1/-1)? That will cause an error while predicting
-
-            let signals: Vec<f32> = next_signals(&mut self.signal_generators, price);
-
-            // TODO: start submitting PRs to improve rustlearn, it has no
-            // error enums for one thing
-            // FIXME: fit/predict after the looping
-            let prediction = self.learner.predict(&vec![signals])?;
-            //     Ok(r) => r,
-            //     Err(msg) => return Err(DecisionTreeError::TrainingError(msg.to_string())),
-            // };
-
-            // TODO: don't hardcode traded shares
-            let position = match prediction[0][0] {
-                // let position = match prediction.get(0, 0) {
-                val if val == LONG => Position::Long(1000),
-                val if val == OUT => Position::Out,
-                val if val == SHORT => Position::Short(1000),
-                val => {
-                    return Err(DecisionTreeError::PredictionError(format!(
-                        "Invalid prediction '{}'",
-                        val
-                    )))
+        // Given each day's prediction, act accordingly
+        for ((day, price), prediction) in prices.iter().zip(predictions.values()) {
+            self.sizer.next(*price);
+
+            // The classifier only picks a side, so it is always fully
+            // convinced. A regressor is more convinced the larger the return
+            // it predicts.
+            let (side, strength) = if self.learner.is_regressor() {
+                return_position(*prediction, self.decision_threshold)
+            } else {
+                match *prediction {
+                    val if val == LONG => (Label::Long, 1.0),
+                    val if val == OUT => (Label::Out, 1.0),
+                    val if val == SHORT => (Label::Short, 1.0),
+                    val => {
+                        return Err(DecisionTreeError::PredictionError(format!(
+                            "Invalid prediction '{}'",
+                            val
+                        )))
+                    }
                 }
             };
-
-            trades.insert(*day, position);
+            trades.insert(*day, self.sizer.trade(side, *price, strength));
         }
 
-        Ok(Trades { trades })
+        Ok(Trades::from(trades))
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        latest_signals(self.features.signal_generators_mut(), prices)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DecisionTreeTrader, Horizon, Trained};
+    use super::{return_position, Horizon, MachineLearningTrader, Trained};
     use crate::{
+        calendar::TradingCalendar,
         date::Date,
         marketdata::prices::Prices,
         signals::{
             bollingerbandssignals::BBSignalsIter, macdsignals::MACDSignalsIter,
             relativestrengthindexsignals::RSISignalsIter, SignalsIter,
         },
+        trading::features::{FeaturePipeline, FeatureSet},
+        trading::labeling::{Label, Labeler},
+        trading::ml::{Algorithm, Hyperparameters, Learner},
         trading::tradingmodel::{Trades, TradingModel},
-        trading::Position,
+        trading::{sizing::PositionSizer, Position},
     };
     use chrono::Duration;
     use std::collections::BTreeMap;
@@ -299,10 +410,17 @@ mod tests {
             dt = dt + Duration::days(1);
         }
 
-        Prices {
-            map: entries,
-            symbol: "jpm".to_string(),
-        }
+        Prices::from_closes("jpm", entries)
+    }
+
+    fn decision_tree_trader(indics: Vec<Box<dyn SignalsIter>>) -> MachineLearningTrader {
+        let learner = Learner::new(
+            Algorithm::DecisionTree,
+            &Hyperparameters::default(),
+            indics.len(),
+        );
+        let features = FeaturePipeline::signals_only(indics);
+        MachineLearningTrader::new(learner, features, PositionSizer::fixed(1000.0)).unwrap()
     }
 
     #[test]
@@ -311,16 +429,25 @@ mod tests {
         let indics: Vec<Box<dyn SignalsIter>> = vec![Box::new(RSISignalsIter::default())];
 
         // Construct the model
-        let dt_trader = DecisionTreeTrader::new(indics, 1000).unwrap();
+        let dt_trader = decision_tree_trader(indics);
 
         // Train it
         let prices = fixture_setup();
         let range = Date::range(Date::from_ymd(2012, 01, 2), Date::from_ymd(2012, 01, 30));
-        let trained_trader = dt_trader.train(&prices, range, Horizon(3), 0.03).unwrap();
+        let trained_trader = dt_trader
+            .train(
+                &prices,
+                range,
+                &Labeler::default(),
+                Horizon(3),
+                &TradingCalendar::every_day(),
+                0.03,
+            )
+            .unwrap();
 
         // Can we turn it into bincode and back?
         let bytes = bincode::serialize(&trained_trader).unwrap();
-        let loaded: DecisionTreeTrader<Trained> = bincode::deserialize(&bytes).unwrap();
+        let loaded: MachineLearningTrader<Learner, Trained> = bincode::deserialize(&bytes).unwrap();
 
         // Predict what trades to make for profit
         let trades = trained_trader.get_trades(&prices).unwrap();
@@ -330,12 +457,66 @@ mod tests {
         assert_eq!(trades, again_trades);
     }
 
+    #[test]
+    fn richer_features() {
+        let indics: Vec<Box<dyn SignalsIter>> = vec![
+            Box::new(MACDSignalsIter::default()),
+            Box::new(RSISignalsIter::default()),
+        ];
+        let features = FeaturePipeline::new(
+            indics,
+            FeatureSet {
+                outputs: true,
+                lags: 2,
+                returns: vec![3],
+                volatility: vec![5],
+                day_of_week: true,
+            },
+        )
+        .unwrap();
+        let names = features.names().len();
+        assert_eq!(names, 2 + 4 + 4 + 1 + 1 + 1);
+        let learner = Learner::new(
+            Algorithm::GradientBoosting,
+            &Hyperparameters::default(),
+            names,
+        );
+        let trader =
+            MachineLearningTrader::new(learner, features, PositionSizer::fixed(1000.0)).unwrap();
+
+        let mut prices = fixture_setup();
+        for (i, (_, bar)) in prices.iter_mut().enumerate() {
+            *bar = (30.0 + (i as f64).sin() * 3.0).into();
+        }
+        let range = Date::range(Date::from_ymd(2012, 01, 2), Date::from_ymd(2012, 01, 30));
+        let trained = trader
+            .train(
+                &prices,
+                range,
+                &Labeler::default(),
+                Horizon(3),
+                &TradingCalendar::every_day(),
+                0.03,
+            )
+            .unwrap();
+        assert_eq!(trained.feature_names().len(), names);
+
+        // The features are computed the same way after a round trip
+        let bytes = bincode::serialize(&trained).unwrap();
+        let loaded: MachineLearningTrader<Learner, Trained> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.feature_names(), trained.feature_names());
+        assert_eq!(
+            loaded.get_trades(&prices).unwrap(),
+            trained.get_trades(&prices).unwrap()
+        );
+    }
+
     #[test]
     fn bull_market() {
         let indics: Vec<Box<dyn SignalsIter>> = vec![Box::new(MACDSignalsIter::default())];
         let new_prices: Vec<f64> = (15..55).map(|f| f.into()).collect();
         let trades = run_trader_test(indics, new_prices, Horizon(3), 0.03);
-        assert!(trades.trades.iter().all(|p| *p.1 == Position::Long(1000)));
+        assert!(trades.held().values().all(|p| *p == Position::Long(1000.0)));
     }
 
     // edits the prices used to train the model before running a test over the
@@ -347,16 +528,25 @@ mod tests {
         threshold: f32,
     ) -> Trades {
         // Construct the model
-        let dt_trader = DecisionTreeTrader::new(indics, 1000).unwrap();
+        let dt_trader = decision_tree_trader(indics);
 
         // Train it
         let mut prices = fixture_setup();
-        for (i, (_, price)) in prices.iter_mut().enumerate() {
-            *price = new_prices[i];
+        for (i, (_, bar)) in prices.iter_mut().enumerate() {
+            *bar = new_prices[i].into();
         }
 
         let range = Date::range(Date::from_ymd(2012, 01, 2), Date::from_ymd(2012, 01, 30));
-        let trained = dt_trader.train(&prices, range, horizon, threshold).unwrap();
+        let trained = dt_trader
+            .train(
+                &prices,
+                range,
+                &Labeler::default(),
+                horizon,
+                &TradingCalendar::every_day(),
+                threshold,
+            )
+            .unwrap();
 
         trained.get_trades(&prices).unwrap()
     }
@@ -366,7 +556,77 @@ mod tests {
         let new_prices: Vec<f64> = (15..55).map(|f| f.into()).rev().collect();
         let indics: Vec<Box<dyn SignalsIter>> = vec![Box::new(MACDSignalsIter::default())];
         let trades = run_trader_test(indics, new_prices, Horizon(3), 0.03);
-        assert!(trades.trades.iter().all(|p| *p.1 == Position::Short(1000)));
+        assert!(trades
+            .held()
+            .values()
+            .all(|p| *p == Position::Short(1000.0)));
+    }
+
+    #[test]
+    fn return_regression() {
+        assert_eq!(return_position(0.01, 0.02), (Label::Out, 0.0));
+        assert_eq!(return_position(0.0, 0.0), (Label::Out, 0.0));
+        assert_eq!(return_position(0.03, 0.02), (Label::Long, 0.75));
+        assert_eq!(return_position(-0.08, 0.02), (Label::Short, 1.0));
+        assert_eq!(return_position(-0.001, 0.0), (Label::Short, 1.0));
+
+        // A regressor sees the returns of a steady climb and goes long
+        let mut prices = fixture_setup();
+        for (i, (_, bar)) in prices.iter_mut().enumerate() {
+            *bar = (15.0 + i as f64).into();
+        }
+        let learner = Learner::new(Algorithm::LinearRegression, &Hyperparameters::default(), 1);
+        let features = FeaturePipeline::signals_only(vec![Box::new(MACDSignalsIter::default())]);
+        let trader =
+            MachineLearningTrader::new(learner, features, PositionSizer::fixed(1000.0)).unwrap();
+        let range = Date::range(Date::from_ymd(2012, 01, 2), Date::from_ymd(2012, 01, 30));
+        let mut trained = trader
+            .train(
+                &prices,
+                range,
+                &Labeler::default(),
+                Horizon(3),
+                &TradingCalendar::every_day(),
+                0.03,
+            )
+            .unwrap();
+        assert!(trained.is_regressor());
+        assert!(trained
+            .predict(&prices)
+            .unwrap()
+            .values()
+            .all(|r| *r > 0.03));
+        let trades = trained.get_trades(&prices).unwrap();
+        assert!(trades.held().values().all(|p| *p == Position::Long(1000.0)));
+    }
+
+    #[test]
+    fn labelers() {
+        let mut prices = fixture_setup();
+        for (i, (_, bar)) in prices.iter_mut().enumerate() {
+            *bar = (15.0 + i as f64).into();
+        }
+        let range = Date::range(Date::from_ymd(2012, 01, 2), Date::from_ymd(2012, 01, 30));
+
+        for labeler in &["fixed", "triple-barrier:2,1", "vol:1,5", "mfe"] {
+            let indics: Vec<Box<dyn SignalsIter>> = vec![Box::new(MACDSignalsIter::default())];
+            let trained = decision_tree_trader(indics)
+                .train(
+                    &prices,
+                    range.clone(),
+                    &labeler.parse().unwrap(),
+                    Horizon(3),
+                    &TradingCalendar::every_day(),
+                    0.03,
+                )
+                .unwrap();
+            let trades = trained.get_trades(&prices).unwrap();
+            assert!(
+                trades.held().values().all(|p| *p == Position::Long(1000.0)),
+                "{}",
+                labeler
+            );
+        }
     }
 
     #[test]
@@ -374,7 +634,7 @@ mod tests {
         let new_prices: Vec<f64> = (15..55).map(|f| f.into()).rev().collect();
         let indics: Vec<Box<dyn SignalsIter>> = vec![Box::new(MACDSignalsIter::default())];
         let trades = run_trader_test(indics, new_prices, Horizon(3), 1.0);
-        assert!(trades.trades.iter().all(|p| *p.1 == Position::Out));
+        assert!(trades.held().values().all(|p| *p == Position::Out));
     }
 
     #[test]
@@ -0,0 +1,432 @@
+//! Combines several trading models into one that takes whichever side its
+//! members vote for each day.
+
+use super::{
+    buyandhold::BuyAndHold,
+    dtmodel::{MachineLearningTrader, Trained},
+    labeling::Label,
+    manual::ManualTradingModel,
+    ml::Learner,
+    sizing::PositionSizer,
+    tradingmodel::{Trades, TradingModel},
+    Position,
+};
+use crate::{
+    error::TechalyzerError, marketdata::prices::Prices, signals::IndicatorSignal, util::solve,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use thiserror::Error;
+
+/// Default number of days ahead whose return stacking fits votes to.
+pub const DEFAULT_STACKING_HORIZON: usize = 5;
+
+/// Default number of days stacking needs to fit votes to before it stops
+/// counting them equally.
+pub const DEFAULT_STACKING_MIN_TRAIN: usize = 60;
+
+/// Keeps the stacking fit solvable when members always vote alike.
+const RIDGE: f64 = 1e-6;
+
+/// How the members' votes are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsembleMethod {
+    /// Every member gets one vote. Long, short or out, whichever gets the most
+    /// votes wins, and ties go out.
+    #[default]
+    Majority,
+
+    /// Like Majority, but each member's vote counts as much as its weight.
+    Weighted,
+
+    /// Weights the votes by a linear model fitted to the return `horizon`
+    /// days after each vote, using only returns known by the day being voted
+    /// on. Members that have been wrong get negative weights.
+    Stacking,
+}
+
+impl Display for EnsembleMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Majority => "majority",
+            Self::Weighted => "weighted",
+            Self::Stacking => "stacking",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Errors specific to ensembles (the members' own errors are passed on).
+#[derive(Debug, Error)]
+pub enum EnsembleError {
+    #[error("An ensemble needs at least one member")]
+    NoMembers,
+}
+
+/// One of the models in an ensemble.
+pub enum Member {
+    Manual(ManualTradingModel),
+    MachineLearning(Box<MachineLearningTrader<Learner, Trained>>),
+    BuyAndHold(BuyAndHold),
+}
+
+impl Member {
+    fn get_trades(self, prices: &Prices) -> Result<Trades, TechalyzerError> {
+        match self {
+            Self::Manual(m) => m.get_trades(prices).map_err(Into::into),
+            Self::MachineLearning(m) => (*m).get_trades(prices).map_err(Into::into),
+            Self::BuyAndHold(m) => m.get_trades(prices).map_err(Into::into),
+        }
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        match self {
+            Self::Manual(m) => m.latest_signals(prices),
+            Self::MachineLearning(m) => m.latest_signals(prices),
+            Self::BuyAndHold(m) => m.latest_signals(prices),
+        }
+    }
+}
+
+impl Display for Member {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Manual(m) => write!(f, "{}", m),
+            Self::MachineLearning(m) => write!(f, "{}", m),
+            Self::BuyAndHold(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+impl From<ManualTradingModel> for Member {
+    fn from(m: ManualTradingModel) -> Self {
+        Self::Manual(m)
+    }
+}
+
+impl From<MachineLearningTrader<Learner, Trained>> for Member {
+    fn from(m: MachineLearningTrader<Learner, Trained>) -> Self {
+        Self::MachineLearning(Box::new(m))
+    }
+}
+
+impl From<BuyAndHold> for Member {
+    fn from(m: BuyAndHold) -> Self {
+        Self::BuyAndHold(m)
+    }
+}
+
+/// A trading model made of other trading models. Only the side each member
+/// takes counts, the ensemble sizes its own trades by how strongly the votes
+/// agree.
+pub struct EnsembleModel {
+    /// Each member and the weight of its vote.
+    members: Vec<(Member, f64)>,
+
+    method: EnsembleMethod,
+
+    /// Decides how many shares will be committed to a trade. The share of
+    /// the vote the winning side got is the ensemble's conviction.
+    sizer: PositionSizer,
+
+    /// Days ahead whose return stacking fits votes to.
+    horizon: usize,
+
+    /// Days stacking needs to fit votes to before it stops counting them
+    /// equally.
+    min_train: usize,
+}
+
+impl EnsembleModel {
+    pub fn new(method: EnsembleMethod) -> Self {
+        Self {
+            members: Vec::new(),
+            method,
+            sizer: PositionSizer::default(),
+            horizon: DEFAULT_STACKING_HORIZON,
+            min_train: DEFAULT_STACKING_MIN_TRAIN,
+        }
+    }
+
+    /// Adds `model` to the ensemble. `weight` only matters to weighted votes.
+    pub fn with_member(mut self, model: impl Into<Member>, weight: f64) -> Self {
+        self.members.push((model.into(), weight));
+        self
+    }
+
+    /// Sizes trades with `sizer` instead of a fixed 1000 shares.
+    pub fn with_sizer(mut self, sizer: PositionSizer) -> Self {
+        self.sizer = sizer;
+        self
+    }
+
+    pub fn sizer(&self) -> &PositionSizer {
+        &self.sizer
+    }
+
+    /// Fits stacking weights to the return `horizon` days ahead, once there
+    /// are at least `min_train` days to fit them to.
+    pub fn with_stacking(mut self, horizon: usize, min_train: usize) -> Self {
+        self.horizon = horizon.max(1);
+        self.min_train = min_train.max(1);
+        self
+    }
+}
+
+impl Display for EnsembleModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let members: Vec<String> = self.members.iter().map(|(m, _)| m.to_string()).collect();
+        write!(f, "EnsembleModel({}: {})", self.method, members.join(", "))
+    }
+}
+
+impl TradingModel for EnsembleModel {
+    type Error = TechalyzerError;
+
+    fn get_trades(mut self, prices: &Prices) -> Result<Trades, Self::Error> {
+        if self.members.is_empty() {
+            return Err(EnsembleError::NoMembers.into());
+        }
+
+        // Each member's side (1 long, -1 short, 0 out) on every day, and the
+        // weight of its vote before any stacking
+        let mut votes: Vec<Vec<f64>> = Vec::new();
+        let mut weights = Vec::new();
+        for (member, weight) in self.members {
+            votes.push(sides(&member.get_trades(prices)?, prices));
+            weights.push(match self.method {
+                EnsembleMethod::Weighted => weight,
+                _ => 1.0,
+            });
+        }
+        let on_day = |i: usize| -> Vec<f64> { votes.iter().map(|v| v[i]).collect() };
+
+        let closes: Vec<f64> = prices.iter().map(|(_, p)| *p).collect();
+        let mut fit = StackingFit::new(votes.len());
+        self.sizer.reset();
+        let mut trades = Trades::default();
+        for (i, (day, price)) in prices.iter().enumerate() {
+            let stacked = match self.method {
+                EnsembleMethod::Stacking => {
+                    // The return after the vote `horizon` days ago is known now
+                    if let Some(j) = i.checked_sub(self.horizon) {
+                        fit.add(&on_day(j), closes[i] / closes[j] - 1.0);
+                    }
+                    if fit.days >= self.min_train {
+                        fit.weights()
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            let (side, strength) = match stacked {
+                Some(w) => linear_vote(&on_day(i), &w),
+                None => vote(&on_day(i), &weights),
+            };
+
+            let side = match side {
+                s if s > 0.0 => Label::Long,
+                s if s < 0.0 => Label::Short,
+                _ => Label::Out,
+            };
+            self.sizer.next(*price);
+            let trade = self.sizer.trade(side, *price, strength);
+            trades.trades.insert(*day, trade);
+        }
+
+        Ok(trades)
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        self.members
+            .iter_mut()
+            .flat_map(|(m, _)| m.latest_signals(prices))
+            .collect()
+    }
+}
+
+/// The side (1 long, -1 short, 0 out) `trades` hold on each day of `prices`.
+fn sides(trades: &Trades, prices: &Prices) -> Vec<f64> {
+    let mut side = 0.0;
+    prices
+        .iter()
+        .map(|(day, _)| {
+            side = match trades.get(day) {
+                Some(Position::Long(_)) => 1.0,
+                Some(Position::Short(_)) => -1.0,
+                Some(Position::Out) => 0.0,
+                Some(Position::Hold) | None => side,
+            };
+            side
+        })
+        .collect()
+}
+
+/// The side with the most (weighted) votes, and its share of the total
+/// weight. Ties go out.
+fn vote(sides: &[f64], weights: &[f64]) -> (f64, f64) {
+    let (mut long, mut short, mut out) = (0.0, 0.0, 0.0);
+    for (side, weight) in sides.iter().zip(weights) {
+        match side {
+            s if *s > 0.0 => long += weight,
+            s if *s < 0.0 => short += weight,
+            _ => out += weight,
+        }
+    }
+
+    let total = long + short + out;
+    if total <= 0.0 {
+        (0.0, 0.0)
+    } else if long > short && long > out {
+        (1.0, long / total)
+    } else if short > long && short > out {
+        (-1.0, short / total)
+    } else {
+        (0.0, out / total)
+    }
+}
+
+/// The weighted sum of the votes (whose sign is the side to take), and its
+/// size relative to the largest it could be.
+fn linear_vote(sides: &[f64], weights: &[f64]) -> (f64, f64) {
+    let sum: f64 = sides.iter().zip(weights).map(|(s, w)| s * w).sum();
+    let most: f64 = weights.iter().map(|w| w.abs()).sum();
+    if most > 0.0 {
+        (sum, sum.abs() / most)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+/// A least squares fit of returns to the votes before them, built up a day at
+/// a time.
+struct StackingFit {
+    /// Sums of the products of every pair of members' votes.
+    xtx: Vec<Vec<f64>>,
+
+    /// Sums of each member's votes times the returns after them.
+    xty: Vec<f64>,
+
+    /// Days fitted to.
+    days: usize,
+}
+
+impl StackingFit {
+    fn new(members: usize) -> Self {
+        Self {
+            xtx: vec![vec![0.0; members]; members],
+            xty: vec![0.0; members],
+            days: 0,
+        }
+    }
+
+    fn add(&mut self, sides: &[f64], ret: f64) {
+        for (a, side_a) in sides.iter().enumerate() {
+            self.xty[a] += side_a * ret;
+            for (b, side_b) in sides.iter().enumerate() {
+                self.xtx[a][b] += side_a * side_b;
+            }
+        }
+        self.days += 1;
+    }
+
+    /// The (ridge regularized) weights of the fit, if there is one.
+    fn weights(&self) -> Option<Vec<f64>> {
+        let mut a = self.xtx.clone();
+        for (i, row) in a.iter_mut().enumerate() {
+            row[i] += RIDGE;
+        }
+        solve(a, self.xty.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{signals::Signal, util::TimeSeries, Date};
+
+    /// Rises a dollar a day for 30 days.
+    fn rising() -> Prices {
+        let closes: TimeSeries<f64> = (1..=30)
+            .map(|d| (Date::from_ymd(2020, 1, d), 100.0 + d as f64))
+            .collect();
+        Prices::from_closes("jpm", closes)
+    }
+
+    /// Never trades, since the signal can't get past a dead zone of 1.
+    fn never() -> ManualTradingModel {
+        ManualTradingModel::new(10.0, Signal::new(1.0), Signal::new(0.0))
+    }
+
+    fn positions(trades: &Trades) -> Vec<Position> {
+        trades.held().values().cloned().collect()
+    }
+
+    #[test]
+    fn votes() {
+        let sides = [1.0, 1.0, -1.0, 0.0];
+        assert_eq!(vote(&sides, &[1.0; 4]), (1.0, 0.5));
+        assert_eq!(vote(&sides, &[1.0, 1.0, 3.0, 0.0]), (-1.0, 0.6));
+        assert_eq!(vote(&[1.0, -1.0], &[1.0, 1.0]), (0.0, 0.0));
+        assert_eq!(linear_vote(&sides, &[0.5, 0.5, -1.0, 2.0]), (2.0, 0.5));
+        assert_eq!(
+            solve(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]),
+            Some(vec![0.8, 1.4])
+        );
+        assert_eq!(
+            solve(vec![vec![1.0, 1.0], vec![1.0, 1.0]], vec![1.0, 1.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn majority_and_weighted_votes() {
+        let prices = rising();
+        let members = |method| {
+            EnsembleModel::new(method)
+                .with_member(BuyAndHold::all_in(10_000.0, 1.0), 3.0)
+                .with_member(never(), 1.0)
+                .with_member(never(), 1.0)
+                .with_sizer(PositionSizer::fixed(10.0))
+        };
+
+        let majority = members(EnsembleMethod::Majority)
+            .get_trades(&prices)
+            .unwrap();
+        assert!(positions(&majority).iter().all(|p| *p == Position::Out));
+
+        let weighted = members(EnsembleMethod::Weighted)
+            .get_trades(&prices)
+            .unwrap();
+        assert!(positions(&weighted)
+            .iter()
+            .all(|p| *p == Position::Long(10.0)));
+
+        assert_eq!(
+            members(EnsembleMethod::Weighted).to_string(),
+            "EnsembleModel(weighted: BuyAndHold, ManualTradingModel, ManualTradingModel)"
+        );
+        assert!(EnsembleModel::new(EnsembleMethod::Majority)
+            .get_trades(&prices)
+            .is_err());
+    }
+
+    #[test]
+    fn stacking_learns_from_returns() {
+        // Buying and holding is always right about a rising price, so once
+        // stacking has fit 5 days it overrules the majority.
+        let trades = EnsembleModel::new(EnsembleMethod::Stacking)
+            .with_member(BuyAndHold::all_in(10_000.0, 1.0), 1.0)
+            .with_member(never(), 1.0)
+            .with_member(never(), 1.0)
+            .with_stacking(1, 5)
+            .with_sizer(PositionSizer::fixed(10.0))
+            .get_trades(&rising())
+            .unwrap();
+        let positions = positions(&trades);
+        assert!(positions[..5].iter().all(|p| *p == Position::Out));
+        assert!(positions[5..].iter().all(|p| *p == Position::Long(10.0)));
+    }
+}
@@ -0,0 +1,337 @@
+//! Stop-loss, take-profit, trailing stop and maximum holding period rules
+//! that close the positions of any other trading model.
+
+use super::{
+    tradingmodel::{Trades, TradingModel},
+    Position,
+};
+use crate::{marketdata::prices::Prices, signals::IndicatorSignal};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Why an exit rule closed a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitReason {
+    /// The position lost more than the stop-loss since it was entered.
+    StopLoss,
+
+    /// The position gained more than the take-profit since it was entered.
+    TakeProfit,
+
+    /// The price came back more than the trailing stop from the best it has
+    /// been since the position was entered.
+    TrailingStop,
+
+    /// The position was held for the maximum holding period.
+    MaxHolding,
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::StopLoss => "stop loss",
+            Self::TakeProfit => "take profit",
+            Self::TrailingStop => "trailing stop",
+            Self::MaxHolding => "max holding",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// When to close a position regardless of what the trading model says.
+/// Returns are fractions of the entry price (0.05 = 5%), measured in the
+/// direction of the position, at each day's close (the price trades are
+/// filled at). Rules that are None never fire.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExitRules {
+    /// Close the position once it has lost this much.
+    pub stop_loss: Option<f64>,
+
+    /// Close the position once it has gained this much.
+    pub take_profit: Option<f64>,
+
+    /// Close the position once it has given back this much of the best price
+    /// since it was entered.
+    pub trailing_stop: Option<f64>,
+
+    /// Close the position after holding it for this many bars.
+    pub max_holding: Option<u32>,
+}
+
+/// A position that is being held, and what the exit rules need to know about
+/// it.
+struct Held {
+    position: Position,
+    entry_price: f64,
+    /// Highest price since entry for a long position, lowest for a short.
+    best_price: f64,
+    bars: u32,
+}
+
+impl ExitRules {
+    /// True if none of the rules are set.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Applies the rules to the trades a model made on `prices`. When a rule
+    /// fires, the position is closed that day and the reason is recorded in
+    /// `exits`. The model's trades are then ignored until it changes its mind
+    /// (goes out, or takes the opposite side), so a stopped-out position is
+    /// not immediately entered again.
+    pub fn apply(&self, trades: Trades, prices: &Prices) -> Trades {
+        if self.is_empty() {
+            return trades;
+        }
+
+        let mut applied = Trades::default();
+        let mut held: Option<Held> = None;
+        // The side of the model's position an exit rule closed
+        let mut stopped: Option<Position> = None;
+        for (day, price) in prices.iter() {
+            let trade = trades.get(day).cloned().unwrap_or(Position::Hold);
+
+            if let Some(side) = stopped {
+                if trade == Position::Hold || same_side(&trade, &side) {
+                    applied.trades.insert(*day, Position::Hold);
+                    continue;
+                }
+                stopped = None;
+            }
+
+            // The model's own exits take precedence over the rules
+            let exited_by_model = held
+                .as_ref()
+                .is_some_and(|h| trade != Position::Hold && !same_side(&trade, &h.position));
+            if let Some(h) = held.as_mut().filter(|_| !exited_by_model) {
+                h.bars += 1;
+                if let Some(reason) = self.check(h, *price) {
+                    stopped = Some(h.position);
+                    held = None;
+                    applied.trades.insert(*day, Position::Out);
+                    applied.exits.insert(*day, reason);
+                    continue;
+                }
+            }
+
+            match trade {
+                Position::Long(_) | Position::Short(_) => {
+                    if !held
+                        .as_ref()
+                        .is_some_and(|h| same_side(&trade, &h.position))
+                    {
+                        held = Some(Held {
+                            position: trade,
+                            entry_price: *price,
+                            best_price: *price,
+                            bars: 0,
+                        });
+                    }
+                }
+                Position::Out => held = None,
+                Position::Hold => {}
+            }
+            applied.trades.insert(*day, trade);
+        }
+
+        applied
+    }
+
+    /// The first rule, if any, that closes `held` at `price`. Stop-losses are
+    /// checked first, so a day that hits more than one rule is counted as the
+    /// more conservative one.
+    fn check(&self, held: &mut Held, price: f64) -> Option<ExitReason> {
+        let long = matches!(held.position, Position::Long(_));
+        // Return in the direction of the position
+        let gain = |from: f64| {
+            if long {
+                price / from - 1.0
+            } else {
+                1.0 - price / from
+            }
+        };
+
+        if long {
+            held.best_price = held.best_price.max(price);
+        } else {
+            held.best_price = held.best_price.min(price);
+        }
+
+        let entry_gain = gain(held.entry_price);
+        if self.stop_loss.is_some_and(|s| entry_gain <= -s) {
+            Some(ExitReason::StopLoss)
+        } else if self.take_profit.is_some_and(|t| entry_gain >= t) {
+            Some(ExitReason::TakeProfit)
+        } else if self
+            .trailing_stop
+            .is_some_and(|t| gain(held.best_price) <= -t)
+        {
+            Some(ExitReason::TrailingStop)
+        } else if self.max_holding.is_some_and(|m| held.bars >= m) {
+            Some(ExitReason::MaxHolding)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for ExitRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rules = Vec::new();
+        let percent =
+            |rule: ExitReason, p: Option<f64>| p.map(|p| format!("{} {}%", rule, p * 100.0));
+        rules.extend(percent(ExitReason::StopLoss, self.stop_loss));
+        rules.extend(percent(ExitReason::TakeProfit, self.take_profit));
+        rules.extend(percent(ExitReason::TrailingStop, self.trailing_stop));
+        rules.extend(
+            self.max_holding
+                .map(|m| format!("{} {} bars", ExitReason::MaxHolding, m)),
+        );
+        write!(f, "{}", rules.join(", "))
+    }
+}
+
+/// True if both positions are long, both short, or both out.
+fn same_side(a: &Position, b: &Position) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+/// Wraps another trading model, closing its positions according to a set of
+/// ExitRules.
+pub struct RiskExits<M: TradingModel> {
+    inner: M,
+    rules: ExitRules,
+}
+
+impl<M: TradingModel> RiskExits<M> {
+    pub fn new(inner: M, rules: ExitRules) -> Self {
+        Self { inner, rules }
+    }
+}
+
+impl<M: TradingModel> Display for RiskExits<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.rules.is_empty() {
+            write!(f, "{}", self.inner)
+        } else {
+            write!(f, "{} ({})", self.inner, self.rules)
+        }
+    }
+}
+
+impl<M: TradingModel> TradingModel for RiskExits<M> {
+    type Error = M::Error;
+
+    fn get_trades(self, prices: &Prices) -> Result<Trades, Self::Error> {
+        let trades = self.inner.get_trades(prices)?;
+        Ok(self.rules.apply(trades, prices))
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        self.inner.latest_signals(prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{trading::Position::*, util::TimeSeries, Date};
+
+    fn ymd(d: u32) -> Date {
+        Date::from_ymd(2020, 6, d)
+    }
+
+    fn prices(closes: &[f64]) -> Prices {
+        let closes = closes
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (ymd(i as u32 + 1), p))
+            .collect();
+        Prices::from_closes("jpm", closes)
+    }
+
+    /// Goes long on the first day, then repeats `rest` every day after.
+    fn trades(days: u32, first: Position, rest: Position) -> Trades {
+        (1..=days)
+            .map(|d| (ymd(d), if d == 1 { first } else { rest }))
+            .collect::<TimeSeries<_>>()
+            .into()
+    }
+
+    fn positions(trades: &Trades) -> Vec<Position> {
+        trades.trades.values().cloned().collect()
+    }
+
+    #[test]
+    fn stops_and_targets() {
+        let p = prices(&[100.0, 97.0, 94.0, 96.0, 90.0]);
+        let rules = ExitRules {
+            stop_loss: Some(0.05),
+            ..ExitRules::default()
+        };
+
+        // The model keeps saying long, so it stays out after the stop
+        let stopped = rules.apply(trades(5, Long(10.0), Long(10.0)), &p);
+        assert_eq!(
+            positions(&stopped),
+            vec![Long(10.0), Long(10.0), Out, Hold, Hold]
+        );
+        assert_eq!(
+            stopped.exits.into_iter().collect::<Vec<_>>(),
+            vec![(ymd(3), ExitReason::StopLoss)]
+        );
+
+        // Shorts gain as the price falls
+        let rules = ExitRules {
+            take_profit: Some(0.05),
+            ..ExitRules::default()
+        };
+        let taken = rules.apply(trades(5, Short(10.0), Hold), &p);
+        assert_eq!(positions(&taken), vec![Short(10.0), Hold, Out, Hold, Hold]);
+        assert_eq!(taken.exit_counts()[&ExitReason::TakeProfit], 1);
+
+        // Nothing to do without any rules
+        let unchanged = ExitRules::default().apply(trades(5, Long(10.0), Hold), &p);
+        assert_eq!(unchanged, trades(5, Long(10.0), Hold));
+    }
+
+    #[test]
+    fn trailing_stops_and_holding_periods() {
+        let p = prices(&[100.0, 110.0, 120.0, 113.0, 130.0, 140.0]);
+        let rules = ExitRules {
+            trailing_stop: Some(0.05),
+            ..ExitRules::default()
+        };
+        let trailed = rules.apply(trades(6, Long(1.0), Hold), &p);
+        assert_eq!(trailed.exits.get(&ymd(4)), Some(&ExitReason::TrailingStop));
+
+        let rules = ExitRules {
+            max_holding: Some(2),
+            ..ExitRules::default()
+        };
+        let held = rules.apply(trades(6, Long(1.0), Hold), &p);
+        assert_eq!(
+            positions(&held),
+            vec![Long(1.0), Hold, Out, Hold, Hold, Hold]
+        );
+
+        // Re-enters once the model changes its mind
+        let mut model = trades(6, Long(1.0), Hold);
+        model.trades.insert(ymd(4), Out);
+        model.trades.insert(ymd(5), Long(2.0));
+        let reentered = rules.apply(model, &p);
+        assert_eq!(
+            positions(&reentered),
+            vec![Long(1.0), Hold, Out, Out, Long(2.0), Hold]
+        );
+        assert_eq!(reentered.exits.len(), 1);
+
+        // The model exiting on its own isn't counted
+        let mut model = trades(6, Long(1.0), Hold);
+        model.trades.insert(ymd(3), Short(1.0));
+        let flipped = rules.apply(model, &p);
+        assert_eq!(flipped.get(&ymd(3)), Some(&Short(1.0)));
+        assert_eq!(flipped.exits.get(&ymd(5)), Some(&ExitReason::MaxHolding));
+    }
+}
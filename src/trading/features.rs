@@ -0,0 +1,319 @@
+//! Turns each bar of price data into the row of features a machine learning
+//! model learns from: one signal per signal generator, and optionally the
+//! generators' raw outputs, lagged signals, rolling returns and volatility,
+//! and the day of the week.
+
+use crate::{indicators::SupportedIndicators, signals::SignalsIter, Date};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// Problems with a FeatureSet.
+#[derive(Debug, Error, PartialEq)]
+pub enum FeatureError {
+    #[error("Return and volatility windows must be at least 1 day long")]
+    ZeroWindow,
+}
+
+/// Which features to compute besides one signal per signal generator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct FeatureSet {
+    /// The raw outputs of each indicator (e.g. the MACD histogram or the RSI
+    /// level), in order of their names.
+    pub outputs: bool,
+
+    /// Each signal from 1 up to this many bars ago.
+    pub lags: usize,
+
+    /// The return over each of these many bars.
+    pub returns: Vec<usize>,
+
+    /// The standard deviation of one-bar returns over each of these many
+    /// bars.
+    pub volatility: Vec<usize>,
+
+    /// The day of the week, from 0 for Monday to 6 for Sunday.
+    pub day_of_week: bool,
+}
+
+/// Computes a FeatureSet bar by bar. Features that need more history than
+/// there has been so far are 0.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeaturePipeline {
+    signal_generators: Vec<Box<dyn SignalsIter>>,
+    features: FeatureSet,
+
+    /// Signals of the previous bars, most recent first.
+    #[serde(skip)]
+    past_signals: VecDeque<Vec<f32>>,
+
+    /// Closes of the recent bars, most recent last.
+    #[serde(skip)]
+    closes: VecDeque<f64>,
+}
+
+/// Names of the values in the Output of each indicator, in order.
+fn output_keys(indicator: SupportedIndicators) -> &'static [&'static str] {
+    match indicator {
+        SupportedIndicators::BollingerBands => &["average", "lower", "upper"],
+        SupportedIndicators::RelativeStrengthIndex => &["rsi"],
+        SupportedIndicators::MACD => &["histogram", "macd", "signal"],
+        SupportedIndicators::SmaCrossover => &["fast", "slow"],
+    }
+}
+
+impl FeaturePipeline {
+    pub fn new(
+        signal_generators: Vec<Box<dyn SignalsIter>>,
+        features: FeatureSet,
+    ) -> Result<Self, FeatureError> {
+        if features.returns.contains(&0) || features.volatility.contains(&0) {
+            return Err(FeatureError::ZeroWindow);
+        }
+
+        Ok(Self {
+            signal_generators,
+            features,
+            past_signals: VecDeque::new(),
+            closes: VecDeque::new(),
+        })
+    }
+
+    /// Just the signals of `signal_generators`.
+    pub fn signals_only(signal_generators: Vec<Box<dyn SignalsIter>>) -> Self {
+        Self::new(signal_generators, FeatureSet::default()).unwrap()
+    }
+
+    pub fn signal_generators(&self) -> &[Box<dyn SignalsIter>] {
+        &self.signal_generators
+    }
+
+    pub fn signal_generators_mut(&mut self) -> &mut [Box<dyn SignalsIter>] {
+        &mut self.signal_generators
+    }
+
+    /// Names of the features in each row, e.g. `macd` for the MACD signal,
+    /// `macd.histogram` for its histogram, `macd[t-1]` for yesterday's
+    /// signal, `return.5`, `volatility.20` and `day_of_week`.
+    pub fn names(&self) -> Vec<String> {
+        let indicators: Vec<SupportedIndicators> = self
+            .signal_generators
+            .iter()
+            .map(|g| g.indicator())
+            .collect();
+
+        let mut names: Vec<String> = indicators.iter().map(|i| i.to_string()).collect();
+        if self.features.outputs {
+            for i in &indicators {
+                names.extend(output_keys(*i).iter().map(|key| format!("{}.{}", i, key)));
+            }
+        }
+        for lag in 1..=self.features.lags {
+            names.extend(indicators.iter().map(|i| format!("{}[t-{}]", i, lag)));
+        }
+        names.extend(
+            self.features
+                .returns
+                .iter()
+                .map(|w| format!("return.{}", w)),
+        );
+        names.extend(
+            self.features
+                .volatility
+                .iter()
+                .map(|w| format!("volatility.{}", w)),
+        );
+        if self.features.day_of_week {
+            names.push("day_of_week".to_string());
+        }
+
+        names
+    }
+
+    /// The features of the bar on `day` that closed at `price`.
+    pub fn next(&mut self, day: &Date, price: f64) -> Vec<f32> {
+        let mut signals = Vec::new();
+        let mut outputs = Vec::new();
+        for gen in self.signal_generators.iter_mut() {
+            let (signal, output) = gen.next(price);
+            signals.push(f32::from(signal));
+            if self.features.outputs {
+                outputs.extend(output_keys(gen.indicator()).iter().map(|key| {
+                    let value = output.output.get(*key).cloned().unwrap_or(0.0);
+                    finite_or_zero(value) as f32
+                }));
+            }
+        }
+
+        let mut row = signals.clone();
+        row.extend(outputs);
+        for lag in 0..self.features.lags {
+            match self.past_signals.get(lag) {
+                Some(past) => row.extend(past),
+                None => row.extend(vec![0.0; signals.len()]),
+            }
+        }
+        if self.features.lags > 0 {
+            self.past_signals.push_front(signals);
+            self.past_signals.truncate(self.features.lags);
+        }
+
+        let longest = self
+            .features
+            .returns
+            .iter()
+            .chain(&self.features.volatility)
+            .max();
+        if let Some(longest) = longest {
+            self.closes.push_back(price);
+            while self.closes.len() > longest + 1 {
+                self.closes.pop_front();
+            }
+        }
+        for &window in &self.features.returns {
+            row.push(self.rolling_return(window) as f32);
+        }
+        for &window in &self.features.volatility {
+            row.push(self.volatility(window) as f32);
+        }
+
+        if self.features.day_of_week {
+            row.push(day.weekday().num_days_from_monday() as f32);
+        }
+
+        row
+    }
+
+    /// Forgets every bar seen so far.
+    pub fn reset(&mut self) {
+        self.signal_generators.iter_mut().for_each(|g| g.reset());
+        self.past_signals.clear();
+        self.closes.clear();
+    }
+
+    /// Return from `window` bars ago to the latest close.
+    fn rolling_return(&self, window: usize) -> f64 {
+        let n = self.closes.len();
+        if n <= window {
+            return 0.0;
+        }
+        finite_or_zero(self.closes[n - 1] / self.closes[n - 1 - window] - 1.0)
+    }
+
+    /// Standard deviation of the last `window` one-bar returns.
+    fn volatility(&self, window: usize) -> f64 {
+        let n = self.closes.len();
+        let first = n.saturating_sub(window + 1);
+        let returns: Vec<f64> = self
+            .closes
+            .iter()
+            .skip(first)
+            .zip(self.closes.iter().skip(first + 1))
+            .map(|(a, b)| b / a - 1.0)
+            .collect();
+        if returns.len() < 2 {
+            return 0.0;
+        }
+
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance =
+            returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+        finite_or_zero(variance.sqrt())
+    }
+}
+
+fn finite_or_zero(x: f64) -> f64 {
+    if x.is_finite() {
+        x
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signals::{
+        macdsignals::MACDSignalsIter, relativestrengthindexsignals::RSISignalsIter,
+    };
+
+    fn pipeline(features: FeatureSet) -> FeaturePipeline {
+        let gens: Vec<Box<dyn SignalsIter>> = vec![
+            Box::new(RSISignalsIter::default()),
+            Box::new(MACDSignalsIter::default()),
+        ];
+        FeaturePipeline::new(gens, features).unwrap()
+    }
+
+    #[test]
+    fn layout_matches_names() {
+        let mut p = pipeline(FeatureSet {
+            outputs: true,
+            lags: 2,
+            returns: vec![1, 3],
+            volatility: vec![3],
+            day_of_week: true,
+        });
+        assert_eq!(
+            p.names(),
+            vec![
+                "RelativeStrengthIndex",
+                "macd",
+                "RelativeStrengthIndex.rsi",
+                "macd.histogram",
+                "macd.macd",
+                "macd.signal",
+                "RelativeStrengthIndex[t-1]",
+                "macd[t-1]",
+                "RelativeStrengthIndex[t-2]",
+                "macd[t-2]",
+                "return.1",
+                "return.3",
+                "volatility.3",
+                "day_of_week",
+            ]
+        );
+
+        // Wednesday June 3rd 2020 to Saturday the 6th
+        let closes = [100.0, 110.0, 99.0, 99.0];
+        let rows: Vec<Vec<f32>> = closes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| p.next(&Date::from_ymd(2020, 6, 3 + i as u32), *c))
+            .collect();
+        assert!(rows.iter().all(|r| r.len() == p.names().len()));
+
+        let last = &rows[3];
+        // Signals lag by one and two days
+        assert_eq!(&last[6..8], &rows[2][..2]);
+        assert_eq!(&last[8..10], &rows[1][..2]);
+        assert_eq!(last[10], 0.0);
+        assert!((last[11] - -0.01).abs() < 1e-6);
+        assert!(last[12] > 0.0);
+        assert_eq!(last[13], 5.0);
+
+        // No history yet
+        assert_eq!(&rows[0][6..13], &[0.0; 7]);
+        assert_eq!(rows[0][13], 2.0);
+
+        p.reset();
+        assert_eq!(p.next(&Date::from_ymd(2020, 6, 3), 100.0), rows[0]);
+    }
+
+    #[test]
+    fn signals_only() {
+        let mut p = pipeline(FeatureSet::default());
+        assert_eq!(p.names(), vec!["RelativeStrengthIndex", "macd"]);
+        assert_eq!(p.next(&Date::from_ymd(2020, 6, 3), 100.0).len(), 2);
+
+        let gens: Vec<Box<dyn SignalsIter>> = vec![Box::new(RSISignalsIter::default())];
+        let zero = FeatureSet {
+            returns: vec![0],
+            ..FeatureSet::default()
+        };
+        assert_eq!(
+            FeaturePipeline::new(gens, zero).err(),
+            Some(FeatureError::ZeroWindow)
+        );
+    }
+}
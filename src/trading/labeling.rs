@@ -0,0 +1,411 @@
+//! Ways of labelling training days Long, Short or Out from the prices that
+//! follow them, for supervised learning.
+
+use crate::{calendar::TradingCalendar, marketdata::prices::Prices, Date};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Default number of daily returns volatility-scaled thresholds are
+/// estimated from.
+const DEFAULT_VOL_WINDOW: usize = 20;
+
+/// What a model should have done on a training day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Label {
+    Long,
+    Out,
+    Short,
+}
+
+/// Ways of labelling a training day from the closes of the `horizon` trading
+/// days after it, given a decision threshold (a return, e.g. 0.03 for 3%).
+/// Parsed from (and displayed as) `name:param1,param2`, e.g. `fixed` or
+/// `triple-barrier:2,1`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Labeler {
+    /// Long if the return at the horizon is at least the threshold, short if
+    /// it is at most minus the threshold, otherwise out.
+    #[default]
+    FixedHorizon,
+
+    /// Long if the price rises `profit_take` times the threshold before it
+    /// falls `stop_loss` times the threshold, short if it falls that far
+    /// first, and out if it does neither by the horizon.
+    TripleBarrier { profit_take: f64, stop_loss: f64 },
+
+    /// Like FixedHorizon, but the threshold is `multiplier` times the
+    /// standard deviation of the last `window` daily returns, scaled to the
+    /// horizon. The decision threshold is only used until there are at least
+    /// two returns to estimate it from.
+    VolatilityScaled { multiplier: f64, window: usize },
+
+    /// Long if the highest price before the horizon is at least the threshold
+    /// above the day's close and further above it than the lowest price is
+    /// below it, short if the lowest price is the further of the two and at
+    /// least the threshold below, otherwise out.
+    MaxFavorableExcursion,
+}
+
+/// Errors parsing a Labeler.
+#[derive(Debug, Error, PartialEq)]
+pub enum LabelerError {
+    #[error("Unknown labeler '{0}' (supported: fixed, triple-barrier, vol, mfe)")]
+    UnknownLabeler(String),
+
+    #[error("Labeler '{labeler}' takes at most {expected} parameter(s), got {found}")]
+    TooManyParameters {
+        labeler: &'static str,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Invalid parameter '{value}' for labeler '{labeler}': {reason}")]
+    InvalidParameter {
+        labeler: &'static str,
+        value: String,
+        reason: &'static str,
+    },
+}
+
+impl Labeler {
+    /// Name used in the `name:params` syntax.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::FixedHorizon => "fixed",
+            Self::TripleBarrier { .. } => "triple-barrier",
+            Self::VolatilityScaled { .. } => "vol",
+            Self::MaxFavorableExcursion => "mfe",
+        }
+    }
+
+    /// How many closes before (and including) a day the labeler needs.
+    pub fn lookback(&self) -> usize {
+        match self {
+            Self::VolatilityScaled { window, .. } => window + 1,
+            _ => 1,
+        }
+    }
+
+    /// Labels a day.
+    ///
+    /// ## Arguments
+    ///
+    /// * `past` - Closes up to and including the day, oldest first. Only the
+    ///   last `lookback()` are used.
+    /// * `path` - Closes of the trading days after it, up to and including
+    ///   the horizon. Must not be empty.
+    /// * `horizon` - How many trading days `path` covers (some may be missing).
+    /// * `threshold` - The decision threshold.
+    pub fn label(&self, past: &[f64], path: &[f64], horizon: u32, threshold: f64) -> Label {
+        let price = past.last().cloned().unwrap_or(f64::NAN);
+        let future_return = |p: f64| p / price - 1.0;
+        let by_threshold = |r: f64, threshold: f64| match r {
+            r if r >= threshold => Label::Long,
+            r if r <= -threshold => Label::Short,
+            _ => Label::Out,
+        };
+        let at_horizon = path.last().map_or(0.0, |p| future_return(*p));
+
+        match self {
+            Self::FixedHorizon => by_threshold(at_horizon, threshold),
+            Self::TripleBarrier {
+                profit_take,
+                stop_loss,
+            } => path
+                .iter()
+                .map(|p| future_return(*p))
+                .find_map(|r| {
+                    if r >= profit_take * threshold {
+                        Some(Label::Long)
+                    } else if r <= -stop_loss * threshold {
+                        Some(Label::Short)
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(Label::Out),
+            Self::VolatilityScaled { multiplier, window } => {
+                let start = past.len().saturating_sub(window + 1);
+                let returns: Vec<f64> = past[start..]
+                    .windows(2)
+                    .map(|w| w[1] / w[0] - 1.0)
+                    .collect();
+                let scaled = match returns.len() {
+                    0 | 1 => threshold,
+                    n => {
+                        let mean = returns.iter().sum::<f64>() / n as f64;
+                        let variance =
+                            returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n as f64;
+                        multiplier * variance.sqrt() * (horizon as f64).sqrt()
+                    }
+                };
+                by_threshold(at_horizon, scaled)
+            }
+            Self::MaxFavorableExcursion => {
+                let up = path.iter().map(|p| future_return(*p)).fold(0.0, f64::max);
+                let down = -path.iter().map(|p| future_return(*p)).fold(0.0, f64::min);
+                if up >= threshold && up > down {
+                    Label::Long
+                } else if down >= threshold && down > up {
+                    Label::Short
+                } else {
+                    Label::Out
+                }
+            }
+        }
+    }
+
+    /// Labels `day` from `prices`, counting the horizon in `calendar`'s
+    /// trading days. A trading day missing from `prices` (e.g. a feed outage)
+    /// is left out of the path, and at the horizon the next price after it is
+    /// used instead. None if the price on `day` isn't known, or there is no
+    /// price at or after the horizon.
+    pub fn label_day(
+        &self,
+        prices: &Prices,
+        day: Date,
+        horizon: u32,
+        calendar: &TradingCalendar,
+        threshold: f64,
+    ) -> Option<Label> {
+        prices.get(&day)?;
+        let horizon_day = calendar.add_trading_days(day, horizon);
+        let at_horizon = price_on_or_after(prices, horizon_day)?;
+
+        let mut path: Vec<f64> = (1..horizon)
+            .filter_map(|k| prices.get(&calendar.add_trading_days(day, k)))
+            .cloned()
+            .collect();
+        path.push(at_horizon);
+        let mut past: Vec<f64> = prices
+            .map
+            .range(..=day)
+            .rev()
+            .take(self.lookback())
+            .map(|(_, bar)| bar.price())
+            .collect();
+        past.reverse();
+
+        Some(self.label(&past, &path, horizon, threshold))
+    }
+}
+
+/// The return from `day` to `horizon` trading days later in `calendar`, which
+/// return regressors learn to predict. If the horizon is missing from
+/// `prices`, the next price after it is used instead. None if the price on
+/// `day` isn't known, or there is no price at or after the horizon.
+pub fn forward_return(
+    prices: &Prices,
+    day: Date,
+    horizon: u32,
+    calendar: &TradingCalendar,
+) -> Option<f64> {
+    let now = prices.get(&day)?;
+    let then = price_on_or_after(prices, calendar.add_trading_days(day, horizon))?;
+    Some(then / now - 1.0)
+}
+
+/// The price on `day`, or on the first day after it that has one.
+fn price_on_or_after(prices: &Prices, day: Date) -> Option<f64> {
+    prices.map.range(day..).next().map(|(_, bar)| bar.price())
+}
+
+impl FromStr for Labeler {
+    type Err = LabelerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = match s.find(':') {
+            Some(i) => (&s[..i], s[i + 1..].split(',').map(str::trim).collect()),
+            None => (s, Vec::new()),
+        };
+
+        let (labeler, max_params): (&'static str, usize) = match name.trim() {
+            "fixed" | "fixed-horizon" => ("fixed", 0),
+            "triple-barrier" | "barrier" => ("triple-barrier", 2),
+            "vol" | "vol-scaled" => ("vol", 2),
+            "mfe" | "max-favorable-excursion" => ("mfe", 0),
+            other => return Err(LabelerError::UnknownLabeler(other.to_string())),
+        };
+        if params.len() > max_params {
+            return Err(LabelerError::TooManyParameters {
+                labeler,
+                expected: max_params,
+                found: params.len(),
+            });
+        }
+
+        let invalid = |value: &str, reason| LabelerError::InvalidParameter {
+            labeler,
+            value: value.to_string(),
+            reason,
+        };
+        let multiple = |i: usize| match params.get(i) {
+            Some(v) => match v.parse::<f64>() {
+                Ok(m) if m > 0.0 && m.is_finite() => Ok(m),
+                _ => Err(invalid(v, "expected a number greater than 0")),
+            },
+            None => Ok(1.0),
+        };
+
+        Ok(match labeler {
+            "fixed" => Self::FixedHorizon,
+            "triple-barrier" => Self::TripleBarrier {
+                profit_take: multiple(0)?,
+                stop_loss: multiple(1)?,
+            },
+            "vol" => Self::VolatilityScaled {
+                multiplier: multiple(0)?,
+                window: match params.get(1) {
+                    Some(v) => match v.parse::<usize>() {
+                        Ok(w) if w >= 2 => w,
+                        _ => return Err(invalid(v, "expected a window of at least 2 days")),
+                    },
+                    None => DEFAULT_VOL_WINDOW,
+                },
+            },
+            _ => Self::MaxFavorableExcursion,
+        })
+    }
+}
+
+impl Display for Labeler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())?;
+        match self {
+            Self::TripleBarrier {
+                profit_take,
+                stop_loss,
+            } => write!(f, ":{},{}", profit_take, stop_loss),
+            Self::VolatilityScaled { multiplier, window } => {
+                write!(f, ":{},{}", multiplier, window)
+            }
+            Self::FixedHorizon | Self::MaxFavorableExcursion => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<String> for Labeler {
+    type Error = LabelerError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Labeler> for String {
+    fn from(l: Labeler) -> Self {
+        l.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Label::*, *};
+
+    #[test]
+    fn parse_labelers() {
+        assert_eq!("fixed".parse(), Ok(Labeler::FixedHorizon));
+        assert_eq!(
+            "triple-barrier:2,0.5".parse(),
+            Ok(Labeler::TripleBarrier {
+                profit_take: 2.0,
+                stop_loss: 0.5
+            })
+        );
+        assert_eq!(
+            "vol".parse(),
+            Ok(Labeler::VolatilityScaled {
+                multiplier: 1.0,
+                window: DEFAULT_VOL_WINDOW
+            })
+        );
+        assert_eq!("mfe".parse(), Ok(Labeler::MaxFavorableExcursion));
+
+        for s in &["fixed", "triple-barrier:2,0.5", "vol:1.5,10", "mfe"] {
+            assert_eq!(s.parse::<Labeler>().unwrap().to_string(), *s);
+        }
+
+        assert!(matches!(
+            "magic".parse::<Labeler>(),
+            Err(LabelerError::UnknownLabeler(_))
+        ));
+        assert!(matches!(
+            "fixed:1".parse::<Labeler>(),
+            Err(LabelerError::TooManyParameters { .. })
+        ));
+        assert!(matches!(
+            "vol:1,1".parse::<Labeler>(),
+            Err(LabelerError::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn labels() {
+        let past = [100.0];
+        // Dips 4% before ending up 5%
+        let path = [98.0, 96.0, 105.0];
+
+        let fixed = Labeler::FixedHorizon;
+        assert_eq!(fixed.label(&past, &path, 3, 0.03), Long);
+        assert_eq!(fixed.label(&past, &path, 3, 0.06), Out);
+        assert_eq!(fixed.label(&past, &[95.0], 3, 0.03), Short);
+
+        // The stop is hit first
+        let barrier = Labeler::TripleBarrier {
+            profit_take: 1.0,
+            stop_loss: 1.0,
+        };
+        assert_eq!(barrier.label(&past, &path, 3, 0.03), Short);
+        let wide_stop = Labeler::TripleBarrier {
+            profit_take: 1.0,
+            stop_loss: 2.0,
+        };
+        assert_eq!(wide_stop.label(&past, &path, 3, 0.03), Long);
+        assert_eq!(wide_stop.label(&past, &path, 3, 0.1), Out);
+
+        let mfe = Labeler::MaxFavorableExcursion;
+        assert_eq!(mfe.label(&past, &path, 3, 0.03), Long);
+        assert_eq!(mfe.label(&past, &[96.0, 102.0], 2, 0.03), Short);
+        assert_eq!(mfe.label(&past, &[99.0, 101.0], 2, 0.03), Out);
+
+        // Daily moves of 1% make for a 1.7% threshold over 3 days
+        let vol = Labeler::VolatilityScaled {
+            multiplier: 1.0,
+            window: 4,
+        };
+        let choppy = [100.0, 101.0, 100.0, 101.0, 100.0];
+        assert_eq!(vol.lookback(), 5);
+        assert_eq!(vol.label(&choppy, &[102.0], 3, 0.03), Long);
+        assert_eq!(vol.label(&choppy, &[101.5], 3, 0.03), Out);
+        // Not enough history, so it falls back to the decision threshold
+        assert_eq!(vol.label(&past, &[102.0], 3, 0.03), Out);
+    }
+
+    #[test]
+    fn labels_across_gaps() {
+        // The 4th is missing, as if the feed missed it
+        let day = |d| Date::from_ymd(2020, 1, d);
+        let closes = [(1, 100.0), (2, 101.0), (3, 102.0), (5, 104.0)];
+        let prices =
+            Prices::from_closes("jpm", closes.iter().map(|(d, c)| (day(*d), *c)).collect());
+        let calendar = TradingCalendar::every_day();
+
+        let fixed = Labeler::FixedHorizon;
+        assert_eq!(
+            fixed.label_day(&prices, day(1), 3, &calendar, 0.03),
+            Some(Long)
+        );
+        assert_eq!(
+            fixed.label_day(&prices, day(2), 1, &calendar, 0.03),
+            Some(Out)
+        );
+        assert_eq!(fixed.label_day(&prices, day(4), 1, &calendar, 0.03), None);
+        assert_eq!(fixed.label_day(&prices, day(3), 3, &calendar, 0.03), None);
+
+        let returned = forward_return(&prices, day(1), 3, &calendar).unwrap();
+        assert!((returned - 0.04).abs() < 1e-9);
+        assert_eq!(forward_return(&prices, day(5), 1, &calendar), None);
+    }
+}
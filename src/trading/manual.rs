@@ -3,13 +3,14 @@
 use super::tradingmodel::{Trades, TradingModel};
 use crate::Date;
 use crate::{
+    indicators::{IndicatorSpec, IndicatorSpecError, SupportedIndicators},
     marketdata::prices::Prices,
-    signals::{
-        bollingerbandssignals::BBSignalsIter, macdsignals::MACDSignalsIter,
-        relativestrengthindexsignals::RSISignalsIter, Signal,
-    },
+    signals::{latest_signals, IndicatorSignal, Signal},
+};
+use crate::{
+    signals::SignalsIter,
+    trading::{labeling::Label, sizing::PositionSizer},
 };
-use crate::{signals::SignalsIter, trading::Position};
 use derive_more::Display;
 use std::{collections::BTreeMap, fmt::Display};
 use structopt::StructOpt;
@@ -22,17 +23,16 @@ pub enum Error {
 
 #[derive(StructOpt, Debug)]
 pub struct ManualParams {
-    pub shares: u64,
+    pub shares: f64,
     pub dead_zone: Signal,
     pub disposition: Signal,
 }
 
 /// The manual trading model.
 pub struct ManualTradingModel {
-    // TODO: make this a max and let the bot decide dynamically how many shares
-    // to trade
-    /// How many shares will be committed to a trade.
-    shares: u64,
+    /// Decides how many shares will be committed to a trade. The strength of
+    /// the averaged signal is the model's conviction.
+    sizer: PositionSizer,
 
     /// How far the signal needs to be from 0 in order to make a trade. For
     /// example, if the dead zone is 0.2, only an average signal of less than
@@ -42,26 +42,54 @@ pub struct ManualTradingModel {
     /// The tendency for the algorithm to be bullish or bearish by adding or
     /// subtracting from the signal before determining a trade.
     disposition: Signal,
+
+    /// Technical indicators whose signals are averaged to decide trades.
+    indicators: Vec<IndicatorSpec>,
 }
 
 impl ManualTradingModel {
-    pub fn new(shares: u64, dead_zone: Signal, disposition: Signal) -> Self {
+    pub fn new(shares: f64, dead_zone: Signal, disposition: Signal) -> Self {
         Self {
-            shares,
+            sizer: PositionSizer::fixed(shares),
             dead_zone,
             disposition,
+            indicators: default_indicators(),
         }
     }
 
-    pub fn set_shares(&mut self, shares: u64) {
-        self.shares = shares;
+    /// Uses the given indicators instead of the defaults (RSI, Bollinger
+    /// Bands and MACD with their default parameters). There must be at least
+    /// one, since their signals are averaged.
+    pub fn with_indicators(
+        mut self,
+        indicators: Vec<IndicatorSpec>,
+    ) -> Result<Self, IndicatorSpecError> {
+        if indicators.is_empty() {
+            return Err(IndicatorSpecError::NoIndicators);
+        }
+        for spec in &indicators {
+            spec.validate()?;
+        }
+
+        self.indicators = indicators;
+        Ok(self)
+    }
+
+    /// Sizes trades with `sizer` instead of a fixed number of shares.
+    pub fn with_sizer(mut self, sizer: PositionSizer) -> Self {
+        self.sizer = sizer;
+        self
+    }
+
+    pub fn set_shares(&mut self, shares: f64) {
+        self.sizer = PositionSizer::fixed(shares);
     }
 }
 
 impl Default for ManualTradingModel {
     /// Sets shares to 1000, deadzone and disposition to 0.0.
     fn default() -> Self {
-        Self::new(1000, Signal::new(0.0), Signal::new(0.0))
+        Self::new(1000.0, Signal::new(0.0), Signal::new(0.0))
     }
 }
 
@@ -100,12 +128,9 @@ pub struct CanNeverHappen;
 impl TradingModel for ManualTradingModel {
     type Error = CanNeverHappen;
 
-    fn get_trades(self, prices: &Prices) -> Result<Trades, Self::Error> {
-        // Make a bin of technical indicators to use - 2 trending, 2 oscillating.
-
-        let mut rsi = RSISignalsIter::default();
-        let mut bb = BBSignalsIter::default();
-        let mut macd = MACDSignalsIter::default();
+    fn get_trades(mut self, prices: &Prices) -> Result<Trades, Self::Error> {
+        let mut signal_generators = self.signal_generators();
+        self.sizer.reset();
 
         let mut trades = BTreeMap::new();
         for (day, price) in prices.iter() {
@@ -115,32 +140,67 @@ impl TradingModel for ManualTradingModel {
             //     MarketState::Trending => todo!("Favor trend indicators"),
             //     MarketState::Oscillating => todo!("Favor oscillating indicators"),
             // };
-            let signals = vec![rsi.next(*price).0, bb.next(*price).0, macd.next(*price).0];
+            let signals: Vec<Signal> = signal_generators
+                .iter_mut()
+                .map(|g| g.next(*price).0)
+                .collect();
             let sum: f64 = signals.iter().map(|s| s.0).sum();
             let signal_average = (sum / signals.len() as f64) + self.disposition;
 
             // Consult the indicators' consensus.
-            let trade = match signal_average {
-                avg if avg > self.dead_zone.into() => Position::Long(self.shares),
-                avg if avg < -(f64::from(self.dead_zone)) => Position::Short(self.shares),
-                _ => Position::Out, // TODO: should I hold instead?
+            let side = match signal_average {
+                avg if avg > self.dead_zone.into() => Label::Long,
+                avg if avg < -(f64::from(self.dead_zone)) => Label::Short,
+                _ => Label::Out, // TODO: should I hold instead?
             };
+            self.sizer.next(*price);
+            let trade = self.sizer.trade(side, *price, signal_average.abs());
 
             // Make a trade.
             trades.insert(*day, trade);
         }
 
-        Ok(Trades { trades })
+        Ok(Trades::from(trades))
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        latest_signals(&mut self.signal_generators(), prices)
     }
 }
 
+impl ManualTradingModel {
+    fn signal_generators(&self) -> Vec<Box<dyn SignalsIter>> {
+        self.indicators
+            .iter()
+            .map(|s| {
+                s.signals_iter()
+                    .expect("indicators are validated by with_indicators")
+            })
+            .collect()
+    }
+}
+
+/// Makes a bin of technical indicators to use - 2 trending, 2 oscillating.
+fn default_indicators() -> Vec<IndicatorSpec> {
+    vec![
+        SupportedIndicators::RelativeStrengthIndex.into(),
+        SupportedIndicators::BollingerBands.into(),
+        SupportedIndicators::MACD.into(),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::ManualTradingModel;
+    use crate::indicators::{IndicatorSpec, IndicatorSpecError};
     use crate::Date;
     use crate::{
-        marketdata::prices::Prices, signals::Signal, trading::tradingmodel::TradingModel,
-        trading::Position, util::TimeSeries,
+        marketdata::prices::Prices,
+        signals::Signal,
+        trading::sizing::{PositionSizer, SizingPolicy},
+        trading::tradingmodel::TradingModel,
+        trading::Position,
+        util::TimeSeries,
     };
     use chrono::Duration;
     use std::collections::BTreeMap;
@@ -157,10 +217,7 @@ mod tests {
             dt = dt + Duration::days(1);
         }
 
-        Prices {
-            map: entries,
-            symbol: "jpm".to_string(),
-        }
+        Prices::from_closes("jpm", entries)
     }
 
     // TODO: a good macro would really help make things easier
@@ -196,10 +253,7 @@ mod tests {
         .cloned()
         .collect();
 
-        let prices = Prices {
-            map: map,
-            symbol: "jpm".to_string(),
-        };
+        let prices = Prices::from_closes("jpm", map);
 
         let algo = ManualTradingModel::default();
         let trades: Vec<Position> = algo
@@ -222,13 +276,42 @@ mod tests {
         let prices = fixture_setup();
 
         // set to perma-bear mode
-        let algo = ManualTradingModel::new(1, Signal::new(0.0), Signal::new(-1.0));
+        let algo = ManualTradingModel::new(1.0, Signal::new(0.0), Signal::new(-1.0));
         let trades = algo.get_trades(&prices).unwrap();
-        assert!(trades.trades.iter().all(|t| *t.1 == Position::Short(1)));
+        assert!(trades.held().values().all(|p| *p == Position::Short(1.0)));
+        // The position is only sized the day it's opened
+        let positions: Vec<&Position> = trades.trades.values().collect();
+        assert_eq!(positions[0], &Position::Short(1.0));
+        assert!(positions[1..].iter().all(|p| **p == Position::Hold));
 
         // perma-bull mode
-        let algo = ManualTradingModel::new(1, Signal::new(0.0), Signal::new(1.0));
+        let algo = ManualTradingModel::new(1.0, Signal::new(0.0), Signal::new(1.0));
         let trades = algo.get_trades(&prices).unwrap();
-        assert!(trades.trades.iter().all(|t| *t.1 == Position::Long(1)));
+        assert!(trades.held().values().all(|p| *p == Position::Long(1.0)));
+
+        // half as bullish, sized by conviction
+        let algo = ManualTradingModel::new(1.0, Signal::new(0.0), Signal::new(0.5)).with_sizer(
+            PositionSizer::new(SizingPolicy::SignalProportional(100.0), 0.0),
+        );
+        let trades = algo.get_trades(&prices).unwrap();
+        assert!(trades.held().values().all(|p| *p == Position::Long(50.0)));
+    }
+
+    #[test]
+    fn test_manual_trader_indicators() {
+        let prices = fixture_setup();
+        let algo = ManualTradingModel::default()
+            .with_indicators(vec!["rsi:5".parse().unwrap(), "bb:10,1.5".parse().unwrap()])
+            .unwrap();
+        assert_eq!(algo.get_trades(&prices).unwrap().len(), prices.map.len());
+
+        let bad = IndicatorSpec::RelativeStrengthIndex { window: 0 };
+        assert!(ManualTradingModel::default()
+            .with_indicators(vec![bad])
+            .is_err());
+        assert_eq!(
+            ManualTradingModel::default().with_indicators(vec![]).err(),
+            Some(IndicatorSpecError::NoIndicators)
+        );
     }
 }
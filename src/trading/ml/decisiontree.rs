@@ -22,23 +22,37 @@ impl DecisionTreeClassifier {
 
 impl MachineLearningAlgorithm<Vec<f32>> for DecisionTreeClassifier {
     fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
-        let result = self
-            .learner
-            .fit(&Array::from(x), &Array::from(y.to_owned()))
-            .map_err(|msg| Error::FitError(msg.to_string()))?;
-        Ok(result)
+        fit(&mut self.learner, x, y)
     }
 
     fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
-        let result = self
-            .learner
-            .predict(&Array::from(x))
-            .map_err(|msg| Error::PredictionError(msg.to_string()))?;
-
-        Ok(array_to_2d_vec(&result))
+        predict(&self.learner, x)
     }
 }
 
+/// Fits any rustlearn model.
+pub(super) fn fit(
+    learner: &mut impl SupervisedModel,
+    x: &Vec<Vec<f32>>,
+    y: &[f32],
+) -> Result<(), Error> {
+    learner
+        .fit(&Array::from(x), &Array::from(y.to_owned()))
+        .map_err(|msg| Error::FitError(msg.to_string()))
+}
+
+/// Predicts with any rustlearn model.
+pub(super) fn predict(
+    learner: &impl SupervisedModel,
+    x: &Vec<Vec<f32>>,
+) -> Result<Vec<Vec<f32>>, Error> {
+    let result = learner
+        .predict(&Array::from(x))
+        .map_err(|msg| Error::PredictionError(msg.to_string()))?;
+
+    Ok(array_to_2d_vec(&result))
+}
+
 // FIXME: why can't we just use the from/into implementation? God this sucks
 fn array_to_2d_vec(a: &Array) -> Vec<Vec<f32>> {
     let mut result = Vec::new();
@@ -0,0 +1,210 @@
+//! Gradient-boosted regression trees for classification. Each round fits one
+//! small tree per class to how far the softmax of the class scores is from
+//! the labels, and adds a fraction of it to the scores.
+
+use super::{
+    mlmodel::{Error, MachineLearningAlgorithm},
+    regressiontree::{RegressionTree, Targets, TreeShape},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GradientBoostedTrees {
+    num_trees: usize,
+    max_depth: usize,
+    min_samples_split: usize,
+    min_samples_leaf: usize,
+
+    /// How much of each tree is added to the scores.
+    learning_rate: f32,
+
+    /// The distinct labels seen while fitting, in order.
+    classes: Vec<f32>,
+
+    /// Score of each class before any trees (the log of how common it is).
+    base: Vec<f32>,
+
+    /// Each round's tree for each class.
+    rounds: Vec<Vec<RegressionTree>>,
+}
+
+impl GradientBoostedTrees {
+    /// Fits `num_trees` rounds of trees up to `max_depth` deep. Nodes with
+    /// fewer than `min_samples_split` rows aren't split, and neither side of
+    /// a split gets fewer than `min_samples_leaf` rows.
+    pub fn new(
+        num_trees: usize,
+        max_depth: usize,
+        min_samples_split: usize,
+        min_samples_leaf: usize,
+        learning_rate: f32,
+    ) -> Self {
+        Self {
+            num_trees,
+            max_depth,
+            min_samples_split: min_samples_split.max(2),
+            min_samples_leaf: min_samples_leaf.max(1),
+            learning_rate,
+            classes: Vec::new(),
+            base: Vec::new(),
+            rounds: Vec::new(),
+        }
+    }
+
+    /// Each class's score for `row`.
+    fn scores(&self, row: &[f32]) -> Vec<f32> {
+        let mut scores = self.base.clone();
+        for trees in &self.rounds {
+            for (score, tree) in scores.iter_mut().zip(trees) {
+                *score += self.learning_rate * tree.predict(row);
+            }
+        }
+        scores
+    }
+
+    /// How far each tree may grow.
+    fn shape(&self) -> TreeShape {
+        TreeShape {
+            max_depth: self.max_depth,
+            min_samples_split: self.min_samples_split,
+            min_samples_leaf: self.min_samples_leaf,
+        }
+    }
+}
+
+fn softmax(scores: &[f32]) -> Vec<f32> {
+    let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|s| (s - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|e| e / sum).collect()
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for GradientBoostedTrees {
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        if x.is_empty() || x.len() != y.len() {
+            return Err(Error::FitError(format!(
+                "Got {} rows of features for {} labels",
+                x.len(),
+                y.len()
+            )));
+        }
+
+        self.classes = y.clone();
+        self.classes.sort_by(f32::total_cmp);
+        self.classes.dedup();
+        let class_of: Vec<usize> = y
+            .iter()
+            .map(|label| self.classes.iter().position(|c| c == label).unwrap_or(0))
+            .collect();
+
+        let k = self.classes.len();
+        let n = y.len() as f32;
+        self.base = (0..k)
+            .map(|c| (class_of.iter().filter(|&&i| i == c).count() as f32 / n).ln())
+            .collect();
+        self.rounds = Vec::new();
+        if k < 2 {
+            return Ok(());
+        }
+
+        // Friedman's scaling of the Newton step for K classes
+        let scale = (k - 1) as f32 / k as f32;
+        let mut scores: Vec<Vec<f32>> = vec![self.base.clone(); x.len()];
+        for _ in 0..self.num_trees {
+            let probabilities: Vec<Vec<f32>> = scores.iter().map(|s| softmax(s)).collect();
+            let mut trees = Vec::new();
+            for class in 0..k {
+                let gradients: Vec<f32> = probabilities
+                    .iter()
+                    .zip(&class_of)
+                    .map(|(p, &c)| {
+                        let label = if c == class { 1.0 } else { 0.0 };
+                        scale * (label - p[class])
+                    })
+                    .collect();
+                let hessians: Vec<f32> = probabilities
+                    .iter()
+                    .map(|p| p[class] * (1.0 - p[class]))
+                    .collect();
+                let targets = Targets {
+                    x,
+                    gradients: &gradients,
+                    hessians: &hessians,
+                };
+                let tree = self.shape().build(&targets, (0..x.len()).collect(), 0);
+                for (row, score) in x.iter().zip(scores.iter_mut()) {
+                    score[class] += self.learning_rate * tree.predict(row);
+                }
+                trees.push(tree);
+            }
+            self.rounds.push(trees);
+        }
+
+        Ok(())
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        if self.classes.is_empty() {
+            return Err(Error::PredictionError("Model has not been fitted".into()));
+        }
+
+        Ok(x.iter()
+            .map(|row| {
+                let scores = self.scores(row);
+                let best = (0..scores.len())
+                    .max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+                    .unwrap_or(0);
+                vec![self.classes[best]]
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_classes() {
+        // Short below -1, long above 1, out in between. The second feature is
+        // noise.
+        let x: Vec<Vec<f32>> = (-20..=20)
+            .map(|i| vec![i as f32 / 10.0, (i * 7 % 5) as f32])
+            .collect();
+        let y: Vec<f32> = x
+            .iter()
+            .map(|row| match row[0] {
+                v if v < -1.0 => -1.0,
+                v if v > 1.0 => 1.0,
+                _ => 0.0,
+            })
+            .collect();
+
+        let mut gbt = GradientBoostedTrees::new(20, 2, 2, 1, 0.3);
+        assert!(gbt.predict(&x).is_err());
+        gbt.fit(&x, &y).unwrap();
+        let predicted: Vec<f32> = gbt.predict(&x).unwrap().into_iter().map(|r| r[0]).collect();
+        assert_eq!(predicted, y);
+        assert_eq!(
+            gbt.predict(&vec![vec![-5.0, 0.0], vec![5.0, 0.0]]).unwrap(),
+            vec![vec![-1.0], vec![1.0]]
+        );
+
+        // Survives a round trip
+        let bytes = bincode::serialize(&gbt).unwrap();
+        let loaded: GradientBoostedTrees = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, gbt);
+
+        // Leaves too big to isolate the short and long rows
+        let mut gbt = GradientBoostedTrees::new(20, 2, 2, 30, 0.3);
+        gbt.fit(&x, &y).unwrap();
+        let predicted: Vec<f32> = gbt.predict(&x).unwrap().into_iter().map(|r| r[0]).collect();
+        assert_ne!(predicted, y);
+
+        // Only one label to predict
+        let mut gbt = GradientBoostedTrees::new(20, 2, 2, 1, 0.3);
+        gbt.fit(&x, &vec![1.0; x.len()]).unwrap();
+        assert_eq!(gbt.predict(&x[..1].to_vec()).unwrap(), vec![vec![1.0]]);
+        assert!(gbt.fit(&x, &y[1..].to_vec()).is_err());
+    }
+}
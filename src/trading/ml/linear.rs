@@ -0,0 +1,136 @@
+//! Least squares linear regression, optionally with a ridge (L2) penalty on
+//! the weights, solved exactly from the normal equations.
+
+use super::mlmodel::{Error, MachineLearningAlgorithm};
+use crate::util::solve;
+use serde::{Deserialize, Serialize};
+
+/// Penalty added even to plain least squares, so that features which never
+/// change (or copy each other) don't leave the equations unsolvable.
+const MIN_PENALTY: f64 = 1e-9;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinearRegression {
+    /// How strongly the weights are pulled towards 0. Not applied to the
+    /// intercept.
+    penalty: f64,
+
+    weights: Vec<f64>,
+    intercept: f64,
+}
+
+impl LinearRegression {
+    /// Plain least squares with a `penalty` of 0, ridge regression otherwise.
+    pub fn new(penalty: f64) -> Self {
+        Self {
+            penalty: penalty.max(0.0),
+            weights: Vec::new(),
+            intercept: 0.0,
+        }
+    }
+
+    fn predict_row(&self, row: &[f32]) -> f64 {
+        let dot: f64 = self
+            .weights
+            .iter()
+            .zip(row)
+            .map(|(w, x)| w * *x as f64)
+            .sum();
+        self.intercept + dot
+    }
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for LinearRegression {
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        if x.is_empty() || x.len() != y.len() {
+            return Err(Error::FitError(format!(
+                "Got {} rows of features for {} targets",
+                x.len(),
+                y.len()
+            )));
+        }
+
+        // Centering the features and targets fits the intercept separately,
+        // so the penalty doesn't shrink it.
+        let n = x.len() as f64;
+        let features = x[0].len();
+        let mean_x: Vec<f64> = (0..features)
+            .map(|j| x.iter().map(|row| row[j] as f64).sum::<f64>() / n)
+            .collect();
+        let mean_y = y.iter().map(|t| *t as f64).sum::<f64>() / n;
+
+        let mut xtx = vec![vec![0.0; features]; features];
+        let mut xty = vec![0.0; features];
+        for (row, target) in x.iter().zip(y) {
+            let centered: Vec<f64> = row
+                .iter()
+                .zip(&mean_x)
+                .map(|(v, m)| *v as f64 - m)
+                .collect();
+            for (a, xa) in centered.iter().enumerate() {
+                xty[a] += xa * (*target as f64 - mean_y);
+                for (b, xb) in centered.iter().enumerate() {
+                    xtx[a][b] += xa * xb;
+                }
+            }
+        }
+        for (i, row) in xtx.iter_mut().enumerate() {
+            row[i] += self.penalty.max(MIN_PENALTY);
+        }
+
+        self.weights = solve(xtx, xty)
+            .ok_or_else(|| Error::FitError("Features are linearly dependent".into()))?;
+        self.intercept = mean_y
+            - self
+                .weights
+                .iter()
+                .zip(&mean_x)
+                .map(|(w, m)| w * m)
+                .sum::<f64>();
+        Ok(())
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        if self.weights.is_empty() {
+            return Err(Error::PredictionError("Model has not been fitted".into()));
+        }
+        Ok(x.iter()
+            .map(|row| vec![self.predict_row(row) as f32])
+            .collect())
+    }
+
+    fn is_regressor(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_lines() {
+        // y = 2a - b + 1, with a constant third feature
+        let x: Vec<Vec<f32>> = (0..20)
+            .map(|i| vec![i as f32, (i * i % 7) as f32, 5.0])
+            .collect();
+        let y: Vec<f32> = x.iter().map(|r| 2.0 * r[0] - r[1] + 1.0).collect();
+
+        let mut linear = LinearRegression::new(0.0);
+        assert!(linear.predict(&x).is_err());
+        linear.fit(&x, &y).unwrap();
+        let predicted = linear.predict(&vec![vec![30.0, 2.0, 5.0]]).unwrap()[0][0];
+        assert!((predicted - 59.0).abs() < 1e-3, "{}", predicted);
+
+        // A big penalty shrinks the fit towards the mean
+        let mut ridge = LinearRegression::new(1e6);
+        ridge.fit(&x, &y).unwrap();
+        let mean = y.iter().sum::<f32>() / y.len() as f32;
+        let predicted = ridge.predict(&vec![vec![30.0, 2.0, 5.0]]).unwrap()[0][0];
+        assert!((predicted - mean).abs() < 1.0, "{}", predicted);
+
+        let bytes = bincode::serialize(&linear).unwrap();
+        let loaded: LinearRegression = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, linear);
+    }
+}
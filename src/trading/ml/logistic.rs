@@ -0,0 +1,37 @@
+//! One-Vs-Rest logistic regression, fitted by stochastic gradient descent
+//! using rustlearn.
+
+use super::{
+    decisiontree::{fit, predict},
+    mlmodel::{Error, MachineLearningAlgorithm},
+};
+use rustlearn::{linear_models::sgdclassifier::SGDClassifier, multiclass::OneVsRestWrapper};
+use serde::{Deserialize, Serialize};
+
+/// Passes of gradient descent over the training data.
+const EPOCHS: usize = 50;
+
+#[derive(Serialize, Deserialize)]
+pub struct LogisticRegression {
+    learner: OneVsRestWrapper<SGDClassifier>,
+}
+
+impl LogisticRegression {
+    pub fn new(learner: OneVsRestWrapper<SGDClassifier>) -> Self {
+        Self { learner }
+    }
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for LogisticRegression {
+    /// rustlearn makes one pass over the data per fit, so this makes several.
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        for _ in 0..EPOCHS {
+            fit(&mut self.learner, x, y)?;
+        }
+        Ok(())
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        predict(&self.learner, x)
+    }
+}
@@ -6,12 +6,18 @@ use derive_more::Display;
 pub trait MachineLearningAlgorithm<T> {
     fn fit(&mut self, x: &Vec<T>, y: &T) -> Result<(), Error>;
     fn predict(&self, x: &Vec<T>) -> Result<Vec<T>, Error>;
+
+    /// Whether the algorithm predicts forward returns rather than long, short
+    /// or out.
+    fn is_regressor(&self) -> bool {
+        false
+    }
 }
 
 /// Errors that can happen during machine learning training or prediction.
 /// Mostly just string wrappers.
 
-#[derive(Display)]
+#[derive(Debug, Display)]
 pub enum Error {
     #[display(fmt = "Error fitting model: {}", _0)]
     FitError(String),
@@ -1,4 +1,304 @@
 //! Wrapped implementations of various machine learning algorithms.
 
 pub mod decisiontree;
+pub mod gbt;
+pub mod linear;
+pub mod logistic;
 pub mod mlmodel;
+pub mod randomforest;
+pub mod regressiontree;
+
+use decisiontree::DecisionTreeClassifier;
+use gbt::GradientBoostedTrees;
+use linear::LinearRegression;
+use logistic::LogisticRegression;
+use mlmodel::{Error, MachineLearningAlgorithm};
+use randomforest::RandomForestClassifier;
+use regressiontree::DecisionTreeRegressor;
+use rustlearn::{ensemble::random_forest, linear_models::sgdclassifier, trees::decision_tree};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+/// Deepest gradient-boosted trees grow unless told otherwise. Boosting works
+/// best with many shallow trees.
+pub const DEFAULT_BOOSTED_DEPTH: usize = 3;
+
+/// The kinds of learner a MachineLearningTrader can be trained with. The
+/// classifiers learn whether to go long, short or out, and the regressors
+/// learn the forward return.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    #[default]
+    DecisionTree,
+    RandomForest,
+    LogisticRegression,
+    GradientBoosting,
+    LinearRegression,
+    RidgeRegression,
+    RegressionTree,
+}
+
+impl Algorithm {
+    /// Whether the algorithm predicts forward returns rather than classes.
+    pub fn is_regression(&self) -> bool {
+        matches!(
+            self,
+            Self::LinearRegression | Self::RidgeRegression | Self::RegressionTree
+        )
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "decision_tree" | "tree" => Ok(Self::DecisionTree),
+            "random_forest" | "forest" => Ok(Self::RandomForest),
+            "logistic_regression" | "logistic" => Ok(Self::LogisticRegression),
+            "gradient_boosting" | "gbt" => Ok(Self::GradientBoosting),
+            "linear_regression" | "linear" => Ok(Self::LinearRegression),
+            "ridge_regression" | "ridge" => Ok(Self::RidgeRegression),
+            "regression_tree" => Ok(Self::RegressionTree),
+            _ => Err(format!(
+                "Unknown algorithm '{}' (supported: decision_tree, random_forest, \
+                 logistic_regression, gradient_boosting, linear_regression, \
+                 ridge_regression, regression_tree)",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::DecisionTree => "decision_tree",
+            Self::RandomForest => "random_forest",
+            Self::LogisticRegression => "logistic_regression",
+            Self::GradientBoosting => "gradient_boosting",
+            Self::LinearRegression => "linear_regression",
+            Self::RidgeRegression => "ridge_regression",
+            Self::RegressionTree => "regression_tree",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Settings for the learners. Each learner ignores the ones that don't apply
+/// to it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hyperparameters {
+    /// Deepest a tree may grow. Unlimited if None, except for gradient
+    /// boosting which defaults to DEFAULT_BOOSTED_DEPTH.
+    pub max_depth: Option<usize>,
+
+    /// Fewest training rows a tree node needs to be split.
+    pub min_samples_split: usize,
+
+    /// Fewest training rows each side of a split must get. Only gradient
+    /// boosting honours it (rustlearn's trees have no such setting).
+    pub min_samples_leaf: usize,
+
+    /// Trees in a random forest, or rounds of gradient boosting.
+    pub num_trees: usize,
+
+    /// Step size of gradient boosting and of logistic regression.
+    pub learning_rate: f64,
+
+    /// How strongly ridge regression pulls its weights towards 0.
+    #[serde(default = "default_ridge_penalty")]
+    pub ridge_penalty: f64,
+}
+
+fn default_ridge_penalty() -> f64 {
+    1.0
+}
+
+impl Default for Hyperparameters {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            min_samples_split: 2,
+            min_samples_leaf: 1,
+            num_trees: 100,
+            learning_rate: 0.1,
+            ridge_penalty: default_ridge_penalty(),
+        }
+    }
+}
+
+/// Any of the supported learners, so that a trained model can be saved and
+/// loaded without knowing ahead of time which kind it is.
+#[derive(Serialize, Deserialize)]
+pub enum Learner {
+    DecisionTree(DecisionTreeClassifier),
+    RandomForest(RandomForestClassifier),
+    LogisticRegression(LogisticRegression),
+    GradientBoosting(GradientBoostedTrees),
+    LinearRegression(LinearRegression),
+    RidgeRegression(LinearRegression),
+    RegressionTree(DecisionTreeRegressor),
+}
+
+impl Learner {
+    /// An untrained `algorithm` for rows of `features` features.
+    pub fn new(algorithm: Algorithm, params: &Hyperparameters, features: usize) -> Self {
+        let mut tree = decision_tree::Hyperparameters::new(features);
+        tree.min_samples_split(params.min_samples_split);
+        if let Some(depth) = params.max_depth {
+            tree.max_depth(depth);
+        }
+
+        match algorithm {
+            Algorithm::DecisionTree => {
+                Self::DecisionTree(DecisionTreeClassifier::new(tree.one_vs_rest()))
+            }
+            Algorithm::RandomForest => Self::RandomForest(RandomForestClassifier::new(
+                random_forest::Hyperparameters::new(tree, params.num_trees).one_vs_rest(),
+            )),
+            Algorithm::LogisticRegression => Self::LogisticRegression(LogisticRegression::new(
+                sgdclassifier::Hyperparameters::new(features)
+                    .learning_rate(params.learning_rate as f32)
+                    .one_vs_rest(),
+            )),
+            Algorithm::GradientBoosting => Self::GradientBoosting(GradientBoostedTrees::new(
+                params.num_trees,
+                params.max_depth.unwrap_or(DEFAULT_BOOSTED_DEPTH),
+                params.min_samples_split,
+                params.min_samples_leaf,
+                params.learning_rate as f32,
+            )),
+            Algorithm::LinearRegression => Self::LinearRegression(LinearRegression::new(0.0)),
+            Algorithm::RidgeRegression => {
+                Self::RidgeRegression(LinearRegression::new(params.ridge_penalty))
+            }
+            Algorithm::RegressionTree => Self::RegressionTree(DecisionTreeRegressor::new(
+                params.max_depth,
+                params.min_samples_split,
+                params.min_samples_leaf,
+            )),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Self::DecisionTree(_) => Algorithm::DecisionTree,
+            Self::RandomForest(_) => Algorithm::RandomForest,
+            Self::LogisticRegression(_) => Algorithm::LogisticRegression,
+            Self::GradientBoosting(_) => Algorithm::GradientBoosting,
+            Self::LinearRegression(_) => Algorithm::LinearRegression,
+            Self::RidgeRegression(_) => Algorithm::RidgeRegression,
+            Self::RegressionTree(_) => Algorithm::RegressionTree,
+        }
+    }
+
+    fn inner(&self) -> &dyn MachineLearningAlgorithm<Vec<f32>> {
+        match self {
+            Self::DecisionTree(l) => l,
+            Self::RandomForest(l) => l,
+            Self::LogisticRegression(l) => l,
+            Self::GradientBoosting(l) => l,
+            Self::LinearRegression(l) => l,
+            Self::RidgeRegression(l) => l,
+            Self::RegressionTree(l) => l,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn MachineLearningAlgorithm<Vec<f32>> {
+        match self {
+            Self::DecisionTree(l) => l,
+            Self::RandomForest(l) => l,
+            Self::LogisticRegression(l) => l,
+            Self::GradientBoosting(l) => l,
+            Self::LinearRegression(l) => l,
+            Self::RidgeRegression(l) => l,
+            Self::RegressionTree(l) => l,
+        }
+    }
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for Learner {
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        self.inner_mut().fit(x, y)
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        self.inner().predict(x)
+    }
+
+    fn is_regressor(&self) -> bool {
+        self.inner().is_regressor()
+    }
+}
+
+impl Display for Learner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.algorithm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_algorithm_learns() {
+        // Long above zero, short below
+        let x: Vec<Vec<f32>> = (-10..10).map(|i| vec![i as f32 + 0.5]).collect();
+        let y: Vec<f32> = x.iter().map(|r| r[0].signum()).collect();
+
+        for algorithm in &[
+            Algorithm::DecisionTree,
+            Algorithm::RandomForest,
+            Algorithm::LogisticRegression,
+            Algorithm::GradientBoosting,
+        ] {
+            let params = Hyperparameters {
+                num_trees: 10,
+                learning_rate: 0.5,
+                ..Hyperparameters::default()
+            };
+            let mut learner = Learner::new(*algorithm, &params, 1);
+            assert_eq!(learner.algorithm(), *algorithm);
+            learner.fit(&x, &y).unwrap();
+            assert_eq!(
+                learner.predict(&vec![vec![-8.0], vec![8.0]]).unwrap(),
+                vec![vec![-1.0], vec![1.0]],
+                "{} didn't learn",
+                algorithm
+            );
+            assert_eq!(algorithm.to_string().parse(), Ok(*algorithm));
+        }
+
+        assert_eq!("gbt".parse(), Ok(Algorithm::GradientBoosting));
+        assert!(!Algorithm::GradientBoosting.is_regression());
+    }
+
+    #[test]
+    fn every_regressor_learns() {
+        // Returns rise with the feature
+        let x: Vec<Vec<f32>> = (-10..10).map(|i| vec![i as f32]).collect();
+        let y: Vec<f32> = x.iter().map(|r| r[0] / 100.0).collect();
+
+        for algorithm in &[
+            Algorithm::LinearRegression,
+            Algorithm::RidgeRegression,
+            Algorithm::RegressionTree,
+        ] {
+            let mut learner = Learner::new(*algorithm, &Hyperparameters::default(), 1);
+            assert!(algorithm.is_regression() && learner.is_regressor());
+            learner.fit(&x, &y).unwrap();
+            let predicted = learner.predict(&vec![vec![-8.0], vec![8.0]]).unwrap();
+            assert!(
+                predicted[0][0] < -0.05 && predicted[1][0] > 0.05,
+                "{} didn't learn: {:?}",
+                algorithm,
+                predicted
+            );
+            assert_eq!(algorithm.to_string().parse(), Ok(*algorithm));
+        }
+        assert_eq!("ridge".parse(), Ok(Algorithm::RidgeRegression));
+        assert!("svm".parse::<Algorithm>().is_err());
+    }
+}
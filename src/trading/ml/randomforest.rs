@@ -0,0 +1,29 @@
+//! A One-Vs-Rest random forest using rustlearn.
+
+use super::{
+    decisiontree::{fit, predict},
+    mlmodel::{Error, MachineLearningAlgorithm},
+};
+use rustlearn::{ensemble::random_forest::RandomForest, multiclass::OneVsRestWrapper};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct RandomForestClassifier {
+    learner: OneVsRestWrapper<RandomForest>,
+}
+
+impl RandomForestClassifier {
+    pub fn new(learner: OneVsRestWrapper<RandomForest>) -> Self {
+        Self { learner }
+    }
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for RandomForestClassifier {
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        fit(&mut self.learner, x, y)
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        predict(&self.learner, x)
+    }
+}
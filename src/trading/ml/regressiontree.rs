@@ -0,0 +1,223 @@
+//! Regression trees that split rows to minimize squared error. Gradient
+//! boosting grows them over gradients, and on their own they predict forward
+//! returns.
+
+use super::mlmodel::{Error, MachineLearningAlgorithm};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(super) enum RegressionTree {
+    Leaf(f32),
+    Split {
+        feature: usize,
+        /// Rows with the feature at or below this go left.
+        threshold: f32,
+        left: Box<RegressionTree>,
+        right: Box<RegressionTree>,
+    },
+}
+
+impl RegressionTree {
+    pub(super) fn predict(&self, row: &[f32]) -> f32 {
+        match self {
+            Self::Leaf(value) => *value,
+            Self::Split {
+                feature,
+                threshold,
+                left,
+                right,
+            } => match row.get(*feature) {
+                Some(x) if x <= threshold => left.predict(row),
+                _ => right.predict(row),
+            },
+        }
+    }
+}
+
+/// What a tree is fitted to: the gradient and hessian of the loss for each
+/// row.
+pub(super) struct Targets<'a> {
+    pub x: &'a [Vec<f32>],
+    pub gradients: &'a [f32],
+    pub hessians: &'a [f32],
+}
+
+/// How far a tree may grow.
+pub(super) struct TreeShape {
+    pub max_depth: usize,
+    pub min_samples_split: usize,
+    pub min_samples_leaf: usize,
+}
+
+impl TreeShape {
+    /// Builds a tree over `rows` that minimizes the squared error of the
+    /// gradients, with Newton steps for leaves.
+    pub(super) fn build(
+        &self,
+        targets: &Targets,
+        rows: Vec<usize>,
+        depth: usize,
+    ) -> RegressionTree {
+        let leaf = || {
+            let g: f32 = rows.iter().map(|&i| targets.gradients[i]).sum();
+            let h: f32 = rows.iter().map(|&i| targets.hessians[i]).sum();
+            RegressionTree::Leaf(if h > f32::EPSILON { g / h } else { 0.0 })
+        };
+        if depth >= self.max_depth || rows.len() < self.min_samples_split {
+            return leaf();
+        }
+
+        match best_split(targets, &rows, self.min_samples_leaf) {
+            Some((feature, threshold)) => {
+                let (left, right) = rows
+                    .into_iter()
+                    .partition(|&i| targets.x[i][feature] <= threshold);
+                RegressionTree::Split {
+                    feature,
+                    threshold,
+                    left: Box::new(self.build(targets, left, depth + 1)),
+                    right: Box::new(self.build(targets, right, depth + 1)),
+                }
+            }
+            None => leaf(),
+        }
+    }
+}
+
+/// The feature and threshold that most reduce the squared error of the
+/// gradients of `rows`, if any split that leaves at least `min_leaf` rows on
+/// each side does.
+fn best_split(targets: &Targets, rows: &[usize], min_leaf: usize) -> Option<(usize, f32)> {
+    let features = targets.x.get(rows[0])?.len();
+    let n = rows.len() as f32;
+    let total: f32 = rows.iter().map(|&i| targets.gradients[i]).sum();
+    let unsplit = total * total / n;
+
+    let mut best: Option<(usize, f32)> = None;
+    let mut best_gain = 0.0;
+    for feature in 0..features {
+        let mut sorted = rows.to_vec();
+        sorted.sort_by(|&a, &b| targets.x[a][feature].total_cmp(&targets.x[b][feature]));
+
+        let mut left = 0.0;
+        for (count, pair) in sorted.windows(2).enumerate() {
+            left += targets.gradients[pair[0]];
+            let (here, next) = (targets.x[pair[0]][feature], targets.x[pair[1]][feature]);
+            if here == next || count + 1 < min_leaf || rows.len() - (count + 1) < min_leaf {
+                continue;
+            }
+            let (nl, nr) = ((count + 1) as f32, n - (count + 1) as f32);
+            let right = total - left;
+            let gain = left * left / nl + right * right / nr - unsplit;
+            if gain > best_gain {
+                best_gain = gain;
+                best = Some((feature, (here + next) / 2.0));
+            }
+        }
+    }
+
+    best
+}
+
+/// A single regression tree, whose leaves predict the mean target of their
+/// training rows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DecisionTreeRegressor {
+    max_depth: Option<usize>,
+    min_samples_split: usize,
+    min_samples_leaf: usize,
+    tree: Option<RegressionTree>,
+}
+
+impl DecisionTreeRegressor {
+    /// Grows a tree up to `max_depth` deep (or as deep as it can if None).
+    /// Nodes with fewer than `min_samples_split` rows aren't split, and
+    /// neither side of a split gets fewer than `min_samples_leaf` rows.
+    pub fn new(
+        max_depth: Option<usize>,
+        min_samples_split: usize,
+        min_samples_leaf: usize,
+    ) -> Self {
+        Self {
+            max_depth,
+            min_samples_split: min_samples_split.max(2),
+            min_samples_leaf: min_samples_leaf.max(1),
+            tree: None,
+        }
+    }
+}
+
+impl MachineLearningAlgorithm<Vec<f32>> for DecisionTreeRegressor {
+    fn fit(&mut self, x: &Vec<Vec<f32>>, y: &Vec<f32>) -> Result<(), Error> {
+        if x.is_empty() || x.len() != y.len() {
+            return Err(Error::FitError(format!(
+                "Got {} rows of features for {} targets",
+                x.len(),
+                y.len()
+            )));
+        }
+
+        // With a hessian of 1 the leaves are the means of their targets.
+        let hessians = vec![1.0; y.len()];
+        let targets = Targets {
+            x,
+            gradients: y,
+            hessians: &hessians,
+        };
+        let shape = TreeShape {
+            max_depth: self.max_depth.unwrap_or(usize::MAX),
+            min_samples_split: self.min_samples_split,
+            min_samples_leaf: self.min_samples_leaf,
+        };
+        self.tree = Some(shape.build(&targets, (0..x.len()).collect(), 0));
+        Ok(())
+    }
+
+    fn predict(&self, x: &Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, Error> {
+        let tree = self
+            .tree
+            .as_ref()
+            .ok_or_else(|| Error::PredictionError("Model has not been fitted".into()))?;
+        Ok(x.iter().map(|row| vec![tree.predict(row)]).collect())
+    }
+
+    fn is_regressor(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_steps() {
+        // 1 below zero, 3 above, with a noise feature
+        let x: Vec<Vec<f32>> = (-10..10)
+            .map(|i| vec![i as f32, (i * 7 % 3) as f32])
+            .collect();
+        let y: Vec<f32> = x
+            .iter()
+            .map(|row| if row[0] < 0.0 { 1.0 } else { 3.0 })
+            .collect();
+
+        let mut tree = DecisionTreeRegressor::new(None, 2, 1);
+        assert!(tree.predict(&x).is_err());
+        tree.fit(&x, &y).unwrap();
+        assert_eq!(
+            tree.predict(&vec![vec![-50.0, 0.0], vec![50.0, 0.0]])
+                .unwrap(),
+            vec![vec![1.0], vec![3.0]]
+        );
+
+        // Too shallow to split predicts the mean
+        let mut stump = DecisionTreeRegressor::new(Some(0), 2, 1);
+        stump.fit(&x, &y).unwrap();
+        assert_eq!(stump.predict(&x[..1].to_vec()).unwrap(), vec![vec![2.0]]);
+
+        let bytes = bincode::serialize(&tree).unwrap();
+        let loaded: DecisionTreeRegressor = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded, tree);
+        assert!(tree.fit(&x, &y[1..].to_vec()).is_err());
+    }
+}
@@ -2,8 +2,15 @@
 
 pub mod buyandhold;
 pub mod dtmodel;
+pub mod ensemble;
+pub mod exits;
+pub mod features;
+pub mod labeling;
 pub mod manual;
+pub mod modelfile;
 pub mod position;
+pub mod qlearner;
+pub mod sizing;
 pub mod tradingmodel;
 pub use position::*;
 pub mod ml;
@@ -16,4 +23,6 @@ pub enum SupportedTradingModel {
     ManualTradingAlgo,
     BuyAndHold,
     MachineLearningModel,
+    QLearningModel,
+    Ensemble,
 }
@@ -0,0 +1,388 @@
+//! The format of the model files written by `techalyzer train` and
+//! `techalyzer train-q-learner`. A file starts with a magic number and a
+//! format version, followed by a JSON header that says what kind of model it
+//! is, describes how it was trained and checksums it, and then the model
+//! itself as bincode.
+//!
+//! ```text
+//! +----------+----------------+------------------+-------------+---------+
+//! | TCHLZMDL | version (u32)  | header len (u64) | header JSON | model   |
+//! +----------+----------------+------------------+-------------+---------+
+//! ```
+//!
+//! Integers are little-endian.
+
+use super::{
+    dtmodel::{DecisionThreshold, Horizon},
+    labeling::Labeler,
+    ml::{Algorithm, Hyperparameters},
+    qlearner::QHyperparameters,
+    sizing::SizingPolicy,
+};
+use crate::{date::Date, indicators::IndicatorSpec};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{convert::TryInto, fmt::Display};
+use thiserror::Error;
+
+/// Marks the start of a model file.
+pub const MAGIC: &[u8; 8] = b"TCHLZMDL";
+
+/// Version of the model file format written by this version of Techalyzer.
+/// Bumped whenever files written by older versions can no longer be read.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Things that can go wrong reading or writing a model file.
+#[derive(Debug, Error, PartialEq)]
+pub enum ModelFileError {
+    #[error(
+        "Not a Techalyzer model file (files saved before model file format \
+         version 1 can't be read, train the model again)"
+    )]
+    NotAModelFile,
+
+    #[error(
+        "Model file format version {0} is not supported (this version of \
+         Techalyzer reads version {}), train the model again",
+        FORMAT_VERSION
+    )]
+    UnsupportedVersion(u32),
+
+    #[error("Model file is corrupt: checksum is {found:016x}, expected {expected:016x}")]
+    ChecksumMismatch { expected: u64, found: u64 },
+
+    #[error("Model file is corrupt: {0}")]
+    Corrupt(String),
+
+    #[error(
+        "Model file is corrupt: its model computes features [{}], but it was trained on [{}]",
+        found.join(", "),
+        expected.join(", ")
+    )]
+    FeatureMismatch {
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+
+    #[error("Expected a {expected} model, but the model file holds a {found} model")]
+    WrongKind {
+        expected: ModelKind,
+        found: ModelKind,
+    },
+
+    #[error("Could not write model file: {0}")]
+    Serialization(String),
+}
+
+/// How a model was trained, as recorded in its model file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelMetadata {
+    /// Symbol of the security the model was trained on.
+    pub symbol: String,
+
+    /// First and last days the model was trained on.
+    pub train_start: Option<Date>,
+    pub train_end: Option<Date>,
+
+    /// Number of days the model was trained on.
+    pub training_days: usize,
+
+    pub horizon: Horizon,
+    pub decision_threshold: DecisionThreshold,
+
+    /// How training days were labelled.
+    pub labeler: Labeler,
+
+    /// The indicators the model's features come from, in order.
+    pub signal_generators: Vec<IndicatorSpec>,
+
+    pub algorithm: Algorithm,
+    pub hyperparameters: Hyperparameters,
+    pub sizing: SizingPolicy,
+
+    /// Version of Techalyzer the model was trained with.
+    pub crate_version: String,
+
+    /// When the model was trained.
+    pub created: Date,
+
+    /// Names of the features the model was trained on, in order (see
+    /// `FeaturePipeline::names`).
+    pub features: Vec<String>,
+}
+
+/// How a `QLearningTrader` was trained, as recorded in its model file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QLearningMetadata {
+    /// Symbol of the security the model was trained on.
+    pub symbol: String,
+
+    /// First and last days the model was trained on.
+    pub train_start: Option<Date>,
+    pub train_end: Option<Date>,
+
+    /// Number of days the model was trained on.
+    pub training_days: usize,
+
+    /// The indicators the model's states come from, in order.
+    pub signal_generators: Vec<IndicatorSpec>,
+
+    pub hyperparameters: QHyperparameters,
+    pub sizing: SizingPolicy,
+
+    /// Version of Techalyzer the model was trained with.
+    pub crate_version: String,
+
+    /// When the model was trained.
+    pub created: Date,
+}
+
+/// The kinds of model a model file can hold.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ModelKind {
+    MachineLearning,
+    QLearning,
+}
+
+impl Display for ModelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModelKind::MachineLearning => write!(f, "machine learning"),
+            ModelKind::QLearning => write!(f, "Q-learning"),
+        }
+    }
+}
+
+/// How the model in a model file was trained, by kind of model.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Metadata {
+    MachineLearning(ModelMetadata),
+    QLearning(QLearningMetadata),
+}
+
+impl Metadata {
+    pub fn kind(&self) -> ModelKind {
+        match self {
+            Metadata::MachineLearning(_) => ModelKind::MachineLearning,
+            Metadata::QLearning(_) => ModelKind::QLearning,
+        }
+    }
+}
+
+impl From<ModelMetadata> for Metadata {
+    fn from(m: ModelMetadata) -> Self {
+        Metadata::MachineLearning(m)
+    }
+}
+
+impl From<QLearningMetadata> for Metadata {
+    fn from(m: QLearningMetadata) -> Self {
+        Metadata::QLearning(m)
+    }
+}
+
+/// Everything in a model file but the model.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelHeader {
+    pub metadata: Metadata,
+
+    /// FNV-1a hash of the serialized model.
+    pub checksum: u64,
+}
+
+/// Serializes `model` into a model file described by `metadata`.
+pub fn to_bytes<M: Serialize>(
+    metadata: impl Into<Metadata>,
+    model: &M,
+) -> Result<Vec<u8>, ModelFileError> {
+    let serialization = |e: &dyn ToString| ModelFileError::Serialization(e.to_string());
+    let payload = bincode::serialize(model).map_err(|e| serialization(&e))?;
+    let header = ModelHeader {
+        metadata: metadata.into(),
+        checksum: checksum(&payload),
+    };
+    let header = serde_json::to_vec(&header).map_err(|e| serialization(&e))?;
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 12 + header.len() + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Reads the header of a model file, and checks the model against its
+/// checksum. Returns the header and the serialized model.
+pub fn read_header(bytes: &[u8]) -> Result<(ModelHeader, &[u8]), ModelFileError> {
+    let truncated = || ModelFileError::Corrupt("file is truncated".to_string());
+    let rest = bytes
+        .strip_prefix(&MAGIC[..])
+        .ok_or(ModelFileError::NotAModelFile)?;
+
+    let (version, rest) = split(rest, 4).ok_or_else(truncated)?;
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(ModelFileError::UnsupportedVersion(version));
+    }
+
+    let (length, rest) = split(rest, 8).ok_or_else(truncated)?;
+    let length = u64::from_le_bytes(length.try_into().unwrap()) as usize;
+    let (header, payload) = split(rest, length).ok_or_else(truncated)?;
+    let header: ModelHeader = serde_json::from_slice(header)
+        .map_err(|e| ModelFileError::Corrupt(format!("unreadable header ({})", e)))?;
+
+    let found = checksum(payload);
+    if found != header.checksum {
+        return Err(ModelFileError::ChecksumMismatch {
+            expected: header.checksum,
+            found,
+        });
+    }
+
+    Ok((header, payload))
+}
+
+/// Reads a model file written by `to_bytes`, which must hold a model of the
+/// given kind.
+pub fn from_bytes<M: DeserializeOwned>(
+    bytes: &[u8],
+    kind: ModelKind,
+) -> Result<(ModelHeader, M), ModelFileError> {
+    let (header, payload) = read_header(bytes)?;
+    if header.metadata.kind() != kind {
+        return Err(ModelFileError::WrongKind {
+            expected: kind,
+            found: header.metadata.kind(),
+        });
+    }
+    let model = bincode::deserialize(payload)
+        .map_err(|e| ModelFileError::Corrupt(format!("unreadable model ({})", e)))?;
+    Ok((header, model))
+}
+
+/// The first `n` bytes and the rest, if there are `n`.
+fn split(bytes: &[u8], n: usize) -> Option<(&[u8], &[u8])> {
+    if bytes.len() < n {
+        None
+    } else {
+        Some(bytes.split_at(n))
+    }
+}
+
+/// 64-bit FNV-1a, which is stable across platforms and Rust versions (unlike
+/// std's hashers).
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators::SupportedIndicators;
+
+    fn metadata() -> ModelMetadata {
+        ModelMetadata {
+            symbol: "jpm".to_string(),
+            train_start: Some(Date::from_ymd(2020, 1, 2)),
+            train_end: Some(Date::from_ymd(2020, 5, 18)),
+            training_days: 94,
+            horizon: Horizon(10),
+            decision_threshold: DecisionThreshold(0.03),
+            labeler: Labeler::TripleBarrier {
+                profit_take: 2.0,
+                stop_loss: 1.0,
+            },
+            signal_generators: vec![SupportedIndicators::MACD.into()],
+            algorithm: Algorithm::RandomForest,
+            hyperparameters: Hyperparameters::default(),
+            sizing: SizingPolicy::default(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created: Date::from_ymd(2020, 6, 1),
+            features: vec!["MACD".to_string()],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let model = vec![1.5_f32, -2.0, 3.25];
+        let bytes = to_bytes(metadata(), &model).unwrap();
+        let (header, loaded): (ModelHeader, Vec<f32>) =
+            from_bytes(&bytes, ModelKind::MachineLearning).unwrap();
+        assert_eq!(header.metadata, Metadata::MachineLearning(metadata()));
+        assert_eq!(loaded, model);
+        assert_eq!(checksum(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(checksum(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn bad_files() {
+        let model = vec![1.5_f32, -2.0, 3.25];
+        let bytes = to_bytes(metadata(), &model).unwrap();
+        let read = |bytes: &[u8]| {
+            from_bytes::<Vec<f32>>(bytes, ModelKind::MachineLearning).map(|(_, m)| m)
+        };
+
+        // A bare model, as older versions saved them
+        let bare = bincode::serialize(&model).unwrap();
+        assert_eq!(read(&bare), Err(ModelFileError::NotAModelFile));
+
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = 99;
+        assert_eq!(read(&newer), Err(ModelFileError::UnsupportedVersion(99)));
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 0xff;
+        assert!(matches!(
+            read(&flipped),
+            Err(ModelFileError::ChecksumMismatch { .. })
+        ));
+
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(ModelFileError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            read(&bytes[..MAGIC.len() + 6]),
+            Err(ModelFileError::Corrupt(_))
+        ));
+
+        // The checksum matches, but it isn't the model that was asked for
+        assert!(matches!(
+            from_bytes::<Vec<String>>(&bytes, ModelKind::MachineLearning),
+            Err(ModelFileError::Corrupt(_))
+        ));
+
+        // Nor the kind of model
+        assert_eq!(
+            from_bytes::<Vec<f32>>(&bytes, ModelKind::QLearning).map(|(_, m)| m),
+            Err(ModelFileError::WrongKind {
+                expected: ModelKind::QLearning,
+                found: ModelKind::MachineLearning
+            })
+        );
+    }
+
+    #[test]
+    fn q_learning_metadata() {
+        let q = QLearningMetadata {
+            symbol: "jpm".to_string(),
+            train_start: Some(Date::from_ymd(2020, 1, 2)),
+            train_end: Some(Date::from_ymd(2020, 5, 18)),
+            training_days: 94,
+            signal_generators: vec![SupportedIndicators::MACD.into()],
+            hyperparameters: QHyperparameters::default(),
+            sizing: SizingPolicy::default(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            created: Date::from_ymd(2020, 6, 1),
+        };
+        let bytes = to_bytes(q.clone(), &vec![0.5_f64]).unwrap();
+        let (header, _) = read_header(&bytes).unwrap();
+        assert_eq!(header.metadata, Metadata::QLearning(q));
+
+        let json = serde_json::to_value(&header.metadata).unwrap();
+        assert_eq!(json["kind"], "QLearning");
+    }
+}
@@ -5,13 +5,13 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 /// A trade with the position (long/short/out) and number of shares commit to
-/// the trade.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// the trade. Shares may be fractional (e.g. 0.25 of a bitcoin).
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Position {
     /// Buy N shares.
-    Long(u64),
+    Long(f64),
     /// Short N shares.
-    Short(u64),
+    Short(f64),
     /// Close position and hold nothing.
     Out,
     /// Hold current position.
@@ -69,8 +69,8 @@ mod tests {
 
     #[test]
     fn entry_and_exit() {
-        let long = Position::Long(1);
-        let short = Position::Short(1);
+        let long = Position::Long(1.0);
+        let short = Position::Short(1.0);
         let out = Position::Out;
         let hold = Position::Hold;
 
@@ -0,0 +1,396 @@
+//! A reinforcement learning trader in the style of the ML4T strategy learner.
+//! Each day's state is the signals of its signal generators, each cut into
+//! bins, plus the position it is holding. Q-learning learns the value of
+//! going long, short or out in each state from the daily returns those
+//! positions earn, over repeated passes through the training prices, and can
+//! speed that up by replaying remembered experience (Dyna-Q).
+
+use super::{
+    dtmodel::Trained,
+    tradingmodel::{Trades, TradingModel},
+};
+use crate::{
+    marketdata::prices::Prices,
+    signals::{latest_signals, IndicatorSignal, SignalsIter},
+    trading::{labeling::Label, sizing::PositionSizer},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom, fmt::Display, marker::PhantomData};
+use thiserror::Error;
+
+/// Most states a QLearningTrader may have, to keep its table in memory.
+pub const MAX_STATES: usize = 1_000_000;
+
+/// Actions, which are also the position held after taking them.
+const OUT: usize = 0;
+const LONG: usize = 1;
+const SHORT: usize = 2;
+const ACTIONS: usize = 3;
+
+/// Things that can go wrong while training or using a QLearningTrader.
+#[derive(Debug, Error, PartialEq)]
+pub enum QLearningError {
+    #[error("No signal generators provided")]
+    NoSignalGeneratorsProvided,
+
+    #[error("Signals must be split into at least 1 bin")]
+    ZeroBins,
+
+    #[error("{0} signal generators with {1} bins each make too many states to learn")]
+    TooManyStates(usize, usize),
+
+    #[error("Need at least 2 days of prices to learn from, got {0}")]
+    NotEnoughData(usize),
+}
+
+/// Settings for learning.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QHyperparameters {
+    /// Bins each signal's range of -1 to 1 is split into.
+    pub bins: usize,
+
+    /// Learning rate: how far each update moves a Q-value.
+    pub alpha: f64,
+
+    /// Discount rate of future rewards.
+    pub gamma: f64,
+
+    /// Chance of taking a random action instead of the best one, at the
+    /// start of training.
+    pub random_action_rate: f64,
+
+    /// What the chance of a random action is multiplied by after each step.
+    pub random_action_decay: f64,
+
+    /// Passes over the training prices.
+    pub epochs: usize,
+
+    /// Updates replayed from remembered experience after each real step
+    /// (Dyna-Q planning). 0 for plain Q-learning.
+    pub dyna: usize,
+
+    /// Penalty for changing position, as a fraction of the price.
+    pub impact: f64,
+
+    /// Seed for the random actions, so that training is repeatable.
+    pub seed: u64,
+}
+
+impl Default for QHyperparameters {
+    fn default() -> Self {
+        Self {
+            bins: 5,
+            alpha: 0.2,
+            gamma: 0.9,
+            random_action_rate: 0.5,
+            random_action_decay: 0.999,
+            epochs: 50,
+            dyna: 0,
+            impact: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Learns when to be long, short or out from the discretized signals of
+/// technical indicators.
+#[derive(Serialize, Deserialize)]
+pub struct QLearningTrader<TrainedState = ()> {
+    signal_generators: Vec<Box<dyn SignalsIter>>,
+    hyperparameters: QHyperparameters,
+
+    /// Expected value of each action in each state.
+    q: Vec<[f64; ACTIONS]>,
+
+    /// Decides how many shares the bot will commit to a given trade
+    sizer: PositionSizer,
+
+    phantom: PhantomData<TrainedState>,
+}
+
+impl QLearningTrader {
+    /// At least one signal generator must be given.
+    pub fn new(
+        signal_generators: Vec<Box<dyn SignalsIter>>,
+        hyperparameters: QHyperparameters,
+        sizer: PositionSizer,
+    ) -> Result<Self, QLearningError> {
+        if signal_generators.is_empty() {
+            return Err(QLearningError::NoSignalGeneratorsProvided);
+        }
+        if hyperparameters.bins == 0 {
+            return Err(QLearningError::ZeroBins);
+        }
+
+        let n = signal_generators.len();
+        let states = u32::try_from(n)
+            .ok()
+            .and_then(|n| hyperparameters.bins.checked_pow(n))
+            .and_then(|s| s.checked_mul(ACTIONS))
+            .filter(|s| *s <= MAX_STATES)
+            .ok_or(QLearningError::TooManyStates(n, hyperparameters.bins))?;
+
+        Ok(Self {
+            signal_generators,
+            hyperparameters,
+            q: vec![[0.0; ACTIONS]; states],
+            sizer,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Learns from every day of `train_prices`. Consumes the caller and
+    /// returns a trained QLearningTrader.
+    pub fn train(
+        mut self,
+        train_prices: &Prices,
+    ) -> Result<QLearningTrader<Trained>, QLearningError> {
+        let closes: Vec<f64> = train_prices.iter().map(|(_, p)| *p).collect();
+        if closes.len() < 2 {
+            return Err(QLearningError::NotEnoughData(closes.len()));
+        }
+
+        let h = self.hyperparameters;
+        let signals = self.signals(train_prices);
+        let mut rng = StdRng::seed_from_u64(h.seed);
+        let mut random_action_rate = h.random_action_rate;
+        let mut experience = Vec::new();
+
+        for _ in 0..h.epochs {
+            let mut holding = OUT;
+            for (t, pair) in closes.windows(2).enumerate() {
+                let state = self.state(&signals[t], holding);
+                let action = if rng.gen::<f64>() < random_action_rate {
+                    rng.gen_range(0, ACTIONS)
+                } else {
+                    best_action(&self.q[state])
+                };
+                random_action_rate *= h.random_action_decay;
+
+                let reward = direction(action) * (pair[1] / pair[0] - 1.0)
+                    - h.impact * (direction(action) - direction(holding)).abs();
+                let next = self.state(&signals[t + 1], action);
+                self.update(state, action, reward, next);
+
+                // Dyna-Q: replay what has happened before as if it were
+                // happening again
+                if h.dyna > 0 {
+                    experience.push((state, action, reward, next));
+                    for _ in 0..h.dyna {
+                        let (s, a, r, n) = experience[rng.gen_range(0, experience.len())];
+                        self.update(s, a, r, n);
+                    }
+                }
+
+                holding = action;
+            }
+        }
+
+        Ok(QLearningTrader {
+            signal_generators: self.signal_generators,
+            hyperparameters: self.hyperparameters,
+            q: self.q,
+            sizer: self.sizer,
+            phantom: PhantomData,
+        })
+    }
+
+    fn update(&mut self, state: usize, action: usize, reward: f64, next: usize) {
+        let h = &self.hyperparameters;
+        let future = self.q[next]
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let q = &mut self.q[state][action];
+        *q = (1.0 - h.alpha) * *q + h.alpha * (reward + h.gamma * future);
+    }
+}
+
+impl<T> QLearningTrader<T> {
+    /// Sizes trades with `sizer` instead of the one the model was built with.
+    pub fn with_sizer(mut self, sizer: PositionSizer) -> Self {
+        self.sizer = sizer;
+        self
+    }
+
+    pub fn sizer(&self) -> &PositionSizer {
+        &self.sizer
+    }
+
+    pub fn hyperparameters(&self) -> &QHyperparameters {
+        &self.hyperparameters
+    }
+
+    /// The signals of every signal generator on each day of `prices`. The
+    /// generators are reset before and after.
+    fn signals(&mut self, prices: &Prices) -> Vec<Vec<f64>> {
+        self.signal_generators.iter_mut().for_each(|g| g.reset());
+        let signals = prices
+            .iter()
+            .map(|(_, price)| {
+                self.signal_generators
+                    .iter_mut()
+                    .map(|g| g.next(*price).0.into())
+                    .collect()
+            })
+            .collect();
+        self.signal_generators.iter_mut().for_each(|g| g.reset());
+        signals
+    }
+
+    /// Index of the state of holding `holding` on a day with `signals`.
+    fn state(&self, signals: &[f64], holding: usize) -> usize {
+        let bins = self.hyperparameters.bins;
+        let binned = signals.iter().fold(0, |state, signal| {
+            let bin = ((signal + 1.0) / 2.0 * bins as f64).floor().max(0.0) as usize;
+            state * bins + bin.min(bins - 1)
+        });
+        binned * ACTIONS + holding
+    }
+}
+
+/// The action with the highest Q-value, preferring to stay out on ties.
+fn best_action(q: &[f64; ACTIONS]) -> usize {
+    (0..ACTIONS).fold(OUT, |best, a| if q[a] > q[best] { a } else { best })
+}
+
+fn direction(action: usize) -> f64 {
+    match action {
+        LONG => 1.0,
+        SHORT => -1.0,
+        _ => 0.0,
+    }
+}
+
+impl<T> Display for QLearningTrader<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "QLearningTrader")
+    }
+}
+
+impl TradingModel for QLearningTrader<Trained> {
+    type Error = QLearningError;
+
+    fn get_trades(mut self, prices: &Prices) -> Result<Trades, Self::Error> {
+        self.sizer.reset();
+        let signals = self.signals(prices);
+
+        let mut trades = BTreeMap::new();
+        let mut holding = OUT;
+        for ((day, price), signals) in prices.iter().zip(&signals) {
+            self.sizer.next(*price);
+            let action = best_action(&self.q[self.state(signals, holding)]);
+
+            let side = match action {
+                LONG => Label::Long,
+                SHORT => Label::Short,
+                _ => Label::Out,
+            };
+            // The learner only picks a side, so it is always fully convinced.
+            trades.insert(*day, self.sizer.trade(side, *price, 1.0));
+            holding = action;
+        }
+
+        Ok(Trades::from(trades))
+    }
+
+    fn latest_signals(&mut self, prices: &Prices) -> Vec<IndicatorSignal> {
+        latest_signals(&mut self.signal_generators, prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        signals::{macdsignals::MACDSignalsIter, relativestrengthindexsignals::RSISignalsIter},
+        trading::Position,
+        Date,
+    };
+
+    fn prices(closes: impl Iterator<Item = f64>) -> Prices {
+        let start = Date::from_ymd(2012, 1, 2);
+        let closes = closes
+            .enumerate()
+            .map(|(i, c)| (start + chrono::Duration::days(i as i64), c))
+            .collect();
+        Prices::from_closes("jpm", closes)
+    }
+
+    fn trader(hyperparameters: QHyperparameters) -> QLearningTrader {
+        let gens: Vec<Box<dyn SignalsIter>> = vec![
+            Box::new(MACDSignalsIter::default()),
+            Box::new(RSISignalsIter::default()),
+        ];
+        QLearningTrader::new(gens, hyperparameters, PositionSizer::fixed(100.0)).unwrap()
+    }
+
+    /// Positions held once the indicators have had a few days to warm up.
+    fn positions(trades: &Trades) -> Vec<Position> {
+        trades.held().values().skip(10).cloned().collect()
+    }
+
+    #[test]
+    fn follows_the_trend() {
+        for dyna in &[0, 10] {
+            let h = QHyperparameters {
+                dyna: *dyna,
+                ..QHyperparameters::default()
+            };
+
+            let bull = prices((0..60).map(|i| 20.0 + i as f64));
+            let trades = trader(h).train(&bull).unwrap().get_trades(&bull).unwrap();
+            assert!(positions(&trades)
+                .iter()
+                .all(|p| *p == Position::Long(100.0)));
+
+            let bear = prices((0..60).map(|i| 80.0 - i as f64));
+            let trades = trader(h).train(&bear).unwrap().get_trades(&bear).unwrap();
+            assert!(positions(&trades)
+                .iter()
+                .all(|p| *p == Position::Short(100.0)));
+        }
+    }
+
+    #[test]
+    fn serializes() {
+        let bull = prices((0..60).map(|i| 20.0 + i as f64));
+        let trained = trader(QHyperparameters::default()).train(&bull).unwrap();
+        let bytes = bincode::serialize(&trained).unwrap();
+        let loaded: QLearningTrader<Trained> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.q, trained.q);
+        assert_eq!(
+            loaded.get_trades(&bull).unwrap(),
+            trained.get_trades(&bull).unwrap()
+        );
+    }
+
+    #[test]
+    fn bad_setup() {
+        let h = QHyperparameters::default();
+        let sizer = PositionSizer::fixed(100.0);
+        assert_eq!(
+            QLearningTrader::new(vec![], h, sizer.clone()).err(),
+            Some(QLearningError::NoSignalGeneratorsProvided)
+        );
+
+        let gens = || -> Vec<Box<dyn SignalsIter>> { vec![Box::new(RSISignalsIter::default())] };
+        let no_bins = QHyperparameters { bins: 0, ..h };
+        assert_eq!(
+            QLearningTrader::new(gens(), no_bins, sizer.clone()).err(),
+            Some(QLearningError::ZeroBins)
+        );
+        let huge = QHyperparameters { bins: 1 << 20, ..h };
+        assert_eq!(
+            QLearningTrader::new(gens(), huge, sizer.clone()).err(),
+            Some(QLearningError::TooManyStates(1, 1 << 20))
+        );
+
+        let one_day = prices(std::iter::once(20.0));
+        assert_eq!(
+            trader(h).train(&one_day).err(),
+            Some(QLearningError::NotEnoughData(1))
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! Position sizing: how many shares a trading model commits to a trade. The
+//! model decides the direction (long/short/out), a SizingPolicy decides the
+//! size, in multiples of a lot size (whole shares by default, or e.g. 0.0001
+//! for crypto).
+
+use super::{labeling::Label, Position};
+use crate::{backtester::statistics::TRADING_DAYS_PER_YEAR, util::clamp};
+use serde::{Deserialize, Serialize};
+use stats::{mean, stddev, variance};
+use std::{collections::VecDeque, convert::TryFrom, fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Default number of daily returns used to estimate volatility.
+const DEFAULT_VOL_WINDOW: u32 = 20;
+
+/// Default number of daily returns used to estimate the Kelly fraction.
+const DEFAULT_KELLY_WINDOW: u32 = 60;
+
+/// Ways of deciding how many shares to trade. Parsed from (and displayed as)
+/// `name:param1,param2`, e.g. `fixed:1000` or `vol:0.15,20`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SizingPolicy {
+    /// Always trade the same number of shares.
+    FixedShares(f64),
+
+    /// Commit a fraction of the capital to every position.
+    CashFraction(f64),
+
+    /// Size positions so their annualized volatility is `target`, estimated
+    /// from the last `window` daily returns. Never commits more than the
+    /// capital.
+    VolatilityTarget { target: f64, window: u32 },
+
+    /// Commit `fraction` of the Kelly-optimal share of the capital (mean
+    /// daily return over its variance), estimated from the last `window`
+    /// daily returns. Never commits more than the capital.
+    Kelly { fraction: f64, window: u32 },
+
+    /// Trade up to `max_shares`, scaled by the strength (0 to 1) of the
+    /// model's conviction.
+    SignalProportional(f64),
+}
+
+impl Default for SizingPolicy {
+    fn default() -> Self {
+        Self::FixedShares(1000.0)
+    }
+}
+
+/// Errors parsing a SizingPolicy.
+#[derive(Debug, Error, PartialEq)]
+pub enum SizingError {
+    #[error("Unknown sizing policy '{0}' (supported: fixed, cash, vol, kelly, signal)")]
+    UnknownPolicy(String),
+
+    #[error("Sizing policy '{policy}' takes at most {expected} parameter(s), got {found}")]
+    TooManyParameters {
+        policy: &'static str,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Invalid parameter '{value}' for sizing policy '{policy}': {reason}")]
+    InvalidParameter {
+        policy: &'static str,
+        value: String,
+        reason: &'static str,
+    },
+}
+
+impl SizingPolicy {
+    /// Name used in the `name:params` syntax.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::FixedShares(_) => "fixed",
+            Self::CashFraction(_) => "cash",
+            Self::VolatilityTarget { .. } => "vol",
+            Self::Kelly { .. } => "kelly",
+            Self::SignalProportional(_) => "signal",
+        }
+    }
+
+    /// How many daily returns the policy needs to look back on.
+    fn window(&self) -> usize {
+        match self {
+            Self::VolatilityTarget { window, .. } | Self::Kelly { window, .. } => *window as usize,
+            _ => 0,
+        }
+    }
+}
+
+impl FromStr for SizingPolicy {
+    type Err = SizingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = match s.find(':') {
+            Some(i) => (&s[..i], s[i + 1..].split(',').map(str::trim).collect()),
+            None => (s, Vec::new()),
+        };
+
+        let (policy, max_params): (&'static str, usize) = match name.trim() {
+            "fixed" | "fixed-shares" => ("fixed", 1),
+            "cash" | "cash-fraction" => ("cash", 1),
+            "vol" | "vol-target" => ("vol", 2),
+            "kelly" => ("kelly", 2),
+            "signal" => ("signal", 1),
+            other => return Err(SizingError::UnknownPolicy(other.to_string())),
+        };
+        if params.len() > max_params {
+            return Err(SizingError::TooManyParameters {
+                policy,
+                expected: max_params,
+                found: params.len(),
+            });
+        }
+
+        let invalid = |value: &str, reason| SizingError::InvalidParameter {
+            policy,
+            value: value.to_string(),
+            reason,
+        };
+        let shares = |i: usize, default: f64| match params.get(i) {
+            Some(v) => match v.parse::<f64>() {
+                Ok(s) if s >= 0.0 && s.is_finite() => Ok(s),
+                _ => Err(invalid(v, "expected a number of shares, 0 or more")),
+            },
+            None => Ok(default),
+        };
+        let fraction = |i: usize, default: f64| match params.get(i) {
+            Some(v) => match v.parse::<f64>() {
+                Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+                _ => Err(invalid(v, "expected a number greater than 0")),
+            },
+            None => Ok(default),
+        };
+        let window = |i: usize, default: u32| match params.get(i) {
+            Some(v) => match v.parse::<u32>() {
+                Ok(w) if w >= 2 => Ok(w),
+                _ => Err(invalid(v, "expected a window of at least 2 days")),
+            },
+            None => Ok(default),
+        };
+
+        Ok(match policy {
+            "fixed" => Self::FixedShares(shares(0, 1000.0)?),
+            "cash" => Self::CashFraction(fraction(0, 1.0)?),
+            "vol" => Self::VolatilityTarget {
+                target: fraction(0, 0.15)?,
+                window: window(1, DEFAULT_VOL_WINDOW)?,
+            },
+            "kelly" => Self::Kelly {
+                fraction: fraction(0, 0.5)?,
+                window: window(1, DEFAULT_KELLY_WINDOW)?,
+            },
+            _ => Self::SignalProportional(shares(0, 1000.0)?),
+        })
+    }
+}
+
+impl Display for SizingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.name())?;
+        match self {
+            Self::FixedShares(shares) | Self::SignalProportional(shares) => write!(f, "{}", shares),
+            Self::CashFraction(fraction) => write!(f, "{}", fraction),
+            Self::VolatilityTarget { target, window } => write!(f, "{},{}", target, window),
+            Self::Kelly { fraction, window } => write!(f, "{},{}", fraction, window),
+        }
+    }
+}
+
+impl TryFrom<String> for SizingPolicy {
+    type Error = SizingError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SizingPolicy> for String {
+    fn from(p: SizingPolicy) -> Self {
+        p.to_string()
+    }
+}
+
+/// Applies a SizingPolicy to a series of daily prices. Call `next` with each
+/// day's price before asking for `shares` (or a `trade`) on that day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSizer {
+    policy: SizingPolicy,
+
+    /// Cash the policy sizes positions against.
+    capital: f64,
+
+    /// Smallest number of shares that can be traded. Sizes computed from
+    /// capital or conviction are rounded to a multiple of it (unless it is 0,
+    /// in which case any fraction of a share can be traded).
+    #[serde(default = "default_lot_size")]
+    lot_size: f64,
+
+    /// Trading days in a year, for annualizing volatility. Set from the
+    /// calendar when the model is used, like the capital.
+    #[serde(skip, default = "default_days_per_year")]
+    days_per_year: f64,
+
+    /// Most recent daily returns, oldest first.
+    #[serde(skip)]
+    returns: VecDeque<f64>,
+
+    #[serde(skip)]
+    last_price: Option<f64>,
+
+    /// Side of the position sized by the last `trade`, if one is open.
+    #[serde(skip)]
+    side: Option<Label>,
+}
+
+fn default_lot_size() -> f64 {
+    1.0
+}
+
+fn default_days_per_year() -> f64 {
+    TRADING_DAYS_PER_YEAR
+}
+
+impl Default for PositionSizer {
+    /// Trades a fixed 1000 shares.
+    fn default() -> Self {
+        Self::fixed(1000.0)
+    }
+}
+
+impl PositionSizer {
+    /// Sizes positions with `policy`, against `capital` in cash.
+    pub fn new(policy: SizingPolicy, capital: f64) -> Self {
+        Self {
+            policy,
+            capital,
+            lot_size: default_lot_size(),
+            days_per_year: default_days_per_year(),
+            returns: VecDeque::new(),
+            last_price: None,
+            side: None,
+        }
+    }
+
+    /// Always trades `shares` shares, regardless of capital.
+    pub fn fixed(shares: f64) -> Self {
+        Self::new(SizingPolicy::FixedShares(shares), 0.0)
+    }
+
+    /// Trades in multiples of `lot_size` shares instead of whole shares.
+    /// Fractional lots (e.g. 0.0001) suit assets like crypto that are priced
+    /// too high to trade whole units of.
+    pub fn with_lot_size(mut self, lot_size: f64) -> Self {
+        self.lot_size = lot_size;
+        self
+    }
+
+    /// Annualizes volatility with `days_per_year` trading days in a year
+    /// (e.g. 365 for crypto) instead of TRADING_DAYS_PER_YEAR.
+    pub fn with_days_per_year(mut self, days_per_year: f64) -> Self {
+        self.days_per_year = days_per_year;
+        self
+    }
+
+    pub fn policy(&self) -> SizingPolicy {
+        self.policy
+    }
+
+    pub fn capital(&self) -> f64 {
+        self.capital
+    }
+
+    pub fn lot_size(&self) -> f64 {
+        self.lot_size
+    }
+
+    /// Forgets all prices seen so far, and any open position.
+    pub fn reset(&mut self) {
+        self.returns.clear();
+        self.last_price = None;
+        self.side = None;
+    }
+
+    /// Records the next day's price.
+    pub fn next(&mut self, price: f64) {
+        if let Some(last) = self.last_price {
+            self.returns.push_back(price / last - 1.0);
+            if self.returns.len() > self.policy.window() {
+                self.returns.pop_front();
+            }
+        }
+        self.last_price = Some(price);
+    }
+
+    /// How many shares to trade at `price`, given the `strength` (0 to 1) of
+    /// the model's conviction. Policies that estimate risk from past returns
+    /// trade nothing until they have at least two returns to go on.
+    pub fn shares(&self, price: f64, strength: f64) -> f64 {
+        let fraction_of_capital = match self.policy {
+            SizingPolicy::FixedShares(shares) => return shares,
+            SizingPolicy::SignalProportional(max_shares) => {
+                return self.in_lots(max_shares * clamp(strength, 0.0, 1.0).unwrap(), f64::round);
+            }
+            SizingPolicy::CashFraction(fraction) => fraction,
+            _ if self.returns.len() < 2 => return 0.0,
+            SizingPolicy::VolatilityTarget { target, .. } => {
+                let vol = stddev(self.returns.iter().cloned()) * self.days_per_year.sqrt();
+                if vol > 0.0 {
+                    (target / vol).min(1.0)
+                } else {
+                    1.0
+                }
+            }
+            SizingPolicy::Kelly { fraction, .. } => {
+                // The model picks the direction, so only the size of the edge
+                // matters.
+                let var = variance(self.returns.iter().cloned());
+                if var > 0.0 {
+                    (fraction * mean(self.returns.iter().cloned()).abs() / var).min(1.0)
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        if price <= 0.0 {
+            return 0.0;
+        }
+        // Never round up past the capital
+        self.in_lots(fraction_of_capital * self.capital / price, f64::floor)
+    }
+
+    /// The trade to make at `price` for a model that wants to be on `side`
+    /// with conviction `strength`. A position is only sized when it is
+    /// opened or switches side; while the model stays on the same side it is
+    /// held, so that changes in price, volatility or conviction don't resize
+    /// (and pay costs on) it every day.
+    pub fn trade(&mut self, side: Label, price: f64, strength: f64) -> Position {
+        if side == Label::Out {
+            self.side = None;
+            return Position::Out;
+        }
+        if self.side == Some(side) {
+            return Position::Hold;
+        }
+
+        let shares = self.shares(price, strength);
+        // Nothing was bought, so try again the next day.
+        self.side = if shares > 0.0 { Some(side) } else { None };
+        match side {
+            Label::Long => Position::Long(shares),
+            _ => Position::Short(shares),
+        }
+    }
+
+    /// Rounds `shares` to a whole number of lots with `round`.
+    fn in_lots(&self, shares: f64, round: fn(f64) -> f64) -> f64 {
+        if self.lot_size > 0.0 {
+            round(shares / self.lot_size) * self.lot_size
+        } else {
+            shares
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_policies() {
+        assert_eq!("fixed:500".parse(), Ok(SizingPolicy::FixedShares(500.0)));
+        assert_eq!("cash:0.5".parse(), Ok(SizingPolicy::CashFraction(0.5)));
+        assert_eq!(
+            "vol:0.1".parse(),
+            Ok(SizingPolicy::VolatilityTarget {
+                target: 0.1,
+                window: DEFAULT_VOL_WINDOW
+            })
+        );
+        assert_eq!(
+            "kelly:0.25,30".parse(),
+            Ok(SizingPolicy::Kelly {
+                fraction: 0.25,
+                window: 30
+            })
+        );
+        assert_eq!(
+            "signal".parse(),
+            Ok(SizingPolicy::SignalProportional(1000.0))
+        );
+
+        // Round trips through Display
+        let p = SizingPolicy::VolatilityTarget {
+            target: 0.2,
+            window: 10,
+        };
+        assert_eq!(p.to_string().parse(), Ok(p));
+
+        assert!(matches!(
+            "martingale".parse::<SizingPolicy>(),
+            Err(SizingError::UnknownPolicy(_))
+        ));
+        assert!(matches!(
+            "cash:0.5,2".parse::<SizingPolicy>(),
+            Err(SizingError::TooManyParameters { .. })
+        ));
+        assert!(matches!(
+            "vol:0.1,1".parse::<SizingPolicy>(),
+            Err(SizingError::InvalidParameter { .. })
+        ));
+    }
+
+    #[test]
+    fn fixed_cash_and_signal_sizes() {
+        assert_eq!(PositionSizer::fixed(10.0).shares(1e6, 0.0), 10.0);
+
+        let sizer = PositionSizer::new(SizingPolicy::CashFraction(0.5), 1000.0);
+        assert_eq!(sizer.shares(30.0, 1.0), 16.0);
+
+        let sizer = PositionSizer::new(SizingPolicy::SignalProportional(100.0), 0.0);
+        assert_eq!(sizer.shares(30.0, 0.25), 25.0);
+        assert_eq!(sizer.shares(30.0, 2.0), 100.0);
+    }
+
+    #[test]
+    fn trades_are_sized_on_entry() {
+        let mut sizer = PositionSizer::new(SizingPolicy::CashFraction(0.5), 1000.0);
+        assert_eq!(sizer.trade(Label::Long, 10.0, 1.0), Position::Long(50.0));
+        // Cheaper shares don't buy more of a position that's already open
+        assert_eq!(sizer.trade(Label::Long, 5.0, 1.0), Position::Hold);
+        assert_eq!(sizer.trade(Label::Short, 5.0, 1.0), Position::Short(100.0));
+        assert_eq!(sizer.trade(Label::Out, 5.0, 1.0), Position::Out);
+        assert_eq!(sizer.trade(Label::Short, 20.0, 1.0), Position::Short(25.0));
+
+        // Nothing affordable, so it's sized again the next day
+        sizer.reset();
+        assert_eq!(sizer.trade(Label::Long, 1e6, 1.0), Position::Long(0.0));
+        assert_eq!(sizer.trade(Label::Long, 10.0, 1.0), Position::Long(50.0));
+    }
+
+    #[test]
+    fn fractional_lots() {
+        assert_eq!("fixed:0.5".parse(), Ok(SizingPolicy::FixedShares(0.5)));
+        assert!("fixed:-1".parse::<SizingPolicy>().is_err());
+
+        // Whole shares can't be bought for more than the cash there is, lots can
+        let sizer = PositionSizer::new(SizingPolicy::CashFraction(1.0), 1000.0);
+        assert_eq!(sizer.shares(30_000.0, 1.0), 0.0);
+        let sizer = sizer.with_lot_size(0.25);
+        assert_eq!(sizer.shares(3_000.0, 1.0), 0.25);
+
+        let sizer =
+            PositionSizer::new(SizingPolicy::SignalProportional(1.0), 0.0).with_lot_size(0.5);
+        assert_eq!(sizer.shares(30.0, 0.7), 0.5);
+        assert_eq!(sizer.lot_size(), 0.5);
+
+        // Any fraction at all
+        let sizer = PositionSizer::new(SizingPolicy::CashFraction(1.0), 1000.0).with_lot_size(0.0);
+        assert_eq!(sizer.shares(4000.0, 1.0), 0.25);
+    }
+
+    #[test]
+    fn volatility_target_scales_down_volatile_prices() {
+        let policy = "vol:0.1,5".parse().unwrap();
+        let mut calm = PositionSizer::new(policy, 10_000.0);
+        let mut wild = PositionSizer::new(policy, 10_000.0);
+
+        // Nothing to go on yet
+        calm.next(100.0);
+        assert_eq!(calm.shares(100.0, 1.0), 0.0);
+
+        for (i, p) in [100.0, 100.1, 100.0, 100.1, 100.0].iter().enumerate() {
+            calm.next(*p);
+            wild.next(if i % 2 == 0 { 100.0 } else { 110.0 });
+        }
+
+        // Calm prices hit the cap of all the capital
+        assert_eq!(calm.shares(100.0, 1.0), 100.0);
+        assert!(wild.shares(100.0, 1.0) < 10.0);
+    }
+
+    #[test]
+    fn volatility_annualized_by_calendar() {
+        let policy = "vol:0.1,5".parse().unwrap();
+        let mut weekdays = PositionSizer::new(policy, 10_000.0).with_lot_size(0.0);
+        let mut every_day = weekdays.clone().with_days_per_year(365.0);
+        for p in &[100.0, 110.0, 100.0, 110.0, 100.0] {
+            weekdays.next(*p);
+            every_day.next(*p);
+        }
+
+        // The same daily moves are more volatile over a longer year
+        let ratio = every_day.shares(100.0, 1.0) / weekdays.shares(100.0, 1.0);
+        assert!((ratio - (252.0f64 / 365.0).sqrt()).abs() < 1e-9);
+    }
+}
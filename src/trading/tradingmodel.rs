@@ -1,17 +1,33 @@
 //! Interface for trading models to integrate with the rest of Techalyzer.
 
-use crate::trading::Position;
+use crate::trading::{exits::ExitReason, Position};
 use crate::Date;
-use crate::{error::TechalyzerError, marketdata::prices::Prices, util::TimeSeries};
+use crate::{
+    error::TechalyzerError, marketdata::prices::Prices, signals::IndicatorSignal, util::TimeSeries,
+};
 
 use serde::Serialize;
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
 /// Thin wrapper around a TimeSeries of Position.
-#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Clone, Debug, PartialEq, Default)]
 pub struct Trades {
     // TODO: use serde flatten and test everything + plotting scripts.
     pub trades: TimeSeries<Position>,
+
+    /// Why the position was closed on the days a stop-loss or other exit rule
+    /// closed it (see `trading::exits`), rather than the model itself.
+    #[serde(skip_serializing_if = "TimeSeries::is_empty")]
+    pub exits: TimeSeries<ExitReason>,
+}
+
+impl From<TimeSeries<Position>> for Trades {
+    fn from(trades: TimeSeries<Position>) -> Self {
+        Self {
+            trades,
+            exits: TimeSeries::new(),
+        }
+    }
 }
 
 impl Trades {
@@ -22,6 +38,62 @@ impl Trades {
     pub fn len(&self) -> usize {
         self.trades.len()
     }
+
+    /// Returns the last day of the trades and the position held going into
+    /// it. A trailing Hold is resolved to the last position that was not a
+    /// Hold (or Out if there never was one).
+    pub fn latest_position(&self) -> Option<(Date, Position)> {
+        let (last_day, _) = self.trades.iter().next_back()?;
+        let position = self
+            .trades
+            .values()
+            .rev()
+            .find(|p| **p != Position::Hold)
+            .cloned()
+            .unwrap_or(Position::Out);
+
+        Some((*last_day, position))
+    }
+
+    /// The position held on each day, with each Hold resolved to the last
+    /// position that was not a Hold (or Out if there never was one).
+    pub fn held(&self) -> TimeSeries<Position> {
+        let mut held = Position::Out;
+        self.trades
+            .iter()
+            .map(|(day, position)| {
+                if *position != Position::Hold {
+                    held = *position;
+                }
+                (*day, held)
+            })
+            .collect()
+    }
+
+    /// Replaces a Hold on `day` with the position held going into it. A
+    /// backtest that starts on `day` starts out of the market, so it would
+    /// otherwise never open a position that was opened before `day`.
+    pub fn open_held_at(&mut self, day: Date) {
+        if self.trades.get(&day) == Some(&Position::Hold) {
+            let held = self
+                .trades
+                .range(..day)
+                .rev()
+                .map(|(_, p)| *p)
+                .find(|p| *p != Position::Hold)
+                .unwrap_or(Position::Out);
+            self.trades.insert(day, held);
+        }
+    }
+
+    /// How many times each exit rule closed a position.
+    pub fn exit_counts(&self) -> BTreeMap<ExitReason, usize> {
+        let mut counts = BTreeMap::new();
+        for reason in self.exits.values() {
+            *counts.entry(*reason).or_insert(0) += 1;
+        }
+        counts
+    }
 }
 
 /// Given historical price data, comes up with a series of trades to attempt
@@ -35,4 +107,46 @@ pub trait TradingModel: Display {
 
     /// Given the Prices time series data, returns a Trades object.
     fn get_trades(self, prices: &Prices) -> Result<Trades, Self::Error>;
+
+    /// Returns the signals that inform the model's decision on the last day
+    /// of `prices`. Models that do not use signals return nothing.
+    fn latest_signals(&mut self, _prices: &Prices) -> Vec<IndicatorSignal> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trades;
+    use crate::{trading::Position, util::TimeSeries, Date};
+
+    #[test]
+    fn latest_position_resolves_hold() {
+        let day1 = Date::from_ymd(2020, 1, 1);
+        let day2 = Date::from_ymd(2020, 1, 2);
+        let day3 = Date::from_ymd(2020, 1, 3);
+        let mut trades = Trades::from(
+            vec![
+                (day1, Position::Long(10.0)),
+                (day2, Position::Hold),
+                (day3, Position::Hold),
+            ]
+            .into_iter()
+            .collect::<TimeSeries<_>>(),
+        );
+        assert_eq!(trades.latest_position(), Some((day3, Position::Long(10.0))));
+        assert!(trades.held().values().all(|p| *p == Position::Long(10.0)));
+
+        let mut from_day2 = trades.clone();
+        from_day2.open_held_at(day2);
+        assert_eq!(from_day2.get(&day2), Some(&Position::Long(10.0)));
+        assert_eq!(from_day2.get(&day3), Some(&Position::Hold));
+
+        trades.trades.insert(day2, Position::Short(5.0));
+        assert_eq!(trades.latest_position(), Some((day3, Position::Short(5.0))));
+
+        trades.trades.insert(day1, Position::Hold);
+        trades.trades.insert(day2, Position::Hold);
+        assert_eq!(trades.latest_position(), Some((day3, Position::Out)));
+    }
 }
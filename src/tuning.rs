@@ -0,0 +1,518 @@
+//! Cross-validation of a machine learning trader's learner over its training
+//! dates, the search for the hyperparameters that score best, and how its
+//! predictions are scored.
+//!
+//! Labels look `horizon` days into the future, so a training day just before a
+//! test window is labelled from prices inside it. Such days are purged from
+//! the training set, and an optional embargo drops the days right after each
+//! test window as well, since their features overlap the test labels.
+
+use crate::{
+    trading::{
+        labeling::Label,
+        ml::{Algorithm, Hyperparameters},
+    },
+    Date,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Errors from setting up cross-validation.
+#[derive(Debug, Error, PartialEq)]
+pub enum TuningError {
+    #[error("{scheme} cross-validation needs at least {min} folds, got {folds}")]
+    TooFewFolds {
+        scheme: CrossValidation,
+        folds: usize,
+        min: usize,
+    },
+
+    #[error(
+        "{days} training days are too few to split into {folds} folds once the \
+         days around each test window are purged"
+    )]
+    NotEnoughData { days: usize, folds: usize },
+
+    #[error("The fraction of training days held out must be between 0 and 1, got {0}")]
+    InvalidHoldout(f64),
+
+    #[error(
+        "{0} training days are too few to hold out a fraction of once the days \
+         before it are purged"
+    )]
+    NotEnoughDataToHoldOut(usize),
+}
+
+/// How the training dates are split into folds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum CrossValidation {
+    /// The dates are cut into `k` blocks, and each is tested on in turn with
+    /// the blocks before and after it (purged and embargoed) to train on.
+    #[default]
+    #[serde(rename = "kfold")]
+    KFold,
+
+    /// The dates are cut into `k + 1` blocks, and each after the first is
+    /// tested on with every block before it (purged) to train on.
+    #[serde(rename = "expanding")]
+    Expanding,
+}
+
+impl FromStr for CrossValidation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "kfold" | "k-fold" | "purged" => Ok(Self::KFold),
+            "expanding" | "expanding-window" => Ok(Self::Expanding),
+            _ => Err(format!(
+                "Unknown cross-validation scheme '{}' (supported: kfold, expanding)",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for CrossValidation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::KFold => "kfold",
+            Self::Expanding => "expanding",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Days to train on and the days to test the trained model on.
+#[derive(Debug, PartialEq)]
+pub struct CvFold {
+    pub train_dates: Vec<Date>,
+    pub test_dates: Vec<Date>,
+}
+
+/// Splits `dates` (consecutive trading days) into `folds` folds. Training
+/// days whose labels reach `horizon` days into a test window are purged, and
+/// with k-fold, so are the `embargo` days after it.
+pub fn cv_folds(
+    dates: &[Date],
+    folds: usize,
+    scheme: CrossValidation,
+    horizon: u32,
+    embargo: usize,
+) -> Result<Vec<CvFold>, TuningError> {
+    let (blocks, min) = match scheme {
+        CrossValidation::KFold => (folds, 2),
+        CrossValidation::Expanding => (folds + 1, 1),
+    };
+    if folds < min {
+        return Err(TuningError::TooFewFolds { scheme, folds, min });
+    }
+
+    let n = dates.len();
+    let bound = |i: usize| i * n / blocks;
+    let first_test = match scheme {
+        CrossValidation::KFold => 0,
+        CrossValidation::Expanding => 1,
+    };
+
+    let mut result = Vec::new();
+    for block in first_test..blocks {
+        let (start, end) = (bound(block), bound(block + 1));
+        let mut train_dates = dates[..start.saturating_sub(horizon as usize)].to_vec();
+        if scheme == CrossValidation::KFold {
+            train_dates.extend_from_slice(&dates[(end + embargo).min(n)..]);
+        }
+
+        if start == end || train_dates.is_empty() {
+            return Err(TuningError::NotEnoughData { days: n, folds });
+        }
+        result.push(CvFold {
+            train_dates,
+            test_dates: dates[start..end].to_vec(),
+        });
+    }
+
+    Ok(result)
+}
+
+/// Holds out the last `fraction` of `dates` (consecutive trading days) to test
+/// on, and trains on the days before them whose labels don't reach `horizon`
+/// days into the held out ones.
+pub fn holdout_fold(dates: &[Date], fraction: f64, horizon: u32) -> Result<CvFold, TuningError> {
+    if !(fraction > 0.0 && fraction < 1.0) {
+        return Err(TuningError::InvalidHoldout(fraction));
+    }
+
+    let n = dates.len();
+    let start = n - (n as f64 * fraction).ceil() as usize;
+    let train_dates = dates[..start.saturating_sub(horizon as usize)].to_vec();
+    if start == n || train_dates.is_empty() {
+        return Err(TuningError::NotEnoughDataToHoldOut(n));
+    }
+
+    Ok(CvFold {
+        train_dates,
+        test_dates: dates[start..].to_vec(),
+    })
+}
+
+/// Values to try for each hyperparameter. Empty lists keep the base value.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HyperparameterGrid {
+    pub max_depths: Vec<usize>,
+    pub min_samples_splits: Vec<usize>,
+    pub min_samples_leaves: Vec<usize>,
+    pub num_trees: Vec<usize>,
+}
+
+impl HyperparameterGrid {
+    /// Every combination of the values that `algorithm` uses, with the rest
+    /// taken from `base`. Tree settings only apply to the tree learners, and
+    /// tree counts only to random forests and gradient boosting.
+    pub fn candidates(&self, base: &Hyperparameters, algorithm: Algorithm) -> Vec<Hyperparameters> {
+        let (trees, ensemble) = match algorithm {
+            Algorithm::DecisionTree | Algorithm::RegressionTree => (true, false),
+            Algorithm::RandomForest | Algorithm::GradientBoosting => (true, true),
+            Algorithm::LogisticRegression
+            | Algorithm::LinearRegression
+            | Algorithm::RidgeRegression => (false, false),
+        };
+        let values = |given: &[usize], used: bool, default: usize| match given {
+            [] => vec![default],
+            _ if !used => vec![default],
+            given => given.to_vec(),
+        };
+
+        let depths: Vec<Option<usize>> = match self.max_depths.as_slice() {
+            [] => vec![base.max_depth],
+            _ if !trees => vec![base.max_depth],
+            given => given.iter().map(|d| Some(*d)).collect(),
+        };
+        let splits = values(&self.min_samples_splits, trees, base.min_samples_split);
+        let leaves = values(&self.min_samples_leaves, trees, base.min_samples_leaf);
+        let num_trees = values(&self.num_trees, ensemble, base.num_trees);
+
+        let mut candidates = Vec::new();
+        for max_depth in &depths {
+            for min_samples_split in &splits {
+                for min_samples_leaf in &leaves {
+                    for num_trees in &num_trees {
+                        candidates.push(Hyperparameters {
+                            max_depth: *max_depth,
+                            min_samples_split: *min_samples_split,
+                            min_samples_leaf: *min_samples_leaf,
+                            num_trees: *num_trees,
+                            ..*base
+                        });
+                    }
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Order of the classes in a ConfusionMatrix.
+const CLASSES: [Label; 3] = [Label::Short, Label::Out, Label::Long];
+
+fn class_index(label: Label) -> usize {
+    match label {
+        Label::Short => 0,
+        Label::Out => 1,
+        Label::Long => 2,
+    }
+}
+
+/// Counts of test days by their actual label (rows) and predicted label
+/// (columns), each in the order short, out, long.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(transparent)]
+pub struct ConfusionMatrix([[usize; 3]; 3]);
+
+/// How well one class was predicted. Precision is None if the class was never
+/// predicted, and recall if it never happened.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ClassMetrics {
+    pub precision: Option<f64>,
+    pub recall: Option<f64>,
+    /// Test days that actually had the class.
+    pub support: usize,
+}
+
+/// ClassMetrics for each class.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ClassReport {
+    pub short: ClassMetrics,
+    pub out: ClassMetrics,
+    pub long: ClassMetrics,
+}
+
+impl ConfusionMatrix {
+    pub fn add(&mut self, actual: Label, predicted: Label) {
+        self.0[class_index(actual)][class_index(predicted)] += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.iter().flatten().sum()
+    }
+
+    /// Fraction of days predicted correctly, or None if there were none.
+    pub fn accuracy(&self) -> Option<f64> {
+        let correct: usize = (0..3).map(|i| self.0[i][i]).sum();
+        ratio(correct, self.total())
+    }
+
+    pub fn class_metrics(&self, class: Label) -> ClassMetrics {
+        let i = class_index(class);
+        let predicted: usize = self.0.iter().map(|row| row[i]).sum();
+        let support: usize = self.0[i].iter().sum();
+        ClassMetrics {
+            precision: ratio(self.0[i][i], predicted),
+            recall: ratio(self.0[i][i], support),
+            support,
+        }
+    }
+
+    pub fn report(&self) -> ClassReport {
+        let [short, out, long] = CLASSES;
+        ClassReport {
+            short: self.class_metrics(short),
+            out: self.class_metrics(out),
+            long: self.class_metrics(long),
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> Option<f64> {
+    match denominator {
+        0 => None,
+        d => Some(numerator as f64 / d as f64),
+    }
+}
+
+/// How well a regressor's predicted returns matched the actual returns on the
+/// test days. Each is None if there were no test days, and the correlation
+/// also if either return never changed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RegressionMetrics {
+    /// Root mean squared error of the predicted returns.
+    pub rmse: Option<f64>,
+    /// Pearson correlation of the predicted and actual returns.
+    pub correlation: Option<f64>,
+    /// Fraction of days whose predicted return had the same sign as the
+    /// actual return.
+    pub hit_rate: Option<f64>,
+    pub days: usize,
+}
+
+impl RegressionMetrics {
+    /// Scores pairs of predicted and actual returns.
+    pub fn new(predicted: &[f64], actual: &[f64]) -> Self {
+        let days = predicted.len().min(actual.len());
+        let pairs = || predicted.iter().zip(actual);
+        if days == 0 {
+            return Self {
+                rmse: None,
+                correlation: None,
+                hit_rate: None,
+                days,
+            };
+        }
+
+        let n = days as f64;
+        let squared_error: f64 = pairs().map(|(p, a)| (p - a).powi(2)).sum();
+        let hits = pairs().filter(|(p, a)| *p * *a > 0.0).count();
+
+        let (mean_p, mean_a) = (
+            stats::mean(predicted.iter().cloned()),
+            stats::mean(actual.iter().cloned()),
+        );
+        let covariance: f64 = pairs().map(|(p, a)| (p - mean_p) * (a - mean_a)).sum();
+        let spread_p: f64 = predicted.iter().map(|p| (p - mean_p).powi(2)).sum();
+        let spread_a: f64 = actual.iter().map(|a| (a - mean_a).powi(2)).sum();
+        let correlation = match spread_p * spread_a {
+            s if s > 0.0 => Some(covariance / s.sqrt()),
+            _ => None,
+        };
+
+        Self {
+            rmse: Some((squared_error / n).sqrt()),
+            correlation,
+            hit_rate: ratio(hits, days),
+            days,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days(n: usize) -> Vec<Date> {
+        (0..n)
+            .map(|i| Date::from_ymd(2020, 1, 1) + chrono::Duration::days(i as i64))
+            .collect()
+    }
+
+    #[test]
+    fn purged_kfold() {
+        let dates = days(20);
+        let folds = cv_folds(&dates, 4, CrossValidation::KFold, 2, 1).unwrap();
+        assert_eq!(folds.len(), 4);
+
+        // The first block has nothing before it, and loses a day to the embargo
+        assert_eq!(folds[0].test_dates, dates[0..5].to_vec());
+        assert_eq!(folds[0].train_dates, dates[6..].to_vec());
+
+        // Two days are purged before the test window and one embargoed after
+        assert_eq!(folds[1].test_dates, dates[5..10].to_vec());
+        let mut train = dates[0..3].to_vec();
+        train.extend_from_slice(&dates[11..]);
+        assert_eq!(folds[1].train_dates, train);
+
+        assert_eq!(folds[3].test_dates, dates[15..20].to_vec());
+        assert_eq!(folds[3].train_dates, dates[0..13].to_vec());
+
+        // No test day is ever trained on, or labelled from
+        for fold in &folds {
+            assert!(fold
+                .train_dates
+                .iter()
+                .all(|d| !fold.test_dates.contains(d)));
+        }
+    }
+
+    #[test]
+    fn expanding_window() {
+        let dates = days(20);
+        let folds = cv_folds(&dates, 3, CrossValidation::Expanding, 2, 10).unwrap();
+        assert_eq!(folds.len(), 3);
+        assert_eq!(folds[0].train_dates, dates[0..3].to_vec());
+        assert_eq!(folds[0].test_dates, dates[5..10].to_vec());
+        assert_eq!(folds[2].train_dates, dates[0..13].to_vec());
+        assert_eq!(folds[2].test_dates, dates[15..20].to_vec());
+    }
+
+    #[test]
+    fn bad_folds() {
+        let dates = days(20);
+        assert_eq!(
+            cv_folds(&dates, 1, CrossValidation::KFold, 2, 0),
+            Err(TuningError::TooFewFolds {
+                scheme: CrossValidation::KFold,
+                folds: 1,
+                min: 2
+            })
+        );
+        // The first test window starts before the horizon is over
+        assert_eq!(
+            cv_folds(&dates, 10, CrossValidation::Expanding, 2, 0),
+            Err(TuningError::NotEnoughData {
+                days: 20,
+                folds: 10
+            })
+        );
+        assert_eq!("expanding".parse(), Ok(CrossValidation::Expanding));
+        assert_eq!(
+            holdout_fold(&dates, 1.0, 2),
+            Err(TuningError::InvalidHoldout(1.0))
+        );
+        assert_eq!(
+            holdout_fold(&dates, 0.95, 2),
+            Err(TuningError::NotEnoughDataToHoldOut(20))
+        );
+        assert_eq!(
+            CrossValidation::KFold.to_string().parse(),
+            Ok(CrossValidation::KFold)
+        );
+    }
+
+    #[test]
+    fn holdout() {
+        let dates = days(20);
+        let fold = holdout_fold(&dates, 0.25, 2).unwrap();
+        assert_eq!(fold.test_dates, dates[15..].to_vec());
+        // Purged up to the horizon before the test days
+        assert_eq!(fold.train_dates, dates[..13].to_vec());
+
+        // Rounds up to at least one day
+        let fold = holdout_fold(&dates, 0.01, 0).unwrap();
+        assert_eq!(fold.test_dates, dates[19..].to_vec());
+    }
+
+    #[test]
+    fn grid() {
+        let grid = HyperparameterGrid {
+            max_depths: vec![2, 4],
+            min_samples_splits: vec![],
+            min_samples_leaves: vec![1, 5],
+            num_trees: vec![10, 50, 100],
+        };
+        let base = Hyperparameters::default();
+
+        let trees = grid.candidates(&base, Algorithm::DecisionTree);
+        assert_eq!(trees.len(), 4);
+        assert!(trees.iter().all(|h| h.num_trees == base.num_trees));
+        assert_eq!(trees[1].max_depth, Some(2));
+        assert_eq!(trees[1].min_samples_leaf, 5);
+
+        assert_eq!(grid.candidates(&base, Algorithm::RandomForest).len(), 12);
+        assert_eq!(grid.candidates(&base, Algorithm::RegressionTree).len(), 4);
+        assert_eq!(
+            grid.candidates(&base, Algorithm::RidgeRegression),
+            vec![base]
+        );
+        assert_eq!(
+            grid.candidates(&base, Algorithm::LogisticRegression),
+            vec![base]
+        );
+        assert_eq!(
+            HyperparameterGrid::default().candidates(&base, Algorithm::GradientBoosting),
+            vec![base]
+        );
+    }
+
+    #[test]
+    fn confusion_matrix() {
+        let mut m = ConfusionMatrix::default();
+        assert_eq!(m.accuracy(), None);
+
+        m.add(Label::Long, Label::Long);
+        m.add(Label::Long, Label::Out);
+        m.add(Label::Short, Label::Long);
+        m.add(Label::Out, Label::Out);
+        assert_eq!(m.total(), 4);
+        assert_eq!(m.accuracy(), Some(0.5));
+
+        let report = m.report();
+        assert_eq!(report.long.precision, Some(0.5));
+        assert_eq!(report.long.recall, Some(0.5));
+        assert_eq!(report.long.support, 2);
+        assert_eq!(report.short.precision, None);
+        assert_eq!(report.short.recall, Some(0.0));
+        assert_eq!(report.out.precision, Some(0.5));
+        assert_eq!(
+            serde_json::to_string(&m).unwrap(),
+            "[[0,0,1],[0,1,0],[0,1,1]]"
+        );
+    }
+
+    #[test]
+    fn regression_metrics() {
+        let m = RegressionMetrics::new(&[0.01, -0.02, 0.03, 0.0], &[0.02, -0.01, -0.01, 0.01]);
+        assert_eq!(m.days, 4);
+        assert!((m.rmse.unwrap() - (0.0019_f64 / 4.0).sqrt()).abs() < 1e-12);
+        assert_eq!(m.hit_rate, Some(0.5));
+        let correlation = m.correlation.unwrap();
+        assert!(correlation > 0.0 && correlation < 1.0);
+
+        // Perfectly correlated, however far off
+        let m = RegressionMetrics::new(&[1.0, 2.0, 3.0], &[0.1, 0.2, 0.3]);
+        assert!((m.correlation.unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(m.hit_rate, Some(1.0));
+
+        let flat = RegressionMetrics::new(&[0.01, 0.01], &[0.02, -0.01]);
+        assert_eq!(flat.correlation, None);
+        assert_eq!(RegressionMetrics::new(&[], &[]).rmse, None);
+    }
+}
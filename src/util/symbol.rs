@@ -1,11 +1,12 @@
-//! Newtype wrapper for a stock ticker symbol as a String.
+//! Newtype wrappers for stock ticker symbols as Strings.
 
 use derive_more::FromStr;
 use serde::{Deserialize, Serialize};
-use std::ops::Deref;
+use std::{convert::TryFrom, fmt::Display, ops::Deref, str::FromStr};
+use thiserror::Error;
 
 /// A stock ticker symbol.
-#[derive(Debug, Default, Serialize, Deserialize, FromStr, PartialEq)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, FromStr, PartialEq)]
 #[serde(transparent)]
 pub struct Symbol(String);
 impl Symbol {
@@ -21,3 +22,90 @@ impl Deref for Symbol {
         &self.0
     }
 }
+
+/// One or more stock ticker symbols, written separated by commas (e.g.
+/// `jpm,aapl`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Symbols(Vec<Symbol>);
+
+/// Returned when parsing a list of symbols with nothing in it.
+#[derive(Debug, Error, PartialEq)]
+#[error("At least one symbol is required")]
+pub struct NoSymbols;
+
+impl Symbols {
+    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Symbol> for Symbols {
+    fn from(s: Symbol) -> Self {
+        Self(vec![s])
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = NoSymbols;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let symbols: Vec<Symbol> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Symbol::new(s.to_string()))
+            .collect();
+
+        match symbols.is_empty() {
+            true => Err(NoSymbols),
+            false => Ok(Self(symbols)),
+        }
+    }
+}
+
+impl Display for Symbols {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let strings: Vec<&str> = self.iter().map(|s| s.as_str()).collect();
+        write!(f, "{}", strings.join(","))
+    }
+}
+
+impl TryFrom<String> for Symbols {
+    type Error = NoSymbols;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Symbols> for String {
+    fn from(s: Symbols) -> Self {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NoSymbols, Symbol, Symbols};
+
+    #[test]
+    fn parse_symbols() {
+        let symbols: Symbols = "jpm, aapl,,msft".parse().unwrap();
+        let strings: Vec<&str> = symbols.iter().map(|s| s.as_str()).collect();
+        assert_eq!(strings, vec!["jpm", "aapl", "msft"]);
+        assert_eq!(symbols.to_string(), "jpm,aapl,msft");
+
+        assert_eq!(
+            "jpm".parse::<Symbols>().unwrap(),
+            Symbols::from(Symbol::new("jpm".to_string()))
+        );
+        assert_eq!(" , ".parse::<Symbols>(), Err(NoSymbols));
+    }
+}